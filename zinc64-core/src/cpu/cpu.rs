@@ -2,11 +2,13 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
 use core::fmt;
 use log::LogLevel;
 
 use crate::factory::{Addressable, Cpu, Register, TickFn};
-use crate::util::{IoPort, IrqLine, Pin, Shared};
+use crate::util::{new_shared, IoPort, IrqLine, Pin, Shared, StateReader, StateWriter};
 
 use super::uops::{decode_opcode, load_program, MicroOp, MicroOpPair, ProgramId};
 
@@ -68,7 +70,7 @@ pub struct Cpu6510 {
     last_pc: u16,
     // I/O
     ba_line: Shared<Pin>,
-    io_port: Shared<IoPort>,
+    io_port: Option<Shared<IoPort>>,
     irq_line: Shared<IrqLine>,
     nmi_line: Shared<IrqLine>,
 }
@@ -94,17 +96,29 @@ impl Cpu6510 {
             last_nmi: false,
             last_pc: 0,
             ba_line,
-            io_port,
+            io_port: Some(io_port),
             irq_line,
             nmi_line,
         }
     }
 
+    /// Constructs a plain 6502 without the on-chip I/O port, as used by disk drives.
+    pub fn new_6502(
+        mem: Shared<dyn Addressable>,
+        irq_line: Shared<IrqLine>,
+        nmi_line: Shared<IrqLine>,
+    ) -> Self {
+        let io_port = new_shared(IoPort::new(0x00, 0xff));
+        let mut cpu = Self::new(mem, io_port, new_shared(Pin::new_high()), irq_line, nmi_line);
+        cpu.io_port = None;
+        cpu
+    }
+
     pub fn clock(&mut self) {
-        if self.ba_line.borrow().is_low() {
-            return;
-        } 
         let pair = self.uops[self.cycle as usize];
+        if self.ba_line.borrow().is_low() && !pair.is_write(self.cycle) {
+            return;
+        }
         self.execute(pair.0);
         if let Some(op1) = pair.1 {
             self.execute(op1);
@@ -891,19 +905,20 @@ impl Cpu6510 {
     // -- Memory Ops
 
     pub fn read_mem(&self, address: u16) -> u8 {
-        let value = match address {
-            0x0000 => self.io_port.borrow().get_direction(),
-            0x0001 => self.io_port.borrow().get_value() & 0x3f,
+        match self.io_port {
+            Some(ref io_port) if address == 0x0000 => io_port.borrow().get_direction(),
+            Some(ref io_port) if address == 0x0001 => io_port.borrow().get_value() & 0x3f,
             _ => self.mem.borrow().read(address),
-        };
-        value
+        }
     }
 
     pub fn write_mem(&mut self, address: u16, value: u8) {
-        match address {
-            0x0000 => self.io_port.borrow_mut().set_direction(value),
-            0x0001 => self.io_port.borrow_mut().set_value(value),
-            _ => {}
+        if let Some(ref io_port) = self.io_port {
+            match address {
+                0x0000 => io_port.borrow_mut().set_direction(value),
+                0x0001 => io_port.borrow_mut().set_value(value),
+                _ => {}
+            }
         }
         self.mem.borrow_mut().write(address, value);
     }
@@ -914,6 +929,21 @@ impl Cpu6510 {
         self.write_mem(address, value);
         self.regs.sp = self.regs.sp.wrapping_sub(1);
     }
+
+    // -- State Ops
+
+    fn program_id(&self) -> Option<ProgramId> {
+        let programs = [
+            ProgramId::Start,
+            ProgramId::Irq,
+            ProgramId::Nmi,
+            ProgramId::Reset,
+        ];
+        programs
+            .iter()
+            .find(|id| core::ptr::eq(self.uops, load_program(**id)))
+            .cloned()
+    }
 }
 
 impl Cpu for Cpu6510 {
@@ -990,11 +1020,15 @@ impl Cpu for Cpu6510 {
         self.page_cross = false;
         self.last_nmi = false;
         self.last_pc = 0;
-        self.io_port.borrow_mut().set_value(0xff);
         self.irq_line.borrow_mut().reset();
         self.nmi_line.borrow_mut().reset();
-        self.write(0x0000, 0b_0010_1111);
-        self.write(0x0001, 0b_0001_1111);
+        if let Some(ref io_port) = self.io_port {
+            io_port.borrow_mut().set_value(0xff);
+        }
+        if self.io_port.is_some() {
+            self.write(0x0000, 0b_0010_1111);
+            self.write(0x0001, 0b_0001_1111);
+        }
         self.opcode = 0;
         self.uops = load_program(ProgramId::Reset);
         self.cycle = 0;
@@ -1009,6 +1043,76 @@ impl Cpu for Cpu6510 {
     fn write(&mut self, address: u16, value: u8) {
         self.write_mem(address, value);
     }
+
+    // -- State
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_tag(b"CPU0");
+        state.write_u8(self.regs.a);
+        state.write_u8(self.regs.x);
+        state.write_u8(self.regs.y);
+        state.write_u8(self.regs.sp);
+        state.write_u16(self.regs.pc);
+        state.write_u8(self.regs.p);
+        // Micro-op program is either one of the fixed programs or decoded from the opcode
+        let program = match self.program_id() {
+            None => 0,
+            Some(ProgramId::Start) => 1,
+            Some(ProgramId::Irq) => 2,
+            Some(ProgramId::Nmi) => 3,
+            Some(ProgramId::Reset) => 4,
+        };
+        state.write_u8(program);
+        state.write_u8(self.opcode);
+        state.write_u8(self.cycle);
+        state.write_u8(self.address_lo);
+        state.write_u8(self.address_hi);
+        state.write_u8(self.data);
+        state.write_bool(self.page_cross);
+        state.write_bool(self.last_nmi);
+        state.write_u16(self.last_pc);
+        if let Some(ref io_port) = self.io_port {
+            io_port.borrow().save_state(state);
+        }
+        self.irq_line.borrow().save_state(state);
+        self.nmi_line.borrow().save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.read_tag(b"CPU0")?;
+        self.regs.a = state.read_u8()?;
+        self.regs.x = state.read_u8()?;
+        self.regs.y = state.read_u8()?;
+        self.regs.sp = state.read_u8()?;
+        self.regs.pc = state.read_u16()?;
+        self.regs.p = state.read_u8()?;
+        let program = state.read_u8()?;
+        self.opcode = state.read_u8()?;
+        self.uops = match program {
+            0 => decode_opcode(self.opcode),
+            1 => load_program(ProgramId::Start),
+            2 => load_program(ProgramId::Irq),
+            3 => load_program(ProgramId::Nmi),
+            4 => load_program(ProgramId::Reset),
+            _ => return Err(format!("Invalid cpu program {}", program)),
+        };
+        self.cycle = state.read_u8()?;
+        if self.cycle as usize >= self.uops.len() {
+            return Err(format!("Invalid cpu cycle {}", self.cycle));
+        }
+        self.address_lo = state.read_u8()?;
+        self.address_hi = state.read_u8()?;
+        self.data = state.read_u8()?;
+        self.page_cross = state.read_bool()?;
+        self.last_nmi = state.read_bool()?;
+        self.last_pc = state.read_u16()?;
+        if let Some(ref io_port) = self.io_port {
+            io_port.borrow_mut().load_state(state)?;
+        }
+        self.irq_line.borrow_mut().load_state(state)?;
+        self.nmi_line.borrow_mut().load_state(state)?;
+        Ok(())
+    }
 }
 
 impl fmt::Display for Cpu6510 {
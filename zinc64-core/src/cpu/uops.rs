@@ -92,6 +92,27 @@ pub enum MicroOp {
     OpRST,
 }
 
+impl MicroOp {
+    /// Check if the micro-op writes to memory when executed in the given cycle of its
+    /// program.
+    pub fn is_write(self, cycle: u8) -> bool {
+        match self {
+            MicroOp::WriteData => true,
+            // The modify cycle of read-modify-write instructions writes the old value back
+            MicroOp::OpDEC
+            | MicroOp::OpINC
+            | MicroOp::OpASL
+            | MicroOp::OpLSR
+            | MicroOp::OpROL
+            | MicroOp::OpROR => true,
+            MicroOp::OpPHA | MicroOp::OpPHP => cycle == 2,
+            MicroOp::OpJSR => cycle == 3 || cycle == 4,
+            MicroOp::OpBRK | MicroOp::OpIRQ | MicroOp::OpNMI => (2..=4).contains(&cycle),
+            _ => false,
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct MicroOpPair(pub MicroOp, pub Option<MicroOp>);
 
@@ -103,6 +124,17 @@ impl MicroOpPair {
     pub const fn pair(op1: MicroOp, op2: MicroOp) -> MicroOpPair {
         MicroOpPair(op1, Some(op2))
     }
+
+    /*
+    RDY is ignored by the 6510 during write cycles, so the CPU only stops on the first
+    read cycle after BA goes low. As no instruction does more than three writes in a
+    row (BRK and interrupts push three bytes, read-modify-write instructions do a dummy
+    write followed by the real one), these are done before the VIC takes over the bus
+    with AEC three cycles later.
+    */
+    pub fn is_write(&self, cycle: u8) -> bool {
+        self.0.is_write(cycle) || self.1.is_some_and(|op| op.is_write(cycle))
+    }
 }
 
 const fn implied(op: MicroOp) -> [MicroOpPair; 3] {
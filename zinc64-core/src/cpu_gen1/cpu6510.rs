@@ -4,8 +4,11 @@
 
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+
 use crate::factory::{make_noop, Addressable, Cpu, Register, TickFn};
-use crate::util::{IoPort, IrqLine, Pin, Shared};
+use crate::util::{IoPort, IrqLine, Pin, Shared, StateReader, StateWriter};
 use core::fmt;
 use log::LogLevel;
 
@@ -717,6 +720,40 @@ impl Cpu for Cpu6510 {
     fn write(&mut self, address: u16, value: u8) {
         self.write_internal(address, value, &make_noop());
     }
+
+    // -- State
+
+    fn save_state(&self, state: &mut StateWriter) {
+        // Instructions are executed as a whole so there is no intra-instruction state
+        state.write_tag(b"CPU1");
+        state.write_u8(self.regs.a);
+        state.write_u8(self.regs.x);
+        state.write_u8(self.regs.y);
+        state.write_u8(self.regs.sp);
+        state.write_u16(self.regs.pc);
+        state.write_u8(self.regs.p);
+        state.write_bool(self.last_nmi);
+        state.write_u16(self.last_pc);
+        self.io_port.borrow().save_state(state);
+        self.irq_line.borrow().save_state(state);
+        self.nmi_line.borrow().save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.read_tag(b"CPU1")?;
+        self.regs.a = state.read_u8()?;
+        self.regs.x = state.read_u8()?;
+        self.regs.y = state.read_u8()?;
+        self.regs.sp = state.read_u8()?;
+        self.regs.pc = state.read_u16()?;
+        self.regs.p = state.read_u8()?;
+        self.last_nmi = state.read_bool()?;
+        self.last_pc = state.read_u16()?;
+        self.io_port.borrow_mut().load_state(state)?;
+        self.irq_line.borrow_mut().load_state(state)?;
+        self.nmi_line.borrow_mut().load_state(state)?;
+        Ok(())
+    }
 }
 
 impl fmt::Display for Cpu6510 {
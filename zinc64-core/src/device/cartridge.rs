@@ -4,13 +4,63 @@
 
 #[cfg(not(feature = "std"))]
 use alloc::prelude::*;
+#[cfg(not(feature = "std"))]
+use alloc::vec;
 use bit_field::BitField;
 use log::LogLevel;
 
+use super::flash::{self, FlashRom};
+use crate::util::{StateReader, StateWriter};
+
 // SPEC: http://ist.uwaterloo.ca/~schepers/formats/CRT.TXT
+// SPEC: GeoRAM, http://www.baltissen.org/newhtm/georam.htm
+// SPEC: Action Replay and Retro Replay, http://rr.pokefinder.org/wiki/Retro_Replay
+// SPEC: EasyFlash, https://skoe.de/easyflash/files/devdocs/EasyFlash-ProgRef.pdf
+
+// Design:
+//   Freezer cartridges have a ROM window at ROML which is also visible at ROMH in 16K and
+//   Ultimax configurations, cartridge RAM that can replace ROM at ROML and both are mirrored
+//   to I/O 2 at $DF00-$DFFF. Pressing the freeze button switches to Ultimax configuration
+//   with bank 0 and asserts NMI, the cartridge stays in that state until the freeze is
+//   released through the control register. NMI is held until the control register is
+//   written so that the next freeze produces a new edge.
+//   EasyFlash keeps ROML and ROMH in two flash chips which are programmed through the
+//   cartridge ROM window. Chips from the CRT image are copied into the flash chips, banks
+//   not present in the image read as erased flash.
+
+pub const GEORAM_MIN_SIZE: usize = 0x8_0000;
+pub const GEORAM_MAX_SIZE: usize = 0x40_0000;
+
+// Epyx FastLoad capacitor discharges about 512 cycles after the last ROML or I/O 1 access
+const EPYX_CAPACITOR_CYCLES: u16 = 512;
+
+#[derive(Copy, Clone)]
+enum Control {
+    Game = 0,
+    Exrom = 1,
+    Disable = 2,
+    BankLo = 3,
+    BankHi = 4,
+    RamEnable = 5,
+    ReleaseFreeze = 6,
+    BankExt = 7,
+}
+
+#[derive(Copy, Clone)]
+enum EasyFlashControl {
+    Game = 0,
+    Exrom = 1,
+    GameMode = 2,
+}
 
-// DEFERRED device: cartridge test cases
+#[derive(Copy, Clone)]
+enum ExtControl {
+    AllowBank = 1,
+    NoFreeze = 2,
+    ReuCompat = 6,
+}
 
+#[derive(Clone, Copy, PartialEq)]
 pub enum ChipType {
     Rom,
     Ram,
@@ -18,12 +68,20 @@ pub enum ChipType {
 }
 
 impl ChipType {
-    pub fn from(chip_type: u16) -> ChipType {
+    pub fn from(chip_type: u16) -> Result<ChipType, String> {
         match chip_type {
-            0x00 => ChipType::Rom,
-            0x01 => ChipType::Ram,
-            0x02 => ChipType::FlashRom,
-            _ => panic!("invalid chip type {}", chip_type),
+            0x00 => Ok(ChipType::Rom),
+            0x01 => Ok(ChipType::Ram),
+            0x02 => Ok(ChipType::FlashRom),
+            _ => Err(format!("unsupported chip type {}", chip_type)),
+        }
+    }
+
+    pub fn value(self) -> u16 {
+        match self {
+            ChipType::Rom => 0x00,
+            ChipType::Ram => 0x01,
+            ChipType::FlashRom => 0x02,
         }
     }
 }
@@ -45,25 +103,84 @@ pub enum HwType {
     MagicDesk,
     SimonsBasic,
     OceanType1,
+    FunPlay,
+    SuperGames,
+    EpyxFastLoad,
+    Westermann,
+    WarpSpeed,
+    Dinamic,
+    Zaxxon,
+    Comal80,
+    Ross,
+    ActionReplay,
+    RetroReplay,
+    GeoRam,
 }
 
 impl HwType {
-    pub fn from(value: u8) -> HwType {
+    pub fn from(value: u8) -> Result<HwType, String> {
         match value {
-            0 => HwType::Normal,
-            3 => HwType::Final3,
-            4 => HwType::SimonsBasic,
-            5 => HwType::OceanType1,
-            15 => HwType::GameSystem,
-            19 => HwType::MagicDesk,
-            32 => HwType::EasyFlash,
-            _ => panic!("invalid hardware type {}", value),
+            0 => Ok(HwType::Normal),
+            1 => Ok(HwType::ActionReplay),
+            3 => Ok(HwType::Final3),
+            4 => Ok(HwType::SimonsBasic),
+            5 => Ok(HwType::OceanType1),
+            7 => Ok(HwType::FunPlay),
+            8 => Ok(HwType::SuperGames),
+            10 => Ok(HwType::EpyxFastLoad),
+            11 => Ok(HwType::Westermann),
+            15 => Ok(HwType::GameSystem),
+            16 => Ok(HwType::WarpSpeed),
+            17 => Ok(HwType::Dinamic),
+            18 => Ok(HwType::Zaxxon),
+            19 => Ok(HwType::MagicDesk),
+            21 => Ok(HwType::Comal80),
+            23 => Ok(HwType::Ross),
+            32 => Ok(HwType::EasyFlash),
+            36 => Ok(HwType::RetroReplay),
+            _ => Err(format!("unsupported hardware type {}", value)),
+        }
+    }
+
+    pub fn value(self) -> u8 {
+        match self {
+            HwType::Normal => 0,
+            HwType::ActionReplay => 1,
+            HwType::Final3 => 3,
+            HwType::SimonsBasic => 4,
+            HwType::OceanType1 => 5,
+            HwType::FunPlay => 7,
+            HwType::SuperGames => 8,
+            HwType::EpyxFastLoad => 10,
+            HwType::Westermann => 11,
+            HwType::GameSystem => 15,
+            HwType::WarpSpeed => 16,
+            HwType::Dinamic => 17,
+            HwType::Zaxxon => 18,
+            HwType::MagicDesk => 19,
+            HwType::Comal80 => 21,
+            HwType::Ross => 23,
+            HwType::EasyFlash => 32,
+            HwType::RetroReplay => 36,
+            // Not a CRT hardware type, only used to identify the device in snapshots
+            HwType::GeoRam => 0xff,
         }
     }
 
+    pub fn is_freezer(self) -> bool {
+        self == HwType::ActionReplay || self == HwType::RetroReplay
+    }
+
     pub fn is_mirrowed(&self) -> bool {
         match *self {
-            HwType::OceanType1 | HwType::MagicDesk | HwType::Normal => true,
+            HwType::OceanType1
+            | HwType::MagicDesk
+            | HwType::Normal
+            | HwType::SuperGames
+            | HwType::Westermann
+            | HwType::WarpSpeed
+            | HwType::Comal80
+            | HwType::Ross => true,
             _ => false,
         }
     }
@@ -89,7 +206,9 @@ pub struct Cartridge {
     hw_type: HwType,
     exrom: bool,
     game: bool,
+    name: [u8; 32],
     banks: [Option<Chip>; 64],
+    banks_hi: [Option<Chip>; 64],
     io_observer: Option<Box<dyn Fn(&IoConfig)>>,
     is_mirrowed: bool,
     // Runtime state
@@ -97,6 +216,21 @@ pub struct Cartridge {
     bank_hi: Option<usize>,
     io_config: IoConfig,
     reg_value: u8,
+    capacitor: u16,
+    // Cartridge RAM
+    ram: Vec<u8>,
+    ram_block: u8,
+    ram_page: u8,
+    // EasyFlash state
+    flash: Vec<FlashRom>,
+    flash_bank: u8,
+    // Freezer state
+    control: u8,
+    ext_control: u8,
+    ext_locked: bool,
+    disabled: bool,
+    freeze_pressed: bool,
+    nmi: bool,
 }
 
 impl Cartridge {
@@ -106,6 +240,7 @@ impl Cartridge {
             hw_type,
             exrom,
             game,
+            name: [0; 32],
             banks: [
                 None, None, None, None, None, None, None, None, None, None, None, None, None, None,
                 None, None, None, None, None, None, None, None, None, None, None, None, None, None,
@@ -113,22 +248,190 @@ impl Cartridge {
                 None, None, None, None, None, None, None, None, None, None, None, None, None, None,
                 None, None, None, None, None, None, None, None,
             ],
+            banks_hi: [
+                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
+                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
+                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
+                None, None, None, None, None, None, None, None, None, None, None, None, None, None,
+                None, None, None, None, None, None, None, None,
+            ],
             io_observer: None,
             is_mirrowed: hw_type.is_mirrowed(),
             bank_lo: None,
             bank_hi: None,
             io_config: IoConfig::new(),
             reg_value: 0,
+            capacitor: 0,
+            ram: match hw_type {
+                HwType::ActionReplay => vec![0; 0x2000],
+                HwType::RetroReplay => vec![0; 0x8000],
+                HwType::EasyFlash => vec![0; 0x100],
+                _ => Vec::new(),
+            },
+            ram_block: 0,
+            ram_page: 0,
+            flash: match hw_type {
+                HwType::EasyFlash => vec![FlashRom::new(), FlashRom::new()],
+                _ => Vec::new(),
+            },
+            flash_bank: 0,
+            control: 0,
+            ext_control: 0,
+            ext_locked: false,
+            disabled: false,
+            freeze_pressed: false,
+            nmi: false,
+        }
+    }
+
+    /// Create GeoRAM expansion with the specified RAM size, which must be a power of two
+    /// between 512K and 4M. RAM is paged in 256 byte pages at $DE00 with page and 16K block
+    /// selected by $DFFE and $DFFF.
+    pub fn new_georam(size: usize) -> Result<Self, String> {
+        if !size.is_power_of_two() || !(GEORAM_MIN_SIZE..=GEORAM_MAX_SIZE).contains(&size) {
+            return Err(format!("invalid GeoRAM size {}", size));
         }
+        let mut cartridge = Self::new(0x0100, HwType::GeoRam, true, true);
+        cartridge.ram = vec![0; size];
+        Ok(cartridge)
+    }
+
+    pub fn get_hw_type(&self) -> HwType {
+        self.hw_type
+    }
+
+    pub fn get_version(&self) -> u16 {
+        self.version
+    }
+
+    /// Get name from the CRT header of the image the cartridge was loaded from.
+    pub fn get_name(&self) -> &[u8; 32] {
+        &self.name
+    }
+
+    pub fn set_name(&mut self, name: [u8; 32]) {
+        self.name = name;
+    }
+
+    /// Get EXROM and GAME line state the cartridge starts with, true if the line is high.
+    pub fn get_io_lines(&self) -> (bool, bool) {
+        (self.exrom, self.game)
+    }
+
+    /// Get ROM chips of the cartridge in the format of a CRT image. EasyFlash chips reflect
+    /// the current flash contents with erased banks left out.
+    pub fn get_chips(&self) -> Vec<Chip> {
+        if !self.flash.is_empty() {
+            let mut chips = Vec::new();
+            for bank_number in 0..(flash::SIZE / 0x2000) {
+                for (flash, offset) in self.flash.iter().zip(&[0x8000u16, 0xa000]) {
+                    let start = bank_number * 0x2000;
+                    let data = &flash.get_data()[start..start + 0x2000];
+                    if data.iter().any(|byte| *byte != 0xff) {
+                        chips.push(Chip {
+                            chip_type: ChipType::FlashRom,
+                            bank_number: bank_number as u8,
+                            offset: *offset,
+                            size: 0x2000,
+                            data: data.to_vec(),
+                        });
+                    }
+                }
+            }
+            chips
+        } else {
+            self.banks
+                .iter()
+                .chain(self.banks_hi.iter())
+                .filter_map(|bank| bank.as_ref())
+                .map(|chip| Chip {
+                    chip_type: chip.chip_type,
+                    bank_number: chip.bank_number,
+                    offset: chip.offset,
+                    size: chip.size,
+                    data: chip.data.clone(),
+                })
+                .collect()
+        }
+    }
+
+    /// Check if the cartridge holds NMI low after the freeze button was pressed.
+    pub fn is_nmi_asserted(&self) -> bool {
+        self.nmi
+    }
+
+    /// Check if flash memory was programmed or erased since the cartridge was loaded.
+    pub fn is_flash_modified(&self) -> bool {
+        self.flash.iter().any(|flash| flash.is_modified())
+    }
+
+    /// Get cartridge RAM contents, if the cartridge has any.
+    pub fn get_ram(&self) -> Option<&[u8]> {
+        if !self.ram.is_empty() {
+            Some(&self.ram)
+        } else {
+            None
+        }
+    }
+
+    /// Load cartridge RAM contents. Images smaller than RAM fill the beginning of RAM.
+    pub fn load_ram(&mut self, data: &[u8]) -> Result<(), String> {
+        if data.len() > self.ram.len() {
+            return Err(format!(
+                "RAM image size {} exceeds cartridge RAM size {}",
+                data.len(),
+                self.ram.len()
+            ));
+        }
+        self.ram[..data.len()].copy_from_slice(data);
+        Ok(())
     }
 
     pub fn set_io_observer(&mut self, observer: Option<Box<dyn Fn(&IoConfig)>>) {
         self.io_observer = observer;
     }
 
-    pub fn add(&mut self, chip: Chip) {
+    /// Add ROM chip. Chips loaded at $8000 are mapped to ROML and chips loaded at $A000 or
+    /// $E000 to ROMH, a bank may have both.
+    pub fn add(&mut self, chip: Chip) -> Result<(), String> {
         let bank_num = chip.bank_number as usize;
-        self.banks[bank_num] = Some(chip);
+        if bank_num >= self.banks.len() {
+            return Err(format!("unsupported bank number {}", bank_num));
+        }
+        if !self.flash.is_empty() {
+            return self.add_flash(chip);
+        }
+        match chip.offset {
+            0x8000 => self.banks[bank_num] = Some(chip),
+            0xa000 | 0xe000 => self.banks_hi[bank_num] = Some(chip),
+            offset => return Err(format!("unsupported load address 0x{:04x}", offset)),
+        }
+        Ok(())
+    }
+
+    fn add_flash(&mut self, chip: Chip) -> Result<(), String> {
+        let start = chip.bank_number as usize * 0x2000;
+        match chip.offset {
+            0x8000 => {
+                // 16K chips cover both ROML and ROMH
+                let split = chip.data.len().min(0x2000);
+                self.flash[0].load(start, &chip.data[..split])?;
+                self.flash[1].load(start, &chip.data[split..])
+            }
+            0xa000 | 0xe000 => self.flash[1].load(start, &chip.data),
+            offset => Err(format!("unsupported load address 0x{:04x}", offset)),
+        }
+    }
+
+    /// Clock cartridge logic that depends on time, like the capacitor of Epyx FastLoad.
+    pub fn clock(&mut self) {
+        if self.capacitor > 0 {
+            self.capacitor -= 1;
+            if self.capacitor == 0 {
+                self.io_config.exrom = true;
+                self.notify_io_changed();
+            }
+        }
     }
 
     pub fn reset(&mut self) {
@@ -138,12 +441,295 @@ impl Cartridge {
             exrom: self.exrom,
             game: self.game,
         };
-        if !self.banks.is_empty() {
+        self.ram_block = 0;
+        self.ram_page = 0;
+        self.flash_bank = 0;
+        for flash in self.flash.iter_mut() {
+            flash.reset();
+        }
+        self.control = 0;
+        self.ext_control = 0;
+        self.ext_locked = false;
+        self.disabled = false;
+        self.freeze_pressed = false;
+        self.nmi = false;
+        self.capacitor = 0;
+        if self.banks[0].is_some() || self.banks_hi[0].is_some() {
             self.switch_bank(0);
         }
+        if self.hw_type == HwType::EpyxFastLoad {
+            self.capacitor = EPYX_CAPACITOR_CYCLES;
+        }
+        self.notify_io_changed();
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.hw_type.value());
+        save_bank(state, self.bank_lo);
+        save_bank(state, self.bank_hi);
+        state.write_bool(self.io_config.exrom);
+        state.write_bool(self.io_config.game);
+        state.write_u8(self.reg_value);
+        state.write_u16(self.capacitor);
+        state.write_u8(self.ram_block);
+        state.write_u8(self.ram_page);
+        if self.hw_type == HwType::GeoRam {
+            state.write_expansion_ram(&self.ram);
+        } else {
+            state.write_bytes(&self.ram);
+        }
+        state.write_u8(self.flash_bank);
+        for flash in self.flash.iter() {
+            flash.save_state(state);
+        }
+        state.write_u8(self.control);
+        state.write_u8(self.ext_control);
+        state.write_bool(self.ext_locked);
+        state.write_bool(self.disabled);
+        state.write_bool(self.freeze_pressed);
+        state.write_bool(self.nmi);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        let hw_type = state.read_u8()?;
+        if hw_type != self.hw_type.value() {
+            return Err(format!("Invalid cartridge hardware type {}", hw_type));
+        }
+        self.bank_lo = self.load_bank(state)?;
+        self.bank_hi = self.load_bank(state)?;
+        self.io_config.exrom = state.read_bool()?;
+        self.io_config.game = state.read_bool()?;
+        self.reg_value = state.read_u8()?;
+        self.capacitor = state.read_u16()?;
+        self.ram_block = state.read_u8()?;
+        self.ram_page = state.read_u8()?;
+        if self.hw_type == HwType::GeoRam {
+            state.read_expansion_ram(&mut self.ram)?;
+        } else {
+            state.read_bytes(&mut self.ram)?;
+        }
+        self.flash_bank = state.read_u8()?;
+        for flash in self.flash.iter_mut() {
+            flash.load_state(state)?;
+        }
+        self.control = state.read_u8()?;
+        self.ext_control = state.read_u8()?;
+        self.ext_locked = state.read_bool()?;
+        self.disabled = state.read_bool()?;
+        self.freeze_pressed = state.read_bool()?;
+        self.nmi = state.read_bool()?;
+        self.notify_io_changed();
+        Ok(())
+    }
+
+    fn load_bank(&self, state: &mut StateReader) -> Result<Option<usize>, String> {
+        if state.read_bool()? {
+            let bank_number = state.read_u8()? as usize;
+            if bank_number < self.banks.len()
+                && (self.banks[bank_number].is_some() || self.banks_hi[bank_number].is_some())
+            {
+                Ok(Some(bank_number))
+            } else {
+                Err(format!("Invalid cartridge bank {}", bank_number))
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Press the freeze button. Returns true if the cartridge responds by asserting NMI.
+    pub fn freeze(&mut self) -> bool {
+        if !self.hw_type.is_freezer()
+            || (self.hw_type == HwType::RetroReplay
+                && self.ext_control.get_bit(ExtControl::NoFreeze as usize))
+        {
+            return false;
+        }
+        info!(target: "cart", "Freeze");
+        self.disabled = false;
+        self.freeze_pressed = true;
+        let mut control = 0u8;
+        control.set_bit(Control::Game as usize, true);
+        control.set_bit(Control::Exrom as usize, true);
+        self.set_freezer_control(control);
+        self.nmi = true;
+        true
+    }
+
+    fn freezer_bank(&self) -> usize {
+        let mut bank = 0usize;
+        bank.set_bit(0, self.control.get_bit(Control::BankLo as usize));
+        bank.set_bit(1, self.control.get_bit(Control::BankHi as usize));
+        if self.hw_type == HwType::RetroReplay {
+            bank.set_bit(2, self.control.get_bit(Control::BankExt as usize));
+        }
+        bank
+    }
+
+    fn freezer_ram_offset(&self, address: u16, io: bool) -> usize {
+        let bank = match self.hw_type {
+            HwType::RetroReplay
+                if !io || self.ext_control.get_bit(ExtControl::AllowBank as usize) =>
+            {
+                self.freezer_bank() & 0x03
+            }
+            _ => 0,
+        };
+        (bank << 13) | (address & 0x1fff) as usize
+    }
+
+    fn freezer_rom(&self, address: u16) -> Option<u8> {
+        self.bank_lo
+            .and_then(|bank_num| self.banks[bank_num].as_ref())
+            .map(|bank| bank.data[(address & 0x1fff) as usize % bank.data.len()])
+    }
+
+    fn is_freezer_io(&self, address: u16) -> bool {
+        if self.disabled {
+            return false;
+        }
+        let reu_compat = self.hw_type == HwType::RetroReplay
+            && self.ext_control.get_bit(ExtControl::ReuCompat as usize);
+        match address {
+            0xde02..=0xdeff => reu_compat,
+            0xdf00..=0xdfff => !reu_compat,
+            _ => false,
+        }
+    }
+
+    fn read_freezer(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0x9fff if self.control.get_bit(Control::RamEnable as usize) => {
+                Some(self.ram[self.freezer_ram_offset(address, false)])
+            }
+            0x8000..=0x9fff | 0xa000..=0xbfff | 0xe000..=0xffff => self.freezer_rom(address),
+            0xde00..=0xde01 if self.hw_type == HwType::RetroReplay && !self.disabled => {
+                let mut value = self.control & 0x98;
+                value.set_bit(
+                    ExtControl::AllowBank as usize,
+                    self.ext_control.get_bit(ExtControl::AllowBank as usize),
+                );
+                // Status reports freeze button state in place of NoFreeze
+                value.set_bit(ExtControl::NoFreeze as usize, self.freeze_pressed);
+                value.set_bit(
+                    ExtControl::ReuCompat as usize,
+                    self.ext_control.get_bit(ExtControl::ReuCompat as usize),
+                );
+                Some(value)
+            }
+            0xde00..=0xdfff if self.is_freezer_io(address) => {
+                if self.control.get_bit(Control::RamEnable as usize) {
+                    Some(self.ram[self.freezer_ram_offset(address, true)])
+                } else {
+                    self.freezer_rom(address)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn write_freezer(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0x9fff if self.control.get_bit(Control::RamEnable as usize) => {
+                let offset = self.freezer_ram_offset(address, false);
+                self.ram[offset] = value;
+            }
+            0xde00 if !self.disabled => self.set_freezer_control(value),
+            0xde01 if self.hw_type == HwType::RetroReplay && !self.disabled => {
+                if !self.ext_locked {
+                    self.ext_control = value & 0x46;
+                    self.ext_locked = true;
+                }
+                let control = (self.control & 0x67) | (value & 0x98);
+                self.set_freezer_control(control);
+            }
+            0xde00..=0xdfff
+                if self.is_freezer_io(address)
+                    && self.control.get_bit(Control::RamEnable as usize) =>
+            {
+                let offset = self.freezer_ram_offset(address, true);
+                self.ram[offset] = value;
+            }
+            _ => {}
+        }
+    }
+
+    fn set_freezer_control(&mut self, value: u8) {
+        if log_enabled!(LogLevel::Trace) {
+            trace!(target: "cart::reg", "Control 0x{:02x}", value);
+        }
+        self.control = value & !(1 << Control::ReleaseFreeze as u8);
+        self.nmi = false;
+        if value.get_bit(Control::ReleaseFreeze as usize) {
+            self.freeze_pressed = false;
+        }
+        if value.get_bit(Control::Disable as usize) {
+            self.disabled = true;
+            self.bank_lo = None;
+            self.io_config.game = true;
+            self.io_config.exrom = true;
+        } else {
+            let bank = self.freezer_bank();
+            self.bank_lo = if self.banks[bank].is_some() {
+                Some(bank)
+            } else {
+                None
+            };
+            self.io_config.game = !value.get_bit(Control::Game as usize);
+            self.io_config.exrom = value.get_bit(Control::Exrom as usize);
+        }
         self.notify_io_changed();
     }
 
+    fn flash_offset(&self, address: u16) -> usize {
+        ((self.flash_bank as usize & 0x3f) << 13) | (address & 0x1fff) as usize
+    }
+
+    fn read_easyflash(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x8000..=0x9fff => Some(self.flash[0].read(self.flash_offset(address))),
+            0xa000..=0xbfff | 0xe000..=0xffff => {
+                Some(self.flash[1].read(self.flash_offset(address)))
+            }
+            0xdf00..=0xdfff => Some(self.ram[(address & 0xff) as usize]),
+            _ => None,
+        }
+    }
+
+    fn write_easyflash(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0x9fff => {
+                let offset = self.flash_offset(address);
+                self.flash[0].write(offset, value);
+            }
+            0xa000..=0xbfff | 0xe000..=0xffff => {
+                let offset = self.flash_offset(address);
+                self.flash[1].write(offset, value);
+            }
+            0xde00 => self.flash_bank = value & 0x3f,
+            0xde02 => {
+                self.control = value;
+                // GAME is controlled by the boot jumper unless mode bit is set
+                let game = if value.get_bit(EasyFlashControl::GameMode as usize) {
+                    !value.get_bit(EasyFlashControl::Game as usize)
+                } else {
+                    self.game
+                };
+                let exrom = !value.get_bit(EasyFlashControl::Exrom as usize);
+                self.set_mode(exrom, game);
+            }
+            0xdf00..=0xdfff => self.ram[(address & 0xff) as usize] = value,
+            _ => {}
+        }
+    }
+
+    fn georam_offset(&self, address: u16) -> usize {
+        let blocks = self.ram.len() >> 14;
+        let block = self.ram_block as usize & (blocks - 1);
+        let page = self.ram_page as usize & 0x3f;
+        (block << 14) | (page << 8) | (address & 0xff) as usize
+    }
+
     fn notify_io_changed(&self) {
         if let Some(ref observer) = self.io_observer {
             observer(&self.io_config);
@@ -154,28 +740,61 @@ impl Cartridge {
         if log_enabled!(LogLevel::Trace) {
             trace!(target: "cart::banks", "Switching to bank {} game {} exrom {}", bank_number, self.io_config.game, self.io_config.exrom);
         }
-        if let Some(ref bank) = self.banks[bank_number as usize] {
-            match bank.offset {
-                0x8000 => {
-                    self.bank_lo = Some(bank.bank_number as usize);
-                    if self.is_mirrowed {
-                        self.bank_hi = self.bank_lo;
-                    } else {
-                        self.bank_hi = None;
-                    }
-                }
-                0xa000 => {
-                    self.bank_hi = Some(bank.bank_number as usize);
-                    if self.is_mirrowed {
-                        self.bank_lo = self.bank_hi;
-                    } else {
-                        self.bank_lo = None;
-                    }
-                }
-                _ => panic!("invalid load address {:04x}", bank.bank_number),
-            }
+        let bank_num = bank_number as usize;
+        let has_lo = bank_num < self.banks.len() && self.banks[bank_num].is_some();
+        let has_hi = bank_num < self.banks_hi.len() && self.banks_hi[bank_num].is_some();
+        if has_lo {
+            self.bank_lo = Some(bank_num);
+            self.bank_hi = if has_hi || self.is_mirrowed {
+                Some(bank_num)
+            } else {
+                None
+            };
+        } else if has_hi {
+            self.bank_hi = Some(bank_num);
+            self.bank_lo = if self.is_mirrowed {
+                Some(bank_num)
+            } else {
+                None
+            };
         } else {
-            panic!("invalid bank number {}", bank_number);
+            warn!(target: "cart::banks", "Invalid bank number {}", bank_number);
+        }
+    }
+
+    fn set_mode(&mut self, exrom: bool, game: bool) {
+        self.io_config.exrom = exrom;
+        self.io_config.game = game;
+        self.notify_io_changed();
+    }
+
+    fn charge_capacitor(&mut self) {
+        self.capacitor = EPYX_CAPACITOR_CYCLES;
+        if self.io_config.exrom {
+            self.io_config.exrom = false;
+            self.notify_io_changed();
+        }
+    }
+
+    fn read_rom_lo(&self, address: u16) -> Option<u8> {
+        self.bank_lo
+            .and_then(|bank_num| {
+                self.banks[bank_num]
+                    .as_ref()
+                    .or_else(|| self.banks_hi[bank_num].as_ref())
+            })
+            .map(|bank| bank.data[(address & 0x1fff) as usize % bank.data.len()])
+    }
+
+    fn read_rom_hi(&self, address: u16) -> Option<u8> {
+        let bank_num = self.bank_hi?;
+        if let Some(ref bank) = self.banks_hi[bank_num] {
+            Some(bank.data[(address & 0x1fff) as usize % bank.data.len()])
+        } else {
+            // 16K chips have ROMH in the upper half
+            self.banks[bank_num]
+                .as_ref()
+                .map(|bank| bank.data[(0x2000 | (address & 0x1fff)) as usize % bank.data.len()])
         }
     }
 
@@ -183,12 +802,34 @@ impl Cartridge {
 
     fn read_io(&mut self, address: u16) -> u8 {
         match self.hw_type {
+            HwType::Dinamic if address <= 0xdeff => {
+                self.switch_bank((address & 0x0f) as u8);
+            }
+            HwType::EpyxFastLoad => match address {
+                0xde00..=0xdeff => self.charge_capacitor(),
+                // I/O 2 shows the last page of the ROM
+                _ => return self.read_rom_lo(0x1f00 | (address & 0xff)).unwrap_or(0),
+            },
             HwType::GameSystem => match address {
                 0xde00..=0xdeff => {
                     self.switch_bank((address & 0x3f) as u8);
                 }
                 _ => {}
             },
+            HwType::Ross => match address {
+                0xde00..=0xdeff => self.switch_bank(1),
+                _ => self.set_mode(true, true),
+            },
+            HwType::WarpSpeed => {
+                // Both I/O areas show the last two pages of ROML
+                return self.read_rom_lo(0x1e00 | (address & 0x01ff)).unwrap_or(0);
+            }
+            HwType::Westermann if address >= 0xdf00 => {
+                self.set_mode(false, true);
+            }
+            HwType::GeoRam if address <= 0xdeff => {
+                return self.ram[self.georam_offset(address)];
+            }
             _ => {}
         }
         self.reg_value
@@ -197,16 +838,36 @@ impl Cartridge {
     fn write_io(&mut self, address: u16, value: u8) {
         self.reg_value = value;
         match self.hw_type {
-            HwType::EasyFlash => {
-                if address == 0xde00 {
-                    self.switch_bank(value & 0x3f);
+            HwType::Comal80 if address == 0xde00 => {
+                self.switch_bank(value & 0x03);
+                match value & 0xe0 {
+                    0xe0 => self.set_mode(true, true),
+                    0x40 => self.set_mode(false, true),
+                    _ => self.set_mode(false, false),
                 }
             }
             HwType::Final3 => {
                 if address == 0xde00 {
-                    self.switch_bank(value - 0x40);
+                    self.switch_bank(value & 0x3f);
+                }
+            }
+            HwType::FunPlay if address == 0xde00 => {
+                if value & 0xc6 == 0x86 {
+                    self.set_mode(true, true);
+                } else {
+                    self.switch_bank(((value >> 3) & 0x07) | ((value & 0x01) << 3));
+                    self.set_mode(false, true);
                 }
             }
+            HwType::GeoRam => match address {
+                0xde00..=0xdeff => {
+                    let offset = self.georam_offset(address);
+                    self.ram[offset] = value;
+                }
+                0xdffe => self.ram_page = value,
+                0xdfff => self.ram_block = value,
+                _ => {}
+            },
             HwType::MagicDesk => {
                 if address == 0xde00 {
                     if value.get_bit(7) == false {
@@ -228,11 +889,8 @@ impl Cartridge {
             }
             HwType::OceanType1 => {
                 if address == 0xde00 {
-                    if value.get_bit(7) == true {
-                        self.switch_bank(value & 0x3f);
-                    } else {
-                        panic!("should not be here");
-                    }
+                    // Bit 7 is set by software but not decoded
+                    self.switch_bank(value & 0x3f);
                 }
             }
             HwType::SimonsBasic => {
@@ -241,41 +899,332 @@ impl Cartridge {
                     self.notify_io_changed();
                 }
             }
+            // Bit 3 write protects the register until reset
+            HwType::SuperGames if address >= 0xdf00 && !self.control.get_bit(3) => {
+                self.control = value;
+                self.switch_bank(value & 0x03);
+                if value.get_bit(2) {
+                    self.set_mode(true, true);
+                } else {
+                    self.set_mode(false, false);
+                }
+            }
+            HwType::WarpSpeed => {
+                if address <= 0xdeff {
+                    self.set_mode(false, false);
+                } else {
+                    self.set_mode(true, true);
+                }
+            }
             _ => {}
         }
     }
 
     pub fn read(&mut self, address: u16) -> Option<u8> {
+        if self.hw_type.is_freezer() {
+            return self.read_freezer(address);
+        }
+        if self.hw_type == HwType::EasyFlash {
+            return self.read_easyflash(address);
+        }
         match address {
             0x8000..=0x9fff => {
-                if let Some(bank_num) = self.bank_lo {
-                    let bank = self.banks[bank_num].as_ref().unwrap();
-                    Some(bank.data[(address - 0x8000) as usize])
-                } else {
-                    None
-                }
-            }
-            0xa000..=0xbfff => {
-                if let Some(bank_num) = self.bank_hi {
-                    let bank = self.banks[bank_num].as_ref().unwrap();
-                    if bank.offset == 0x8000 {
-                        Some(bank.data[(address - 0x8000) as usize])
-                    } else {
-                        Some(bank.data[(address - 0xa000) as usize])
+                match self.hw_type {
+                    HwType::EpyxFastLoad => self.charge_capacitor(),
+                    HwType::Zaxxon => {
+                        // ROML access selects ROMH bank through address line A12
+                        let bank_num = (address >> 12) as usize & 0x01;
+                        if self.banks_hi[bank_num].is_some() {
+                            self.bank_hi = Some(bank_num);
+                        }
                     }
-                } else {
-                    None
+                    _ => {}
                 }
+                self.read_rom_lo(address)
             }
+            0xa000..=0xbfff | 0xe000..=0xffff => self.read_rom_hi(address),
             0xde00..=0xdfff => Some(self.read_io(address)),
-            _ => panic!("invalid address {:04x}", address),
+            _ => None,
         }
     }
 
     pub fn write(&mut self, address: u16, value: u8) {
-        match address {
-            0xde00..=0xdfff => self.write_io(address, value),
-            _ => panic!("writes to cartridge are not supported"),
+        if self.hw_type.is_freezer() {
+            self.write_freezer(address, value);
+        } else if self.hw_type == HwType::EasyFlash {
+            self.write_easyflash(address, value);
+        } else if let 0xde00..=0xdfff = address {
+            self.write_io(address, value);
+        }
+    }
+}
+
+fn save_bank(state: &mut StateWriter, bank: Option<usize>) {
+    if let Some(bank_number) = bank {
+        state.write_bool(true);
+        state.write_u8(bank_number as u8);
+    } else {
+        state.write_bool(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chip(bank_number: u8, offset: u16, size: u16) -> Chip {
+        let mut data = vec![bank_number; size as usize];
+        // Tag the upper half of 16K chips so ROMH reads can be told apart
+        if size > 0x2000 {
+            for byte in data[0x2000..].iter_mut() {
+                *byte |= 0x80;
+            }
         }
+        data[size as usize - 1] = 0xf0 | bank_number;
+        Chip {
+            chip_type: ChipType::Rom,
+            bank_number,
+            offset,
+            size,
+            data,
+        }
+    }
+
+    fn setup(
+        hw_type: HwType,
+        exrom: bool,
+        game: bool,
+        banks: u8,
+        offset: u16,
+        size: u16,
+    ) -> Cartridge {
+        let mut cartridge = Cartridge::new(0x0100, hw_type, exrom, game);
+        for bank in 0..banks {
+            cartridge.add(chip(bank, offset, size)).unwrap();
+        }
+        cartridge.reset();
+        cartridge
+    }
+
+    fn mode(cartridge: &Cartridge) -> (bool, bool) {
+        (cartridge.io_config.exrom, cartridge.io_config.game)
+    }
+
+    #[test]
+    fn unsupported_types() {
+        assert!(HwType::from(2).is_err());
+        assert!(HwType::from(7).is_ok());
+        assert!(ChipType::from(3).is_err());
+        let mut cartridge = Cartridge::new(0x0100, HwType::Normal, false, true);
+        assert!(cartridge.add(chip(64, 0x8000, 0x2000)).is_err());
+        assert!(cartridge.add(chip(0, 0xc000, 0x2000)).is_err());
+    }
+
+    #[test]
+    fn normal_16k_with_two_chips() {
+        let mut cartridge = Cartridge::new(0x0100, HwType::Normal, false, false);
+        cartridge.add(chip(0, 0x8000, 0x2000)).unwrap();
+        let mut hi = chip(0, 0xa000, 0x2000);
+        hi.data[0] = 0xaa;
+        cartridge.add(hi).unwrap();
+        cartridge.reset();
+        assert_eq!(Some(0x00), cartridge.read(0x8000));
+        assert_eq!(Some(0xaa), cartridge.read(0xa000));
+    }
+
+    #[test]
+    fn easyflash_banks_and_control() {
+        let mut cartridge = Cartridge::new(0x0100, HwType::EasyFlash, true, false);
+        cartridge.add(chip(0, 0x8000, 0x2000)).unwrap();
+        cartridge.add(chip(0, 0xa000, 0x2000)).unwrap();
+        cartridge.add(chip(3, 0xe000, 0x2000)).unwrap();
+        cartridge.reset();
+        assert_eq!((true, false), mode(&cartridge));
+        assert_eq!(Some(0xf0), cartridge.read(0xffff));
+        cartridge.write(0xde00, 0x03);
+        assert_eq!(Some(0xf3), cartridge.read(0xbfff));
+        // Bank without ROML chip reads as erased flash
+        assert_eq!(Some(0xff), cartridge.read(0x8000));
+        cartridge.write(0xde02, 0x07);
+        assert_eq!((false, false), mode(&cartridge));
+        cartridge.write(0xde02, 0x06);
+        assert_eq!((false, true), mode(&cartridge));
+        cartridge.write(0xde02, 0x04);
+        assert_eq!((true, true), mode(&cartridge));
+        cartridge.write(0xdf10, 0x42);
+        assert_eq!(Some(0x42), cartridge.read(0xdf10));
+    }
+
+    #[test]
+    fn easyflash_program() {
+        let mut cartridge = Cartridge::new(0x0100, HwType::EasyFlash, true, false);
+        cartridge.add(chip(0, 0x8000, 0x2000)).unwrap();
+        cartridge.reset();
+        assert!(!cartridge.is_flash_modified());
+        cartridge.write(0xde00, 0x05);
+        cartridge.write(0x8555, 0xaa);
+        cartridge.write(0x82aa, 0x55);
+        cartridge.write(0x8555, 0xa0);
+        cartridge.write(0x8123, 0x5a);
+        assert_eq!(Some(0x5a), cartridge.read(0x8123));
+        assert!(cartridge.is_flash_modified());
+        let chips = cartridge.get_chips();
+        assert_eq!(2, chips.len());
+        assert_eq!(5, chips[1].bank_number);
+        assert_eq!(0x8000, chips[1].offset);
+        assert_eq!(0x5a, chips[1].data[0x123]);
+    }
+
+    #[test]
+    fn fun_play() {
+        let mut cartridge = setup(HwType::FunPlay, false, true, 16, 0x8000, 0x2000);
+        cartridge.write(0xde00, 0x08 << 1 | 0x01);
+        assert_eq!(Some(0x0a), cartridge.read(0x8000));
+        cartridge.write(0xde00, 0x05 << 3);
+        assert_eq!(Some(0x05), cartridge.read(0x8000));
+        assert_eq!((false, true), mode(&cartridge));
+        cartridge.write(0xde00, 0x86);
+        assert_eq!((true, true), mode(&cartridge));
+    }
+
+    #[test]
+    fn ocean_type1_ignores_bit_7() {
+        let mut cartridge = setup(HwType::OceanType1, false, true, 4, 0x8000, 0x2000);
+        cartridge.write(0xde00, 0x82);
+        assert_eq!(Some(0x02), cartridge.read(0x8000));
+        cartridge.write(0xde00, 0x03);
+        assert_eq!(Some(0x03), cartridge.read(0x8000));
+    }
+
+    #[test]
+    fn final3_masks_bank_number() {
+        let mut cartridge = setup(HwType::Final3, false, false, 4, 0x8000, 0x4000);
+        cartridge.write(0xde00, 0x42);
+        assert_eq!(Some(0x02), cartridge.read(0x8000));
+        cartridge.write(0xde00, 0x01);
+        assert_eq!(Some(0x01), cartridge.read(0x8000));
+        // Banks past the last one are ignored
+        cartridge.write(0xde00, 0x10);
+        assert_eq!(Some(0x01), cartridge.read(0x8000));
+    }
+
+    #[test]
+    fn super_games() {
+        let mut cartridge = setup(HwType::SuperGames, false, false, 4, 0x8000, 0x4000);
+        cartridge.write(0xdf00, 0x02);
+        assert_eq!(Some(0x02), cartridge.read(0x8000));
+        assert_eq!(Some(0x82), cartridge.read(0xa000));
+        cartridge.write(0xdf00, 0x04);
+        assert_eq!((true, true), mode(&cartridge));
+        cartridge.write(0xdf00, 0x09);
+        assert_eq!(Some(0x01), cartridge.read(0x8000));
+        assert_eq!((false, false), mode(&cartridge));
+        // Register is write protected
+        cartridge.write(0xdf00, 0x03);
+        assert_eq!(Some(0x01), cartridge.read(0x8000));
+    }
+
+    #[test]
+    fn epyx_fastload() {
+        let mut cartridge = setup(HwType::EpyxFastLoad, false, true, 1, 0x8000, 0x2000);
+        assert_eq!((false, true), mode(&cartridge));
+        assert_eq!(Some(0xf0), cartridge.read(0xdfff));
+        for _ in 0..EPYX_CAPACITOR_CYCLES {
+            cartridge.clock();
+        }
+        assert_eq!((true, true), mode(&cartridge));
+        cartridge.read(0xde00);
+        assert_eq!((false, true), mode(&cartridge));
+        for _ in 0..EPYX_CAPACITOR_CYCLES - 1 {
+            cartridge.clock();
+        }
+        assert_eq!(Some(0x00), cartridge.read(0x8000));
+        cartridge.clock();
+        assert_eq!((false, true), mode(&cartridge));
+    }
+
+    #[test]
+    fn westermann() {
+        let mut cartridge = setup(HwType::Westermann, false, false, 1, 0x8000, 0x4000);
+        assert_eq!(Some(0x80), cartridge.read(0xa000));
+        cartridge.read(0xdf00);
+        assert_eq!((false, true), mode(&cartridge));
+    }
+
+    #[test]
+    fn warp_speed() {
+        let mut cartridge = Cartridge::new(0x0100, HwType::WarpSpeed, false, false);
+        let mut rom = chip(0, 0x8000, 0x4000);
+        rom.data[0x1e00] = 0x1e;
+        rom.data[0x1fff] = 0x1f;
+        cartridge.add(rom).unwrap();
+        cartridge.reset();
+        assert_eq!(Some(0x1e), cartridge.read(0xde00));
+        assert_eq!(Some(0x1f), cartridge.read(0xdfff));
+        cartridge.write(0xdf00, 0x00);
+        assert_eq!((true, true), mode(&cartridge));
+        cartridge.write(0xde00, 0x00);
+        assert_eq!((false, false), mode(&cartridge));
+    }
+
+    #[test]
+    fn dinamic() {
+        let mut cartridge = setup(HwType::Dinamic, false, true, 16, 0x8000, 0x2000);
+        cartridge.read(0xde0c);
+        assert_eq!(Some(0x0c), cartridge.read(0x8000));
+        cartridge.read(0xde00);
+        assert_eq!(Some(0x00), cartridge.read(0x8000));
+    }
+
+    #[test]
+    fn zaxxon() {
+        let mut cartridge = Cartridge::new(0x0100, HwType::Zaxxon, false, false);
+        let mut lo = chip(0, 0x8000, 0x1000);
+        lo.data[0] = 0x11;
+        cartridge.add(lo).unwrap();
+        cartridge.add(chip(0, 0xa000, 0x2000)).unwrap();
+        cartridge.add(chip(1, 0xa000, 0x2000)).unwrap();
+        cartridge.reset();
+        // 4K ROML is mirrored at $9000
+        assert_eq!(Some(0x11), cartridge.read(0x9000));
+        assert_eq!(Some(0x01), cartridge.read(0xa000));
+        assert_eq!(Some(0x11), cartridge.read(0x8000));
+        assert_eq!(Some(0x00), cartridge.read(0xa000));
+    }
+
+    #[test]
+    fn comal80() {
+        let mut cartridge = setup(HwType::Comal80, false, false, 4, 0x8000, 0x4000);
+        cartridge.write(0xde00, 0x83);
+        assert_eq!(Some(0x03), cartridge.read(0x8000));
+        assert_eq!(Some(0x83), cartridge.read(0xa000));
+        assert_eq!((false, false), mode(&cartridge));
+        cartridge.write(0xde00, 0x41);
+        assert_eq!((false, true), mode(&cartridge));
+        cartridge.write(0xde00, 0xe0);
+        assert_eq!((true, true), mode(&cartridge));
+    }
+
+    #[test]
+    fn ross() {
+        let mut cartridge = setup(HwType::Ross, false, false, 2, 0x8000, 0x4000);
+        assert_eq!(Some(0x80), cartridge.read(0xa000));
+        cartridge.read(0xde00);
+        assert_eq!(Some(0x01), cartridge.read(0x8000));
+        assert_eq!(Some(0x81), cartridge.read(0xa000));
+        cartridge.read(0xdf00);
+        assert_eq!((true, true), mode(&cartridge));
+    }
+    #[test]
+    fn freeze_holds_nmi_until_control_write() {
+        let mut cartridge = setup(HwType::ActionReplay, false, true, 4, 0x8000, 0x2000);
+        assert!(!cartridge.is_nmi_asserted());
+        assert!(cartridge.freeze());
+        assert!(cartridge.is_nmi_asserted());
+        cartridge.write(0xde00, 0x00);
+        assert!(!cartridge.is_nmi_asserted());
+        assert!(cartridge.freeze());
+        cartridge.reset();
+        assert!(!cartridge.is_nmi_asserted());
     }
 }
@@ -7,7 +7,7 @@ use alloc::prelude::*;
 use bit_field::BitField;
 
 use crate::factory::Tape;
-use crate::util::{IoPort, Pin, Shared};
+use crate::util::{IoPort, Pin, Shared, StateReader, StateWriter};
 
 // DEFERRED device: datassette test cases
 
@@ -149,6 +149,36 @@ impl Datassette {
         }
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.playing);
+        state.write_u32(self.current_pulse.low_cycles);
+        state.write_u32(self.current_pulse.remaining_cycles);
+        if let Some(ref tape) = self.tape {
+            state.write_bool(true);
+            state.write_u32(tape.get_pos() as u32);
+        } else {
+            state.write_bool(false);
+        }
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.playing = state.read_bool()?;
+        self.current_pulse.low_cycles = state.read_u32()?;
+        self.current_pulse.remaining_cycles = state.read_u32()?;
+        if state.read_bool()? {
+            let pos = state.read_u32()? as usize;
+            match self.tape {
+                Some(ref mut tape) => {
+                    if !tape.seek(pos) {
+                        return Err(format!("Invalid tape position {}", pos));
+                    }
+                }
+                None => return Err("Snapshot requires an attached tape".to_string()),
+            }
+        }
+        Ok(())
+    }
+
     pub fn stop(&mut self) {
         info!(target: "device", "Stopping datassette");
         self.cpu_io_port
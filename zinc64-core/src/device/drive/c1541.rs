@@ -0,0 +1,284 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+use bit_field::BitField;
+
+use crate::cpu::Cpu6510;
+use crate::factory::{Addressable, Chip, Cpu, Disk, Register};
+use crate::io::Via;
+use crate::util::{new_shared, IoPort, IrqLine, Ram, Rom, Shared, StateReader, StateWriter};
+
+use super::super::IecBus;
+use super::disk_unit::DiskUnit;
+use super::drive_memory::{DriveMemory, ROM_BASE, ROM_SIZE};
+
+// Design:
+//   Drive emulates Commodore 1541 running its own DOS ROM on a 6502 with two 6522 VIAs.
+//   VIA 1 is connected to the IEC serial bus and VIA 2 controls the disk mechanics. The drive
+//   runs at 1 MHz and is clocked from the computer clock, so a fractional cycle counter keeps
+//   both in lockstep regardless of the computer's clock frequency.
+
+/*
+  +-----+--------+---------------------------------------------+
+  | BIT | DIR    | VIA 1 PORT B                                |
+  +-----+--------+---------------------------------------------+
+  |  0  | INPUT  | DATA IN (1 = line low)                      |
+  |  1  | OUTPUT | DATA OUT (1 = pull line low)                |
+  |  2  | INPUT  | CLK IN (1 = line low)                       |
+  |  3  | OUTPUT | CLK OUT (1 = pull line low)                 |
+  |  4  | OUTPUT | ATN acknowledge                             |
+  | 5-6 | INPUT  | Device address jumpers (device = 8 + value) |
+  |  7  | INPUT  | ATN IN (1 = line low), also wired to CA1    |
+  +-----+--------+---------------------------------------------+
+*/
+
+const DRIVE_FREQ: u32 = 1_000_000;
+
+#[derive(Copy, Clone)]
+enum IrqSource {
+    Via1 = 0,
+    Via2 = 1,
+}
+
+impl IrqSource {
+    pub fn value(self) -> usize {
+        self as usize
+    }
+}
+
+pub struct Drive {
+    // Configuration
+    cpu_freq: u32,
+    device: u8,
+    // Chipset
+    cpu: Cpu6510,
+    via_1: Shared<Via>,
+    via_2: Shared<Via>,
+    // Memory
+    ram: Shared<Ram>,
+    // Functional Units
+    disk_unit: DiskUnit,
+    // I/O
+    iec_bus: Shared<IecBus>,
+    via_1_port_b: Shared<IoPort>,
+    via_2_port_a: Shared<IoPort>,
+    via_2_port_b: Shared<IoPort>,
+    // Runtime State
+    cycle_frac: u32,
+}
+
+impl Drive {
+    pub fn new(
+        rom: &[u8],
+        device: u8,
+        cpu_freq: u32,
+        iec_bus: Shared<IecBus>,
+    ) -> Result<Self, String> {
+        if rom.len() != ROM_SIZE {
+            return Err(format!(
+                "Invalid drive rom size {}, expected {}",
+                rom.len(),
+                ROM_SIZE
+            ));
+        }
+        if !(8..=11).contains(&device) {
+            return Err(format!("Invalid drive device number {}", device));
+        }
+        let irq_line = new_shared(IrqLine::new("drive_irq"));
+        let nmi_line = new_shared(IrqLine::new("drive_nmi"));
+        let via_1_port_a = new_shared(IoPort::new(0x00, 0xff));
+        let via_1_port_b = new_shared(IoPort::new(0x00, 0xff));
+        let via_2_port_a = new_shared(IoPort::new(0x00, 0xff));
+        let via_2_port_b = new_shared(IoPort::new(0x00, 0xff));
+        let via_1 = new_shared(Via::new(
+            via_1_port_a,
+            via_1_port_b.clone(),
+            irq_line.clone(),
+            IrqSource::Via1.value(),
+        ));
+        let via_2 = new_shared(Via::new(
+            via_2_port_a.clone(),
+            via_2_port_b.clone(),
+            irq_line.clone(),
+            IrqSource::Via2.value(),
+        ));
+        let ram = new_shared(Ram::new(0x0800));
+        let rom = new_shared(Rom::new_with_data(rom, ROM_BASE));
+        let mem: Shared<dyn Addressable> = new_shared(DriveMemory::new(
+            ram.clone(),
+            rom,
+            via_1.clone(),
+            via_2.clone(),
+        ));
+        let cpu = Cpu6510::new_6502(mem, irq_line, nmi_line);
+        Ok(Self {
+            cpu_freq,
+            device,
+            cpu,
+            via_1,
+            via_2,
+            ram,
+            disk_unit: DiskUnit::new(),
+            iec_bus,
+            via_1_port_b,
+            via_2_port_a,
+            via_2_port_b,
+            cycle_frac: 0,
+        })
+    }
+
+    pub fn attach_disk(&mut self, disk: Box<dyn Disk>) {
+        info!(target: "drive", "Attaching disk to device {}", self.device);
+        self.disk_unit.attach(disk);
+    }
+
+    pub fn detach_disk(&mut self) -> Option<Box<dyn Disk>> {
+        info!(target: "drive", "Detaching disk from device {}", self.device);
+        self.disk_unit.detach()
+    }
+
+    pub fn get_cpu(&self) -> &dyn Cpu {
+        &self.cpu
+    }
+
+    pub fn get_disk(&self) -> Option<&dyn Disk> {
+        self.disk_unit.get_disk()
+    }
+
+    pub fn get_device(&self) -> u8 {
+        self.device
+    }
+
+    /// Get current head position as track number (1-based), half tracks end with .5
+    pub fn get_track(&self) -> f32 {
+        self.disk_unit.get_half_track() as f32 / 2.0 + 1.0
+    }
+
+    pub fn has_disk(&self) -> bool {
+        self.disk_unit.has_disk()
+    }
+
+    pub fn is_led_on(&self) -> bool {
+        self.disk_unit.is_led_on()
+    }
+
+    pub fn is_motor_on(&self) -> bool {
+        self.disk_unit.is_motor_on()
+    }
+
+    /// Advance drive by one computer clock cycle.
+    pub fn clock(&mut self) {
+        self.cycle_frac += DRIVE_FREQ;
+        while self.cycle_frac >= self.cpu_freq {
+            self.cycle_frac -= self.cpu_freq;
+            self.clock_drive();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.via_1.borrow_mut().reset();
+        self.via_2.borrow_mut().reset();
+        self.disk_unit.reset();
+        self.iec_bus.borrow_mut().reset();
+        self.cycle_frac = 0;
+    }
+
+    fn clock_drive(&mut self) {
+        self.update_bus_input();
+        self.cpu.clock();
+        self.via_1.borrow_mut().clock();
+        self.via_2.borrow_mut().clock();
+        self.update_bus_output();
+        self.clock_disk();
+    }
+
+    fn clock_disk(&mut self) {
+        self.disk_unit
+            .set_control(self.via_2_port_b.borrow().get_value());
+        let (write_mode, byte_ready_enabled) = {
+            let via_2 = self.via_2.borrow();
+            (!via_2.get_cb2(), via_2.get_ca2())
+        };
+        let write_value = self.via_2_port_a.borrow().get_value();
+        if self.disk_unit.clock(write_mode, write_value) {
+            if !write_mode {
+                self.via_2_port_a
+                    .borrow_mut()
+                    .set_input(self.disk_unit.get_data());
+            }
+            // Byte ready is wired to CA1 and the 6502 SO pin
+            let mut via_2 = self.via_2.borrow_mut();
+            via_2.set_ca1(false);
+            via_2.set_ca1(true);
+            if byte_ready_enabled {
+                let p = self.cpu.get_register(Register::P);
+                self.cpu.set_register(Register::P, p | 0x40);
+            }
+        }
+        self.via_2_port_b
+            .borrow_mut()
+            .set_input(self.disk_unit.get_status());
+    }
+
+    fn update_bus_input(&mut self) {
+        let (atn_low, clk_low, data_low) = {
+            let iec_bus = self.iec_bus.borrow();
+            (
+                iec_bus.is_atn_low(),
+                iec_bus.is_clk_low(),
+                iec_bus.is_data_low(),
+            )
+        };
+        let mut input = 0x1a | ((self.device - 8) << 5);
+        input.set_bit(0, data_low);
+        input.set_bit(2, clk_low);
+        input.set_bit(7, atn_low);
+        self.via_1_port_b.borrow_mut().set_input(input);
+        self.via_1.borrow_mut().set_ca1(atn_low);
+    }
+
+    fn update_bus_output(&mut self) {
+        let value = self.via_1_port_b.borrow().get_value();
+        let mut iec_bus = self.iec_bus.borrow_mut();
+        // ATN acknowledge logic pulls DATA low until ATNA matches ATN
+        let atn_ack = iec_bus.is_atn_low() != value.get_bit(4);
+        iec_bus.set_device_output(value.get_bit(3), value.get_bit(1) || atn_ack);
+    }
+
+    // -- State
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_tag(b"DRV0");
+        state.write_u8(self.device);
+        state.write_u32(self.cycle_frac);
+        self.cpu.save_state(state);
+        self.via_1.borrow().save_state(state);
+        self.via_2.borrow().save_state(state);
+        self.ram.borrow().save_state(state);
+        self.disk_unit.save_state(state);
+        self.iec_bus.borrow().save_state(state);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.read_tag(b"DRV0")?;
+        let device = state.read_u8()?;
+        if device != self.device {
+            return Err(format!(
+                "Invalid snapshot, drive device {} does not match {}",
+                device, self.device
+            ));
+        }
+        self.cycle_frac = state.read_u32()?;
+        self.cpu.load_state(state)?;
+        self.via_1.borrow_mut().load_state(state)?;
+        self.via_2.borrow_mut().load_state(state)?;
+        self.ram.borrow_mut().load_state(state)?;
+        self.disk_unit.load_state(state)?;
+        self.iec_bus.borrow_mut().load_state(state)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+use bit_field::BitField;
+
+use crate::factory::Disk;
+use crate::util::{StateReader, StateWriter};
+
+use super::gcr;
+
+// Design:
+//   DiskUnit models the drive mechanics: spindle motor, stepper motor moving the head
+//   between half tracks and the read/write electronics. The head reads one bit at a time
+//   at the rate selected by the speed zone bits. Ten consecutive one bits are reported as
+//   sync and reset the byte counter; every eighth bit after that completes a byte.
+
+/*
+  +-----+--------+-------------------------------------------+
+  | BIT | DIR    | VIA 2 PORT B                              |
+  +-----+--------+-------------------------------------------+
+  | 0-1 | OUTPUT | Stepper motor phase                       |
+  |  2  | OUTPUT | Spindle motor (1 = on)                    |
+  |  3  | OUTPUT | Drive LED (1 = on)                        |
+  |  4  | INPUT  | Write protect sense (0 = protected)       |
+  | 5-6 | OUTPUT | Speed zone                                |
+  |  7  | INPUT  | Sync detected (0 = sync)                  |
+  +-----+--------+-------------------------------------------+
+*/
+
+const DEFAULT_HALF_TRACK: usize = 34;
+
+pub struct DiskUnit {
+    disk: Option<Box<dyn Disk>>,
+    // Mechanics
+    half_track: usize,
+    led_on: bool,
+    motor_on: bool,
+    stepper_phase: u8,
+    speed_zone: u8,
+    // Read/Write Electronics
+    bit_clock: u32,
+    bit_counter: u8,
+    bit_pos: usize,
+    read_shift: u16,
+    sync: bool,
+    write_shift: u8,
+}
+
+impl DiskUnit {
+    pub fn new() -> Self {
+        Self {
+            disk: None,
+            half_track: DEFAULT_HALF_TRACK,
+            led_on: false,
+            motor_on: false,
+            stepper_phase: 0,
+            speed_zone: 0,
+            bit_clock: 0,
+            bit_counter: 0,
+            bit_pos: 0,
+            read_shift: 0,
+            sync: false,
+            write_shift: 0,
+        }
+    }
+
+    pub fn attach(&mut self, disk: Box<dyn Disk>) {
+        self.disk = Some(disk);
+    }
+
+    pub fn detach(&mut self) -> Option<Box<dyn Disk>> {
+        self.disk.take()
+    }
+
+    pub fn get_disk(&self) -> Option<&dyn Disk> {
+        self.disk.as_deref()
+    }
+
+    pub fn get_half_track(&self) -> usize {
+        self.half_track
+    }
+
+    /// Get VIA 2 port B input bits.
+    pub fn get_status(&self) -> u8 {
+        let write_protected = match self.disk {
+            Some(ref disk) => disk.is_write_protected(),
+            None => false,
+        };
+        let mut result = 0x6f;
+        result.set_bit(4, !write_protected);
+        result.set_bit(7, !self.sync);
+        result
+    }
+
+    pub fn has_disk(&self) -> bool {
+        self.disk.is_some()
+    }
+
+    pub fn is_led_on(&self) -> bool {
+        self.led_on
+    }
+
+    pub fn is_motor_on(&self) -> bool {
+        self.motor_on
+    }
+
+    /// Update motors and LED from VIA 2 port B output.
+    pub fn set_control(&mut self, value: u8) {
+        self.motor_on = value.get_bit(2);
+        self.led_on = value.get_bit(3);
+        self.speed_zone = (value >> 5) & 0x03;
+        let phase = value & 0x03;
+        if phase == (self.stepper_phase + 1) & 0x03 {
+            if self.half_track < gcr::MAX_HALF_TRACKS - 1 {
+                self.half_track += 1;
+            }
+        } else if phase == self.stepper_phase.wrapping_sub(1) & 0x03 && self.half_track > 0 {
+            self.half_track -= 1;
+        }
+        self.stepper_phase = phase;
+    }
+
+    /// Advance disk rotation by one drive cycle. Returns true if a byte was read or written.
+    pub fn clock(&mut self, write_mode: bool, write_value: u8) -> bool {
+        if !self.motor_on {
+            return false;
+        }
+        // Bit cell takes 1/8 of 26, 28, 30 or 32 cycles for speed zone 3 to 0
+        let cycles_per_byte = 32 - 2 * u32::from(self.speed_zone);
+        let mut byte_ready = false;
+        self.bit_clock += 8;
+        while self.bit_clock >= cycles_per_byte {
+            self.bit_clock -= cycles_per_byte;
+            byte_ready |= if write_mode {
+                self.write_bit(write_value)
+            } else {
+                self.read_bit()
+            };
+        }
+        byte_ready
+    }
+
+    /// Get last byte assembled by the read electronics.
+    pub fn get_data(&self) -> u8 {
+        self.read_shift as u8
+    }
+
+    pub fn reset(&mut self) {
+        self.led_on = false;
+        self.motor_on = false;
+        self.bit_clock = 0;
+        self.bit_counter = 0;
+        self.read_shift = 0;
+        self.sync = false;
+        self.write_shift = 0;
+    }
+
+    fn track_bits(&self) -> usize {
+        let track_size = match self.disk {
+            Some(ref disk) => disk.get_track(self.half_track).map(|data| data.len()),
+            None => None,
+        };
+        track_size.unwrap_or_else(|| gcr::track_size(self.speed_zone)) * 8
+    }
+
+    fn advance_bit(&mut self) {
+        self.bit_pos += 1;
+        if self.bit_pos >= self.track_bits() {
+            self.bit_pos = 0;
+        }
+    }
+
+    fn read_bit(&mut self) -> bool {
+        let bit = match self.disk {
+            Some(ref disk) => match disk.get_track(self.half_track) {
+                Some(data) if self.bit_pos / 8 < data.len() => {
+                    data[self.bit_pos / 8].get_bit(7 - self.bit_pos % 8)
+                }
+                _ => false,
+            },
+            None => false,
+        };
+        self.advance_bit();
+        self.read_shift = ((self.read_shift << 1) | bit as u16) & 0x03ff;
+        if self.read_shift == 0x03ff {
+            self.sync = true;
+            self.bit_counter = 0;
+            false
+        } else {
+            self.sync = false;
+            self.bit_counter += 1;
+            if self.bit_counter == 8 {
+                self.bit_counter = 0;
+                true
+            } else {
+                false
+            }
+        }
+    }
+
+    fn write_bit(&mut self, write_value: u8) -> bool {
+        self.sync = false;
+        let bit = self.write_shift.get_bit(7);
+        self.write_shift <<= 1;
+        let half_track = self.half_track;
+        let offset = self.bit_pos / 8;
+        if let Some(ref mut disk) = self.disk {
+            if !disk.is_write_protected() {
+                let value = match disk.get_track(half_track) {
+                    Some(data) if offset < data.len() => {
+                        let mut value = data[offset];
+                        value.set_bit(7 - self.bit_pos % 8, bit);
+                        Some(value)
+                    }
+                    _ => None,
+                };
+                if let Some(value) = value {
+                    disk.write(half_track, offset, value);
+                }
+            }
+        }
+        self.advance_bit();
+        self.bit_counter += 1;
+        if self.bit_counter >= 8 {
+            self.bit_counter = 0;
+            self.write_shift = write_value;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.half_track as u8);
+        state.write_bool(self.led_on);
+        state.write_bool(self.motor_on);
+        state.write_u8(self.stepper_phase);
+        state.write_u8(self.speed_zone);
+        state.write_u32(self.bit_clock);
+        state.write_u8(self.bit_counter);
+        state.write_u32(self.bit_pos as u32);
+        state.write_u16(self.read_shift);
+        state.write_bool(self.sync);
+        state.write_u8(self.write_shift);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        let half_track = state.read_u8()? as usize;
+        if half_track >= gcr::MAX_HALF_TRACKS {
+            return Err(format!("Invalid drive half track {}", half_track));
+        }
+        self.half_track = half_track;
+        self.led_on = state.read_bool()?;
+        self.motor_on = state.read_bool()?;
+        self.stepper_phase = state.read_u8()? & 0x03;
+        self.speed_zone = state.read_u8()? & 0x03;
+        self.bit_clock = state.read_u32()?;
+        self.bit_counter = state.read_u8()?;
+        self.bit_pos = state.read_u32()? as usize;
+        if self.bit_pos >= self.track_bits() {
+            self.bit_pos = 0;
+        }
+        self.read_shift = state.read_u16()?;
+        self.sync = state.read_bool()?;
+        self.write_shift = state.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Default for DiskUnit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::gcr::GcrDisk;
+    use super::*;
+
+    fn setup_disk_unit(data: Vec<u8>) -> DiskUnit {
+        let mut disk = GcrDisk::new(gcr::MAX_HALF_TRACKS);
+        disk.set_track(DEFAULT_HALF_TRACK, data, 3);
+        let mut disk_unit = DiskUnit::new();
+        disk_unit.attach(Box::new(disk));
+        disk_unit.set_control(0x64);
+        disk_unit
+    }
+
+    fn read_byte(disk_unit: &mut DiskUnit) -> u8 {
+        while !disk_unit.clock(false, 0) {}
+        disk_unit.get_data()
+    }
+
+    #[test]
+    fn read_after_sync() {
+        let mut data = vec![0x55; 100];
+        data[10] = 0xff;
+        data[11] = 0xff;
+        data[12] = 0x52;
+        data[13] = 0x94;
+        let mut disk_unit = setup_disk_unit(data);
+        let mut value = read_byte(&mut disk_unit);
+        while value != 0x52 {
+            value = read_byte(&mut disk_unit);
+        }
+        assert_eq!(0x94, read_byte(&mut disk_unit));
+        assert_eq!(0x6f | 0x10 | 0x80, disk_unit.get_status());
+    }
+
+    #[test]
+    fn step_head() {
+        let mut disk_unit = DiskUnit::new();
+        disk_unit.set_control(0x00);
+        disk_unit.set_control(0x01);
+        assert_eq!(DEFAULT_HALF_TRACK + 1, disk_unit.get_half_track());
+        disk_unit.set_control(0x00);
+        disk_unit.set_control(0x03);
+        assert_eq!(DEFAULT_HALF_TRACK - 1, disk_unit.get_half_track());
+    }
+
+    #[test]
+    fn byte_rate_by_speed_zone() {
+        let mut disk_unit = setup_disk_unit(vec![0x55; 100]);
+        read_byte(&mut disk_unit);
+        let mut cycles = 1;
+        while !disk_unit.clock(false, 0) {
+            cycles += 1;
+        }
+        assert_eq!(26, cycles);
+    }
+
+    #[test]
+    fn write_byte() {
+        let mut disk_unit = setup_disk_unit(vec![0x55; 100]);
+        // Load write shift register then write it out
+        while !disk_unit.clock(true, 0xa7) {}
+        while !disk_unit.clock(true, 0x00) {}
+        disk_unit.set_control(0x60);
+        let disk = disk_unit.detach().unwrap();
+        let data = disk.get_track(DEFAULT_HALF_TRACK).unwrap();
+        assert_eq!(0xa7, data[1]);
+    }
+}
@@ -0,0 +1,68 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+
+use crate::factory::{Addressable, Chip};
+use crate::util::{Ram, Rom, Shared};
+
+// Spec: 1541 memory map
+//   $0000-$07ff 2KB RAM
+//   $1800-$180f VIA 1 (serial bus), mirrored up to $1bff
+//   $1c00-$1c0f VIA 2 (disk controller), mirrored up to $1fff
+//   $c000-$ffff 16KB DOS ROM, mirrored at $8000-$bfff
+// Address lines A13/A14 are not decoded so the lower 8KB repeat up to $7fff.
+
+pub const ROM_BASE: u16 = 0xc000;
+pub const ROM_SIZE: usize = 0x4000;
+
+pub struct DriveMemory {
+    ram: Shared<Ram>,
+    rom: Shared<Rom>,
+    via_1: Shared<dyn Chip>,
+    via_2: Shared<dyn Chip>,
+}
+
+impl DriveMemory {
+    pub fn new(
+        ram: Shared<Ram>,
+        rom: Shared<Rom>,
+        via_1: Shared<dyn Chip>,
+        via_2: Shared<dyn Chip>,
+    ) -> Self {
+        Self {
+            ram,
+            rom,
+            via_1,
+            via_2,
+        }
+    }
+}
+
+impl Addressable for DriveMemory {
+    fn read(&self, address: u16) -> u8 {
+        if address >= 0x8000 {
+            return self.rom.borrow().read(ROM_BASE | (address & 0x3fff));
+        }
+        match address & 0x1c00 {
+            0x1800 => self.via_1.borrow_mut().read((address & 0x0f) as u8),
+            0x1c00 => self.via_2.borrow_mut().read((address & 0x0f) as u8),
+            0x0000 | 0x0400 => self.ram.borrow().read(address & 0x07ff),
+            _ => (address >> 8) as u8,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address >= 0x8000 {
+            return;
+        }
+        match address & 0x1c00 {
+            0x1800 => self.via_1.borrow_mut().write((address & 0x0f) as u8, value),
+            0x1c00 => self.via_2.borrow_mut().write((address & 0x0f) as u8, value),
+            0x0000 | 0x0400 => self.ram.borrow_mut().write(address & 0x07ff, value),
+            _ => {}
+        }
+    }
+}
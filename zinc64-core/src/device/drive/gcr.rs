@@ -0,0 +1,285 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+#[cfg(not(feature = "std"))]
+use alloc::vec;
+
+use crate::factory::Disk;
+
+// Spec: http://www.unusedino.de/ec64/technical/formats/g64.html
+// Spec: Inside Commodore DOS, chapter 7 - GCR encoding and track layout
+
+pub const GCR_SYNC: u8 = 0xff;
+pub const GCR_GAP: u8 = 0x55;
+pub const MAX_HALF_TRACKS: usize = 84;
+
+const SYNC_LEN: usize = 5;
+const HEADER_GAP_LEN: usize = 9;
+const SECTOR_GAP_LEN: usize = 8;
+
+static GCR_ENCODE: [u8; 16] = [
+    0x0a, 0x0b, 0x12, 0x13, 0x0e, 0x0f, 0x16, 0x17, 0x09, 0x19, 0x1a, 0x1b, 0x0d, 0x1d, 0x1e, 0x15,
+];
+
+static GCR_DECODE: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x08, 0x00, 0x01, 0xff, 0x0c, 0x04, 0x05,
+    0xff, 0xff, 0x02, 0x03, 0xff, 0x0f, 0x06, 0x07, 0xff, 0x09, 0x0a, 0x0b, 0xff, 0x0d, 0x0e, 0xff,
+];
+
+/// Encode 4 data bytes into 5 GCR bytes.
+pub fn encode(data: &[u8], out: &mut [u8]) {
+    let mut bits = 0u64;
+    for byte in data.iter().take(4) {
+        bits = (bits << 10)
+            | (u64::from(GCR_ENCODE[(byte >> 4) as usize]) << 5)
+            | u64::from(GCR_ENCODE[(byte & 0x0f) as usize]);
+    }
+    for (i, value) in out.iter_mut().take(5).enumerate() {
+        *value = (bits >> (32 - i * 8)) as u8;
+    }
+}
+
+/// Decode 5 GCR bytes into 4 data bytes. Returns false if input contains invalid GCR codes.
+pub fn decode(data: &[u8], out: &mut [u8]) -> bool {
+    let mut bits = 0u64;
+    for byte in data.iter().take(5) {
+        bits = (bits << 8) | u64::from(*byte);
+    }
+    let mut valid = true;
+    for (i, value) in out.iter_mut().take(4).enumerate() {
+        let hi = GCR_DECODE[((bits >> (35 - i * 10)) & 0x1f) as usize];
+        let lo = GCR_DECODE[((bits >> (30 - i * 10)) & 0x1f) as usize];
+        valid &= hi != 0xff && lo != 0xff;
+        *value = (hi << 4) | (lo & 0x0f);
+    }
+    valid
+}
+
+/// Number of sectors on the specified track (1-based) as formatted by 1541 DOS.
+pub fn sectors_per_track(track: u8) -> u8 {
+    match track {
+        1..=17 => 21,
+        18..=24 => 19,
+        25..=30 => 18,
+        _ => 17,
+    }
+}
+
+/// Speed zone used by 1541 DOS for the specified track (1-based).
+pub fn speed_zone(track: u8) -> u8 {
+    match track {
+        1..=17 => 3,
+        18..=24 => 2,
+        25..=30 => 1,
+        _ => 0,
+    }
+}
+
+/// Number of GCR bytes that fit on a track recorded with the specified speed zone.
+pub fn track_size(zone: u8) -> usize {
+    match zone {
+        3 => 7692,
+        2 => 7142,
+        1 => 6666,
+        _ => 6250,
+    }
+}
+
+fn encode_block(data: &[u8], out: &mut Vec<u8>) {
+    let mut buffer = [0u8; 5];
+    for chunk in data.chunks(4) {
+        encode(chunk, &mut buffer);
+        out.extend_from_slice(&buffer);
+    }
+}
+
+/// Encode sector header and data blocks including syncs and gaps.
+pub fn encode_sector(track: u8, sector: u8, id: [u8; 2], data: &[u8], out: &mut Vec<u8>) {
+    encode_sector_with_error(track, sector, id, data, 0x01, out);
+}
+
+/// Encode sector reproducing the read error described by the D64 error code.
+/// Code 1 means no error; 2 (header block not found), 3 (no sync), 4 (data block not found),
+/// 5 (data checksum), 9 (header checksum) and 0x0b (disk id mismatch) are recreated.
+pub fn encode_sector_with_error(
+    track: u8,
+    sector: u8,
+    id: [u8; 2],
+    data: &[u8],
+    error: u8,
+    out: &mut Vec<u8>,
+) {
+    let id = if error == 0x0b {
+        [id[0] ^ 0xff, id[1] ^ 0xff]
+    } else {
+        id
+    };
+    let mut header_checksum = sector ^ track ^ id[1] ^ id[0];
+    if error == 0x09 {
+        header_checksum ^= 0xff;
+    }
+    let header = [
+        if error == 0x02 { 0x00 } else { 0x08 },
+        header_checksum,
+        sector,
+        track,
+        id[1],
+        id[0],
+        0x0f,
+        0x0f,
+    ];
+    let sync = if error == 0x03 { GCR_GAP } else { GCR_SYNC };
+    out.extend_from_slice(&[sync; SYNC_LEN]);
+    encode_block(&header, out);
+    out.extend_from_slice(&[GCR_GAP; HEADER_GAP_LEN]);
+    let mut block = [0u8; 260];
+    block[0] = if error == 0x04 { 0x00 } else { 0x07 };
+    block[1..257].copy_from_slice(&data[..256]);
+    block[257] = data[..256].iter().fold(0, |acc, value| acc ^ value);
+    if error == 0x05 {
+        block[257] ^= 0xff;
+    }
+    out.extend_from_slice(&[sync; SYNC_LEN]);
+    encode_block(&block, out);
+    out.extend_from_slice(&[GCR_GAP; SECTOR_GAP_LEN]);
+}
+
+/// Encode a track from its sector data. The remaining space is filled with gap bytes.
+pub fn encode_track(track: u8, id: [u8; 2], sectors: &[&[u8]]) -> Vec<u8> {
+    encode_track_with_errors(track, id, sectors, &[])
+}
+
+/// Encode a track from its sector data and per sector D64 error codes.
+/// Sectors without an error code are encoded without errors.
+pub fn encode_track_with_errors(
+    track: u8,
+    id: [u8; 2],
+    sectors: &[&[u8]],
+    errors: &[u8],
+) -> Vec<u8> {
+    let size = track_size(speed_zone(track));
+    let mut out = Vec::with_capacity(size);
+    for (sector, data) in sectors.iter().enumerate() {
+        let error = errors.get(sector).cloned().unwrap_or(0x01);
+        encode_sector_with_error(track, sector as u8, id, data, error, &mut out);
+    }
+    if out.len() < size {
+        out.resize(size, GCR_GAP);
+    }
+    out
+}
+
+/// In-memory disk made of GCR tracks.
+pub struct GcrDisk {
+    speed_zones: Vec<u8>,
+    tracks: Vec<Option<Vec<u8>>>,
+    modified: bool,
+    write_protected: bool,
+}
+
+impl GcrDisk {
+    pub fn new(half_tracks: usize) -> Self {
+        Self {
+            speed_zones: vec![0; half_tracks],
+            tracks: vec![None; half_tracks],
+            modified: false,
+            write_protected: false,
+        }
+    }
+
+    pub fn set_track(&mut self, half_track: usize, data: Vec<u8>, speed_zone: u8) {
+        self.tracks[half_track] = Some(data);
+        self.speed_zones[half_track] = speed_zone;
+    }
+
+    pub fn set_write_protected(&mut self, value: bool) {
+        self.write_protected = value;
+    }
+}
+
+impl Disk for GcrDisk {
+    fn get_half_tracks(&self) -> usize {
+        self.tracks.len()
+    }
+
+    fn get_track(&self, half_track: usize) -> Option<&[u8]> {
+        match self.tracks.get(half_track) {
+            Some(Some(data)) => Some(data.as_slice()),
+            _ => None,
+        }
+    }
+
+    fn get_speed_zone(&self, half_track: usize) -> u8 {
+        self.speed_zones.get(half_track).cloned().unwrap_or(0)
+    }
+
+    fn is_write_protected(&self) -> bool {
+        self.write_protected
+    }
+
+    fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    fn write(&mut self, half_track: usize, offset: usize, value: u8) {
+        if let Some(Some(data)) = self.tracks.get_mut(half_track) {
+            if offset < data.len() {
+                data[offset] = value;
+                self.modified = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode() {
+        let data = [0x08, 0x12, 0xab, 0xff];
+        let mut gcr = [0u8; 5];
+        encode(&data, &mut gcr);
+        let mut result = [0u8; 4];
+        assert!(decode(&gcr, &mut result));
+        assert_eq!(data, result);
+    }
+
+    #[test]
+    fn encode_zero_bytes() {
+        let mut gcr = [0u8; 5];
+        encode(&[0, 0, 0, 0], &mut gcr);
+        assert_eq!([0x52, 0x94, 0xa5, 0x29, 0x4a], gcr);
+    }
+
+    #[test]
+    fn decode_invalid_code() {
+        let mut result = [0u8; 4];
+        assert!(!decode(&[0x00; 5], &mut result));
+    }
+
+    #[test]
+    fn encode_track_layout() {
+        let sector = [0u8; 256];
+        let sectors = vec![&sector[..]; 21];
+        let data = encode_track(1, [0x41, 0x42], &sectors);
+        assert_eq!(track_size(3), data.len());
+        assert_eq!([GCR_SYNC; 5], data[0..5]);
+        let mut header = [0u8; 4];
+        assert!(decode(&data[5..10], &mut header));
+        assert_eq!([0x08, 0x01 ^ 0x41 ^ 0x42, 0x00, 0x01], header);
+    }
+
+    #[test]
+    fn encode_header_not_found_error() {
+        let sector = [0u8; 256];
+        let mut data = Vec::new();
+        encode_sector_with_error(1, 0, [0x41, 0x42], &sector, 0x02, &mut data);
+        let mut header = [0u8; 4];
+        assert!(decode(&data[5..10], &mut header));
+        assert_eq!(0x00, header[0]);
+    }
+}
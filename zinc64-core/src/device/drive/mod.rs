@@ -0,0 +1,11 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+mod c1541;
+mod disk_unit;
+mod drive_memory;
+pub mod gcr;
+
+pub use self::c1541::Drive;
+pub use self::gcr::GcrDisk;
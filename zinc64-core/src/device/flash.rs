@@ -0,0 +1,255 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+#[cfg(not(feature = "std"))]
+use alloc::vec;
+use log::LogLevel;
+
+use crate::util::{StateReader, StateWriter};
+
+// SPEC: AMD Am29F040B 4 Megabit CMOS 5.0 Volt-only, Uniform Sector Flash Memory
+
+// Design:
+//   Commands are decoded from address lines A0-A10 only, as EasyFlash connects the upper
+//   address lines to the bank register. Program and erase operations complete immediately
+//   instead of reporting progress through the data polling and toggle bits.
+
+pub const SIZE: usize = 0x8_0000;
+
+const SECTOR_SIZE: usize = 0x1_0000;
+const MANUFACTURER_ID: u8 = 0x01;
+const DEVICE_ID: u8 = 0xa4;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+    Read,
+    Unlock1,
+    Unlock2,
+    Program,
+    EraseUnlock0,
+    EraseUnlock1,
+    EraseUnlock2,
+    Autoselect,
+}
+
+impl State {
+    pub fn from(value: u8) -> Result<State, String> {
+        match value {
+            0 => Ok(State::Read),
+            1 => Ok(State::Unlock1),
+            2 => Ok(State::Unlock2),
+            3 => Ok(State::Program),
+            4 => Ok(State::EraseUnlock0),
+            5 => Ok(State::EraseUnlock1),
+            6 => Ok(State::EraseUnlock2),
+            7 => Ok(State::Autoselect),
+            _ => Err(format!("Invalid flash state {}", value)),
+        }
+    }
+
+    pub fn value(self) -> u8 {
+        self as u8
+    }
+}
+
+pub struct FlashRom {
+    data: Vec<u8>,
+    // Runtime state
+    state: State,
+    modified: bool,
+}
+
+impl FlashRom {
+    pub fn new() -> Self {
+        FlashRom {
+            data: vec![0xff; SIZE],
+            state: State::Read,
+            modified: false,
+        }
+    }
+
+    pub fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Check if contents were changed by program or erase commands since loading.
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    pub fn load(&mut self, offset: usize, data: &[u8]) -> Result<(), String> {
+        if offset + data.len() > self.data.len() {
+            return Err(format!(
+                "Flash image at 0x{:05x} exceeds flash size {}",
+                offset,
+                self.data.len()
+            ));
+        }
+        self.data[offset..offset + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        self.state = State::Read;
+    }
+
+    pub fn read(&self, offset: usize) -> u8 {
+        match self.state {
+            State::Autoselect => match offset & 0xff {
+                0x00 => MANUFACTURER_ID,
+                0x01 => DEVICE_ID,
+                // Sectors are never write protected
+                _ => 0x00,
+            },
+            _ => self.data[offset & (SIZE - 1)],
+        }
+    }
+
+    pub fn write(&mut self, offset: usize, value: u8) {
+        let offset = offset & (SIZE - 1);
+        let command_addr = offset & 0x7ff;
+        self.state = match (self.state, command_addr, value) {
+            (State::Program, _, _) => {
+                // Programming can only clear bits
+                self.data[offset] &= value;
+                self.modified = true;
+                State::Read
+            }
+            (_, _, 0xf0) => State::Read,
+            (State::Read, 0x555, 0xaa) | (State::Autoselect, 0x555, 0xaa) => State::Unlock1,
+            (State::Unlock1, 0x2aa, 0x55) => State::Unlock2,
+            (State::Unlock2, 0x555, 0xa0) => State::Program,
+            (State::Unlock2, 0x555, 0x80) => State::EraseUnlock0,
+            (State::Unlock2, 0x555, 0x90) => State::Autoselect,
+            (State::EraseUnlock0, 0x555, 0xaa) => State::EraseUnlock1,
+            (State::EraseUnlock1, 0x2aa, 0x55) => State::EraseUnlock2,
+            (State::EraseUnlock2, 0x555, 0x10) => {
+                self.erase(0, SIZE);
+                State::Read
+            }
+            (State::EraseUnlock2, _, 0x30) => {
+                self.erase(offset & !(SECTOR_SIZE - 1), SECTOR_SIZE);
+                State::Read
+            }
+            (State::Autoselect, _, _) => State::Autoselect,
+            (state, _, _) => {
+                if state != State::Read && log_enabled!(LogLevel::Trace) {
+                    trace!(target: "cart::flash", "Invalid command 0x{:02x} at 0x{:05x} in state {:?}", value, offset, state);
+                }
+                State::Read
+            }
+        };
+    }
+
+    fn erase(&mut self, offset: usize, size: usize) {
+        if log_enabled!(LogLevel::Trace) {
+            trace!(target: "cart::flash", "Erasing 0x{:05x} size {}", offset, size);
+        }
+        for byte in self.data[offset..offset + size].iter_mut() {
+            *byte = 0xff;
+        }
+        self.modified = true;
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.state.value());
+        state.write_bool(self.modified);
+        state.write_expansion_ram(&self.data);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.state = State::from(state.read_u8()?)?;
+        // Rewind snapshots leave the contents out, so changes made since then are kept
+        self.modified |= state.read_bool()?;
+        state.read_expansion_ram(&mut self.data)
+    }
+}
+
+impl Default for FlashRom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(flash: &mut FlashRom, value: u8) {
+        flash.write(0x555, 0xaa);
+        flash.write(0x2aa, 0x55);
+        flash.write(0x555, value);
+    }
+
+    #[test]
+    fn program_clears_bits() {
+        let mut flash = FlashRom::new();
+        command(&mut flash, 0xa0);
+        flash.write(0x12345, 0x3c);
+        assert_eq!(0x3c, flash.read(0x12345));
+        command(&mut flash, 0xa0);
+        flash.write(0x12345, 0xf0);
+        assert_eq!(0x30, flash.read(0x12345));
+        assert!(flash.is_modified());
+    }
+
+    #[test]
+    fn write_without_command_is_ignored() {
+        let mut flash = FlashRom::new();
+        flash.load(0, &[0x12]).unwrap();
+        flash.write(0x0000, 0x00);
+        assert_eq!(0x12, flash.read(0x0000));
+        assert!(!flash.is_modified());
+    }
+
+    #[test]
+    fn sector_erase() {
+        let mut flash = FlashRom::new();
+        flash.load(0x0ffff, &[0x00, 0x00]).unwrap();
+        command(&mut flash, 0x80);
+        flash.write(0x555, 0xaa);
+        flash.write(0x2aa, 0x55);
+        flash.write(0x10000, 0x30);
+        assert_eq!(0x00, flash.read(0x0ffff));
+        assert_eq!(0xff, flash.read(0x10000));
+    }
+
+    #[test]
+    fn chip_erase() {
+        let mut flash = FlashRom::new();
+        flash.load(0x7ffff, &[0x00]).unwrap();
+        command(&mut flash, 0x80);
+        flash.write(0x555, 0xaa);
+        flash.write(0x2aa, 0x55);
+        flash.write(0x555, 0x10);
+        assert_eq!(0xff, flash.read(0x7ffff));
+    }
+
+    #[test]
+    fn autoselect() {
+        let mut flash = FlashRom::new();
+        command(&mut flash, 0x90);
+        assert_eq!(MANUFACTURER_ID, flash.read(0x00));
+        assert_eq!(DEVICE_ID, flash.read(0x01));
+        flash.write(0x00, 0xf0);
+        assert_eq!(0xff, flash.read(0x00));
+    }
+
+    #[test]
+    fn rewind_state_leaves_out_contents() {
+        let mut flash = FlashRom::new();
+        flash.load(0, &[0x12]).unwrap();
+        let mut state = StateWriter::new().skip_expansion_ram();
+        flash.save_state(&mut state);
+        let data = state.into_inner();
+        assert!(data.len() < 16);
+        command(&mut flash, 0xa0);
+        flash.write(0x0000, 0x02);
+        flash.load_state(&mut StateReader::new(&data)).unwrap();
+        assert_eq!(0x02, flash.read(0x0000));
+        assert!(flash.is_modified());
+    }
+}
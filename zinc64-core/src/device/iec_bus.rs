@@ -0,0 +1,109 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+use bit_field::BitField;
+
+use crate::util::{StateReader, StateWriter};
+
+// Design:
+//   IEC serial bus lines are open collector so a line is low if any of the attached
+//   devices pulls it low. The computer drives ATN, CLK and DATA through CIA 2 port A
+//   inverters and reads CLK and DATA back. Devices only drive CLK and DATA.
+
+/*
+  +-----+--------+------------------------------+
+  | BIT | DIR    | CIA 2 PORT A                 |
+  +-----+--------+------------------------------+
+  |  3  | OUTPUT | ATN OUT (1 = pull line low)  |
+  |  4  | OUTPUT | CLK OUT (1 = pull line low)  |
+  |  5  | OUTPUT | DATA OUT (1 = pull line low) |
+  |  6  | INPUT  | CLK IN (0 = line low)        |
+  |  7  | INPUT  | DATA IN (0 = line low)       |
+  +-----+--------+------------------------------+
+*/
+
+pub struct IecBus {
+    // Computer
+    atn_out: bool,
+    clk_out: bool,
+    data_out: bool,
+    // Devices
+    device_clk_out: bool,
+    device_data_out: bool,
+}
+
+impl IecBus {
+    pub fn new() -> Self {
+        Self {
+            atn_out: false,
+            clk_out: false,
+            data_out: false,
+            device_clk_out: false,
+            device_data_out: false,
+        }
+    }
+
+    /// Get CIA 2 port A input bits reflecting CLK and DATA line state.
+    pub fn get_cia_input(&self) -> u8 {
+        let mut result = 0x3f;
+        result.set_bit(6, !self.is_clk_low());
+        result.set_bit(7, !self.is_data_low());
+        result
+    }
+
+    pub fn is_atn_low(&self) -> bool {
+        self.atn_out
+    }
+
+    pub fn is_clk_low(&self) -> bool {
+        self.clk_out || self.device_clk_out
+    }
+
+    pub fn is_data_low(&self) -> bool {
+        self.data_out || self.device_data_out
+    }
+
+    /// Update computer outputs from CIA 2 port A value.
+    pub fn set_cia_output(&mut self, value: u8) {
+        self.atn_out = value.get_bit(3);
+        self.clk_out = value.get_bit(4);
+        self.data_out = value.get_bit(5);
+    }
+
+    /// Update lines pulled low by the attached devices.
+    pub fn set_device_output(&mut self, clk: bool, data: bool) {
+        self.device_clk_out = clk;
+        self.device_data_out = data;
+    }
+
+    pub fn reset(&mut self) {
+        self.device_clk_out = false;
+        self.device_data_out = false;
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.atn_out);
+        state.write_bool(self.clk_out);
+        state.write_bool(self.data_out);
+        state.write_bool(self.device_clk_out);
+        state.write_bool(self.device_data_out);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.atn_out = state.read_bool()?;
+        self.clk_out = state.read_bool()?;
+        self.data_out = state.read_bool()?;
+        self.device_clk_out = state.read_bool()?;
+        self.device_data_out = state.read_bool()?;
+        Ok(())
+    }
+}
+
+impl Default for IecBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -2,13 +2,15 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
 use bit_field::BitField;
 
-use crate::util::SharedCell;
+use crate::util::{SharedCell, StateReader, StateWriter};
 
 // DEFERRED device: joystick test cases
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Button {
     Up = 0,
     Down = 1,
@@ -76,6 +78,15 @@ impl Joystick {
         self.state.set(0);
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.state.get());
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.state.set(state.read_u8()?);
+        Ok(())
+    }
+
     fn set_state(&mut self, bit: usize, value: bool) {
         let mut new_state = self.state.get();
         new_state.set_bit(bit, value);
@@ -6,7 +6,7 @@
 use alloc::prelude::*;
 use bit_field::BitField;
 
-use crate::util::Shared;
+use crate::util::{Shared, StateReader, StateWriter};
 
 // SPEC: https://www.c64-wiki.com/index.php/Keyboard#Hardware
 
@@ -170,6 +170,18 @@ impl Keyboard {
         self.queue.clear();
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&*self.matrix.borrow());
+        state.write_u8(self.disabled_shift);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.read_bytes(&mut *self.matrix.borrow_mut())?;
+        self.disabled_shift = state.read_u8()?;
+        self.queue.clear();
+        Ok(())
+    }
+
     pub fn set_key(&mut self, keycode: Key, enabled: bool) {
         let mapping = self.map_keycode(keycode);
         self.matrix.borrow_mut()[mapping.0].set_bit(mapping.1, !enabled);
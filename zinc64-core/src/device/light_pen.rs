@@ -0,0 +1,123 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+use bit_field::BitField;
+
+use super::joystick::Button;
+use crate::util::SharedCell;
+
+// Design:
+//   The LP input of the VIC is wired to CIA 1 port B bit 4, which is also the fire line of
+//   control port 1. It is low while the CIA drives the bit low, while fire is pressed on
+//   port 1, or while the beam passes the point a light pen or gun is aimed at. Pen
+//   position is given in frame buffer pixels. The pen button is reported on one of the
+//   joystick lines of port 1, as pens and guns use different ones.
+
+pub struct LightPen {
+    // Configuration
+    button: Button,
+    // Runtime State
+    port_low: bool,
+    position: Option<(u16, u16)>,
+    // I/O
+    joystick_state: SharedCell<u8>,
+}
+
+impl LightPen {
+    pub fn new(joystick_state: SharedCell<u8>) -> Self {
+        LightPen {
+            button: Button::Up,
+            port_low: false,
+            position: None,
+            joystick_state,
+        }
+    }
+
+    pub fn get_position(&self) -> Option<(u16, u16)> {
+        self.position
+    }
+
+    /// Set the frame buffer position the pen sees, or none when it is off screen.
+    pub fn set_position(&mut self, position: Option<(u16, u16)>) {
+        self.position = position;
+    }
+
+    /// Select the joystick line of port 1 the pen button is reported on.
+    pub fn set_button_line(&mut self, button: Button) {
+        self.button = button;
+    }
+
+    pub fn set_button(&mut self, pressed: bool) {
+        let mut state = self.joystick_state.get();
+        state.set_bit(self.button.bit(), pressed);
+        self.joystick_state.set(state);
+    }
+
+    /// Update the level CIA 1 drives on port B bit 4.
+    pub fn set_port_output(&mut self, high: bool) {
+        self.port_low = !high;
+    }
+
+    pub fn is_line_low(&self) -> bool {
+        self.port_low || self.joystick_state.get().get_bit(Button::Fire.bit())
+    }
+
+    /// Check if the line goes low while the beam draws the 8 pixels starting at `x` on
+    /// line `y`, and return the offset of the pixel at which it does.
+    pub fn sample(&self, x: i32, y: u16) -> Option<u16> {
+        if self.is_line_low() {
+            return Some(0);
+        }
+        match self.position {
+            Some((pen_x, pen_y)) if pen_y == y => {
+                let offset = i32::from(pen_x) - x;
+                if (0..8).contains(&offset) {
+                    Some(offset as u16)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::new_shared_cell;
+
+    #[test]
+    fn sample_pen_position() {
+        let mut light_pen = LightPen::new(new_shared_cell(0));
+        assert_eq!(None, light_pen.sample(100, 50));
+        light_pen.set_position(Some((103, 50)));
+        assert_eq!(None, light_pen.sample(100, 51));
+        assert_eq!(None, light_pen.sample(104, 50));
+        assert_eq!(Some(3), light_pen.sample(100, 50));
+    }
+
+    #[test]
+    fn port_and_fire_pull_line_low() {
+        let joystick_state = new_shared_cell(0);
+        let mut light_pen = LightPen::new(joystick_state.clone());
+        light_pen.set_port_output(false);
+        assert_eq!(Some(0), light_pen.sample(0, 0));
+        light_pen.set_port_output(true);
+        assert!(!light_pen.is_line_low());
+        joystick_state.set(1 << Button::Fire.bit());
+        assert!(light_pen.is_line_low());
+    }
+
+    #[test]
+    fn button_sets_joystick_line() {
+        let joystick_state = new_shared_cell(0);
+        let mut light_pen = LightPen::new(joystick_state.clone());
+        light_pen.set_button_line(Button::Left);
+        light_pen.set_button(true);
+        assert_eq!(1 << Button::Left.bit(), joystick_state.get());
+        light_pen.set_button(false);
+        assert_eq!(0, joystick_state.get());
+    }
+}
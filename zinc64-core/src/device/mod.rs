@@ -4,10 +4,20 @@
 
 pub mod cartridge;
 mod datassette;
+pub mod drive;
+pub mod flash;
+mod iec_bus;
 pub mod joystick;
 pub mod keyboard;
+mod light_pen;
+pub mod reu;
 
 pub use self::cartridge::Cartridge;
 pub use self::datassette::Datassette;
+pub use self::drive::Drive;
+pub use self::flash::FlashRom;
+pub use self::iec_bus::IecBus;
 pub use self::joystick::Joystick;
 pub use self::keyboard::{Key, KeyEvent, Keyboard};
+pub use self::light_pen::LightPen;
+pub use self::reu::Reu;
@@ -0,0 +1,547 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+#[cfg(not(feature = "std"))]
+use alloc::vec;
+use bit_field::BitField;
+use log::LogLevel;
+
+use crate::factory::Addressable;
+use crate::util::{IrqLine, Shared, StateReader, StateWriter};
+
+// SPEC: Commodore 1764 RAM Expansion Module User's Guide, 8726 REC datasheet
+
+// Design:
+//   The REC registers are mirrored every 32 bytes across $DF00-$DFFF. Transfers are started
+//   by writing the command register and, unless the $FF00 trigger is disabled, a subsequent
+//   write to $FF00. While a transfer is active the system clocks one byte per cycle through
+//   clock_dma and holds the BA line low to keep the CPU off the bus. Swap takes two cycles
+//   per byte.
+
+pub const MIN_SIZE: usize = 0x2_0000;
+pub const MAX_SIZE: usize = 0x100_0000;
+
+const REC_ADDR_SIZE: usize = 0x8_0000;
+
+#[derive(Copy, Clone)]
+enum IrqSource {
+    Reu = 3,
+}
+
+impl IrqSource {
+    pub fn value(self) -> usize {
+        self as usize
+    }
+}
+
+pub mod reg {
+    pub const STATUS: u8 = 0x00;
+    pub const COMMAND: u8 = 0x01;
+    pub const C64_ADDR_LO: u8 = 0x02;
+    pub const C64_ADDR_HI: u8 = 0x03;
+    pub const REU_ADDR_LO: u8 = 0x04;
+    pub const REU_ADDR_HI: u8 = 0x05;
+    pub const REU_BANK: u8 = 0x06;
+    pub const LENGTH_LO: u8 = 0x07;
+    pub const LENGTH_HI: u8 = 0x08;
+    pub const IRQ_MASK: u8 = 0x09;
+    pub const ADDR_CONTROL: u8 = 0x0a;
+}
+
+#[derive(Copy, Clone)]
+enum Status {
+    Size = 4,
+    Fault = 5,
+    EndOfBlock = 6,
+    IrqPending = 7,
+}
+
+#[derive(Copy, Clone)]
+enum Command {
+    Ff00Disabled = 4,
+    Autoload = 5,
+    Execute = 7,
+}
+
+#[derive(Copy, Clone)]
+enum IrqMask {
+    Fault = 5,
+    EndOfBlock = 6,
+    Enable = 7,
+}
+
+#[derive(Copy, Clone)]
+enum AddrControl {
+    FixReu = 6,
+    FixC64 = 7,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TransferType {
+    Stash,
+    Fetch,
+    Swap,
+    Verify,
+}
+
+impl TransferType {
+    pub fn from(value: u8) -> TransferType {
+        match value & 0x03 {
+            0 => TransferType::Stash,
+            1 => TransferType::Fetch,
+            2 => TransferType::Swap,
+            _ => TransferType::Verify,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct Registers {
+    c64_addr: u16,
+    reu_addr: u32,
+    length: u16,
+}
+
+pub struct Reu {
+    ram: Vec<u8>,
+    // Registers
+    status: u8,
+    command: u8,
+    regs: Registers,
+    shadow: Registers,
+    irq_mask: u8,
+    addr_control: u8,
+    // Runtime State
+    active: bool,
+    swap_pending: Option<(u8, u8)>,
+    // I/O
+    irq_line: Shared<IrqLine>,
+}
+
+impl Reu {
+    /// Create REU with the specified RAM size, which must be a power of two
+    /// between 128K and 16M.
+    pub fn new(size: usize, irq_line: Shared<IrqLine>) -> Result<Self, String> {
+        if !size.is_power_of_two() || !(MIN_SIZE..=MAX_SIZE).contains(&size) {
+            return Err(format!("invalid REU size {}", size));
+        }
+        Ok(Self {
+            ram: vec![0; size],
+            status: 0,
+            command: 0,
+            regs: Registers::default(),
+            shadow: Registers::default(),
+            irq_mask: 0,
+            addr_control: 0,
+            active: false,
+            swap_pending: None,
+            irq_line,
+        })
+    }
+
+    pub fn get_ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn get_size(&self) -> usize {
+        self.ram.len()
+    }
+
+    pub fn is_dma_active(&self) -> bool {
+        self.active
+    }
+
+    /// Load RAM image. Images smaller than the REU size fill the beginning of RAM.
+    pub fn load_ram(&mut self, data: &[u8]) -> Result<(), String> {
+        if data.len() > self.ram.len() {
+            return Err(format!(
+                "REU image size {} exceeds REU size {}",
+                data.len(),
+                self.ram.len()
+            ));
+        }
+        self.ram[..data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        self.status = 0;
+        self.status
+            .set_bit(Status::Size as usize, self.ram.len() > MIN_SIZE);
+        self.command = 0;
+        self.command.set_bit(Command::Ff00Disabled as usize, true);
+        self.regs = Registers::default();
+        self.regs.length = 0xffff;
+        self.shadow = self.regs;
+        self.irq_mask = 0;
+        self.addr_control = 0;
+        self.active = false;
+        self.swap_pending = None;
+        self.irq_line
+            .borrow_mut()
+            .set_low(IrqSource::Reu.value(), false);
+    }
+
+    /// Transfer the next byte between C64 memory and REU RAM.
+    pub fn clock_dma(&mut self, mem: &mut dyn Addressable) {
+        if !self.active {
+            return;
+        }
+        let c64_addr = self.regs.c64_addr;
+        let reu_addr = self.ram_addr();
+        match TransferType::from(self.command) {
+            TransferType::Stash => {
+                self.ram[reu_addr] = mem.read(c64_addr);
+                self.advance();
+            }
+            TransferType::Fetch => {
+                mem.write(c64_addr, self.ram[reu_addr]);
+                self.advance();
+            }
+            TransferType::Swap => {
+                if let Some((c64_value, reu_value)) = self.swap_pending.take() {
+                    mem.write(c64_addr, reu_value);
+                    self.ram[reu_addr] = c64_value;
+                    self.advance();
+                } else {
+                    self.swap_pending = Some((mem.read(c64_addr), self.ram[reu_addr]));
+                }
+            }
+            TransferType::Verify => {
+                let fault = mem.read(c64_addr) != self.ram[reu_addr];
+                if fault {
+                    self.status.set_bit(Status::Fault as usize, true);
+                }
+                self.advance();
+                if fault && self.active {
+                    self.finish();
+                }
+            }
+        }
+    }
+
+    /// Notify REU about CPU write to $FF00 which starts armed transfers.
+    pub fn trigger(&mut self) {
+        if self.command.get_bit(Command::Execute as usize)
+            && !self.command.get_bit(Command::Ff00Disabled as usize)
+            && !self.active
+        {
+            self.start();
+        }
+    }
+
+    fn advance(&mut self) {
+        if !self.addr_control.get_bit(AddrControl::FixC64 as usize) {
+            self.regs.c64_addr = self.regs.c64_addr.wrapping_add(1);
+        }
+        if !self.addr_control.get_bit(AddrControl::FixReu as usize) {
+            self.regs.reu_addr = (self.regs.reu_addr + 1) & self.addr_mask();
+        }
+        if self.regs.length == 1 {
+            self.status.set_bit(Status::EndOfBlock as usize, true);
+            self.finish();
+        } else {
+            self.regs.length = self.regs.length.wrapping_sub(1);
+        }
+    }
+
+    fn addr_mask(&self) -> u32 {
+        (self.ram.len().max(REC_ADDR_SIZE) - 1) as u32
+    }
+
+    fn finish(&mut self) {
+        if log_enabled!(LogLevel::Trace) {
+            trace!(target: "reu", "Transfer done, status 0x{:02x}", self.status);
+        }
+        self.active = false;
+        self.swap_pending = None;
+        self.command.set_bit(Command::Execute as usize, false);
+        self.command.set_bit(Command::Ff00Disabled as usize, true);
+        if self.command.get_bit(Command::Autoload as usize) {
+            self.regs = self.shadow;
+        }
+        self.update_irq();
+    }
+
+    fn ram_addr(&self) -> usize {
+        self.regs.reu_addr as usize & (self.ram.len() - 1)
+    }
+
+    fn start(&mut self) {
+        if log_enabled!(LogLevel::Trace) {
+            trace!(
+                target: "reu",
+                "Transfer {:?} c64 0x{:04x} reu 0x{:06x} length {}",
+                TransferType::from(self.command),
+                self.regs.c64_addr,
+                self.regs.reu_addr,
+                self.regs.length
+            );
+        }
+        self.active = true;
+        self.swap_pending = None;
+    }
+
+    fn update_irq(&mut self) {
+        let pending = self.irq_mask.get_bit(IrqMask::Enable as usize)
+            && ((self.irq_mask.get_bit(IrqMask::EndOfBlock as usize)
+                && self.status.get_bit(Status::EndOfBlock as usize))
+                || (self.irq_mask.get_bit(IrqMask::Fault as usize)
+                    && self.status.get_bit(Status::Fault as usize)));
+        self.status.set_bit(Status::IrqPending as usize, pending);
+        self.irq_line
+            .borrow_mut()
+            .set_low(IrqSource::Reu.value(), pending);
+    }
+
+    // -- Device I/O
+
+    pub fn read(&mut self, address: u16) -> u8 {
+        match (address & 0x1f) as u8 {
+            reg::STATUS => {
+                let value = self.status;
+                self.status &= 0x1f;
+                self.irq_line
+                    .borrow_mut()
+                    .set_low(IrqSource::Reu.value(), false);
+                value
+            }
+            reg::COMMAND => self.command,
+            reg::C64_ADDR_LO => self.regs.c64_addr as u8,
+            reg::C64_ADDR_HI => (self.regs.c64_addr >> 8) as u8,
+            reg::REU_ADDR_LO => self.regs.reu_addr as u8,
+            reg::REU_ADDR_HI => (self.regs.reu_addr >> 8) as u8,
+            reg::REU_BANK => {
+                let bank = (self.regs.reu_addr >> 16) as u8;
+                if self.ram.len() <= REC_ADDR_SIZE {
+                    bank | 0xf8
+                } else {
+                    bank
+                }
+            }
+            reg::LENGTH_LO => self.regs.length as u8,
+            reg::LENGTH_HI => (self.regs.length >> 8) as u8,
+            reg::IRQ_MASK => self.irq_mask | 0x1f,
+            reg::ADDR_CONTROL => self.addr_control | 0x3f,
+            _ => 0xff,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match (address & 0x1f) as u8 {
+            reg::COMMAND => {
+                self.command = value;
+                if value.get_bit(Command::Execute as usize)
+                    && value.get_bit(Command::Ff00Disabled as usize)
+                {
+                    self.start();
+                }
+            }
+            reg::C64_ADDR_LO => {
+                self.shadow.c64_addr = (self.shadow.c64_addr & 0xff00) | u16::from(value);
+                self.regs.c64_addr = self.shadow.c64_addr;
+            }
+            reg::C64_ADDR_HI => {
+                self.shadow.c64_addr = (self.shadow.c64_addr & 0x00ff) | (u16::from(value) << 8);
+                self.regs.c64_addr = self.shadow.c64_addr;
+            }
+            reg::REU_ADDR_LO => {
+                self.shadow.reu_addr = (self.shadow.reu_addr & 0xff_ff00) | u32::from(value);
+                self.regs.reu_addr = self.shadow.reu_addr & self.addr_mask();
+            }
+            reg::REU_ADDR_HI => {
+                self.shadow.reu_addr = (self.shadow.reu_addr & 0xff_00ff) | (u32::from(value) << 8);
+                self.regs.reu_addr = self.shadow.reu_addr & self.addr_mask();
+            }
+            reg::REU_BANK => {
+                self.shadow.reu_addr =
+                    (self.shadow.reu_addr & 0x00_ffff) | (u32::from(value) << 16);
+                self.regs.reu_addr = self.shadow.reu_addr & self.addr_mask();
+            }
+            reg::LENGTH_LO => {
+                self.shadow.length = (self.shadow.length & 0xff00) | u16::from(value);
+                self.regs.length = self.shadow.length;
+            }
+            reg::LENGTH_HI => {
+                self.shadow.length = (self.shadow.length & 0x00ff) | (u16::from(value) << 8);
+                self.regs.length = self.shadow.length;
+            }
+            reg::IRQ_MASK => {
+                self.irq_mask = value & 0xe0;
+                self.update_irq();
+            }
+            reg::ADDR_CONTROL => {
+                self.addr_control = value & 0xc0;
+            }
+            _ => {}
+        }
+    }
+
+    // -- State
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.status);
+        state.write_u8(self.command);
+        for regs in [&self.regs, &self.shadow].iter() {
+            state.write_u16(regs.c64_addr);
+            state.write_u32(regs.reu_addr);
+            state.write_u16(regs.length);
+        }
+        state.write_u8(self.irq_mask);
+        state.write_u8(self.addr_control);
+        state.write_bool(self.active);
+        if let Some((c64_value, reu_value)) = self.swap_pending {
+            state.write_bool(true);
+            state.write_u8(c64_value);
+            state.write_u8(reu_value);
+        } else {
+            state.write_bool(false);
+        }
+        state.write_expansion_ram(&self.ram);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.status = state.read_u8()?;
+        self.command = state.read_u8()?;
+        for regs in [&mut self.regs, &mut self.shadow].iter_mut() {
+            regs.c64_addr = state.read_u16()?;
+            regs.reu_addr = state.read_u32()?;
+            regs.length = state.read_u16()?;
+        }
+        self.irq_mask = state.read_u8()?;
+        self.addr_control = state.read_u8()?;
+        self.active = state.read_bool()?;
+        self.swap_pending = if state.read_bool()? {
+            Some((state.read_u8()?, state.read_u8()?))
+        } else {
+            None
+        };
+        state.read_expansion_ram(&mut self.ram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::new_shared;
+
+    struct TestMemory {
+        data: Vec<u8>,
+    }
+
+    impl Addressable for TestMemory {
+        fn read(&self, address: u16) -> u8 {
+            self.data[address as usize]
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.data[address as usize] = value;
+        }
+    }
+
+    fn setup_reu() -> (Reu, TestMemory) {
+        let irq_line = new_shared(IrqLine::new("irq"));
+        let mut reu = Reu::new(0x8_0000, irq_line).unwrap();
+        reu.reset();
+        let mem = TestMemory {
+            data: vec![0; 0x10000],
+        };
+        (reu, mem)
+    }
+
+    fn setup_transfer(reu: &mut Reu, c64_addr: u16, reu_addr: u32, length: u16) {
+        reu.write(0xdf02, c64_addr as u8);
+        reu.write(0xdf03, (c64_addr >> 8) as u8);
+        reu.write(0xdf04, reu_addr as u8);
+        reu.write(0xdf05, (reu_addr >> 8) as u8);
+        reu.write(0xdf06, (reu_addr >> 16) as u8);
+        reu.write(0xdf07, length as u8);
+        reu.write(0xdf08, (length >> 8) as u8);
+    }
+
+    fn run_dma(reu: &mut Reu, mem: &mut TestMemory) -> u32 {
+        let mut cycles = 0;
+        while reu.is_dma_active() {
+            reu.clock_dma(mem);
+            cycles += 1;
+        }
+        cycles
+    }
+
+    #[test]
+    fn stash_and_fetch() {
+        let (mut reu, mut mem) = setup_reu();
+        mem.data[0x1000..0x1004].copy_from_slice(&[1, 2, 3, 4]);
+        setup_transfer(&mut reu, 0x1000, 0x1_0000, 4);
+        reu.write(0xdf01, 0x90);
+        assert_eq!(4, run_dma(&mut reu, &mut mem));
+        assert_eq!(&[1, 2, 3, 4], &reu.get_ram()[0x1_0000..0x1_0004]);
+        assert_eq!(0x50, reu.read(0xdf00));
+        setup_transfer(&mut reu, 0x2000, 0x1_0000, 4);
+        reu.write(0xdf01, 0x91);
+        run_dma(&mut reu, &mut mem);
+        assert_eq!(&[1, 2, 3, 4], &mem.data[0x2000..0x2004]);
+        assert_eq!(
+            0x2004,
+            u16::from(reu.read(0xdf02)) | u16::from(reu.read(0xdf03)) << 8
+        );
+        assert_eq!(1, reu.read(0xdf07));
+    }
+
+    #[test]
+    fn swap_takes_two_cycles_per_byte() {
+        let (mut reu, mut mem) = setup_reu();
+        mem.data[0x1000..0x1002].copy_from_slice(&[1, 2]);
+        reu.load_ram(&[3, 4]).unwrap();
+        setup_transfer(&mut reu, 0x1000, 0, 2);
+        reu.write(0xdf01, 0x92);
+        assert_eq!(4, run_dma(&mut reu, &mut mem));
+        assert_eq!(&[3, 4], &mem.data[0x1000..0x1002]);
+        assert_eq!(&[1, 2], &reu.get_ram()[0..2]);
+    }
+
+    #[test]
+    fn verify_stops_on_mismatch() {
+        let (mut reu, mut mem) = setup_reu();
+        mem.data[0x1000..0x1004].copy_from_slice(&[1, 2, 0, 4]);
+        reu.load_ram(&[1, 2, 3, 4]).unwrap();
+        setup_transfer(&mut reu, 0x1000, 0, 4);
+        reu.write(0xdf01, 0x93);
+        assert_eq!(3, run_dma(&mut reu, &mut mem));
+        assert_eq!(0x20, reu.read(0xdf00) & 0x60);
+    }
+
+    #[test]
+    fn ff00_trigger_and_autoload() {
+        let (mut reu, mut mem) = setup_reu();
+        setup_transfer(&mut reu, 0x1000, 0, 2);
+        reu.write(0xdf01, 0xa0);
+        assert!(!reu.is_dma_active());
+        reu.trigger();
+        assert!(reu.is_dma_active());
+        run_dma(&mut reu, &mut mem);
+        assert_eq!(0x00, reu.read(0xdf02));
+        assert_eq!(0x10, reu.read(0xdf03));
+        assert_eq!(2, reu.read(0xdf07));
+    }
+
+    #[test]
+    fn irq_on_end_of_block() {
+        let irq_line = new_shared(IrqLine::new("irq"));
+        let mut reu = Reu::new(MIN_SIZE, irq_line.clone()).unwrap();
+        reu.reset();
+        let mut mem = TestMemory {
+            data: vec![0; 0x10000],
+        };
+        reu.write(0xdf09, 0xc0);
+        setup_transfer(&mut reu, 0x1000, 0, 1);
+        reu.write(0xdf01, 0x90);
+        run_dma(&mut reu, &mut mem);
+        assert!(irq_line.borrow().is_low());
+        assert_eq!(0xc0, reu.read(0xdf00));
+        assert!(!irq_line.borrow().is_low());
+    }
+}
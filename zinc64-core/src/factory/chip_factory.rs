@@ -15,8 +15,10 @@ use std::rc::Rc;
 #[cfg(feature = "std")]
 use std::sync::Arc;
 
+use crate::device::LightPen;
 use crate::factory::system_model::{SidModel, VicModel};
 use crate::factory::types::*;
+use crate::sound::VoiceControl;
 use crate::util::{Clock, IoPort, IrqLine, Pin, Ram, Rom, Shared, SharedCell};
 
 /// ChipFactory serves as the foundation of an extensible emulator architecture and
@@ -117,6 +119,22 @@ pub trait ChipFactory {
         sound_buffer: Arc<dyn SoundOutput>,
     ) -> Shared<dyn Chip>;
 
+    /// Constructs SID chip with voice control, which carries mute settings from and voice
+    /// waveform taps to the frontend. Factories that do not support it construct a SID
+    /// without voice control.
+    ///
+    /// # I/O
+    /// `voice_control` - voice mute settings and waveform taps
+    fn new_sid_with_voice_control(
+        &self,
+        chip_model: SidModel,
+        system_clock: Rc<Clock>,
+        sound_buffer: Arc<dyn SoundOutput>,
+        _voice_control: Shared<VoiceControl>,
+    ) -> Shared<dyn Chip> {
+        self.new_sid(chip_model, system_clock, sound_buffer)
+    }
+
     /// Constructs VIC chip.
     ///
     /// Since VIC relies on CIA 2 port A for its memory address generation,
@@ -138,6 +156,8 @@ pub trait ChipFactory {
     /// # Signals
     /// `ba_line` - ba output
     /// `irq_line` - interrupt request output
+    /// `light_pen` - light pen input
+    #[allow(clippy::too_many_arguments)]
     fn new_vic(
         &self,
         chip_model: VicModel,
@@ -149,6 +169,7 @@ pub trait ChipFactory {
         vsync_flag: SharedCell<bool>,
         ba_line: Shared<Pin>,
         irq_line: Shared<IrqLine>,
+        light_pen: Shared<LightPen>,
     ) -> Shared<dyn Chip>;
 
     // -- Memory
@@ -159,6 +180,8 @@ pub trait ChipFactory {
     /// any given time. Bank switching is controlled through 5 latch bits that control
     /// the memory management unit (LORAM, HIRAM, CHAREN, GAME, EXROM) that does address
     /// translation.
+    ///
+    /// Extra SIDs are mapped to I/O with their base addresses in addition to the primary SID.
     fn new_memory(
         &self,
         mmu: Shared<dyn Mmu>,
@@ -171,6 +194,7 @@ pub trait ChipFactory {
         rom_charset: Shared<Rom>,
         rom_kernal: Shared<Rom>,
         sid: Shared<dyn Chip>,
+        extra_sids: Vec<(u16, Shared<dyn Chip>)>,
         vic: Shared<dyn Chip>,
     ) -> Shared<dyn Addressable>;
 
@@ -19,7 +19,7 @@
    6569   |  300   |   15   |  404 ($194)  | 480 ($1e0) | 380 ($17c)
 */
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum SidModel {
     Mos6581,
     Mos8580,
@@ -27,15 +27,23 @@ pub enum SidModel {
 
 #[derive(Copy, Clone)]
 pub enum VicModel {
-    Mos6567, // NTSC
-    Mos6569, // PAL
+    Mos6567R56A, // NTSC, 64 cycles
+    Mos6567R8,   // NTSC, 65 cycles
+    Mos6569,     // PAL
+    Mos6572,     // PAL-N, 65 cycles
+    Mos8565,     // PAL, C64C
 }
 
 pub struct SystemModel {
+    /// The machine boots only from cartridges, as the C64GS does.
+    pub cartridge_only: bool,
     pub color_ram: usize,
     pub cpu_freq: u32,
     pub cycles_per_frame: u16,
     pub frame_buffer_size: (u32, u32),
+    pub has_datassette: bool,
+    /// File name of the kernal ROM the machine ships with.
+    pub kernal_rom: &'static str,
     pub memory_size: usize,
     pub refresh_rate: f32,
     pub sid_model: SidModel,
@@ -49,33 +57,63 @@ impl SystemModel {
         match model {
             "ntsc" => SystemModel::c64_ntsc(),
             "pal" => SystemModel::c64_pal(),
+            "ntsc-old" => SystemModel::c64_ntsc_old(),
             "c64-ntsc" => SystemModel::c64_ntsc(),
+            "c64-ntsc-old" => SystemModel::c64_ntsc_old(),
             "c64-pal" => SystemModel::c64_pal(),
+            "pal-n" | "c64-pal-n" | "drean" => SystemModel::c64_pal_n(),
+            "c64c" | "c64c-pal" => SystemModel::c64c_pal(),
+            "sx64" | "sx-64" => SystemModel::sx64_pal(),
+            "c64gs" => SystemModel::c64gs_pal(),
             _ => panic!("invalid model {}", model),
         }
     }
 
     pub fn c64_ntsc() -> SystemModel {
         SystemModel {
+            cartridge_only: false,
             color_ram: 1024,
             cpu_freq: 1_022_727,
             cycles_per_frame: 17095,
             frame_buffer_size: (512, 263),
+            has_datassette: true,
+            kernal_rom: "kernal.rom",
             memory_size: 65536,
             refresh_rate: 59.826,
             sid_model: SidModel::Mos6581,
-            vic_model: VicModel::Mos6567,
+            vic_model: VicModel::Mos6567R8,
             viewport_offset: (77, 16),
             viewport_size: (418, 235),
         }
     }
 
+    pub fn c64_ntsc_old() -> SystemModel {
+        SystemModel {
+            cartridge_only: false,
+            color_ram: 1024,
+            cpu_freq: 1_022_727,
+            cycles_per_frame: 16768,
+            frame_buffer_size: (512, 262),
+            has_datassette: true,
+            kernal_rom: "kernal.rom",
+            memory_size: 65536,
+            refresh_rate: 60.992,
+            sid_model: SidModel::Mos6581,
+            vic_model: VicModel::Mos6567R56A,
+            viewport_offset: (76, 16),
+            viewport_size: (411, 234),
+        }
+    }
+
     pub fn c64_pal() -> SystemModel {
         SystemModel {
+            cartridge_only: false,
             color_ram: 1024,
             cpu_freq: 985_248,
             cycles_per_frame: 19656,
             frame_buffer_size: (504, 312),
+            has_datassette: true,
+            kernal_rom: "kernal.rom",
             memory_size: 65536,
             refresh_rate: 50.125,
             sid_model: SidModel::Mos6581,
@@ -84,4 +122,49 @@ impl SystemModel {
             viewport_size: (403, 284),
         }
     }
+
+    pub fn c64_pal_n() -> SystemModel {
+        SystemModel {
+            cartridge_only: false,
+            color_ram: 1024,
+            cpu_freq: 1_023_440,
+            cycles_per_frame: 20280,
+            frame_buffer_size: (512, 312),
+            has_datassette: true,
+            kernal_rom: "kernal.rom",
+            memory_size: 65536,
+            refresh_rate: 50.466,
+            sid_model: SidModel::Mos6581,
+            vic_model: VicModel::Mos6572,
+            viewport_offset: (77, 16),
+            viewport_size: (418, 284),
+        }
+    }
+
+    pub fn c64c_pal() -> SystemModel {
+        SystemModel {
+            sid_model: SidModel::Mos8580,
+            vic_model: VicModel::Mos8565,
+            ..SystemModel::c64_pal()
+        }
+    }
+
+    pub fn sx64_pal() -> SystemModel {
+        SystemModel {
+            has_datassette: false,
+            kernal_rom: "sx64-kernal.rom",
+            ..SystemModel::c64_pal()
+        }
+    }
+
+    pub fn c64gs_pal() -> SystemModel {
+        SystemModel {
+            cartridge_only: true,
+            has_datassette: false,
+            kernal_rom: "c64gs-kernal.rom",
+            sid_model: SidModel::Mos8580,
+            vic_model: VicModel::Mos8565,
+            ..SystemModel::c64_pal()
+        }
+    }
 }
@@ -2,11 +2,15 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
 #[cfg(not(feature = "std"))]
 use alloc::rc::Rc;
 #[cfg(feature = "std")]
 use std::rc::Rc;
 
+use crate::util::{StateReader, StateWriter};
+
 /// A tick represents a callback invoked by the cpu for each clock cycle
 /// during instruction execution.
 pub type TickFn = Rc<dyn Fn()>;
@@ -59,6 +63,11 @@ pub trait Chip {
     fn read(&mut self, reg: u8) -> u8;
     /// Write value to the specified register.
     fn write(&mut self, reg: u8, value: u8);
+    // State
+    /// Save chip state to the snapshot.
+    fn save_state(&self, state: &mut StateWriter);
+    /// Restore chip state from the snapshot.
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String>;
 }
 
 #[derive(Copy, Clone)]
@@ -90,6 +99,11 @@ pub trait Cpu {
     fn read(&self, address: u16) -> u8;
     /// Write byte to the specified address.
     fn write(&mut self, address: u16, value: u8);
+    // State
+    /// Save cpu state including the position within the current instruction.
+    fn save_state(&self, state: &mut StateWriter);
+    /// Restore cpu state from the snapshot.
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String>;
 }
 
 /// Represents memory management unit which controls visible memory banks.
@@ -106,6 +120,18 @@ pub trait SoundOutput {
     fn reset(&self);
     /// Write generated sample to the output buffer.
     fn write(&self, samples: &[i16]);
+    /// Write interleaved frames with one sample for each of the SID chips. Outputs
+    /// without support for multiple channels get the frames mixed down to mono.
+    fn write_frames(&self, frames: &[i16], channels: usize) {
+        let samples = frames
+            .chunks(channels)
+            .map(|frame| {
+                let sum = frame.iter().map(|sample| *sample as i32).sum::<i32>();
+                (sum / channels as i32) as i16
+            })
+            .collect::<Vec<i16>>();
+        self.write(&samples);
+    }
 }
 
 /// Video output used by VIC chip.
@@ -119,7 +145,24 @@ pub trait VideoOutput {
     fn write(&mut self, index: usize, color: u8);
 }
 
+/// Disk represents a floppy disk as a set of GCR encoded tracks. Tracks are indexed by
+/// half track starting with 0 for track 1.
+pub trait Disk {
+    /// Get number of half tracks available on the disk.
+    fn get_half_tracks(&self) -> usize;
+    /// Get GCR data for the specified half track. Unformatted tracks have no data.
+    fn get_track(&self, half_track: usize) -> Option<&[u8]>;
+    /// Get speed zone (0-3) the specified half track was recorded with.
+    fn get_speed_zone(&self, half_track: usize) -> u8;
+    fn is_write_protected(&self) -> bool;
+    /// Check if the disk was written to since it was created.
+    fn is_modified(&self) -> bool;
+    /// Write GCR byte to the specified half track at the byte offset.
+    fn write(&mut self, half_track: usize, offset: usize, value: u8);
+}
+
 pub trait Tape {
+    fn get_pos(&self) -> usize;
     fn read_pulse(&mut self) -> Option<u32>;
     fn seek(&mut self, pos: usize) -> bool;
 }
@@ -2,11 +2,15 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
 use bit_field::BitField;
 use log::LogLevel;
 
 use crate::factory::Chip;
-use crate::util::{new_shared, IoPort, IrqControl, IrqLine, Pin, Shared, SharedCell};
+use crate::util::{
+    new_shared, IoPort, IrqControl, IrqLine, Pin, Shared, SharedCell, StateReader, StateWriter,
+};
 
 use super::cycle_counter::CycleCounter;
 use super::rtc::Rtc;
@@ -420,6 +424,44 @@ impl Chip for Cia {
             _ => panic!("invalid reg {}", reg),
         }
     }
+
+    // State
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_tag(b"CIA0");
+        state.write_u8(self.mode as u8);
+        self.irq_control.save_state(state);
+        self.irq_delay.save_state(state);
+        self.timer_a.save_state(state);
+        self.timer_b.save_state(state);
+        self.tod_alarm.save_state(state);
+        self.tod_clock.save_state(state);
+        state.write_bool(self.tod_set_alarm);
+        self.cnt_pin.borrow().save_state(state);
+        self.flag_pin.borrow().save_state(state);
+        self.port_a.borrow().save_state(state);
+        self.port_b.borrow().save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.read_tag(b"CIA0")?;
+        let mode = state.read_u8()?;
+        if mode != self.mode as u8 {
+            return Err(format!("Invalid cia mode {}", mode));
+        }
+        self.irq_control.load_state(state)?;
+        self.irq_delay.load_state(state)?;
+        self.timer_a.load_state(state)?;
+        self.timer_b.load_state(state)?;
+        self.tod_alarm.load_state(state)?;
+        self.tod_clock.load_state(state)?;
+        self.tod_set_alarm = state.read_bool()?;
+        self.cnt_pin.borrow_mut().load_state(state)?;
+        self.flag_pin.borrow_mut().load_state(state)?;
+        self.port_a.borrow_mut().load_state(state)?;
+        self.port_b.borrow_mut().load_state(state)?;
+        Ok(())
+    }
 }
 
 fn from_bcd(decimal: u8) -> u8 {
@@ -740,4 +782,26 @@ mod tests {
         assert_eq!(cia.read(reg::PRB), 0x00);
         assert_eq!(cia.irq_control.get_raw_data(), 0x03); // 0x83
     }
+
+    #[test]
+    fn save_and_load_state() {
+        let mut cia = setup_cia();
+        cia.write(reg::TALO, 0x10);
+        cia.write(reg::TAHI, 0x00);
+        cia.write(reg::CRA, 0b_0001_0001_u8);
+        for _ in 0..4 {
+            cia.clock();
+        }
+        let mut state = StateWriter::new();
+        cia.save_state(&mut state);
+        let data = state.into_inner();
+        let counter = cia.read(reg::TALO);
+        for _ in 0..4 {
+            cia.clock();
+        }
+        assert_ne!(counter, cia.read(reg::TALO));
+        cia.load_state(&mut StateReader::new(&data)).unwrap();
+        assert_eq!(counter, cia.read(reg::TALO));
+        assert_eq!(0b_0000_0001, cia.read(reg::CRA));
+    }
 }
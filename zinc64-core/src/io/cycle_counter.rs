@@ -2,6 +2,11 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+
+use crate::util::{StateReader, StateWriter};
+
 pub struct CycleCounter {
     // Configuration
     mask: u16,
@@ -53,4 +58,15 @@ impl CycleCounter {
         self.cycles = 0;
         self.feed = 0;
     }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.cycles);
+        state.write_u16(self.feed);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.cycles = state.read_u16()?;
+        self.feed = state.read_u16()?;
+        Ok(())
+    }
 }
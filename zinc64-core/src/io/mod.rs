@@ -6,5 +6,7 @@ pub mod cia;
 mod cycle_counter;
 mod rtc;
 mod timer;
+pub mod via;
 
 pub use self::cia::Cia;
+pub use self::via::Via;
@@ -2,8 +2,12 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
 use core::cmp::PartialEq;
 
+use crate::util::{StateReader, StateWriter};
+
 pub struct Rtc {
     enabled: bool,
     hours: u8,
@@ -69,6 +73,25 @@ impl Rtc {
         self.pm = pm;
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        state.write_u8(self.hours);
+        state.write_u8(self.minutes);
+        state.write_u8(self.seconds);
+        state.write_u8(self.tenth);
+        state.write_bool(self.pm);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.enabled = state.read_bool()?;
+        self.hours = state.read_u8()?;
+        self.minutes = state.read_u8()?;
+        self.seconds = state.read_u8()?;
+        self.tenth = state.read_u8()?;
+        self.pm = state.read_bool()?;
+        Ok(())
+    }
+
     pub fn tick(&mut self) {
         if self.enabled {
             self.tenth += 1;
@@ -4,9 +4,11 @@
 
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
 use bit_field::BitField;
 
-use crate::util::{Pin, Shared};
+use crate::util::{Pin, Shared, StateReader, StateWriter};
 
 use super::cycle_counter::CycleCounter;
 
@@ -31,7 +33,7 @@ pub enum Mode {
     TimerB,
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 enum InputMode {
     SystemClock = 0,
     External = 1,
@@ -39,13 +41,13 @@ enum InputMode {
     TimerAWithCNT = 3,
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 enum OutputMode {
     Pulse,
     Toggle,
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 enum RunMode {
     Continuous,
     OneShot,
@@ -287,6 +289,47 @@ impl Timer {
         self.pb_toggle = false;
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        state.write_u8(self.input_mode as u8);
+        state.write_u8(self.output_mode as u8);
+        state.write_bool(self.pb_on);
+        state.write_u8(self.run_mode as u8);
+        state.write_u16(self.counter);
+        self.delay.save_state(state);
+        state.write_u16(self.latch);
+        state.write_bool(self.pb_output);
+        state.write_bool(self.pb_toggle);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.enabled = state.read_bool()?;
+        self.input_mode = match state.read_u8()? {
+            0 => InputMode::SystemClock,
+            1 => InputMode::External,
+            2 => InputMode::TimerA,
+            3 => InputMode::TimerAWithCNT,
+            value => return Err(format!("Invalid timer input mode {}", value)),
+        };
+        self.output_mode = match state.read_u8()? {
+            0 => OutputMode::Pulse,
+            1 => OutputMode::Toggle,
+            value => return Err(format!("Invalid timer output mode {}", value)),
+        };
+        self.pb_on = state.read_bool()?;
+        self.run_mode = match state.read_u8()? {
+            0 => RunMode::Continuous,
+            1 => RunMode::OneShot,
+            value => return Err(format!("Invalid timer run mode {}", value)),
+        };
+        self.counter = state.read_u16()?;
+        self.delay.load_state(state)?;
+        self.latch = state.read_u16()?;
+        self.pb_output = state.read_bool()?;
+        self.pb_toggle = state.read_bool()?;
+        Ok(())
+    }
+
     fn enable(&mut self, enabled: bool) {
         self.enabled = enabled;
         if enabled && self.input_mode == InputMode::SystemClock {
@@ -0,0 +1,520 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+use bit_field::BitField;
+use log::LogLevel;
+
+use crate::factory::Chip;
+use crate::util::{IoPort, IrqLine, Shared, StateReader, StateWriter};
+
+// Spec: R6522 VERSATILE INTERFACE ADAPTER (VIA) Datasheet
+// http://archive.6502.org/datasheets/rockwell_r6522_via.pdf
+
+// Design:
+//   Port pins are modeled with IoPort where the input value is driven by the device the VIA
+//   is wired to. Control lines CA1/CB1 are inputs fed through set_ca1/set_cb1, while CA2/CB2
+//   are only supported in manual output mode which is all the 1541 needs. Shift register
+//   stores the value written but does not shift.
+
+pub mod reg {
+    pub const ORB: u8 = 0x00;
+    pub const ORA: u8 = 0x01;
+    pub const DDRB: u8 = 0x02;
+    pub const DDRA: u8 = 0x03;
+    pub const T1CL: u8 = 0x04;
+    pub const T1CH: u8 = 0x05;
+    pub const T1LL: u8 = 0x06;
+    pub const T1LH: u8 = 0x07;
+    pub const T2CL: u8 = 0x08;
+    pub const T2CH: u8 = 0x09;
+    pub const SR: u8 = 0x0a;
+    pub const ACR: u8 = 0x0b;
+    pub const PCR: u8 = 0x0c;
+    pub const IFR: u8 = 0x0d;
+    pub const IER: u8 = 0x0e;
+    pub const ORA_NH: u8 = 0x0f;
+}
+
+#[derive(Copy, Clone)]
+enum Interrupt {
+    Ca2 = 0,
+    Ca1 = 1,
+    Cb2 = 3,
+    Cb1 = 4,
+    Timer2 = 5,
+    Timer1 = 6,
+}
+
+impl Interrupt {
+    pub fn mask(self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+pub struct Via {
+    // Configuration
+    irq_source: usize,
+    // Registers
+    acr: u8,
+    pcr: u8,
+    ifr: u8,
+    ier: u8,
+    sr: u8,
+    // Functional Units
+    ca1: bool,
+    cb1: bool,
+    port_a_latch: u8,
+    t1_counter: u16,
+    t1_latch: u16,
+    t1_armed: bool,
+    t1_reload: bool,
+    t1_pb7: bool,
+    t2_counter: u16,
+    t2_latch_lo: u8,
+    t2_armed: bool,
+    // I/O
+    irq_line: Shared<IrqLine>,
+    port_a: Shared<IoPort>,
+    port_b: Shared<IoPort>,
+}
+
+impl Via {
+    pub fn new(
+        port_a: Shared<IoPort>,
+        port_b: Shared<IoPort>,
+        irq_line: Shared<IrqLine>,
+        irq_source: usize,
+    ) -> Self {
+        Self {
+            irq_source,
+            acr: 0,
+            pcr: 0,
+            ifr: 0,
+            ier: 0,
+            sr: 0,
+            ca1: true,
+            cb1: true,
+            port_a_latch: 0xff,
+            t1_counter: 0xffff,
+            t1_latch: 0xffff,
+            t1_armed: false,
+            t1_reload: false,
+            t1_pb7: true,
+            t2_counter: 0xffff,
+            t2_latch_lo: 0xff,
+            t2_armed: false,
+            irq_line,
+            port_a,
+            port_b,
+        }
+    }
+
+    /// CA2 output level when configured in manual output mode.
+    pub fn get_ca2(&self) -> bool {
+        self.pcr & 0x0e != 0x0c
+    }
+
+    /// CB2 output level when configured in manual output mode.
+    pub fn get_cb2(&self) -> bool {
+        self.pcr & 0xe0 != 0xc0
+    }
+
+    pub fn set_ca1(&mut self, value: bool) {
+        let positive = self.pcr.get_bit(0);
+        if self.ca1 != value && value == positive {
+            if self.acr.get_bit(0) {
+                self.port_a_latch = self.port_a.borrow().get_value();
+            }
+            self.set_interrupt(Interrupt::Ca1);
+        }
+        self.ca1 = value;
+    }
+
+    pub fn set_cb1(&mut self, value: bool) {
+        let positive = self.pcr.get_bit(4);
+        if self.cb1 != value && value == positive {
+            self.set_interrupt(Interrupt::Cb1);
+        }
+        self.cb1 = value;
+    }
+
+    fn clear_interrupt(&mut self, mask: u8) {
+        self.ifr &= !mask;
+        self.update_irq();
+    }
+
+    fn set_interrupt(&mut self, interrupt: Interrupt) {
+        self.ifr |= interrupt.mask();
+        self.update_irq();
+    }
+
+    fn update_irq(&mut self) {
+        let active = self.ifr & self.ier & 0x7f != 0;
+        self.irq_line.borrow_mut().set_low(self.irq_source, active);
+    }
+
+    fn read_port_a(&self) -> u8 {
+        if self.acr.get_bit(0) {
+            let port = self.port_a.borrow();
+            (port.get_value() & port.get_direction()) | (self.port_a_latch & !port.get_direction())
+        } else {
+            self.port_a.borrow().get_value()
+        }
+    }
+
+    fn read_port_b(&self) -> u8 {
+        let mut result = self.port_b.borrow().get_value();
+        if self.acr.get_bit(7) {
+            result.set_bit(7, self.t1_pb7);
+        }
+        result
+    }
+
+    fn clock_timer_1(&mut self) {
+        if self.t1_reload {
+            self.t1_counter = self.t1_latch;
+            self.t1_reload = false;
+            return;
+        }
+        self.t1_counter = self.t1_counter.wrapping_sub(1);
+        if self.t1_counter == 0xffff && self.t1_armed {
+            self.set_interrupt(Interrupt::Timer1);
+            if self.acr.get_bit(6) {
+                self.t1_reload = true;
+                self.t1_pb7 = !self.t1_pb7;
+            } else {
+                self.t1_armed = false;
+                self.t1_pb7 = true;
+            }
+        }
+    }
+
+    fn clock_timer_2(&mut self) {
+        // Pulse counting mode on PB6 is not supported
+        if self.acr.get_bit(5) {
+            return;
+        }
+        self.t2_counter = self.t2_counter.wrapping_sub(1);
+        if self.t2_counter == 0xffff && self.t2_armed {
+            self.t2_armed = false;
+            self.set_interrupt(Interrupt::Timer2);
+        }
+    }
+
+    fn is_ca2_independent(&self) -> bool {
+        self.pcr & 0x0a == 0x02
+    }
+
+    fn is_cb2_independent(&self) -> bool {
+        self.pcr & 0xa0 == 0x20
+    }
+}
+
+impl Chip for Via {
+    fn clock(&mut self) {
+        self.clock_timer_1();
+        self.clock_timer_2();
+    }
+
+    fn clock_delta(&mut self, delta: u32) {
+        for _i in 0..delta {
+            self.clock();
+        }
+    }
+
+    fn process_vsync(&mut self) {}
+
+    fn reset(&mut self) {
+        /*
+        The reset input clears all internal registers to logic 0 (except T1 and T2 latches
+        and counters and the Shift Register). This places all peripheral interface lines
+        in the input state, disables the timers, shift register, etc. and disables interrupting
+        from the chip.
+        */
+        self.acr = 0;
+        self.pcr = 0;
+        self.ifr = 0;
+        self.ier = 0;
+        self.port_a_latch = 0xff;
+        self.t1_armed = false;
+        self.t1_reload = false;
+        self.t1_pb7 = true;
+        self.t2_armed = false;
+        self.port_a.borrow_mut().reset();
+        self.port_b.borrow_mut().reset();
+        self.update_irq();
+    }
+
+    // I/O
+
+    fn read(&mut self, reg: u8) -> u8 {
+        let value = match reg {
+            reg::ORB => {
+                let mut mask = Interrupt::Cb1.mask();
+                if !self.is_cb2_independent() {
+                    mask |= Interrupt::Cb2.mask();
+                }
+                self.clear_interrupt(mask);
+                self.read_port_b()
+            }
+            reg::ORA => {
+                let mut mask = Interrupt::Ca1.mask();
+                if !self.is_ca2_independent() {
+                    mask |= Interrupt::Ca2.mask();
+                }
+                self.clear_interrupt(mask);
+                self.read_port_a()
+            }
+            reg::DDRB => self.port_b.borrow().get_direction(),
+            reg::DDRA => self.port_a.borrow().get_direction(),
+            reg::T1CL => {
+                self.clear_interrupt(Interrupt::Timer1.mask());
+                self.t1_counter as u8
+            }
+            reg::T1CH => (self.t1_counter >> 8) as u8,
+            reg::T1LL => self.t1_latch as u8,
+            reg::T1LH => (self.t1_latch >> 8) as u8,
+            reg::T2CL => {
+                self.clear_interrupt(Interrupt::Timer2.mask());
+                self.t2_counter as u8
+            }
+            reg::T2CH => (self.t2_counter >> 8) as u8,
+            reg::SR => self.sr,
+            reg::ACR => self.acr,
+            reg::PCR => self.pcr,
+            reg::IFR => {
+                let mut result = self.ifr;
+                result.set_bit(7, self.ifr & self.ier & 0x7f != 0);
+                result
+            }
+            reg::IER => self.ier | 0x80,
+            reg::ORA_NH => self.read_port_a(),
+            _ => panic!("invalid reg {}", reg),
+        };
+        if log_enabled!(LogLevel::Trace) {
+            trace!(target: "via::reg", "Read 0x{:02x} = 0x{:02x}", reg, value);
+        }
+        value
+    }
+
+    fn write(&mut self, reg: u8, value: u8) {
+        if log_enabled!(LogLevel::Trace) {
+            trace!(target: "via::reg", "Write 0x{:02x} = 0x{:02x}", reg, value);
+        }
+        match reg {
+            reg::ORB => {
+                let mut mask = Interrupt::Cb1.mask();
+                if !self.is_cb2_independent() {
+                    mask |= Interrupt::Cb2.mask();
+                }
+                self.clear_interrupt(mask);
+                self.port_b.borrow_mut().set_value(value);
+            }
+            reg::ORA => {
+                let mut mask = Interrupt::Ca1.mask();
+                if !self.is_ca2_independent() {
+                    mask |= Interrupt::Ca2.mask();
+                }
+                self.clear_interrupt(mask);
+                self.port_a.borrow_mut().set_value(value);
+            }
+            reg::DDRB => {
+                self.port_b.borrow_mut().set_direction(value);
+            }
+            reg::DDRA => {
+                self.port_a.borrow_mut().set_direction(value);
+            }
+            reg::T1CL | reg::T1LL => {
+                self.t1_latch = (self.t1_latch & 0xff00) | u16::from(value);
+            }
+            reg::T1CH => {
+                self.t1_latch = (u16::from(value) << 8) | (self.t1_latch & 0x00ff);
+                self.t1_counter = self.t1_latch;
+                self.t1_armed = true;
+                self.t1_reload = false;
+                self.t1_pb7 = false;
+                self.clear_interrupt(Interrupt::Timer1.mask());
+            }
+            reg::T1LH => {
+                self.t1_latch = (u16::from(value) << 8) | (self.t1_latch & 0x00ff);
+                self.clear_interrupt(Interrupt::Timer1.mask());
+            }
+            reg::T2CL => {
+                self.t2_latch_lo = value;
+            }
+            reg::T2CH => {
+                self.t2_counter = (u16::from(value) << 8) | u16::from(self.t2_latch_lo);
+                self.t2_armed = true;
+                self.clear_interrupt(Interrupt::Timer2.mask());
+            }
+            reg::SR => {
+                self.sr = value;
+            }
+            reg::ACR => {
+                self.acr = value;
+            }
+            reg::PCR => {
+                self.pcr = value;
+            }
+            reg::IFR => {
+                self.clear_interrupt(value & 0x7f);
+            }
+            reg::IER => {
+                if value.get_bit(7) {
+                    self.ier |= value & 0x7f;
+                } else {
+                    self.ier &= !value & 0x7f;
+                }
+                self.update_irq();
+            }
+            reg::ORA_NH => {
+                self.port_a.borrow_mut().set_value(value);
+            }
+            _ => panic!("invalid reg {}", reg),
+        }
+    }
+
+    // State
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_tag(b"VIA0");
+        state.write_u8(self.acr);
+        state.write_u8(self.pcr);
+        state.write_u8(self.ifr);
+        state.write_u8(self.ier);
+        state.write_u8(self.sr);
+        state.write_bool(self.ca1);
+        state.write_bool(self.cb1);
+        state.write_u8(self.port_a_latch);
+        state.write_u16(self.t1_counter);
+        state.write_u16(self.t1_latch);
+        state.write_bool(self.t1_armed);
+        state.write_bool(self.t1_reload);
+        state.write_bool(self.t1_pb7);
+        state.write_u16(self.t2_counter);
+        state.write_u8(self.t2_latch_lo);
+        state.write_bool(self.t2_armed);
+        self.port_a.borrow().save_state(state);
+        self.port_b.borrow().save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.read_tag(b"VIA0")?;
+        self.acr = state.read_u8()?;
+        self.pcr = state.read_u8()?;
+        self.ifr = state.read_u8()?;
+        self.ier = state.read_u8()?;
+        self.sr = state.read_u8()?;
+        self.ca1 = state.read_bool()?;
+        self.cb1 = state.read_bool()?;
+        self.port_a_latch = state.read_u8()?;
+        self.t1_counter = state.read_u16()?;
+        self.t1_latch = state.read_u16()?;
+        self.t1_armed = state.read_bool()?;
+        self.t1_reload = state.read_bool()?;
+        self.t1_pb7 = state.read_bool()?;
+        self.t2_counter = state.read_u16()?;
+        self.t2_latch_lo = state.read_u8()?;
+        self.t2_armed = state.read_bool()?;
+        self.port_a.borrow_mut().load_state(state)?;
+        self.port_b.borrow_mut().load_state(state)?;
+        self.update_irq();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::new_shared;
+
+    fn setup_via() -> (Via, Shared<IrqLine>) {
+        let irq_line = new_shared(IrqLine::new("irq"));
+        let port_a = new_shared(IoPort::new(0x00, 0xff));
+        let port_b = new_shared(IoPort::new(0x00, 0xff));
+        let mut via = Via::new(port_a, port_b, irq_line.clone(), 0);
+        via.reset();
+        (via, irq_line)
+    }
+
+    #[test]
+    fn timer_1_one_shot() {
+        let (mut via, irq_line) = setup_via();
+        via.write(reg::IER, 0xc0);
+        via.write(reg::T1CL, 0x02);
+        via.write(reg::T1CH, 0x00);
+        via.clock_delta(2);
+        assert!(!irq_line.borrow().is_low());
+        via.clock();
+        assert!(irq_line.borrow().is_low());
+        assert_eq!(0xc0, via.read(reg::IFR));
+        via.read(reg::T1CL);
+        assert!(!irq_line.borrow().is_low());
+        via.clock_delta(0x10000);
+        assert!(!irq_line.borrow().is_low());
+    }
+
+    #[test]
+    fn timer_1_free_run() {
+        let (mut via, irq_line) = setup_via();
+        via.write(reg::ACR, 0x40);
+        via.write(reg::IER, 0xc0);
+        via.write(reg::T1CL, 0x02);
+        via.write(reg::T1CH, 0x00);
+        via.clock_delta(3);
+        assert!(irq_line.borrow().is_low());
+        via.read(reg::T1CL);
+        // Period is latch + 2 cycles
+        via.clock_delta(3);
+        assert!(!irq_line.borrow().is_low());
+        via.clock();
+        assert!(irq_line.borrow().is_low());
+    }
+
+    #[test]
+    fn timer_2_one_shot() {
+        let (mut via, irq_line) = setup_via();
+        via.write(reg::IER, 0xa0);
+        via.write(reg::T2CL, 0x01);
+        via.write(reg::T2CH, 0x00);
+        via.clock_delta(2);
+        assert!(irq_line.borrow().is_low());
+        assert_eq!(0xa0, via.read(reg::IFR));
+    }
+
+    #[test]
+    fn ca1_positive_edge() {
+        let (mut via, irq_line) = setup_via();
+        via.write(reg::PCR, 0x01);
+        via.write(reg::IER, 0x82);
+        via.set_ca1(false);
+        assert!(!irq_line.borrow().is_low());
+        via.set_ca1(true);
+        assert!(irq_line.borrow().is_low());
+        via.read(reg::ORA);
+        assert!(!irq_line.borrow().is_low());
+    }
+
+    #[test]
+    fn ier_set_and_clear() {
+        let (mut via, _) = setup_via();
+        via.write(reg::IER, 0x86);
+        assert_eq!(0x86, via.read(reg::IER));
+        via.write(reg::IER, 0x02);
+        assert_eq!(0x84, via.read(reg::IER));
+    }
+
+    #[test]
+    fn manual_control_outputs() {
+        let (mut via, _) = setup_via();
+        via.write(reg::PCR, 0xee);
+        assert!(via.get_ca2());
+        assert!(via.get_cb2());
+        via.write(reg::PCR, 0xcc);
+        assert!(!via.get_ca2());
+        assert!(!via.get_cb2());
+    }
+}
@@ -2,11 +2,15 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+
 use crate::factory::AddressableFaded;
-use crate::util::{IoPort, Shared};
+use crate::util::{IoPort, IrqLine, Shared, StateReader, StateWriter};
 use bit_field::BitField;
 
 use crate::device::cartridge::Cartridge;
+use crate::device::Reu;
 
 // DEFERRED device: expansion port test cases
 
@@ -22,17 +26,36 @@ impl IoLine {
     }
 }
 
+#[derive(Copy, Clone)]
+enum IrqSource {
+    Cartridge = 4,
+}
+
+impl IrqSource {
+    pub fn value(self) -> usize {
+        self as usize
+    }
+}
+
+// Design:
+//   The REU decodes I/O 2 at $DF00-$DFFF and takes precedence over the cartridge there.
+//   Register access is skipped while the REU is borrowed for its own DMA transfer.
+
 pub struct ExpansionPort {
     cartridge: Option<Cartridge>,
+    reu: Option<Shared<Reu>>,
     // I/O
     io_line: Shared<IoPort>,
+    nmi_line: Shared<IrqLine>,
 }
 
 impl ExpansionPort {
-    pub fn new(io_line: Shared<IoPort>) -> Self {
+    pub fn new(io_line: Shared<IoPort>, nmi_line: Shared<IrqLine>) -> Self {
         Self {
             cartridge: None,
+            reu: None,
             io_line,
+            nmi_line,
         }
     }
 
@@ -45,6 +68,7 @@ impl ExpansionPort {
             io_line_clone.borrow_mut().set_value(io_value);
         })));
         self.cartridge = Some(cartridge);
+        self.update_nmi();
     }
 
     pub fn detach(&mut self) {
@@ -52,9 +76,44 @@ impl ExpansionPort {
         if let Some(ref mut cartridge) = cartridge {
             cartridge.set_io_observer(None);
         }
+        self.update_nmi();
+    }
+
+    /// Press the freeze button of the attached cartridge. Returns false if there is no
+    /// cartridge with freeze support.
+    pub fn freeze(&mut self) -> bool {
+        let frozen = match self.cartridge {
+            Some(ref mut cartridge) => cartridge.freeze(),
+            None => false,
+        };
+        if frozen {
+            self.update_nmi();
+        }
+        frozen
+    }
+
+    pub fn clock(&mut self) {
+        if let Some(ref mut cartridge) = self.cartridge {
+            cartridge.clock();
+        }
+    }
+
+    pub fn get_cartridge(&self) -> Option<&Cartridge> {
+        self.cartridge.as_ref()
+    }
+
+    pub fn attach_reu(&mut self, reu: Shared<Reu>) {
+        self.reu = Some(reu);
+    }
+
+    pub fn detach_reu(&mut self) -> Option<Shared<Reu>> {
+        self.reu.take()
     }
 
     pub fn reset(&mut self) {
+        if let Some(ref reu) = self.reu {
+            reu.borrow_mut().reset();
+        }
         if let Some(ref mut cartridge) = self.cartridge {
             cartridge.reset();
         } else {
@@ -63,17 +122,92 @@ impl ExpansionPort {
             io_value.set_bit(IoLine::Exrom.value(), true);
             self.io_line.borrow_mut().set_value(io_value);
         }
+        self.update_nmi();
+    }
+
+    fn update_nmi(&mut self) {
+        let asserted = self
+            .cartridge
+            .as_ref()
+            .is_some_and(|cartridge| cartridge.is_nmi_asserted());
+        self.nmi_line
+            .borrow_mut()
+            .set_low(IrqSource::Cartridge.value(), asserted);
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        self.io_line.borrow().save_state(state);
+        if let Some(ref cartridge) = self.cartridge {
+            state.write_bool(true);
+            cartridge.save_state(state);
+        } else {
+            state.write_bool(false);
+        }
+        if let Some(ref reu) = self.reu {
+            state.write_bool(true);
+            reu.borrow().save_state(state);
+        } else {
+            state.write_bool(false);
+        }
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.io_line.borrow_mut().load_state(state)?;
+        let present = state.read_bool()?;
+        match self.cartridge {
+            Some(ref mut cartridge) if present => cartridge.load_state(state)?,
+            None if !present => (),
+            Some(_) => return Err("Snapshot was taken without a cartridge".to_string()),
+            None => return Err("Snapshot requires an attached cartridge".to_string()),
+        }
+        let reu_present = state.read_bool()?;
+        match self.reu {
+            Some(ref reu) if reu_present => reu.borrow_mut().load_state(state),
+            None if !reu_present => Ok(()),
+            Some(_) => Err("Snapshot was taken without a REU".to_string()),
+            None => Err("Snapshot requires a REU".to_string()),
+        }
     }
 }
 
 impl AddressableFaded for ExpansionPort {
     fn read(&mut self, address: u16) -> Option<u8> {
-        self.cartridge.as_mut().and_then(|crt| crt.read(address))
+        match self.reu {
+            Some(ref reu) if (0xdf00..=0xdfff).contains(&address) => {
+                reu.try_borrow_mut().ok().map(|mut reu| reu.read(address))
+            }
+            _ => self.cartridge.as_mut().and_then(|crt| crt.read(address)),
+        }
     }
 
     fn write(&mut self, address: u16, value: u8) {
-        if let Some(ref mut cartridge) = self.cartridge {
-            cartridge.write(address, value)
+        if address == 0xff00 {
+            if let Some(ref reu) = self.reu {
+                if let Ok(mut reu) = reu.try_borrow_mut() {
+                    reu.trigger();
+                }
+            }
+            return;
+        }
+        match self.reu {
+            Some(ref reu) if (0xdf00..=0xdfff).contains(&address) => {
+                if let Ok(mut reu) = reu.try_borrow_mut() {
+                    reu.write(address, value);
+                }
+            }
+            _ => {
+                let released = match self.cartridge {
+                    Some(ref mut cartridge) => {
+                        let asserted = cartridge.is_nmi_asserted();
+                        cartridge.write(address, value);
+                        asserted && !cartridge.is_nmi_asserted()
+                    }
+                    None => false,
+                };
+                if released {
+                    self.update_nmi();
+                }
+            }
         }
     }
 }
@@ -80,11 +80,17 @@ impl Addressable for Memory {
             Bank::Basic => self.ram.borrow_mut().write(address, value),
             Bank::Charset => self.ram.borrow_mut().write(address, value),
             Bank::Kernal => self.ram.borrow_mut().write(address, value),
-            Bank::RomL => self.ram.borrow_mut().write(address, value),
-            Bank::RomH => self.ram.borrow_mut().write(address, value),
+            Bank::RomL | Bank::RomH => {
+                self.ram.borrow_mut().write(address, value);
+                self.expansion_port.borrow_mut().write(address, value);
+            }
             Bank::Io => self.io.write(address, value),
             Bank::Disabled => {}
         }
+        // REU transfers can be armed to start on write to $FF00
+        if address == 0xff00 {
+            self.expansion_port.borrow_mut().write(address, value);
+        }
     }
 }
 
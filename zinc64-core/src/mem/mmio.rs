@@ -2,15 +2,24 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+
 use crate::factory::{AddressableFaded, Chip};
 use crate::util::{Ram, Shared};
 
+// Design:
+//   Extra SIDs decode 32 bytes at their base address, which may be in the SID area, where
+//   they take precedence over the mirrors of the primary SID, or in the I/O 1 and I/O 2 areas,
+//   where they take precedence over the expansion port.
+
 pub struct Mmio {
     cia_1: Shared<dyn Chip>,
     cia_2: Shared<dyn Chip>,
     color_ram: Shared<Ram>,
     expansion_port: Shared<dyn AddressableFaded>,
     sid: Shared<dyn Chip>,
+    extra_sids: Vec<(u16, Shared<dyn Chip>)>,
     vic: Shared<dyn Chip>,
 }
 
@@ -21,6 +30,7 @@ impl Mmio {
         color_ram: Shared<Ram>,
         expansion_port: Shared<dyn AddressableFaded>,
         sid: Shared<dyn Chip>,
+        extra_sids: Vec<(u16, Shared<dyn Chip>)>,
         vic: Shared<dyn Chip>,
     ) -> Self {
         Self {
@@ -29,18 +39,35 @@ impl Mmio {
             color_ram,
             expansion_port,
             sid,
+            extra_sids,
             vic,
         }
     }
 
+    /// Find extra SID decoding the 32 byte block of the specified address.
+    fn find_sid(&self, address: u16) -> Option<&Shared<dyn Chip>> {
+        let base_address = address & 0xffe0;
+        self.extra_sids
+            .iter()
+            .find(|(sid_address, _)| *sid_address == base_address)
+            .map(|(_, sid)| sid)
+    }
+
     pub fn read(&self, address: u16) -> u8 {
         match address {
             0xd000..=0xd3ff => self.vic.borrow_mut().read((address & 0x003f) as u8),
-            0xd400..=0xd7ff => self.sid.borrow_mut().read((address & 0x001f) as u8),
+            0xd400..=0xd7ff => self
+                .find_sid(address)
+                .unwrap_or(&self.sid)
+                .borrow_mut()
+                .read((address & 0x001f) as u8),
             0xd800..=0xdbff => self.color_ram.borrow().read(address - 0xd800),
             0xdc00..=0xdcff => self.cia_1.borrow_mut().read((address & 0x000f) as u8),
             0xdd00..=0xddff => self.cia_2.borrow_mut().read((address & 0x000f) as u8),
-            0xde00..=0xdfff => self.expansion_port.borrow_mut().read(address).unwrap_or(0),
+            0xde00..=0xdfff => match self.find_sid(address) {
+                Some(sid) => sid.borrow_mut().read((address & 0x001f) as u8),
+                None => self.expansion_port.borrow_mut().read(address).unwrap_or(0),
+            },
             _ => panic!("invalid address 0x{:x}", address),
         }
     }
@@ -48,7 +75,11 @@ impl Mmio {
     pub fn write(&mut self, address: u16, value: u8) {
         match address {
             0xd000..=0xd3ff => self.vic.borrow_mut().write((address & 0x003f) as u8, value),
-            0xd400..=0xd7ff => self.sid.borrow_mut().write((address & 0x001f) as u8, value),
+            0xd400..=0xd7ff => self
+                .find_sid(address)
+                .unwrap_or(&self.sid)
+                .borrow_mut()
+                .write((address & 0x001f) as u8, value),
             0xd800..=0xdbff => self.color_ram.borrow_mut().write(address - 0xd800, value),
             0xdc00..=0xdcff => self
                 .cia_1
@@ -58,7 +89,10 @@ impl Mmio {
                 .cia_2
                 .borrow_mut()
                 .write((address & 0x000f) as u8, value),
-            0xde00..=0xdfff => self.expansion_port.borrow_mut().write(address, value),
+            0xde00..=0xdfff => match self.find_sid(address) {
+                Some(sid) => sid.borrow_mut().write((address & 0x001f) as u8, value),
+                None => self.expansion_port.borrow_mut().write(address, value),
+            },
             _ => panic!("invalid address 0x{:x}", address),
         }
     }
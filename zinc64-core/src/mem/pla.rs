@@ -5,6 +5,7 @@
 // SPEC: https://www.c64-wiki.com/index.php/Bank_Switching
 
 use crate::factory::{Bank, Mmu};
+use crate::util::{StateReader, StateWriter};
 #[cfg(not(feature = "std"))]
 use alloc::prelude::*;
 use log::LogLevel;
@@ -40,6 +41,7 @@ impl Mode {
 pub struct Pla {
     map: MemoryMap,
     mode: Mode,
+    mode_id: u8,
 }
 
 impl Pla {
@@ -49,8 +51,22 @@ impl Pla {
         Pla {
             map,
             mode: configuration,
+            mode_id: 0,
         }
     }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.mode_id);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        let mode = state.read_u8()?;
+        if mode >= 32 {
+            return Err(format!("Invalid bank mode {}", mode));
+        }
+        self.switch_banks(mode);
+        Ok(())
+    }
 }
 
 impl Mmu for Pla {
@@ -64,6 +80,7 @@ impl Mmu for Pla {
             trace!(target: "mem::banks", "Switching to {}", mode);
         }
         self.mode = self.map.get(mode);
+        self.mode_id = mode;
     }
 }
 
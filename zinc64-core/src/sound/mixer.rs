@@ -0,0 +1,140 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+#[cfg(not(feature = "std"))]
+use alloc::sync::Arc;
+#[cfg(not(feature = "std"))]
+use alloc::vec;
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+use crate::factory::SoundOutput;
+use crate::util::Shared;
+
+// Design:
+//   With more than one SID each chip writes to its own mixer channel. SIDs generate samples
+//   lazily when their registers are accessed and on v-sync, so channels fill at different
+//   times. Once all chips are synced up the mixer interleaves the samples into frames with
+//   one sample per chip and passes them to the sound output.
+
+pub struct SoundMixer {
+    // I/O
+    output: Arc<dyn SoundOutput>,
+    // Runtime state
+    channels: Vec<Vec<i16>>,
+    frames: Vec<i16>,
+}
+
+impl SoundMixer {
+    pub fn new(channels: usize, output: Arc<dyn SoundOutput>) -> Self {
+        SoundMixer {
+            output,
+            channels: vec![Vec::new(); channels],
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn get_channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Write frames made from samples available on all channels to the sound output.
+    pub fn flush(&mut self) {
+        let count = self
+            .channels
+            .iter()
+            .map(|channel| channel.len())
+            .min()
+            .unwrap_or(0);
+        if count == 0 {
+            return;
+        }
+        self.frames.clear();
+        for i in 0..count {
+            for channel in self.channels.iter() {
+                self.frames.push(channel[i]);
+            }
+        }
+        for channel in self.channels.iter_mut() {
+            channel.drain(..count);
+        }
+        self.output.write_frames(&self.frames, self.channels.len());
+    }
+
+    pub fn reset(&mut self) {
+        for channel in self.channels.iter_mut() {
+            channel.clear();
+        }
+        self.output.reset();
+    }
+
+    fn write(&mut self, channel: usize, samples: &[i16]) {
+        self.channels[channel].extend_from_slice(samples);
+    }
+}
+
+/// Sound output of a single SID feeding one of the mixer channels.
+pub struct MixerChannel {
+    mixer: Shared<SoundMixer>,
+    channel: usize,
+}
+
+impl MixerChannel {
+    pub fn new(mixer: Shared<SoundMixer>, channel: usize) -> Self {
+        MixerChannel { mixer, channel }
+    }
+}
+
+impl SoundOutput for MixerChannel {
+    fn reset(&self) {
+        self.mixer.borrow_mut().channels[self.channel].clear();
+    }
+
+    fn write(&self, samples: &[i16]) {
+        self.mixer.borrow_mut().write(self.channel, samples);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::new_shared;
+    use std::sync::Mutex;
+
+    struct FrameBuffer {
+        frames: Mutex<Vec<(Vec<i16>, usize)>>,
+    }
+
+    impl SoundOutput for FrameBuffer {
+        fn reset(&self) {}
+
+        fn write(&self, _samples: &[i16]) {}
+
+        fn write_frames(&self, frames: &[i16], channels: usize) {
+            self.frames
+                .lock()
+                .unwrap()
+                .push((frames.to_vec(), channels));
+        }
+    }
+
+    #[test]
+    fn interleave_channels() {
+        let output = Arc::new(FrameBuffer {
+            frames: Mutex::new(Vec::new()),
+        });
+        let mixer = new_shared(SoundMixer::new(2, output.clone()));
+        let left = MixerChannel::new(mixer.clone(), 0);
+        let right = MixerChannel::new(mixer.clone(), 1);
+        left.write(&[1, 2, 3]);
+        right.write(&[-1, -2]);
+        mixer.borrow_mut().flush();
+        right.write(&[-3]);
+        mixer.borrow_mut().flush();
+        let frames = output.frames.lock().unwrap();
+        assert_eq!(vec![(vec![1, -1, 2, -2], 2), (vec![3, -3], 2)], *frames);
+    }
+}
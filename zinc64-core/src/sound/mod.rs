@@ -2,6 +2,10 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+pub mod mixer;
 pub mod sid;
+pub mod sid_log;
 
-pub use self::sid::Sid;
+pub use self::mixer::{MixerChannel, SoundMixer};
+pub use self::sid::{Sid, SidChannel, VoiceControl};
+pub use self::sid_log::{SidCapture, SidLog, SidLogPlayer, SidWrite};
@@ -4,22 +4,148 @@
 
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
 #[cfg(not(feature = "std"))]
 use alloc::rc::Rc;
 #[cfg(not(feature = "std"))]
 use alloc::sync::Arc;
+#[cfg(not(feature = "std"))]
+use alloc::vec;
 #[cfg(feature = "std")]
 use std::rc::Rc;
 #[cfg(feature = "std")]
 use std::sync::Arc;
 
 use crate::factory::{Chip, SidModel, SoundOutput};
-use crate::util::Clock;
+use crate::util::{new_shared, Clock, Shared, StateReader, StateWriter};
 use log::LogLevel;
 use resid;
 
+// Design:
+//   resid does not expose its voices, so muted voices are silenced in a copy of the chip
+//   that is clocked along with it and used for sound output only. The copy has envelopes
+//   of muted voices held at zero and their gate bits cleared, while the emulated chip
+//   keeps its registers as written, so envelopes, OSC3 and ENV3 are not affected by mute
+//   settings. Mute settings live in a voice control shared with the frontend and are
+//   applied whenever the SID syncs up. Voice taps are rebuilt from the oscillator and
+//   envelope state at a fixed cycle period, with combined waveforms approximated by
+//   ANDing their outputs.
+
 // TODO sound: add sid output sample rate test cases
 
+const REG_CR: [u8; 3] = [0x04, 0x0b, 0x12];
+const REG_RES_FILT: u8 = 0x17;
+const TAP_PERIOD: u32 = 32;
+const TAP_SIZE: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SidChannel {
+    Voice1,
+    Voice2,
+    Voice3,
+    Filter,
+}
+
+impl SidChannel {
+    pub fn from(index: usize) -> Option<SidChannel> {
+        match index {
+            0 => Some(SidChannel::Voice1),
+            1 => Some(SidChannel::Voice2),
+            2 => Some(SidChannel::Voice3),
+            3 => Some(SidChannel::Filter),
+            _ => None,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Mute settings and waveform taps of SID voices shared between the chip and the frontend.
+pub struct VoiceControl {
+    muted: [bool; 4],
+    solo: Option<SidChannel>,
+    taps_enabled: bool,
+    taps: [Vec<i16>; 3],
+}
+
+impl VoiceControl {
+    pub fn new() -> Self {
+        VoiceControl {
+            muted: [false; 4],
+            solo: None,
+            taps_enabled: false,
+            taps: [Vec::new(), Vec::new(), Vec::new()],
+        }
+    }
+
+    pub fn is_muted(&self, channel: SidChannel) -> bool {
+        self.muted[channel.index()]
+    }
+
+    pub fn set_muted(&mut self, channel: SidChannel, muted: bool) {
+        self.muted[channel.index()] = muted;
+    }
+
+    pub fn get_solo(&self) -> Option<SidChannel> {
+        self.solo
+    }
+
+    /// Play only the specified channel, with the filter channel covering all voices
+    /// routed through the filter. Mute settings are kept and apply again once solo is off.
+    pub fn set_solo(&mut self, solo: Option<SidChannel>) {
+        self.solo = solo;
+    }
+
+    /// Check if the voice can be heard given whether it is routed through the filter.
+    pub fn is_audible(&self, voice: usize, filtered: bool) -> bool {
+        match self.solo {
+            Some(SidChannel::Filter) => filtered,
+            Some(channel) => channel.index() == voice,
+            None => {
+                let filter_muted = self.muted[SidChannel::Filter.index()];
+                !(self.muted[voice] || filtered && filter_muted)
+            }
+        }
+    }
+
+    pub fn is_taps_enabled(&self) -> bool {
+        self.taps_enabled
+    }
+
+    pub fn enable_taps(&mut self, enabled: bool) {
+        self.taps_enabled = enabled;
+        if !enabled {
+            for tap in self.taps.iter_mut() {
+                tap.clear();
+            }
+        }
+    }
+
+    /// Return up to `TAP_SIZE` most recent output samples of the voice, oldest first.
+    pub fn get_tap(&self, voice: usize) -> &[i16] {
+        let tap = &self.taps[voice];
+        &tap[tap.len().saturating_sub(TAP_SIZE)..]
+    }
+
+    fn push_taps(&mut self, samples: [i16; 3]) {
+        for (tap, sample) in self.taps.iter_mut().zip(samples.iter()) {
+            if tap.len() >= 2 * TAP_SIZE {
+                tap.drain(..TAP_SIZE);
+            }
+            tap.push(*sample);
+        }
+    }
+}
+
+impl Default for VoiceControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum SamplingMethod {
     Fast,
@@ -32,11 +158,16 @@ pub struct Sid {
     // Dependencies
     system_clock: Rc<Clock>,
     sound_buffer: Arc<dyn SoundOutput>,
+    voice_control: Shared<VoiceControl>,
     // Functional Units
     resid: resid::Sid,
+    output: Option<resid::Sid>,
     // Runtime State
     buffer: [i16; 8192],
     cycles: u64,
+    registers: [u8; 0x20],
+    audible: [bool; 3],
+    tap_cycles: u32,
 }
 
 impl Sid {
@@ -54,12 +185,41 @@ impl Sid {
         Sid {
             system_clock,
             sound_buffer,
+            voice_control: new_shared(VoiceControl::new()),
             resid,
+            output: None,
             buffer: [0i16; 8192],
             cycles: 0,
+            registers: [0; 0x20],
+            audible: [true; 3],
+            tap_cycles: 0,
         }
     }
 
+    pub fn get_voice_control(&self) -> Shared<VoiceControl> {
+        self.voice_control.clone()
+    }
+
+    /// Use voice control shared with the frontend for mute settings and voice taps.
+    pub fn set_voice_control(&mut self, voice_control: Shared<VoiceControl>) {
+        self.voice_control = voice_control;
+        self.apply_mute();
+    }
+
+    pub fn set_voice_muted(&mut self, channel: SidChannel, muted: bool) {
+        self.voice_control.borrow_mut().set_muted(channel, muted);
+        self.apply_mute();
+    }
+
+    pub fn set_solo(&mut self, solo: Option<SidChannel>) {
+        self.voice_control.borrow_mut().set_solo(solo);
+        self.apply_mute();
+    }
+
+    pub fn enable_voice_taps(&mut self, enabled: bool) {
+        self.voice_control.borrow_mut().enable_taps(enabled);
+    }
+
     pub fn enable_filter(&mut self, enabled: bool) {
         self.resid.enable_filter(enabled);
     }
@@ -85,23 +245,160 @@ impl Sid {
             let delta = (self.system_clock.get() - self.cycles) as u32;
             self.clock_delta(delta);
         }
+        self.apply_mute();
+    }
+
+    fn apply_mute(&mut self) {
+        let audible = self.get_audible();
+        if audible != self.audible {
+            self.audible = audible;
+            self.update_output();
+        }
+    }
+
+    /// Rebuild the copy of the chip used for sound output from the current chip state.
+    fn update_output(&mut self) {
+        self.output = if self.audible.iter().all(|audible| *audible) {
+            None
+        } else {
+            let mut output = self.resid.clone();
+            let mut state = output.read_state();
+            for voice in (0..3).filter(|voice| !self.audible[*voice]) {
+                state.sid_register[REG_CR[voice] as usize] &= 0xfe;
+                state.envelope_state[voice] = 2;
+                state.envelope_counter[voice] = 0;
+                state.hold_zero[voice] = 1;
+            }
+            output.write_state(&state);
+            Some(output)
+        };
+    }
+
+    fn get_audible(&self) -> [bool; 3] {
+        let voice_control = self.voice_control.borrow();
+        [
+            voice_control.is_audible(0, self.is_filtered(0)),
+            voice_control.is_audible(1, self.is_filtered(1)),
+            voice_control.is_audible(2, self.is_filtered(2)),
+        ]
+    }
+
+    /// Get register value written to the output copy, which keeps gates of muted voices off.
+    fn output_value(&self, reg: u8, value: u8) -> u8 {
+        match reg {
+            0x04 | 0x0b | 0x12 if !self.audible[(reg / 7) as usize] => value & 0xfe,
+            _ => value,
+        }
+    }
+
+    fn is_filtered(&self, voice: usize) -> bool {
+        self.registers[REG_RES_FILT as usize] & (1 << voice) != 0
+    }
+
+    fn generate(&mut self, delta: u32) {
+        let mut delta = delta;
+        while delta > 0 {
+            let (samples, next_delta) = self.resid.sample(delta, &mut self.buffer[..], 1);
+            // The copy starts from the same sampler state, so it produces as many samples
+            if let Some(ref mut output) = self.output {
+                output.sample(delta, &mut self.buffer[..], 1);
+            }
+            self.sound_buffer.write(&self.buffer[0..samples]);
+            delta = next_delta;
+        }
+    }
+
+    fn tap_voices(&mut self) {
+        let state = self.resid.read_state();
+        let mut samples = [0i16; 3];
+        for (voice, sample) in samples.iter_mut().enumerate() {
+            let base = voice * 7;
+            let pulse_width = (u16::from(self.registers[base + 3] & 0x0f) << 8)
+                | u16::from(self.registers[base + 2]);
+            let wave = wave_output(
+                self.registers[base + 4],
+                pulse_width,
+                state.accumulator[voice],
+                state.accumulator[(voice + 2) % 3],
+                state.shift_register[voice],
+            );
+            let envelope = i32::from(state.envelope_counter[voice]);
+            *sample = (((i32::from(wave) - 0x800) * envelope) >> 4) as i16;
+        }
+        self.voice_control.borrow_mut().push_taps(samples);
+    }
+}
+
+/// Compute the 12-bit waveform output of a voice from its oscillator state.
+fn wave_output(
+    control: u8,
+    pulse_width: u16,
+    accumulator: u32,
+    ring_accumulator: u32,
+    shift_register: u32,
+) -> u16 {
+    let mut output = 0x0fffu16;
+    let mut selected = false;
+    if control & 0x10 != 0 {
+        let ring_msb = control & 0x04 != 0 && ring_accumulator & 0x80_0000 != 0;
+        let msb = (accumulator & 0x80_0000 != 0) ^ ring_msb;
+        let value = if msb { !accumulator } else { accumulator };
+        output &= ((value >> 11) & 0x0fff) as u16;
+        selected = true;
+    }
+    if control & 0x20 != 0 {
+        output &= (accumulator >> 12) as u16;
+        selected = true;
+    }
+    if control & 0x40 != 0 {
+        let high = control & 0x08 != 0 || (accumulator >> 12) as u16 >= pulse_width;
+        output &= if high { 0x0fff } else { 0x0000 };
+        selected = true;
+    }
+    if control & 0x80 != 0 {
+        let sr = shift_register;
+        let value = ((sr & 0x40_0000) >> 11)
+            | ((sr & 0x10_0000) >> 10)
+            | ((sr & 0x01_0000) >> 7)
+            | ((sr & 0x00_2000) >> 5)
+            | ((sr & 0x00_0800) >> 4)
+            | ((sr & 0x00_0080) >> 1)
+            | ((sr & 0x00_0010) << 1)
+            | ((sr & 0x00_0004) << 2);
+        output &= value as u16;
+        selected = true;
+    }
+    if selected {
+        output
+    } else {
+        0
     }
 }
 
 impl Chip for Sid {
     fn clock(&mut self) {
         self.resid.clock();
+        if let Some(ref mut output) = self.output {
+            output.clock();
+        }
         self.cycles = self.cycles.wrapping_add(1);
     }
 
     fn clock_delta(&mut self, delta: u32) {
-        if delta > 0 {
-            let mut delta = delta;
-            while delta > 0 {
-                let (samples, next_delta) = self.resid.sample(delta, &mut self.buffer[..], 1);
-                self.sound_buffer.write(&self.buffer[0..samples]);
-                delta = next_delta;
+        if self.voice_control.borrow().is_taps_enabled() {
+            let mut remaining = delta;
+            while remaining > 0 {
+                let step = remaining.min(TAP_PERIOD - self.tap_cycles);
+                self.generate(step);
+                remaining -= step;
+                self.tap_cycles += step;
+                if self.tap_cycles == TAP_PERIOD {
+                    self.tap_cycles = 0;
+                    self.tap_voices();
+                }
             }
+        } else {
+            self.generate(delta);
         }
         self.cycles = self.cycles.wrapping_add(delta as u64);
     }
@@ -113,6 +410,9 @@ impl Chip for Sid {
     fn reset(&mut self) {
         self.resid.reset();
         self.cycles = self.system_clock.get();
+        self.registers = [0; 0x20];
+        self.audible = self.get_audible();
+        self.update_output();
     }
 
     // I/O
@@ -127,7 +427,77 @@ impl Chip for Sid {
             trace!(target: "sid::reg", "Write 0x{:02x} = 0x{:02x}", reg, value);
         }
         self.sync();
-        self.resid.write(reg, value)
+        self.registers[(reg & 0x1f) as usize] = value;
+        self.resid.write(reg, value);
+        let output_value = self.output_value(reg & 0x1f, value);
+        if let Some(ref mut output) = self.output {
+            output.write(reg, output_value);
+        }
+        // Filter routing decides which voices are muted with the filter channel
+        if reg & 0x1f == REG_RES_FILT {
+            self.apply_mute();
+        }
+    }
+
+    // State
+
+    fn save_state(&self, state: &mut StateWriter) {
+        let resid_state = self.resid.read_state();
+        state.write_tag(b"SID0");
+        state.write_u64(self.cycles);
+        for value in self.registers.iter() {
+            state.write_u8(*value);
+        }
+        state.write_u8(resid_state.bus_value);
+        state.write_u32(resid_state.bus_value_ttl);
+        state.write_i32(resid_state.ext_in);
+        for i in 0..3 {
+            state.write_u32(resid_state.accumulator[i]);
+            state.write_u32(resid_state.shift_register[i]);
+            state.write_u8(resid_state.envelope_state[i]);
+            state.write_u8(resid_state.envelope_counter[i]);
+            state.write_u8(resid_state.exponential_counter[i]);
+            state.write_u8(resid_state.exponential_counter_period[i]);
+            state.write_u8(resid_state.hold_zero[i]);
+            state.write_u16(resid_state.rate_counter[i]);
+            state.write_u16(resid_state.rate_counter_period[i]);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        // resid does not export its state type so start from the current one
+        let mut resid_state = self.resid.read_state();
+        state.read_tag(b"SID0")?;
+        let cycles = state.read_u64()?;
+        for value in self.registers.iter_mut() {
+            *value = state.read_u8()?;
+        }
+        resid_state.bus_value = state.read_u8()?;
+        resid_state.bus_value_ttl = state.read_u32()?;
+        resid_state.ext_in = state.read_i32()?;
+        for i in 0..3 {
+            resid_state.accumulator[i] = state.read_u32()?;
+            resid_state.shift_register[i] = state.read_u32()?;
+            resid_state.envelope_state[i] = state.read_u8()?;
+            if resid_state.envelope_state[i] > 2 {
+                return Err(format!(
+                    "Invalid envelope state {}",
+                    resid_state.envelope_state[i]
+                ));
+            }
+            resid_state.envelope_counter[i] = state.read_u8()?;
+            resid_state.exponential_counter[i] = state.read_u8()?;
+            resid_state.exponential_counter_period[i] = state.read_u8()?;
+            resid_state.hold_zero[i] = state.read_u8()?;
+            resid_state.rate_counter[i] = state.read_u16()?;
+            resid_state.rate_counter_period[i] = state.read_u16()?;
+        }
+        resid_state.sid_register = self.registers;
+        self.resid.write_state(&resid_state);
+        self.audible = self.get_audible();
+        self.update_output();
+        self.cycles = cycles;
+        Ok(())
     }
 }
 
@@ -183,4 +553,116 @@ mod tests {
         assert_eq!(clock.get() * 44100 / 985248, buffer.len() as u64);
     }
     */
+
+    use super::*;
+
+    struct NullOutput;
+
+    impl SoundOutput for NullOutput {
+        fn reset(&self) {}
+        fn write(&self, _samples: &[i16]) {}
+    }
+
+    fn setup_sid(clock: Rc<Clock>) -> Sid {
+        let mut sid = Sid::new(SidModel::Mos6581, clock, Arc::new(NullOutput {}));
+        sid.reset();
+        sid
+    }
+
+    fn resid_register(sid: &Sid, reg: u8) -> u8 {
+        sid.resid.read_state().sid_register[reg as usize]
+    }
+
+    fn output_register(sid: &Sid, reg: u8) -> u8 {
+        sid.output.as_ref().unwrap().read_state().sid_register[reg as usize]
+    }
+
+    fn output_envelope(sid: &Sid, voice: usize) -> u8 {
+        sid.output.as_ref().unwrap().read_state().envelope_counter[voice]
+    }
+
+    #[test]
+    fn mute_voice_silences_output_only() {
+        let clock = Rc::new(Clock::default());
+        let mut sid = setup_sid(clock.clone());
+        sid.write(0x0c, 0x00); // AD2
+        sid.write(0x0d, 0xf0); // SR2
+        sid.write(0x0b, 0x41);
+        clock.tick_delta(1000);
+        sid.set_voice_muted(SidChannel::Voice2, true);
+        assert_eq!(0x41, resid_register(&sid, 0x0b));
+        assert_eq!(0x40, output_register(&sid, 0x0b));
+        assert_eq!(0, output_envelope(&sid, 1));
+        sid.write(0x0b, 0x15);
+        assert_eq!(0x15, resid_register(&sid, 0x0b));
+        assert_eq!(0x14, output_register(&sid, 0x0b));
+        sid.set_voice_muted(SidChannel::Voice2, false);
+        assert!(sid.output.is_none());
+    }
+
+    #[test]
+    fn mute_voice_3_keeps_osc3_and_env3() {
+        let clock = Rc::new(Clock::default());
+        let mut sid = setup_sid(clock.clone());
+        let mut muted = setup_sid(clock.clone());
+        muted.set_voice_muted(SidChannel::Voice3, true);
+        for sid in [&mut sid, &mut muted].iter_mut() {
+            sid.write(0x0f, 0x10); // FREQHI3
+            sid.write(0x14, 0xf0); // SR3
+            sid.write(0x12, 0x21); // CR3
+        }
+        clock.tick_delta(5000);
+        assert_ne!(0, sid.read(0x1c));
+        assert_eq!(sid.read(0x1b), muted.read(0x1b));
+        assert_eq!(sid.read(0x1c), muted.read(0x1c));
+        assert_eq!(0, output_envelope(&muted, 2));
+    }
+
+    #[test]
+    fn solo_filter_mutes_unfiltered_voices() {
+        let mut sid = setup_sid(Rc::new(Clock::default()));
+        sid.write(0x04, 0x11);
+        sid.write(0x0b, 0x11);
+        sid.write(0x17, 0x01);
+        sid.set_voice_muted(SidChannel::Filter, true);
+        assert_eq!(0x10, output_register(&sid, 0x04));
+        assert_eq!(0x11, output_register(&sid, 0x0b));
+        sid.set_solo(Some(SidChannel::Filter));
+        assert_eq!(0x11, output_register(&sid, 0x04));
+        assert_eq!(0x10, output_register(&sid, 0x0b));
+        sid.set_solo(None);
+        assert_eq!(0x10, output_register(&sid, 0x04));
+        assert_eq!(0x11, resid_register(&sid, 0x04));
+    }
+
+    #[test]
+    fn save_state_keeps_written_registers() {
+        let mut sid = setup_sid(Rc::new(Clock::default()));
+        sid.write(0x04, 0x21);
+        sid.set_voice_muted(SidChannel::Voice1, true);
+        let mut state = StateWriter::new();
+        sid.save_state(&mut state);
+        let mut other = setup_sid(Rc::new(Clock::default()));
+        let data = state.into_inner();
+        other.load_state(&mut StateReader::new(&data)).unwrap();
+        assert_eq!(0x21, resid_register(&other, 0x04));
+    }
+
+    #[test]
+    fn voice_taps_follow_waveform() {
+        let clock = Rc::new(Clock::default());
+        let mut sid = setup_sid(clock.clone());
+        sid.enable_voice_taps(true);
+        sid.write(0x01, 0x10); // FREQHI1
+        sid.write(0x05, 0x00); // AD1
+        sid.write(0x06, 0xf0); // SR1
+        sid.write(0x04, 0x21); // CR1
+        clock.tick_delta(40000);
+        sid.process_vsync();
+        let voice_control = sid.get_voice_control();
+        let voice_control = voice_control.borrow();
+        assert_eq!(TAP_SIZE, voice_control.get_tap(0).len());
+        assert!(voice_control.get_tap(0).iter().any(|sample| *sample != 0));
+        assert!(voice_control.get_tap(1).iter().all(|sample| *sample == 0));
+    }
 }
@@ -0,0 +1,323 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+#[cfg(not(feature = "std"))]
+use alloc::rc::Rc;
+#[cfg(not(feature = "std"))]
+use alloc::vec;
+#[cfg(feature = "std")]
+use std::rc::Rc;
+
+use crate::factory::{Chip, SidModel};
+use crate::util::{Clock, Shared, StateReader, StateWriter};
+use resid;
+
+// Design:
+//   SID chips are wrapped by a capture chip that forwards all accesses and records register
+//   writes with the system clock cycle while capturing is enabled. Writes of all chips go to
+//   one log and are told apart by their address. The player feeds writes of one chip into a
+//   standalone resid instance, clocking it for the cycles elapsed between writes.
+
+// Log format:
+//   Text with one write per line, "<cycle> <address> <value>" with the cycle in decimal and
+//   the address and value in hex, e.g. "19656 d418 0f". Empty lines and lines starting
+//   with '#' are ignored.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SidWrite {
+    pub cycle: u64,
+    pub address: u16,
+    pub value: u8,
+}
+
+pub struct SidLog {
+    capturing: bool,
+    writes: Vec<SidWrite>,
+}
+
+impl SidLog {
+    pub fn new() -> Self {
+        SidLog {
+            capturing: false,
+            writes: Vec::new(),
+        }
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capturing
+    }
+
+    pub fn start(&mut self) {
+        self.writes.clear();
+        self.capturing = true;
+    }
+
+    /// Stop capturing and return the writes captured so far.
+    pub fn stop(&mut self) -> Vec<SidWrite> {
+        self.capturing = false;
+        let mut writes = Vec::new();
+        writes.append(&mut self.writes);
+        writes
+    }
+
+    pub fn record(&mut self, cycle: u64, address: u16, value: u8) {
+        if self.capturing {
+            self.writes.push(SidWrite {
+                cycle,
+                address,
+                value,
+            });
+        }
+    }
+
+    pub fn to_text(writes: &[SidWrite]) -> String {
+        let mut text = String::from("# zinc64 SID log\n");
+        for write in writes {
+            text.push_str(&format!(
+                "{} {:04x} {:02x}\n",
+                write.cycle, write.address, write.value
+            ));
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Vec<SidWrite>, String> {
+        let mut writes = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("Invalid SID log line {}: {}", i + 1, line);
+            let mut tokens = line.split_whitespace();
+            let cycle = tokens.next().and_then(|s| s.parse::<u64>().ok());
+            let address = tokens.next().and_then(|s| u16::from_str_radix(s, 16).ok());
+            let value = tokens.next().and_then(|s| u8::from_str_radix(s, 16).ok());
+            match (cycle, address, value, tokens.next()) {
+                (Some(cycle), Some(address), Some(value), None) => writes.push(SidWrite {
+                    cycle,
+                    address,
+                    value,
+                }),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(writes)
+    }
+}
+
+impl Default for SidLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Chip wrapping a SID that records register writes to the log.
+pub struct SidCapture {
+    // Dependencies
+    chip: Shared<dyn Chip>,
+    system_clock: Rc<Clock>,
+    log: Shared<SidLog>,
+    // Configuration
+    base_address: u16,
+}
+
+impl SidCapture {
+    pub fn new(
+        chip: Shared<dyn Chip>,
+        base_address: u16,
+        system_clock: Rc<Clock>,
+        log: Shared<SidLog>,
+    ) -> Self {
+        SidCapture {
+            chip,
+            system_clock,
+            log,
+            base_address,
+        }
+    }
+}
+
+impl Chip for SidCapture {
+    fn clock(&mut self) {
+        self.chip.borrow_mut().clock();
+    }
+
+    fn clock_delta(&mut self, delta: u32) {
+        self.chip.borrow_mut().clock_delta(delta);
+    }
+
+    fn process_vsync(&mut self) {
+        self.chip.borrow_mut().process_vsync();
+    }
+
+    fn reset(&mut self) {
+        self.chip.borrow_mut().reset();
+    }
+
+    // I/O
+
+    fn read(&mut self, reg: u8) -> u8 {
+        self.chip.borrow_mut().read(reg)
+    }
+
+    fn write(&mut self, reg: u8, value: u8) {
+        self.log.borrow_mut().record(
+            self.system_clock.get(),
+            self.base_address | u16::from(reg & 0x1f),
+            value,
+        );
+        self.chip.borrow_mut().write(reg, value);
+    }
+
+    // State
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.chip.borrow().save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.chip.borrow_mut().load_state(state)
+    }
+}
+
+/// Player rendering logged writes of the SID at the base address with resid.
+pub struct SidLogPlayer {
+    resid: resid::Sid,
+    base_address: u16,
+    buffer: Vec<i16>,
+}
+
+impl SidLogPlayer {
+    pub fn new(chip_model: SidModel, base_address: u16, clock_freq: u32, sample_freq: u32) -> Self {
+        let resid_model = match chip_model {
+            SidModel::Mos6581 => resid::ChipModel::Mos6581,
+            SidModel::Mos8580 => resid::ChipModel::Mos8580,
+        };
+        let mut resid = resid::Sid::new(resid_model);
+        resid.set_sampling_parameters(resid::SamplingMethod::Fast, clock_freq, sample_freq);
+        SidLogPlayer {
+            resid,
+            base_address,
+            buffer: vec![0; 8192],
+        }
+    }
+
+    /// Render samples from the first write until the specified number of cycles after the
+    /// last one.
+    pub fn play(&mut self, writes: &[SidWrite], tail_cycles: u32, output: &mut Vec<i16>) {
+        let mut cycle = match writes.first() {
+            Some(write) => write.cycle,
+            None => return,
+        };
+        for write in writes {
+            if write.address & 0xffe0 != self.base_address {
+                continue;
+            }
+            if write.cycle > cycle {
+                self.render((write.cycle - cycle) as u32, output);
+                cycle = write.cycle;
+            }
+            self.resid.write((write.address & 0x1f) as u8, write.value);
+        }
+        self.render(tail_cycles, output);
+    }
+
+    fn render(&mut self, cycles: u32, output: &mut Vec<i16>) {
+        let mut delta = cycles;
+        while delta > 0 {
+            let (samples, next_delta) = self.resid.sample(delta, &mut self.buffer[..], 1);
+            output.extend_from_slice(&self.buffer[0..samples]);
+            delta = next_delta;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::new_shared;
+
+    struct NullChip;
+
+    impl Chip for NullChip {
+        fn clock(&mut self) {}
+        fn clock_delta(&mut self, _delta: u32) {}
+        fn process_vsync(&mut self) {}
+        fn reset(&mut self) {}
+        fn read(&mut self, _reg: u8) -> u8 {
+            0
+        }
+        fn write(&mut self, _reg: u8, _value: u8) {}
+        fn save_state(&self, _state: &mut StateWriter) {}
+        fn load_state(&mut self, _state: &mut StateReader) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn capture_writes_while_enabled() {
+        let clock = Rc::new(Clock::default());
+        let log = new_shared(SidLog::new());
+        let mut capture =
+            SidCapture::new(new_shared(NullChip {}), 0xd420, clock.clone(), log.clone());
+        capture.write(0x18, 0x0f);
+        log.borrow_mut().start();
+        clock.tick_delta(100);
+        capture.write(0x18, 0x0e);
+        let writes = log.borrow_mut().stop();
+        capture.write(0x18, 0x0d);
+        assert_eq!(
+            vec![SidWrite {
+                cycle: 100,
+                address: 0xd438,
+                value: 0x0e
+            }],
+            writes
+        );
+        assert!(log.borrow_mut().stop().is_empty());
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let writes = vec![
+            SidWrite {
+                cycle: 19656,
+                address: 0xd418,
+                value: 0x0f,
+            },
+            SidWrite {
+                cycle: 19700,
+                address: 0xd401,
+                value: 0xa0,
+            },
+        ];
+        let text = SidLog::to_text(&writes);
+        assert_eq!(writes, SidLog::from_text(&text).unwrap());
+        assert!(SidLog::from_text("19656 d418").is_err());
+    }
+
+    #[test]
+    fn play_renders_elapsed_cycles() {
+        let writes = vec![
+            SidWrite {
+                cycle: 1000,
+                address: 0xd418,
+                value: 0x0f,
+            },
+            SidWrite {
+                cycle: 99_525,
+                address: 0xd418,
+                value: 0x00,
+            },
+        ];
+        let mut player = SidLogPlayer::new(SidModel::Mos6581, 0xd400, 985_248, 44100);
+        let mut output = Vec::new();
+        player.play(&writes, 98_525, &mut output);
+        // 197050 cycles at 985248 Hz is 0.2 s
+        assert!((output.len() as i32 - 8820).abs() <= 1);
+    }
+}
@@ -22,6 +22,10 @@ impl Clock {
         self.counter.set(0);
     }
 
+    pub fn set(&self, value: u64) {
+        self.counter.set(value);
+    }
+
     pub fn tick(&self) {
         let result = self.counter.get().wrapping_add(1);
         self.counter.set(result);
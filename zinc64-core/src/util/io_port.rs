@@ -6,6 +6,8 @@
 use alloc::prelude::*;
 use bit_field::BitField;
 
+use super::{StateReader, StateWriter};
+
 pub type Observer = Box<dyn Fn(u8)>;
 
 // direction - (where 1 is an output, and 0 is an input).
@@ -70,6 +72,20 @@ impl IoPort {
         self.notify_observer();
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.direction);
+        state.write_u8(self.input);
+        state.write_u8(self.output);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.direction = state.read_u8()?;
+        self.input = state.read_u8()?;
+        self.output = state.read_u8()?;
+        self.notify_observer();
+        Ok(())
+    }
+
     fn notify_observer(&self) {
         if let Some(ref observer) = self.observer {
             observer(self.get_value());
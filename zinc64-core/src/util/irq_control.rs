@@ -2,8 +2,12 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
 use bit_field::BitField;
 
+use super::{StateReader, StateWriter};
+
 #[derive(Default)]
 pub struct IrqControl {
     data: u8,
@@ -43,6 +47,17 @@ impl IrqControl {
         self.mask = 0;
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.data);
+        state.write_u8(self.mask);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.data = state.read_u8()?;
+        self.mask = state.read_u8()?;
+        Ok(())
+    }
+
     pub fn set_event(&mut self, bit: usize) {
         self.data.set_bit(bit, true);
     }
@@ -2,7 +2,11 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
 use bit_field::BitField;
+
+use super::{StateReader, StateWriter};
 // use log::LogLevel;
 
 pub struct IrqLine {
@@ -24,6 +28,15 @@ impl IrqLine {
         self.signal = 0;
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.signal);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.signal = state.read_u8()?;
+        Ok(())
+    }
+
     pub fn set_low(&mut self, source: usize, value: bool) {
         /* if log_enabled!(LogLevel::Trace) {
             trace!(
@@ -10,6 +10,7 @@ mod pin;
 mod ram;
 mod rom;
 mod shared;
+mod state;
 
 pub use self::clock::Clock;
 pub use self::io_port::IoPort;
@@ -19,3 +20,4 @@ pub use self::pin::Pin;
 pub use self::ram::Ram;
 pub use self::rom::Rom;
 pub use self::shared::{new_shared, new_shared_cell, Shared, SharedCell};
+pub use self::state::{StateReader, StateWriter};
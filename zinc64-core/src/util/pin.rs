@@ -2,6 +2,11 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+
+use super::{StateReader, StateWriter};
+
 #[derive(Clone, Copy, PartialEq)]
 enum State {
     High,
@@ -52,6 +57,25 @@ impl Pin {
         }
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.state == State::High);
+        state.write_bool(self.last == State::High);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.state = if state.read_bool()? {
+            State::High
+        } else {
+            State::Low
+        };
+        self.last = if state.read_bool()? {
+            State::High
+        } else {
+            State::Low
+        };
+        Ok(())
+    }
+
     fn set(&mut self, state: State) {
         self.last = self.state;
         self.state = state;
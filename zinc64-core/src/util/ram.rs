@@ -7,6 +7,8 @@ use alloc::prelude::*;
 #[cfg(not(feature = "std"))]
 use alloc::vec;
 
+use super::{StateReader, StateWriter};
+
 pub struct Ram {
     data: Vec<u8>,
 }
@@ -39,6 +41,14 @@ impl Ram {
     pub fn write(&mut self, address: u16, value: u8) {
         self.data[address as usize] = value
     }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.data);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.read_bytes(&mut self.data)
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,266 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+
+// Design:
+//   Machine state is written as a flat little-endian byte stream. Each component writes its
+//   fields in a fixed order and reads them back in the same order. Components prefix their
+//   data with a 4-byte tag so that a stream that got out of sync is detected early.
+
+pub struct StateWriter {
+    data: Vec<u8>,
+    skip_expansion_ram: bool,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self {
+            data: Vec::new(),
+            skip_expansion_ram: false,
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            data: Vec::with_capacity(capacity),
+            skip_expansion_ram: false,
+        }
+    }
+
+    /// Leave RAM of memory expansions and cartridge flash out of the state. Rewind snapshots
+    /// use this as they would otherwise hold a copy of up to 16M of expansion RAM each.
+    pub fn skip_expansion_ram(mut self) -> Self {
+        self.skip_expansion_ram = true;
+        self
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn write_tag(&mut self, tag: &[u8; 4]) {
+        self.data.extend_from_slice(tag);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Write a length-prefixed block of bytes.
+    pub fn write_bytes(&mut self, data: &[u8]) {
+        self.write_u32(data.len() as u32);
+        self.data.extend_from_slice(data);
+    }
+
+    /// Write contents of expansion RAM unless it is left out, flagging if they are present.
+    pub fn write_expansion_ram(&mut self, data: &[u8]) {
+        self.write_bool(!self.skip_expansion_ram);
+        if !self.skip_expansion_ram {
+            self.write_bytes(data);
+        }
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn is_eos(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    pub fn read_tag(&mut self, tag: &[u8; 4]) -> Result<(), String> {
+        let value = self.take(4)?;
+        if value == tag {
+            Ok(())
+        } else {
+            Err(format!(
+                "Invalid state section, expected {}",
+                String::from_utf8_lossy(tag)
+            ))
+        }
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(format!("Invalid state value {}", value)),
+        }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        let mut buf = [0u8; 2];
+        buf.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, String> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(i32::from_le_bytes(buf))
+    }
+
+    /// Read a length-prefixed block of bytes into buffer of the same length.
+    pub fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(), String> {
+        let len = self.read_u32()? as usize;
+        if len != buf.len() {
+            return Err(format!(
+                "Invalid state block size {}, expected {}",
+                len,
+                buf.len()
+            ));
+        }
+        buf.copy_from_slice(self.take(len)?);
+        Ok(())
+    }
+
+    /// Read contents of expansion RAM if present, otherwise keep the current contents.
+    pub fn read_expansion_ram(&mut self, buf: &mut [u8]) -> Result<(), String> {
+        if self.read_bool()? {
+            self.read_bytes(buf)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Read a length-prefixed block of bytes of any length.
+    pub fn read_byte_vec(&mut self) -> Result<Vec<u8>, String> {
+        let len = self.read_u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if len <= self.data.len() - self.pos {
+            let data = &self.data[self.pos..self.pos + len];
+            self.pos += len;
+            Ok(data)
+        } else {
+            Err("Unexpected end of state data".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_read_values() {
+        let mut writer = StateWriter::new();
+        writer.write_tag(b"TEST");
+        writer.write_bool(true);
+        writer.write_u8(0x12);
+        writer.write_u16(0x3456);
+        writer.write_u32(0x789a_bcde);
+        writer.write_u64(0x0123_4567_89ab_cdef);
+        writer.write_i32(-2);
+        writer.write_bytes(&[1, 2, 3]);
+        let data = writer.into_inner();
+        let mut reader = StateReader::new(&data);
+        assert_eq!(Ok(()), reader.read_tag(b"TEST"));
+        assert_eq!(Ok(true), reader.read_bool());
+        assert_eq!(Ok(0x12), reader.read_u8());
+        assert_eq!(Ok(0x3456), reader.read_u16());
+        assert_eq!(Ok(0x789a_bcde), reader.read_u32());
+        assert_eq!(Ok(0x0123_4567_89ab_cdef), reader.read_u64());
+        assert_eq!(Ok(-2), reader.read_i32());
+        let mut buf = [0u8; 3];
+        assert_eq!(Ok(()), reader.read_bytes(&mut buf));
+        assert_eq!([1, 2, 3], buf);
+        assert!(reader.is_eos());
+    }
+
+    #[test]
+    fn read_invalid_tag() {
+        let mut reader = StateReader::new(b"CPU0");
+        assert!(reader.read_tag(b"VIC0").is_err());
+    }
+
+    #[test]
+    fn read_past_end() {
+        let mut reader = StateReader::new(&[0x01]);
+        assert!(reader.read_u16().is_err());
+    }
+
+    #[test]
+    fn read_bytes_size_mismatch() {
+        let mut writer = StateWriter::new();
+        writer.write_bytes(&[1, 2]);
+        let data = writer.into_inner();
+        let mut reader = StateReader::new(&data);
+        let mut buf = [0u8; 3];
+        assert!(reader.read_bytes(&mut buf).is_err());
+    }
+
+    #[test]
+    fn skip_expansion_ram_keeps_contents() {
+        let mut writer = StateWriter::new().skip_expansion_ram();
+        writer.write_expansion_ram(&[1, 2]);
+        let data = writer.into_inner();
+        let mut reader = StateReader::new(&data);
+        let mut buf = [3u8; 2];
+        assert_eq!(Ok(()), reader.read_expansion_ram(&mut buf));
+        assert_eq!([3, 3], buf);
+        assert!(reader.is_eos());
+    }
+}
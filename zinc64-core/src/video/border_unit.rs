@@ -4,6 +4,11 @@
 
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::collapsible_if))]
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+
+use crate::util::{StateReader, StateWriter};
+
 pub struct Config {
     pub border_color: u8,
     pub csel: bool,
@@ -55,6 +60,23 @@ impl BorderUnit {
         self.vertical_flop = false;
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.config.border_color);
+        state.write_bool(self.config.csel);
+        state.write_bool(self.config.rsel);
+        state.write_bool(self.main_flop);
+        state.write_bool(self.vertical_flop);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.config.border_color = state.read_u8()?;
+        self.config.csel = state.read_bool()?;
+        self.config.rsel = state.read_bool()?;
+        self.main_flop = state.read_bool()?;
+        self.vertical_flop = state.read_bool()?;
+        Ok(())
+    }
+
     /*
            |   CSEL=0   |   CSEL=1
      ------+------------+-----------
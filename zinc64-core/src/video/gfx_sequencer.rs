@@ -2,8 +2,12 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
 use bit_field::BitField;
 
+use crate::util::{StateReader, StateWriter};
+
 #[derive(Copy, Clone)]
 pub enum Mode {
     // (ECM/BMM/MCM=0/0/0)
@@ -93,6 +97,14 @@ impl GfxSequencer {
     }
 
     pub fn clock(&mut self) {
+        /*
+        Section: 3.7.3.6. Invalid text mode (ECM/BMM/MCM=1/0/1)
+         Setting the ECM and MCM bits simultaneously doesn't select one of the
+         "official" graphics modes of the VIC but creates only black pixels.
+         Nevertheless, the graphics data is still read and used for the sprite
+         collision detection, as in multicolor text mode. The same applies to the
+         two invalid bitmap modes with standard and multicolor bitmap data.
+        */
         if !self.mc_cycle {
             match self.config.mode {
                 Mode::Text => self.output = self.output_text(),
@@ -106,9 +118,15 @@ impl GfxSequencer {
                     self.output = self.output_bitmap_mc()
                 }
                 Mode::EcmText => self.output = self.output_text_ecm(),
-                Mode::InvalidText => self.output = (0, false),
-                Mode::InvalidBitmap1 => self.output = (0, false),
-                Mode::InvalidBitmap2 => self.output = (0, false),
+                Mode::InvalidText => {
+                    self.mc_cycle = self.c_color.get_bit(3);
+                    self.output = (0, self.output_text_mc().1)
+                }
+                Mode::InvalidBitmap1 => self.output = (0, self.output_bitmap().1),
+                Mode::InvalidBitmap2 => {
+                    self.mc_cycle = true;
+                    self.output = (0, self.output_bitmap_mc().1)
+                }
             };
             self.data <<= if !self.mc_cycle { 1 } else { 2 };
         } else {
@@ -134,6 +152,34 @@ impl GfxSequencer {
         self.output = (0, false);
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.config.mode.value());
+        for color in self.config.bg_color.iter() {
+            state.write_u8(*color);
+        }
+        state.write_u8(self.c_data);
+        state.write_u8(self.c_color);
+        state.write_u8(self.g_data);
+        state.write_u8(self.data);
+        state.write_bool(self.mc_cycle);
+        state.write_u8(self.output.0);
+        state.write_bool(self.output.1);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.config.mode = Mode::from(state.read_u8()? & 0x07);
+        for color in self.config.bg_color.iter_mut() {
+            *color = state.read_u8()?;
+        }
+        self.c_data = state.read_u8()?;
+        self.c_color = state.read_u8()?;
+        self.g_data = state.read_u8()?;
+        self.data = state.read_u8()?;
+        self.mc_cycle = state.read_bool()?;
+        self.output = (state.read_u8()?, state.read_bool()?);
+        Ok(())
+    }
+
     /*
      +----+----+----+----+----+----+----+----+
      |  7 |  6 |  5 |  4 |  3 |  2 |  1 |  0 |
@@ -250,3 +296,64 @@ impl GfxSequencer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock_pixels(gfx_seq: &mut GfxSequencer, count: usize) -> Vec<(u8, bool)> {
+        let mut pixels = Vec::new();
+        for _ in 0..count {
+            gfx_seq.clock();
+            pixels.push(gfx_seq.output());
+        }
+        pixels
+    }
+
+    #[test]
+    fn invalid_bitmap_mode_1_outputs_black_foreground() {
+        let mut gfx_seq = GfxSequencer::new();
+        gfx_seq.config.mode = Mode::InvalidBitmap1;
+        gfx_seq.set_data(0x12, 0x03, 0b1010_0000);
+        gfx_seq.load_data();
+        assert_eq!(
+            vec![(0, true), (0, false), (0, true), (0, false)],
+            clock_pixels(&mut gfx_seq, 4)
+        );
+    }
+
+    #[test]
+    fn invalid_bitmap_mode_2_outputs_black_foreground() {
+        let mut gfx_seq = GfxSequencer::new();
+        gfx_seq.config.mode = Mode::InvalidBitmap2;
+        gfx_seq.set_data(0x12, 0x03, 0b0110_1100);
+        gfx_seq.load_data();
+        assert_eq!(
+            vec![
+                (0, false),
+                (0, false),
+                (0, true),
+                (0, true),
+                (0, true),
+                (0, true)
+            ],
+            clock_pixels(&mut gfx_seq, 6)
+        );
+    }
+
+    #[test]
+    fn invalid_text_mode_outputs_black_foreground() {
+        let mut gfx_seq = GfxSequencer::new();
+        gfx_seq.config.mode = Mode::InvalidText;
+        gfx_seq.set_data(0x41, 0x0f, 0b1000_0000);
+        gfx_seq.load_data();
+        assert_eq!(
+            vec![(0, true), (0, true), (0, false), (0, false)],
+            clock_pixels(&mut gfx_seq, 4)
+        );
+        // Hires characters when the MC flag is clear
+        gfx_seq.set_data(0x41, 0x07, 0b0100_0000);
+        gfx_seq.load_data();
+        assert_eq!(vec![(0, false), (0, true)], clock_pixels(&mut gfx_seq, 2));
+    }
+}
@@ -2,8 +2,12 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
 use bit_field::BitField;
 
+use crate::util::{StateReader, StateWriter};
+
 const PRIO_SCREEN_BORDER: u8 = 0;
 const PRIO_FG_SPRITE: u8 = 1;
 const PRIO_FG_GRAPHICS: u8 = 2;
@@ -113,6 +117,31 @@ impl MuxUnit {
         self.output_priority = 0;
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        for priority in self.config.data_priority.iter() {
+            state.write_bool(*priority);
+        }
+        state.write_u8(self.mb_collision);
+        state.write_bool(self.mb_interrupt);
+        state.write_u8(self.mm_collision);
+        state.write_bool(self.mm_interrupt);
+        state.write_u8(self.output);
+        state.write_u8(self.output_priority);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        for priority in self.config.data_priority.iter_mut() {
+            *priority = state.read_bool()?;
+        }
+        self.mb_collision = state.read_u8()?;
+        self.mb_interrupt = state.read_bool()?;
+        self.mm_collision = state.read_u8()?;
+        self.mm_interrupt = state.read_bool()?;
+        self.output = state.read_u8()?;
+        self.output_priority = state.read_u8()?;
+        Ok(())
+    }
+
     fn output_pixel(&mut self, pixel: u8, priority: u8) {
         self.output = pixel;
         self.output_priority = priority;
@@ -9,6 +9,8 @@ pub struct Spec {
     pub raster_lines: u16,
     pub cycles_per_raster: u16,
     pub first_x_coord: u16,
+    pub sprite_fetch_cycle: u16,
+    pub grey_dots: bool,
 }
 
 /*
@@ -26,21 +28,47 @@ pub struct Spec {
  6567R56A |   13   |   40   |  412 ($19c)  | 488 ($1e8) | 388 ($184)
   6567R8  |   13   |   40   |  412 ($19c)  | 489 ($1e9) | 396 ($18c)
    6569   |  300   |   15   |  404 ($194)  | 480 ($1e0) | 380 ($17c)
+
+   The NTSC chips add their extra cycles as idle cycles after the last g-access, so the
+   p-access of sprite 0 moves from cycle 58 to 59 (6567R56A) or 60 (6567R8) while the
+   fetches of sprites 3-7 stay in cycles 1-10. The 6572 (PAL-N) combines the 65 cycle
+   line of the 6567R8 with the 312 lines of the 6569.
+
+   The 85xx chips show a light grey pixel when a color register is written, as the
+   register briefly reads $f while it is being updated.
 */
 
 impl Spec {
     pub fn new(chip_model: VicModel) -> Spec {
         match chip_model {
-            VicModel::Mos6567 => Spec::ntsc(),
+            VicModel::Mos6567R56A => Spec::ntsc_r56a(),
+            VicModel::Mos6567R8 => Spec::ntsc_r8(),
             VicModel::Mos6569 => Spec::pal(),
+            VicModel::Mos6572 => Spec::pal_n(),
+            VicModel::Mos8565 => Spec {
+                grey_dots: true,
+                ..Spec::pal()
+            },
+        }
+    }
+
+    fn ntsc_r56a() -> Spec {
+        Spec {
+            raster_lines: 262,
+            cycles_per_raster: 64,
+            first_x_coord: 0x19c,
+            sprite_fetch_cycle: 59,
+            grey_dots: false,
         }
     }
 
-    fn ntsc() -> Spec {
+    fn ntsc_r8() -> Spec {
         Spec {
             raster_lines: 263,
             cycles_per_raster: 65,
             first_x_coord: 0x19c,
+            sprite_fetch_cycle: 60,
+            grey_dots: false,
         }
     }
 
@@ -49,6 +77,18 @@ impl Spec {
             raster_lines: 312,
             cycles_per_raster: 63,
             first_x_coord: 0x194,
+            sprite_fetch_cycle: 58,
+            grey_dots: false,
+        }
+    }
+
+    fn pal_n() -> Spec {
+        Spec {
+            raster_lines: 312,
+            cycles_per_raster: 65,
+            first_x_coord: 0x19c,
+            sprite_fetch_cycle: 60,
+            grey_dots: false,
         }
     }
 }
@@ -4,8 +4,12 @@
 
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
 use bit_field::BitField;
 
+use crate::util::{StateReader, StateWriter};
+
 #[derive(Copy, Clone, PartialEq)]
 pub enum Mode {
     Standard = 0,
@@ -143,6 +147,52 @@ impl SpriteSequencer {
         self.output = None;
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.config.mode as u8);
+        state.write_u8(self.config.color);
+        state.write_bool(self.config.enabled);
+        state.write_bool(self.config.expand_x);
+        state.write_bool(self.config.expand_y);
+        state.write_u8(self.config.multicolor[0]);
+        state.write_u8(self.config.multicolor[1]);
+        state.write_u16(self.config.x);
+        state.write_u16(self.config.x_screen);
+        state.write_u8(self.config.y);
+        state.write_u32(self.counter);
+        state.write_u32(self.data);
+        state.write_u8(self.delay_cycles);
+        state.write_bool(self.display);
+        state.write_bool(self.expansion_flop);
+        state.write_bool(self.output.is_some());
+        state.write_u8(self.output.unwrap_or(0));
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.config.mode = match state.read_u8()? {
+            0 => Mode::Standard,
+            1 => Mode::Multicolor,
+            value => return Err(format!("Invalid sprite mode {}", value)),
+        };
+        self.config.color = state.read_u8()?;
+        self.config.enabled = state.read_bool()?;
+        self.config.expand_x = state.read_bool()?;
+        self.config.expand_y = state.read_bool()?;
+        self.config.multicolor[0] = state.read_u8()?;
+        self.config.multicolor[1] = state.read_u8()?;
+        self.config.x = state.read_u16()?;
+        self.config.x_screen = state.read_u16()?;
+        self.config.y = state.read_u8()?;
+        self.counter = state.read_u32()?;
+        self.data = state.read_u32()?;
+        self.delay_cycles = state.read_u8()?;
+        self.display = state.read_bool()?;
+        self.expansion_flop = state.read_bool()?;
+        let has_output = state.read_bool()?;
+        let output = state.read_u8()?;
+        self.output = if has_output { Some(output) } else { None };
+        Ok(())
+    }
+
     fn output_pixel(&self) -> Option<u8> {
         if self.data.get_bit(31) {
             Some(self.config.color)
@@ -5,6 +5,10 @@
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::cyclomatic_complexity))]
 
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+
+use crate::device::LightPen;
 use crate::factory::{Chip, VicModel, VideoOutput};
 use crate::util::*;
 use bit_field::BitField;
@@ -45,8 +49,6 @@ X coo. \\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\\
 
 */
 
-// TODO vic: fix ntsc support
-
 #[derive(Copy, Clone)]
 pub enum IrqSource {
     Vic = 2,
@@ -125,6 +127,54 @@ impl RasterUnit {
         }
         self.sprites_on = false;
     }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.display_on);
+        state.write_bool(self.display_state);
+        state.write_bool(self.is_bad_line);
+        state.write_u8(self.rc);
+        state.write_u16(self.vc);
+        state.write_u16(self.vc_base);
+        state.write_u8(self.vmli as u8);
+        state.write_bytes(&self.vm_color_line);
+        state.write_bytes(&self.vm_data_line);
+        // Sprites
+        state.write_bytes(&self.mc);
+        state.write_bytes(&self.mc_base);
+        for dma in self.sprite_dma.iter() {
+            state.write_bool(*dma);
+        }
+        for ptr in self.sprite_ptrs.iter() {
+            state.write_u16(*ptr);
+        }
+        state.write_bool(self.sprites_on);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.display_on = state.read_bool()?;
+        self.display_state = state.read_bool()?;
+        self.is_bad_line = state.read_bool()?;
+        self.rc = state.read_u8()?;
+        self.vc = state.read_u16()?;
+        self.vc_base = state.read_u16()?;
+        self.vmli = state.read_u8()? as usize;
+        if self.vmli > self.vm_data_line.len() {
+            return Err(format!("Invalid vic vmli {}", self.vmli));
+        }
+        state.read_bytes(&mut self.vm_color_line)?;
+        state.read_bytes(&mut self.vm_data_line)?;
+        // Sprites
+        state.read_bytes(&mut self.mc)?;
+        state.read_bytes(&mut self.mc_base)?;
+        for dma in self.sprite_dma.iter_mut() {
+            *dma = state.read_bool()?;
+        }
+        for ptr in self.sprite_ptrs.iter_mut() {
+            *ptr = state.read_u16()?;
+        }
+        self.sprites_on = state.read_bool()?;
+        Ok(())
+    }
 }
 
 pub struct Vic {
@@ -150,14 +200,21 @@ pub struct Vic {
     // Runtime State
     cycle: u16,
     y: u16,
+    lp_triggered: bool,
+    lp_x: u8,
+    lp_y: u8,
+    grey_dot: bool,
+    ba_cycles: u8,
     // I/O
     ba_line: Shared<Pin>,
     irq_line: Shared<IrqLine>,
     frame_buffer: Shared<dyn VideoOutput>,
+    light_pen: Shared<LightPen>,
     vsync_flag: SharedCell<bool>,
 }
 
 impl Vic {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         chip_model: VicModel,
         color_ram: Shared<Ram>,
@@ -166,6 +223,7 @@ impl Vic {
         vsync_flag: SharedCell<bool>,
         ba_line: Shared<Pin>,
         irq_line: Shared<IrqLine>,
+        light_pen: Shared<LightPen>,
     ) -> Vic {
         info!(target: "video", "Initializing VIC");
         let spec = Spec::new(chip_model);
@@ -203,10 +261,16 @@ impl Vic {
             // Runtime State
             cycle: 1,
             y: 0,
+            lp_triggered: false,
+            lp_x: 0,
+            lp_y: 0,
+            grey_dot: false,
+            ba_cycles: 0,
             // I/O
             frame_buffer,
             ba_line,
             irq_line,
+            light_pen,
             vsync_flag,
         }
     }
@@ -239,7 +303,7 @@ impl Vic {
                     self.trigger_irq(2);
                 }
             }
-            let pixel = self.mux_unit.output();
+            let pixel = self.output_pixel();
             self.frame_buffer.borrow_mut().write(pixel_idx, pixel);
             pixel_idx += 1;
         }
@@ -274,7 +338,7 @@ impl Vic {
                     self.trigger_irq(2);
                 }
             }
-            let pixel = self.mux_unit.output();
+            let pixel = self.output_pixel();
             self.frame_buffer.borrow_mut().write(pixel_idx, pixel);
             pixel_idx += 1;
         }
@@ -300,12 +364,36 @@ impl Vic {
                     self.trigger_irq(2);
                 }
             }
-            let pixel = self.mux_unit.output();
+            let pixel = self.output_pixel();
             self.frame_buffer.borrow_mut().write(pixel_idx, pixel);
             pixel_idx += 1;
         }
     }
 
+    fn output_pixel(&mut self) -> u8 {
+        if self.grey_dot {
+            self.grey_dot = false;
+            0x0f
+        } else {
+            self.mux_unit.output()
+        }
+    }
+
+    fn get_raster(&self) -> u16 {
+        /*
+        Section: 3.12. VIC interrupts
+         [..] The test for reaching the interrupt raster line is done in cycle 0 of
+         every line (for line 0, in cycle 1).
+        */
+        // RASTER is cleared one cycle into line 0, so it still reads the last line of the
+        // previous frame while the CPU accesses the VIC in the first cycle of line 0.
+        if self.y == 0 && self.cycle == 1 {
+            self.spec.raster_lines - 1
+        } else {
+            self.y
+        }
+    }
+
     fn map_sprite_to_screen(&self, x: u16) -> u16 {
         match self.spec.first_x_coord {
             0x194 => {
@@ -326,6 +414,14 @@ impl Vic {
         }
     }
 
+    fn map_screen_to_raster_x(&self, x: i32) -> u16 {
+        if x >= 0x64 {
+            (x - 0x64) as u16
+        } else {
+            (x + self.spec.first_x_coord as i32) as u16
+        }
+    }
+
     fn output_sprites(&self) -> [Option<u8>; 8] {
         [
             self.sprite_units[0].output(),
@@ -343,6 +439,42 @@ impl Vic {
         self.ba_line.borrow_mut().set_active(!is_bad_line);
     }
 
+    fn update_ba(&mut self) {
+        /*
+        Section: 3.7.2. VC and RC
+        3. If there is a Bad Line Condition in cycles 12-54, BA is set low and the
+           c-accesses are started. Once started, one c-access is done in the second
+           phase of every clock cycle in the range 15-54.
+        */
+        // For sprites, BA goes low three cycles before the p-access of a sprite with DMA
+        // turned on and stays low until its last s-access. Sprites 0-2 are fetched at the
+        // end of the line, sprites 3-7 at the beginning of the next one. The CPU accesses
+        // memory before the VIC is clocked in the same cycle, so BA is set up for the
+        // cycle that comes next.
+        let cycle = self.cycle;
+        let bad_line = (12..=54).contains(&cycle) && self.raster_unit.is_bad_line;
+        let sprite_dma = (0..8).any(|n| {
+            let p_cycles = if n < 3 {
+                [0, self.spec.sprite_fetch_cycle + 2 * n as u16]
+            } else {
+                let p_cycle = 1 + 2 * (n as u16 - 3);
+                [p_cycle, p_cycle + self.spec.cycles_per_raster]
+            };
+            let dma_on = self.raster_unit.sprite_dma[n]
+                || ((cycle == 55 || cycle == 56) && self.is_sprite_dma_due(n));
+            dma_on
+                && p_cycles
+                    .iter()
+                    .any(|&p_cycle| cycle + 3 >= p_cycle && cycle <= p_cycle + 1)
+        });
+        self.set_ba(bad_line || sprite_dma);
+        self.ba_cycles = if bad_line || sprite_dma {
+            self.ba_cycles.saturating_add(1)
+        } else {
+            0
+        };
+    }
+
     fn trigger_irq(&mut self, source: usize) {
         self.irq_control.set_event(source);
         if self.irq_control.is_triggered() {
@@ -359,6 +491,25 @@ impl Vic {
         }
     }
 
+    fn update_light_pen(&mut self) {
+        /*
+        Section: 3.11. Light pen
+         On a negative edge on the LP input, the current position of the raster beam
+         is latched in the registers LPX ($d013) and LPY ($d014). LPX contains the upper
+         8 bits (of 9) of the X position and LPY the lower 8 bits (also of 9) of the Y
+         position. [..] The light pen input can only be triggered once per frame.
+        */
+        let x_start = ((self.cycle << 3) as i32) - 12;
+        let offset = self.light_pen.borrow().sample(x_start, self.y);
+        if let Some(offset) = offset {
+            let x = self.map_screen_to_raster_x(x_start + offset as i32);
+            self.lp_x = (x >> 1) as u8;
+            self.lp_y = (self.y & 0x00ff) as u8;
+            self.lp_triggered = true;
+            self.trigger_irq(3);
+        }
+    }
+
     fn update_bad_line(&mut self) {
         /*
         Section: 3.5. Bad Lines
@@ -423,13 +574,10 @@ impl Vic {
            set the expansion flip flip is reset.
         */
         for n in 0..8 {
-            let sprite = &mut self.sprite_units[n];
-            if sprite.config.enabled
-                && sprite.config.y == (self.y as u8)
-                && !self.raster_unit.sprite_dma[n]
-            {
+            if self.is_sprite_dma_due(n) {
                 self.raster_unit.sprite_dma[n] = true;
                 self.raster_unit.mc_base[n] = 0;
+                let sprite = &mut self.sprite_units[n];
                 if sprite.config.expand_y {
                     sprite.expansion_flop = false;
                 }
@@ -437,6 +585,13 @@ impl Vic {
         }
     }
 
+    fn is_sprite_dma_due(&self, n: usize) -> bool {
+        let sprite = &self.sprite_units[n];
+        sprite.config.enabled
+            && sprite.config.y == (self.y as u8)
+            && !self.raster_unit.sprite_dma[n]
+    }
+
     fn update_sprite_dma_off(&mut self) {
         /*
         Section: 3.8. Sprites
@@ -473,12 +628,19 @@ impl Vic {
     // -- Memory Ops
 
     fn c_access(&mut self) {
-        if self.raster_unit.is_bad_line {
-            let address = self.video_matrix | self.raster_unit.vc;
-            self.raster_unit.vm_data_line[self.raster_unit.vmli] = self.mem.read(address);
-            self.raster_unit.vm_color_line[self.raster_unit.vmli] =
-                self.color_ram.borrow().read(self.raster_unit.vc) & 0x0f;
-            // DEFERRED vic: memory no access unless ba down for 3 cycles
+        if self.raster_unit.is_bad_line && self.ba_cycles > 0 {
+            // The VIC takes over the bus with AEC three cycles after BA went low, until
+            // then the CPU may still write and the c-accesses read $ff.
+            if self.ba_cycles > 3 {
+                let address = self.video_matrix | self.raster_unit.vc;
+                self.raster_unit.vm_data_line[self.raster_unit.vmli] = self.mem.read(address);
+                self.raster_unit.vm_color_line[self.raster_unit.vmli] =
+                    self.color_ram.borrow().read(self.raster_unit.vc) & 0x0f;
+            } else {
+                // The color nibble comes from the CPU bus, which is not modeled
+                self.raster_unit.vm_data_line[self.raster_unit.vmli] = 0xff;
+                self.raster_unit.vm_color_line[self.raster_unit.vmli] = 0x0f;
+            }
         }
     }
 
@@ -491,7 +653,7 @@ impl Vic {
                         | self.raster_unit.rc as u16;
                     self.mem.read(address)
                 }
-                Mode::EcmText => {
+                Mode::EcmText | Mode::InvalidText => {
                     let address = self.char_base
                         | (((self.raster_unit.vm_data_line[self.raster_unit.vmli] & 0x3f) as u16)
                             << 3)
@@ -504,7 +666,19 @@ impl Vic {
                         | self.raster_unit.rc as u16;
                     self.mem.read(address)
                 }
-                Mode::InvalidText | Mode::InvalidBitmap1 | Mode::InvalidBitmap2 => 0,
+                Mode::InvalidBitmap1 | Mode::InvalidBitmap2 => {
+                    /*
+                    Section: 3.7.3.7. Invalid bitmap mode 1 (ECM/BMM/MCM=1/1/0)
+                     [..] the structure of the graphics data is identical to the one in
+                     standard bitmap mode, but the bits 9 and 10 of the g-addresses are
+                     always zero due to the set ECM bit.
+                    */
+                    let address = (self.char_base & 0x2000
+                        | (self.raster_unit.vc << 3)
+                        | self.raster_unit.rc as u16)
+                        & 0x39ff;
+                    self.mem.read(address)
+                }
             };
             let c_data = self.raster_unit.vm_data_line[self.raster_unit.vmli];
             let c_color = self.raster_unit.vm_color_line[self.raster_unit.vmli];
@@ -516,7 +690,19 @@ impl Vic {
             self.raster_unit.vc += 1;
             self.raster_unit.vmli += 1;
         } else {
-            let g_data = self.mem.read(0x3fff);
+            /*
+            Section: 3.7.3.9. Idle state
+             In idle state, the VIC reads the graphics data from address $3fff (resp.
+             $39ff if the ECM bit is set) and displays it in the selected graphics mode,
+             but with the video matrix data (normally read in the c-accesses) all being
+             "0".
+            */
+            let address = if self.gfx_seq.config.mode.value().get_bit(2) {
+                0x39ff
+            } else {
+                0x3fff
+            };
+            let g_data = self.mem.read(address);
             self.gfx_seq.set_data(0, 0, g_data);
         }
     }
@@ -546,6 +732,9 @@ impl Vic {
 
 impl Chip for Vic {
     fn clock(&mut self) {
+        if !self.lp_triggered {
+            self.update_light_pen();
+        }
         match self.cycle {
             1 => {
                 /*
@@ -563,8 +752,6 @@ impl Chip for Vic {
                 }
                 self.update_display_on();
                 self.update_bad_line();
-                let sprite_dma = self.raster_unit.sprite_dma[3] | self.raster_unit.sprite_dma[4];
-                self.set_ba(sprite_dma);
                 self.p_access(3);
                 if self.raster_unit.sprite_dma[3] {
                     self.s_access(3, 0);
@@ -574,97 +761,61 @@ impl Chip for Vic {
                 if self.y == self.raster_compare && self.y == 0 {
                     self.trigger_irq(0);
                 }
-                let sprite_dma = self.raster_unit.sprite_dma[3]
-                    | self.raster_unit.sprite_dma[4]
-                    | self.raster_unit.sprite_dma[5];
-                self.set_ba(sprite_dma);
                 if self.raster_unit.sprite_dma[3] {
                     self.s_access(3, 1);
                     self.s_access(3, 2);
                 }
             }
             3 => {
-                let sprite_dma = self.raster_unit.sprite_dma[4] | self.raster_unit.sprite_dma[5];
-                self.set_ba(sprite_dma);
                 self.p_access(4);
                 if self.raster_unit.sprite_dma[4] {
                     self.s_access(4, 0);
                 }
             }
             4 => {
-                let sprite_dma = self.raster_unit.sprite_dma[4]
-                    | self.raster_unit.sprite_dma[5]
-                    | self.raster_unit.sprite_dma[6];
-                self.set_ba(sprite_dma);
                 if self.raster_unit.sprite_dma[4] {
                     self.s_access(4, 1);
                     self.s_access(4, 2);
                 }
             }
             5 => {
-                let sprite_dma = self.raster_unit.sprite_dma[5] | self.raster_unit.sprite_dma[6];
-                self.set_ba(sprite_dma);
                 self.p_access(5);
                 if self.raster_unit.sprite_dma[5] {
                     self.s_access(5, 0);
                 }
             }
             6 => {
-                let sprite_dma = self.raster_unit.sprite_dma[5]
-                    | self.raster_unit.sprite_dma[6]
-                    | self.raster_unit.sprite_dma[7];
-                self.set_ba(sprite_dma);
                 if self.raster_unit.sprite_dma[5] {
                     self.s_access(5, 1);
                     self.s_access(5, 2);
                 }
             }
             7 => {
-                let sprite_dma = self.raster_unit.sprite_dma[6] | self.raster_unit.sprite_dma[7];
-                self.set_ba(sprite_dma);
                 self.p_access(6);
                 if self.raster_unit.sprite_dma[6] {
                     self.s_access(6, 0);
                 }
             }
             8 => {
-                let sprite_dma = self.raster_unit.sprite_dma[6] | self.raster_unit.sprite_dma[7];
-                self.set_ba(sprite_dma);
                 if self.raster_unit.sprite_dma[6] {
                     self.s_access(6, 1);
                     self.s_access(6, 2);
                 }
             }
             9 => {
-                let sprite_dma = self.raster_unit.sprite_dma[7];
-                self.set_ba(sprite_dma);
                 self.p_access(7);
                 if self.raster_unit.sprite_dma[7] {
                     self.s_access(7, 0);
                 }
             }
             10 => {
-                let sprite_dma = self.raster_unit.sprite_dma[7];
-                self.set_ba(sprite_dma);
                 if self.raster_unit.sprite_dma[7] {
                     self.s_access(7, 1);
                     self.s_access(7, 2);
                 }
             }
-            11 => {
+            11..=13 => {
                 self.draw_border();
-                self.set_ba(false);
-            }
-            12..=13 => {
-                self.draw_border();
-                /*
-                Section: 3.7.2. VC and RC
-                3. If there is a Bad Line Condition in cycles 12-54, BA is set low and the
-                   c-accesses are started. Once started, one c-access is done in the second
-                   phase of every clock cycle in the range 15-54.
-                */
-                let is_bad_line = self.raster_unit.is_bad_line;
-                self.set_ba(is_bad_line);
             }
             14 => {
                 self.draw_border();
@@ -679,8 +830,6 @@ impl Chip for Vic {
                 if self.raster_unit.is_bad_line {
                     self.raster_unit.rc = 0;
                 }
-                let is_bad_line = self.raster_unit.is_bad_line;
-                self.set_ba(is_bad_line);
             }
             15 => {
                 self.draw_border();
@@ -695,29 +844,21 @@ impl Chip for Vic {
                         self.raster_unit.mc_base[i] = self.raster_unit.mc_base[i].wrapping_add(2);
                     }
                 }
-                let is_bad_line = self.raster_unit.is_bad_line;
-                self.set_ba(is_bad_line);
                 self.c_access();
             }
             16 => {
                 self.draw_border();
                 self.update_sprite_dma_off();
-                let is_bad_line = self.raster_unit.is_bad_line;
-                self.set_ba(is_bad_line);
                 self.g_access();
                 self.c_access();
             }
             17 => {
                 self.draw_cycle_17_56();
-                let is_bad_line = self.raster_unit.is_bad_line;
-                self.set_ba(is_bad_line);
                 self.g_access();
                 self.c_access();
             }
             18..=54 => {
                 self.draw();
-                let is_bad_line = self.raster_unit.is_bad_line;
-                self.set_ba(is_bad_line);
                 self.g_access();
                 self.c_access();
             }
@@ -725,106 +866,59 @@ impl Chip for Vic {
                 self.draw_cycle_17_56();
                 self.update_sprite_dma_on();
                 self.update_sprite_expansion_ff();
-                let sprite_dma = self.raster_unit.sprite_dma[0];
-                self.set_ba(sprite_dma);
                 self.g_access();
             }
             56 => {
                 self.draw_cycle_17_56();
                 self.update_sprite_dma_on();
-                let sprite_dma = self.raster_unit.sprite_dma[0];
-                self.set_ba(sprite_dma);
-            }
-            57 => {
-                self.draw_border();
-                let sprite_dma = self.raster_unit.sprite_dma[0] | self.raster_unit.sprite_dma[1];
-                self.set_ba(sprite_dma);
             }
-            58 => {
-                self.draw_border();
-                /*
-                Section: 3.7.2. VC and RC
-                5. In the first phase of cycle 58, the VIC checks if RC=7. If so, the video
-                   logic goes to idle state and VCBASE is loaded from VC (VC->VCBASE). If
-                   the video logic is in display state afterwards (this is always the case
-                   if there is a Bad Line Condition), RC is incremented.
-                */
-                if self.raster_unit.rc == 7 {
-                    self.raster_unit.vc_base = self.raster_unit.vc;
-                    if !self.raster_unit.is_bad_line {
-                        self.raster_unit.display_state = false;
-                    }
+            57..=65 => {
+                if self.cycle <= self.spec.cycles_per_raster - 2 {
+                    self.draw_border();
                 }
-                self.update_display_state();
-                if self.raster_unit.display_state {
-                    self.raster_unit.rc += 1;
-                }
-                /*
-                Section: 3.8. Sprites
-                4. In the first phase of cycle 58, the MC of every sprite is loaded from
-                   its belonging MCBASE (MCBASE->MC) ..=
-                */
-                self.raster_unit.mc[..8].clone_from_slice(&self.raster_unit.mc_base[..8]);
-                self.update_sprite_display();
-                let sprite_dma = self.raster_unit.sprite_dma[0] | self.raster_unit.sprite_dma[1];
-                self.set_ba(sprite_dma);
-                self.p_access(0);
-                if self.raster_unit.sprite_dma[0] {
-                    self.s_access(0, 0);
-                }
-            }
-            59 => {
-                self.draw_border();
-                let sprite_dma = self.raster_unit.sprite_dma[0]
-                    | self.raster_unit.sprite_dma[1]
-                    | self.raster_unit.sprite_dma[2];
-                self.set_ba(sprite_dma);
-                if self.raster_unit.sprite_dma[0] {
-                    self.s_access(0, 1);
-                    self.s_access(0, 2);
-                }
-            }
-            60 => {
-                self.draw_border();
-                let sprite_dma = self.raster_unit.sprite_dma[1] | self.raster_unit.sprite_dma[2];
-                self.set_ba(sprite_dma);
-                self.p_access(1);
-                if self.raster_unit.sprite_dma[1] {
-                    self.s_access(1, 0);
-                }
-            }
-            61 => {
-                self.draw_border();
-                let sprite_dma = self.raster_unit.sprite_dma[1]
-                    | self.raster_unit.sprite_dma[2]
-                    | self.raster_unit.sprite_dma[3];
-                self.set_ba(sprite_dma);
-                if self.raster_unit.sprite_dma[1] {
-                    self.s_access(1, 1);
-                    self.s_access(1, 2);
+                if self.cycle == 58 {
+                    /*
+                    Section: 3.7.2. VC and RC
+                    5. In the first phase of cycle 58, the VIC checks if RC=7. If so, the video
+                       logic goes to idle state and VCBASE is loaded from VC (VC->VCBASE). If
+                       the video logic is in display state afterwards (this is always the case
+                       if there is a Bad Line Condition), RC is incremented.
+                    */
+                    if self.raster_unit.rc == 7 {
+                        self.raster_unit.vc_base = self.raster_unit.vc;
+                        if !self.raster_unit.is_bad_line {
+                            self.raster_unit.display_state = false;
+                        }
+                    }
+                    self.update_display_state();
+                    if self.raster_unit.display_state {
+                        self.raster_unit.rc += 1;
+                    }
+                    /*
+                    Section: 3.8. Sprites
+                    4. In the first phase of cycle 58, the MC of every sprite is loaded from
+                       its belonging MCBASE (MCBASE->MC) ..=
+                    */
+                    self.raster_unit.mc[..8].clone_from_slice(&self.raster_unit.mc_base[..8]);
+                    self.update_sprite_display();
                 }
-            }
-            62 => {
-                let sprite_dma = self.raster_unit.sprite_dma[2] | self.raster_unit.sprite_dma[3];
-                self.set_ba(sprite_dma);
-                self.p_access(2);
-                if self.raster_unit.sprite_dma[2] {
-                    self.s_access(2, 0);
+                if self.cycle >= self.spec.sprite_fetch_cycle {
+                    let slot = self.cycle - self.spec.sprite_fetch_cycle;
+                    let n = (slot >> 1) as usize;
+                    if slot & 0x01 == 0 {
+                        self.p_access(n);
+                        if self.raster_unit.sprite_dma[n] {
+                            self.s_access(n, 0);
+                        }
+                    } else if self.raster_unit.sprite_dma[n] {
+                        self.s_access(n, 1);
+                        self.s_access(n, 2);
+                    }
                 }
-            }
-            63 => {
-                let sprite_dma = self.raster_unit.sprite_dma[2]
-                    | self.raster_unit.sprite_dma[3]
-                    | self.raster_unit.sprite_dma[4];
-                self.set_ba(sprite_dma);
-                if self.raster_unit.sprite_dma[2] {
-                    self.s_access(2, 1);
-                    self.s_access(2, 2);
+                if self.cycle == self.spec.cycles_per_raster {
+                    self.border_unit.update_vertical_flop(self.y, self.den);
                 }
-                self.border_unit.update_vertical_flop(self.y, self.den);
             }
-            64 => {}
-            65 => {}
             _ => panic!("invalid cycle"),
         }
         self.update_display_state();
@@ -844,9 +938,11 @@ impl Chip for Vic {
                    and is irrelevant.
                 */
                 self.raster_unit.vc_base = 0;
+                self.lp_triggered = false;
                 self.vsync_flag.set(true);
             }
         }
+        self.update_ba();
     }
 
     fn clock_delta(&mut self, delta: u32) {
@@ -878,6 +974,93 @@ impl Chip for Vic {
         // Runtime State
         self.cycle = 1;
         self.y = 0x0100;
+        self.lp_triggered = false;
+        self.lp_x = 0;
+        self.lp_y = 0;
+        self.grey_dot = false;
+        self.ba_cycles = 0;
+    }
+
+    // State
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_tag(b"VIC0");
+        state.write_u16(self.spec.raster_lines);
+        state.write_u16(self.spec.cycles_per_raster);
+        // Functional Units
+        self.border_unit.save_state(state);
+        self.gfx_seq.save_state(state);
+        self.irq_control.save_state(state);
+        self.mux_unit.save_state(state);
+        self.raster_unit.save_state(state);
+        for sprite_unit in self.sprite_units.iter() {
+            sprite_unit.save_state(state);
+        }
+        // Configuration
+        state.write_u16(self.char_base);
+        state.write_bool(self.den);
+        state.write_u16(self.raster_compare);
+        state.write_u8(self.x_scroll);
+        state.write_u8(self.y_scroll);
+        state.write_u16(self.video_matrix);
+        // Runtime State
+        state.write_u16(self.cycle);
+        state.write_u16(self.y);
+        state.write_bool(self.lp_triggered);
+        state.write_u8(self.lp_x);
+        state.write_u8(self.lp_y);
+        state.write_bool(self.grey_dot);
+        state.write_u8(self.ba_cycles);
+        self.ba_line.borrow().save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.read_tag(b"VIC0")?;
+        let raster_lines = state.read_u16()?;
+        let cycles_per_raster = state.read_u16()?;
+        if raster_lines != self.spec.raster_lines
+            || cycles_per_raster != self.spec.cycles_per_raster
+        {
+            return Err(format!(
+                "Invalid vic model with {} lines, {} cycles per line",
+                raster_lines, cycles_per_raster
+            ));
+        }
+        // Functional Units
+        self.border_unit.load_state(state)?;
+        self.gfx_seq.load_state(state)?;
+        self.irq_control.load_state(state)?;
+        self.mux_unit.load_state(state)?;
+        self.raster_unit.load_state(state)?;
+        for sprite_unit in self.sprite_units.iter_mut() {
+            sprite_unit.load_state(state)?;
+        }
+        // Configuration
+        self.char_base = state.read_u16()?;
+        self.den = state.read_bool()?;
+        self.raster_compare = state.read_u16()?;
+        self.x_scroll = state.read_u8()?;
+        self.y_scroll = state.read_u8()?;
+        self.video_matrix = state.read_u16()?;
+        // Runtime State
+        self.cycle = state.read_u16()?;
+        self.y = state.read_u16()?;
+        if self.cycle == 0
+            || self.cycle > self.spec.cycles_per_raster
+            || self.y >= self.spec.raster_lines
+        {
+            return Err(format!(
+                "Invalid vic raster position {}:{}",
+                self.y, self.cycle
+            ));
+        }
+        self.lp_triggered = state.read_bool()?;
+        self.lp_x = state.read_u8()?;
+        self.lp_y = state.read_u8()?;
+        self.grey_dot = state.read_bool()?;
+        self.ba_cycles = state.read_u8()?;
+        self.ba_line.borrow_mut().load_state(state)?;
+        Ok(())
     }
 
     // I/O
@@ -904,7 +1087,7 @@ impl Chip for Vic {
             0x11 => {
                 let mut result = 0;
                 result
-                    .set_bit(7, self.y.get_bit(8))
+                    .set_bit(7, self.get_raster().get_bit(8))
                     .set_bit(6, self.gfx_seq.config.mode.value().get_bit(2))
                     .set_bit(5, self.gfx_seq.config.mode.value().get_bit(1))
                     .set_bit(4, self.den)
@@ -912,11 +1095,11 @@ impl Chip for Vic {
                 result | (self.y_scroll & 0x07)
             }
             // Reg::RASTER
-            0x12 => (self.y & 0x00ff) as u8,
+            0x12 => (self.get_raster() & 0x00ff) as u8,
             // Reg::LPX
-            0x13 => 0,
+            0x13 => self.lp_x,
             // Reg::LPY
-            0x14 => 0,
+            0x14 => self.lp_y,
             // Reg::ME
             0x15 => {
                 let mut result = 0;
@@ -1011,6 +1194,9 @@ impl Chip for Vic {
         if log_enabled!(LogLevel::Trace) {
             trace!(target: "vic::reg", "Write 0x{:02x} = 0x{:02x}", reg, value);
         }
+        if self.spec.grey_dots && (0x20..=0x2e).contains(&reg) {
+            self.grey_dot = true;
+        }
         match reg {
             // Reg::M0X - Reg::M7X
             0x00 | 0x02 | 0x04 | 0x06 | 0x08 | 0x0a | 0x0c | 0x0e => {
@@ -1035,7 +1221,12 @@ impl Chip for Vic {
             }
             // Reg::CR1
             0x11 => {
-                self.raster_compare.set_bit(8, value.get_bit(7));
+                let mut new_value = self.raster_compare;
+                new_value.set_bit(8, value.get_bit(7));
+                if self.raster_compare != new_value && self.get_raster() == new_value {
+                    self.trigger_irq(0);
+                }
+                self.raster_compare = new_value;
                 let mut mode = self.gfx_seq.config.mode.value();
                 mode.set_bit(2, value.get_bit(6))
                     .set_bit(1, value.get_bit(5));
@@ -1045,14 +1236,11 @@ impl Chip for Vic {
                 self.y_scroll = value & 0x07;
                 self.update_display_on();
                 self.update_bad_line();
-                if self.y == self.raster_compare {
-                    self.trigger_irq(0);
-                }
             }
             // Reg::RASTER
             0x12 => {
                 let new_value = (self.raster_compare & 0xff00) | (value as u16);
-                if self.raster_compare != new_value && self.y == new_value {
+                if self.raster_compare != new_value && self.get_raster() == new_value {
                     self.trigger_irq(0);
                 }
                 self.raster_compare = new_value;
@@ -6,7 +6,7 @@ use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
 use zinc64_core::cpu::Cpu6510;
-use zinc64_core::factory::{Addressable, Cpu, TickFn};
+use zinc64_core::factory::{Addressable, Cpu, Register, TickFn};
 use zinc64_core::util::{IoPort, IrqLine, Pin, Ram};
 
 struct MockMemory {
@@ -30,7 +30,10 @@ impl Addressable for MockMemory {
 }
 
 fn setup_cpu() -> Cpu6510 {
-    let ba_line = Rc::new(RefCell::new(Pin::new_high()));
+    setup_cpu_with_ba_line(Rc::new(RefCell::new(Pin::new_high())))
+}
+
+fn setup_cpu_with_ba_line(ba_line: Rc<RefCell<Pin>>) -> Cpu6510 {
     let cpu_io_port = Rc::new(RefCell::new(IoPort::new(0x00, 0xff)));
     let cpu_irq = Rc::new(RefCell::new(IrqLine::new("irq")));
     let cpu_nmi = Rc::new(RefCell::new(IrqLine::new("nmi")));
@@ -344,3 +347,73 @@ fn opcode_timing() {
         }
     }
 }
+
+#[test]
+fn ba_low_stalls_reads_only() {
+    let ba_line = Rc::new(RefCell::new(Pin::new_high()));
+    let mut cpu = setup_cpu_with_ba_line(ba_line.clone());
+    cpu.reset();
+    // STA $2000; LDA #$55
+    cpu.write_mem(0x1000, 0x8d);
+    cpu.write_mem(0x1001, 0x00);
+    cpu.write_mem(0x1002, 0x20);
+    cpu.write_mem(0x1003, 0xa9);
+    cpu.write_mem(0x1004, 0x55);
+    cpu.set_register(Register::A, 0xaa);
+    cpu.set_pc(0x1000);
+    cpu.clock();
+    cpu.clock();
+    // The read of the address high byte waits for BA
+    ba_line.borrow_mut().set_active(false);
+    cpu.clock();
+    cpu.clock();
+    assert_eq!(0x1002, cpu.get_pc());
+    ba_line.borrow_mut().set_active(true);
+    cpu.clock();
+    assert_eq!(0x1003, cpu.get_pc());
+    // The write cycle goes ahead with BA low, the next opcode fetch does not
+    ba_line.borrow_mut().set_active(false);
+    cpu.clock();
+    assert_eq!(0xaa, cpu.read_mem(0x2000));
+    for _ in 0..3 {
+        cpu.clock();
+    }
+    assert_eq!(0xaa, cpu.get_register(Register::A));
+    ba_line.borrow_mut().set_active(true);
+    for _ in 0..3 {
+        cpu.clock();
+    }
+    assert_eq!(0x55, cpu.get_register(Register::A));
+}
+
+#[test]
+fn ba_low_lets_rmw_writes_finish() {
+    let ba_line = Rc::new(RefCell::new(Pin::new_high()));
+    let mut cpu = setup_cpu_with_ba_line(ba_line.clone());
+    cpu.reset();
+    // INC $2000; LDA #$55
+    cpu.write_mem(0x1000, 0xee);
+    cpu.write_mem(0x1001, 0x00);
+    cpu.write_mem(0x1002, 0x20);
+    cpu.write_mem(0x1003, 0xa9);
+    cpu.write_mem(0x1004, 0x55);
+    cpu.write_mem(0x2000, 0x10);
+    cpu.set_pc(0x1000);
+    for _ in 0..4 {
+        cpu.clock();
+    }
+    // The dummy write and the write of the result go ahead with BA low
+    ba_line.borrow_mut().set_active(false);
+    cpu.clock();
+    cpu.clock();
+    assert_eq!(0x11, cpu.read_mem(0x2000));
+    for _ in 0..3 {
+        cpu.clock();
+    }
+    assert_eq!(0x00, cpu.get_register(Register::A));
+    ba_line.borrow_mut().set_active(true);
+    for _ in 0..3 {
+        cpu.clock();
+    }
+    assert_eq!(0x55, cpu.get_register(Register::A));
+}
@@ -45,6 +45,7 @@ pub enum Cmd {
     RegWrite(Vec<RegOp>),
     Return,
     Step(u16),
+    StepBack,
     // Memory
     Compare(u16, u16, u16),
     Disassemble(Option<u16>, Option<u16>),
@@ -156,6 +157,7 @@ impl Connection {
             Cmd::RegWrite(ops) => self.cmd_reg_write(ops),
             Cmd::Return => self.cmd_return(),
             Cmd::Step(count) => self.cmd_step(count),
+            Cmd::StepBack => self.cmd_step_back(),
             // Memory
             Cmd::Compare(start, end, target) => self.cmd_compare(start, end, target),
             Cmd::Disassemble(start, end) => self.cmd_disassemble(start, end),
@@ -387,6 +389,15 @@ impl Connection {
         Ok(buffer)
     }
 
+    fn cmd_step_back(&mut self) -> io::Result<String> {
+        self.execute_unit_cmd(Command::StepBack)?;
+        let regs = self.read_regs()?;
+        let mem = self.read_mem(regs.pc, regs.pc.wrapping_add(10))?;
+        let dis = Disassembler::new(mem.clone(), regs.pc);
+        let (instr, instr_len) = dis.disassemble(regs.pc);
+        Ok(self.format_instr(&regs, &instr, &mem[0..instr_len]))
+    }
+
     // -- Memory
 
     fn cmd_compare(&mut self, start: u16, end: u16, target: u16) -> io::Result<String> {
@@ -774,6 +785,7 @@ impl CommandParser {
                 "registers" | "r" => self.parse_registers(&mut tokens),
                 "return" | "ret" => self.parse_return(&mut tokens),
                 "step" | "z" => self.parse_step(&mut tokens),
+                "stepback" | "sb" => self.parse_step_back(&mut tokens),
                 // Memory
                 "compare" | "c" => self.parse_compare(&mut tokens),
                 "disass" | "d" => self.parse_disassemble(&mut tokens),
@@ -932,6 +944,11 @@ impl CommandParser {
         Ok(Cmd::Step(count.unwrap_or(1)))
     }
 
+    fn parse_step_back(&self, tokens: &mut dyn Iterator<Item = &str>) -> Result<Cmd, String> {
+        self.ensure_eos(tokens)?;
+        Ok(Cmd::StepBack)
+    }
+
     // -- Memory
 
     fn parse_compare(&self, tokens: &mut dyn Iterator<Item = &str>) -> Result<Cmd, String> {
@@ -1134,6 +1151,7 @@ impl CommandHelp {
                 }
                 "return" | "ret" => CommandHelp::help_cmd("return", "ret"),
                 "step" | "z" => CommandHelp::help_cmd("step [<count>]", "z"),
+                "stepback" | "sb" => CommandHelp::help_cmd("stepback", "sb"),
                 // Memory
                 "compare" | "c" => CommandHelp::help_cmd("compare", "c"),
                 "disass" | "d" => CommandHelp::help_cmd("disass [<address> [<address>]]", "d"),
@@ -1189,6 +1207,7 @@ impl CommandHelp {
         buffer.push_str("registers (r)\n");
         buffer.push_str("return (ret)\n");
         buffer.push_str("step (z)\n");
+        buffer.push_str("stepback (sb)\n");
         buffer.push_str("\n");
         buffer.push_str("* Memory *\n");
         buffer.push_str("compare (c)\n");
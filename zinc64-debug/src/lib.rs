@@ -21,6 +21,7 @@ pub enum Command {
     Detach,
     Continue,
     Step,
+    StepBack,
     BpClear,
     BpCondition(u16, String, u32),
     BpDisable(u16),
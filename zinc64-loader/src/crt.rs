@@ -8,7 +8,7 @@
 use alloc::prelude::*;
 #[cfg(not(feature = "std"))]
 use alloc::vec;
-use byteorder::BigEndian;
+use byteorder::{BigEndian, ByteOrder};
 use core::str;
 use zinc64_core::device::cartridge;
 use zinc64_system::autostart;
@@ -22,6 +22,9 @@ use crate::io::{self, ReadBytesExt, Reader};
 static HEADER_SIG: &'static str = "C64 CARTRIDGE   ";
 static CHIP_SIG: &'static str = "CHIP";
 
+const HEADER_SIZE: u32 = 0x40;
+const CHIP_HEADER_SIZE: u32 = 0x10;
+
 struct Header {
     signature: [u8; 16],
     header_length: u32,
@@ -64,23 +67,34 @@ impl CrtLoader {
         Self {}
     }
 
-    fn build_cartridge(&self, header: &Header) -> cartridge::Cartridge {
-        cartridge::Cartridge::new(
+    fn build_cartridge(&self, header: &Header) -> io::Result<cartridge::Cartridge> {
+        if header.hw_type > 0xff {
+            return Err(format!("unsupported hardware type {}", header.hw_type));
+        }
+        let mut cartridge = cartridge::Cartridge::new(
             header.version,
-            cartridge::HwType::from(header.hw_type as u8),
+            cartridge::HwType::from(header.hw_type as u8)?,
             header.exrom_line != 0,
             header.game_line != 0,
-        )
+        );
+        cartridge.set_name(header.name);
+        Ok(cartridge)
     }
 
-    fn build_chip(&self, header: &ChipHeader, data: Vec<u8>) -> cartridge::Chip {
-        cartridge::Chip {
-            chip_type: cartridge::ChipType::from(header.chip_type),
+    fn build_chip(&self, header: &ChipHeader, data: Vec<u8>) -> io::Result<cartridge::Chip> {
+        if header.bank_number > 0xff {
+            return Err(format!("unsupported bank number {}", header.bank_number));
+        }
+        if data.is_empty() {
+            return Err(format!("empty cartridge chip {}", header.bank_number));
+        }
+        Ok(cartridge::Chip {
+            chip_type: cartridge::ChipType::from(header.chip_type)?,
             bank_number: header.bank_number as u8,
             offset: header.load_address,
             size: header.image_size,
             data,
-        }
+        })
     }
 
     fn read_chip_header(&self, rdr: &mut dyn Reader) -> io::Result<Option<ChipHeader>> {
@@ -137,20 +151,27 @@ impl CrtLoader {
     fn validate_chip_header(&self, header: &ChipHeader) -> io::Result<()> {
         let sig =
             str::from_utf8(&header.signature).map_err(|_| "invalid chip signature".to_owned())?;
-        if sig == CHIP_SIG {
-            Ok(())
-        } else {
+        if sig != CHIP_SIG {
             Err("invalid chip signature".to_owned())
+        } else if header.length < CHIP_HEADER_SIZE {
+            Err(format!("invalid chip length {}", header.length))
+        } else {
+            Ok(())
         }
     }
 
     fn validate_header(&self, header: &Header) -> io::Result<()> {
         let sig = str::from_utf8(&header.signature)
             .map_err(|_| "invalid cartridge signature".to_owned())?;
-        if sig == HEADER_SIG {
-            Ok(())
-        } else {
+        if sig != HEADER_SIG {
             Err("invalid cartridge signature".to_owned())
+        } else if header.header_length < HEADER_SIZE {
+            Err(format!(
+                "invalid cartridge header length {}",
+                header.header_length
+            ))
+        } else {
+            Ok(())
         }
     }
 }
@@ -172,24 +193,24 @@ impl Loader for CrtLoader {
               header.version & 0xff,
               header.hw_type);
         self.validate_header(&header)?;
-        reader.consume((header.header_length - 0x40) as usize);
-        let mut cartridge = self.build_cartridge(&header);
+        reader.consume((header.header_length - HEADER_SIZE) as usize);
+        let mut cartridge = self.build_cartridge(&header)?;
         loop {
             let chip_header_opt = self
                 .read_chip_header(reader)
                 .map_err(|_| "invalid cartridge chip header".to_owned())?;
             match chip_header_opt {
                 Some(chip_header) => {
-                    info!(target: "loader", "Found chip {}, type {}, offset 0x{:x}, size {}",
-                          chip_header.bank_number, chip_header.chip_type, chip_header.load_address, chip_header.length - 0x10);
                     self.validate_chip_header(&chip_header)?;
+                    info!(target: "loader", "Found chip {}, type {}, offset 0x{:x}, size {}",
+                          chip_header.bank_number, chip_header.chip_type, chip_header.load_address, chip_header.length - CHIP_HEADER_SIZE);
                     let chip_data = self
-                        .read_data(reader, (chip_header.length - 0x10) as usize)
+                        .read_data(reader, (chip_header.length - CHIP_HEADER_SIZE) as usize)
                         .map_err(|_| {
                             format!("invalid cartridge chip {} data", chip_header.bank_number)
                         })?;
-                    let chip = self.build_chip(&chip_header, chip_data);
-                    cartridge.add(chip);
+                    let chip = self.build_chip(&chip_header, chip_data)?;
+                    cartridge.add(chip)?;
                 }
                 None => {
                     break;
@@ -201,3 +222,92 @@ impl Loader for CrtLoader {
         }))
     }
 }
+
+/// Serializes cartridge to CRT image, used to save programmed EasyFlash contents.
+pub struct CrtWriter;
+
+impl CrtWriter {
+    pub fn write(cartridge: &cartridge::Cartridge) -> Vec<u8> {
+        let (exrom, game) = cartridge.get_io_lines();
+        let mut data = Vec::new();
+        data.extend_from_slice(HEADER_SIG.as_bytes());
+        write_u32(&mut data, HEADER_SIZE);
+        write_u16(&mut data, cartridge.get_version());
+        write_u16(&mut data, cartridge.get_hw_type().value() as u16);
+        data.push(exrom as u8);
+        data.push(game as u8);
+        data.extend_from_slice(&[0u8; 6]);
+        data.extend_from_slice(cartridge.get_name());
+        for chip in cartridge.get_chips() {
+            data.extend_from_slice(CHIP_SIG.as_bytes());
+            write_u32(&mut data, CHIP_HEADER_SIZE + chip.data.len() as u32);
+            write_u16(&mut data, chip.chip_type.value());
+            write_u16(&mut data, chip.bank_number as u16);
+            write_u16(&mut data, chip.offset);
+            write_u16(&mut data, chip.data.len() as u16);
+            data.extend_from_slice(&chip.data);
+        }
+        data
+    }
+}
+
+fn write_u16(data: &mut Vec<u8>, value: u16) {
+    let mut buf = [0u8; 2];
+    BigEndian::write_u16(&mut buf, value);
+    data.extend_from_slice(&buf);
+}
+
+fn write_u32(data: &mut Vec<u8>, value: u32) {
+    let mut buf = [0u8; 4];
+    BigEndian::write_u32(&mut buf, value);
+    data.extend_from_slice(&buf);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_crt(header_length: u32, chip_length: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(HEADER_SIG.as_bytes());
+        write_u32(&mut data, header_length);
+        write_u16(&mut data, 0x0100);
+        write_u16(&mut data, 0);
+        data.extend_from_slice(&[0, 1]);
+        data.extend_from_slice(&[0u8; 6 + 32]);
+        data.extend_from_slice(CHIP_SIG.as_bytes());
+        write_u32(&mut data, chip_length);
+        write_u16(&mut data, 0);
+        write_u16(&mut data, 0);
+        write_u16(&mut data, 0x8000);
+        write_u16(&mut data, 0x2000);
+        data.extend_from_slice(&[0u8; 0x2000]);
+        data
+    }
+
+    fn load(data: &[u8]) -> io::Result<Box<dyn Image>> {
+        let mut reader = data;
+        CrtLoader::new().load(&mut reader)
+    }
+
+    #[test]
+    fn load_cartridge() {
+        assert!(load(&build_crt(HEADER_SIZE, CHIP_HEADER_SIZE + 0x2000)).is_ok());
+    }
+
+    #[test]
+    fn reject_short_header_length() {
+        match load(&build_crt(0x20, CHIP_HEADER_SIZE + 0x2000)) {
+            Err(err) => assert_eq!("invalid cartridge header length 32", err),
+            Ok(_) => panic!("short header accepted"),
+        }
+    }
+
+    #[test]
+    fn reject_short_chip_length() {
+        match load(&build_crt(HEADER_SIZE, 0x08)) {
+            Err(err) => assert_eq!("invalid chip length 8", err),
+            Ok(_) => panic!("short chip accepted"),
+        }
+    }
+}
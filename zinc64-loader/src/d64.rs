@@ -0,0 +1,462 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+use zinc64_core::device::drive::gcr;
+use zinc64_core::device::drive::GcrDisk;
+use zinc64_system::autostart;
+use zinc64_system::virtual_drive::{DirEntry, FileType};
+use zinc64_system::{Autostart, AutostartMethod, FileSystem, Image, C64};
+
+use super::Loader;
+use crate::io::{self, Reader};
+
+// SPEC: http://ist.uwaterloo.ca/~schepers/formats/D64.TXT
+
+const SECTOR_SIZE: usize = 256;
+const SECTORS_35: usize = 683;
+const SECTORS_40: usize = 768;
+const DIR_TRACK: u8 = 18;
+const BAM_SECTOR: u8 = 0;
+const DIR_SECTOR: u8 = 1;
+const BAM_TRACKS: u8 = 35;
+const INTERLEAVE: u8 = 10;
+const DIR_INTERLEAVE: u8 = 3;
+const ENTRY_SIZE: usize = 32;
+const PADDING: u8 = 0xa0;
+
+struct D64Image {
+    disk: Option<D64Disk>,
+}
+
+impl Image for D64Image {
+    fn mount(&mut self, c64: &mut C64) {
+        info!(target: "loader", "Mounting D64 image");
+        let disk = self.disk.take().unwrap();
+        if c64.get_drive().is_some() {
+            c64.attach_disk(Box::new(disk.to_gcr()));
+        } else {
+            c64.attach_file_system(Box::new(disk));
+        }
+    }
+
+    fn unmount(&mut self, c64: &mut C64) {
+        c64.detach_disk();
+        c64.detach_file_system();
+    }
+}
+
+pub struct D64Loader;
+
+impl D64Loader {
+    pub fn new() -> impl Loader {
+        Self {}
+    }
+}
+
+impl Loader for D64Loader {
+    fn autostart(&self, reader: &mut dyn Reader) -> io::Result<AutostartMethod> {
+        let image = self.load(reader)?;
+        let autostart = Autostart::new(autostart::Mode::LoadRun, image);
+        Ok(AutostartMethod::WithAutostart(Some(autostart)))
+    }
+
+    fn load(&self, reader: &mut dyn Reader) -> io::Result<Box<dyn Image>> {
+        info!(target: "loader", "Loading D64");
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let disk = D64Disk::from_bytes(data)?;
+        info!(
+            target: "loader",
+            "Found disk, tracks {}, error info {}",
+            disk.tracks,
+            disk.errors.is_some()
+        );
+        for entry in disk.get_entries() {
+            debug!(target: "loader", "File {:?}, blocks {}", entry.file_type, entry.blocks);
+        }
+        Ok(Box::new(D64Image { disk: Some(disk) }))
+    }
+}
+
+/// Disk image made of 256 byte sectors with optional per sector error codes.
+pub struct D64Disk {
+    data: Vec<u8>,
+    errors: Option<Vec<u8>>,
+    tracks: u8,
+}
+
+impl D64Disk {
+    pub fn from_bytes(mut data: Vec<u8>) -> io::Result<D64Disk> {
+        let (tracks, sectors) = match data.len() {
+            len if len == SECTORS_35 * SECTOR_SIZE => (35, SECTORS_35),
+            len if len == SECTORS_35 * (SECTOR_SIZE + 1) => (35, SECTORS_35),
+            len if len == SECTORS_40 * SECTOR_SIZE => (40, SECTORS_40),
+            len if len == SECTORS_40 * (SECTOR_SIZE + 1) => (40, SECTORS_40),
+            len => return Err(format!("invalid disk image size {}", len)),
+        };
+        let errors = if data.len() > sectors * SECTOR_SIZE {
+            Some(data.split_off(sectors * SECTOR_SIZE))
+        } else {
+            None
+        };
+        Ok(D64Disk {
+            data,
+            errors,
+            tracks,
+        })
+    }
+
+    /// Encode disk as GCR tracks for use with true drive emulation.
+    pub fn to_gcr(&self) -> GcrDisk {
+        let bam = &self.data[self.index(DIR_TRACK, BAM_SECTOR).unwrap() * SECTOR_SIZE..];
+        let id = [bam[0xa2], bam[0xa3]];
+        let mut disk = GcrDisk::new(gcr::MAX_HALF_TRACKS);
+        for track in 1..=self.tracks {
+            let first = self.index(track, 0).unwrap();
+            let count = gcr::sectors_per_track(track) as usize;
+            let sectors = self.data[first * SECTOR_SIZE..(first + count) * SECTOR_SIZE]
+                .chunks(SECTOR_SIZE)
+                .collect::<Vec<&[u8]>>();
+            let errors = match self.errors {
+                Some(ref errors) => &errors[first..first + count],
+                None => &[],
+            };
+            let data = gcr::encode_track_with_errors(track, id, &sectors, errors);
+            disk.set_track((track as usize - 1) * 2, data, gcr::speed_zone(track));
+        }
+        disk
+    }
+
+    fn index(&self, track: u8, sector: u8) -> Option<usize> {
+        if track == 0 || track > self.tracks || sector >= gcr::sectors_per_track(track) {
+            return None;
+        }
+        let first = (1..track)
+            .map(|t| gcr::sectors_per_track(t) as usize)
+            .sum::<usize>();
+        Some(first + sector as usize)
+    }
+
+    fn read_sector(&self, track: u8, sector: u8) -> Result<&[u8], String> {
+        let index = self
+            .index(track, sector)
+            .ok_or_else(|| format!("illegal track {} sector {}", track, sector))?;
+        if let Some(ref errors) = self.errors {
+            if errors[index] > 0x01 {
+                return Err(format!(
+                    "read error {} on track {} sector {}",
+                    errors[index], track, sector
+                ));
+            }
+        }
+        Ok(&self.data[index * SECTOR_SIZE..(index + 1) * SECTOR_SIZE])
+    }
+
+    fn write_sector(&mut self, track: u8, sector: u8, data: &[u8]) {
+        let index = self.index(track, sector).unwrap();
+        self.data[index * SECTOR_SIZE..(index + 1) * SECTOR_SIZE].copy_from_slice(data);
+        if let Some(ref mut errors) = self.errors {
+            errors[index] = 0x01;
+        }
+    }
+
+    /// Follow sector chain starting at the specified sector.
+    fn read_chain(&self, track: u8, sector: u8) -> Result<Vec<(u8, u8)>, String> {
+        let mut result = Vec::new();
+        let (mut track, mut sector) = (track, sector);
+        while track != 0 {
+            if result.len() >= self.data.len() / SECTOR_SIZE {
+                return Err("sector chain contains a loop".to_string());
+            }
+            let data = self.read_sector(track, sector)?;
+            result.push((track, sector));
+            track = data[0];
+            sector = data[1];
+        }
+        Ok(result)
+    }
+
+    /// Get directory entries along with their location in the directory.
+    fn read_directory(&self) -> Vec<(DirEntry, u8, u8)> {
+        let mut entries = Vec::new();
+        let chain = match self.read_chain(DIR_TRACK, DIR_SECTOR) {
+            Ok(chain) => chain,
+            Err(err) => {
+                warn!(target: "loader", "Invalid directory, error: {}", err);
+                return entries;
+            }
+        };
+        for (track, sector) in chain {
+            let data = self.read_sector(track, sector).unwrap();
+            for entry in data.chunks(ENTRY_SIZE) {
+                // Only closed files are listed
+                if entry[2] & 0x80 == 0 {
+                    continue;
+                }
+                let name = entry[5..21]
+                    .iter()
+                    .cloned()
+                    .take_while(|c| *c != PADDING)
+                    .collect();
+                entries.push((
+                    DirEntry {
+                        name,
+                        file_type: FileType::from(entry[2]),
+                        blocks: u16::from(entry[30]) | (u16::from(entry[31]) << 8),
+                    },
+                    entry[3],
+                    entry[4],
+                ));
+            }
+        }
+        entries
+    }
+
+    // -- BAM Ops
+
+    fn bam_offset(track: u8) -> usize {
+        4 + (track as usize - 1) * 4
+    }
+
+    fn is_free(&self, track: u8, sector: u8) -> bool {
+        let bam = self.read_sector(DIR_TRACK, BAM_SECTOR).unwrap();
+        let offset = Self::bam_offset(track) + 1 + sector as usize / 8;
+        bam[offset] & (1 << (sector % 8)) != 0
+    }
+
+    fn allocate(&mut self, track: u8, sector: u8) {
+        let mut bam = self.read_sector(DIR_TRACK, BAM_SECTOR).unwrap().to_vec();
+        let offset = Self::bam_offset(track);
+        bam[offset + 1 + sector as usize / 8] &= !(1 << (sector % 8));
+        bam[offset] = bam[offset].saturating_sub(1);
+        self.write_sector(DIR_TRACK, BAM_SECTOR, &bam);
+    }
+
+    fn free(&mut self, track: u8, sector: u8) {
+        if self.is_free(track, sector) {
+            return;
+        }
+        let mut bam = self.read_sector(DIR_TRACK, BAM_SECTOR).unwrap().to_vec();
+        let offset = Self::bam_offset(track);
+        bam[offset + 1 + sector as usize / 8] |= 1 << (sector % 8);
+        bam[offset] = bam[offset].saturating_add(1);
+        self.write_sector(DIR_TRACK, BAM_SECTOR, &bam);
+    }
+
+    /// Find free sector on the specified track starting at the sector given.
+    fn find_free_on_track(&self, track: u8, start: u8) -> Option<u8> {
+        let count = gcr::sectors_per_track(track);
+        (0..count)
+            .map(|i| (start + i) % count)
+            .find(|sector| self.is_free(track, *sector))
+    }
+
+    /// Find free sector following the previous one as DOS would, using tracks
+    /// closest to the directory first.
+    fn find_free(&self, prev: Option<(u8, u8)>) -> Option<(u8, u8)> {
+        if let Some((track, sector)) = prev {
+            let start = (sector + INTERLEAVE) % gcr::sectors_per_track(track);
+            if let Some(sector) = self.find_free_on_track(track, start) {
+                return Some((track, sector));
+            }
+        }
+        for distance in 1..DIR_TRACK {
+            for track in &[DIR_TRACK - distance, DIR_TRACK + distance] {
+                if *track <= BAM_TRACKS {
+                    if let Some(sector) = self.find_free_on_track(*track, 0) {
+                        return Some((*track, sector));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Find directory sector and slot of the file at the specified directory index.
+    fn find_entry(&self, index: usize) -> Result<(u8, u8, usize), String> {
+        let mut count = 0;
+        for (dir_track, dir_sector) in self.read_chain(DIR_TRACK, DIR_SECTOR)? {
+            let dir = self.read_sector(dir_track, dir_sector)?;
+            for slot in 0..8 {
+                if dir[slot * ENTRY_SIZE + 2] & 0x80 == 0 {
+                    continue;
+                }
+                if count == index {
+                    return Ok((dir_track, dir_sector, slot));
+                }
+                count += 1;
+            }
+        }
+        Err(format!("invalid file index {}", index))
+    }
+
+    /// Find free directory slot, extending the directory if needed.
+    fn find_dir_slot(&mut self) -> Result<(u8, u8, usize), String> {
+        let chain = self.read_chain(DIR_TRACK, DIR_SECTOR)?;
+        for (track, sector) in chain.iter() {
+            let data = self.read_sector(*track, *sector)?;
+            if let Some(slot) = (0..8).find(|i| data[i * ENTRY_SIZE + 2] == 0) {
+                return Ok((*track, *sector, slot));
+            }
+        }
+        let (last_track, last_sector) = *chain.last().unwrap();
+        let start = (last_sector + DIR_INTERLEAVE) % gcr::sectors_per_track(DIR_TRACK);
+        let sector = self
+            .find_free_on_track(DIR_TRACK, start)
+            .ok_or_else(|| "directory full".to_string())?;
+        self.allocate(DIR_TRACK, sector);
+        let mut data = self.read_sector(last_track, last_sector)?.to_vec();
+        data[0] = DIR_TRACK;
+        data[1] = sector;
+        self.write_sector(last_track, last_sector, &data);
+        let mut block = [0u8; SECTOR_SIZE];
+        block[1] = 0xff;
+        self.write_sector(DIR_TRACK, sector, &block);
+        Ok((DIR_TRACK, sector, 0))
+    }
+}
+
+impl FileSystem for D64Disk {
+    fn get_name(&self) -> Vec<u8> {
+        match self.read_sector(DIR_TRACK, BAM_SECTOR) {
+            Ok(bam) => bam[0x90..0xa0]
+                .iter()
+                .cloned()
+                .take_while(|c| *c != PADDING)
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn get_id(&self) -> Vec<u8> {
+        match self.read_sector(DIR_TRACK, BAM_SECTOR) {
+            Ok(bam) => bam[0xa2..0xa7]
+                .iter()
+                .map(|c| if *c == PADDING { b' ' } else { *c })
+                .collect(),
+            Err(_) => b"     ".to_vec(),
+        }
+    }
+
+    fn get_blocks_free(&self) -> u16 {
+        match self.read_sector(DIR_TRACK, BAM_SECTOR) {
+            Ok(bam) => (1..=BAM_TRACKS)
+                .filter(|track| *track != DIR_TRACK)
+                .map(|track| u16::from(bam[Self::bam_offset(track)]))
+                .sum(),
+            Err(_) => 0,
+        }
+    }
+
+    fn get_entries(&self) -> Vec<DirEntry> {
+        self.read_directory()
+            .into_iter()
+            .map(|(entry, _, _)| entry)
+            .collect()
+    }
+
+    fn read_file(&self, index: usize) -> Result<Vec<u8>, String> {
+        let (_, track, sector) = self
+            .read_directory()
+            .into_iter()
+            .nth(index)
+            .ok_or_else(|| format!("invalid file index {}", index))?;
+        let mut result = Vec::new();
+        for (track, sector) in self.read_chain(track, sector)? {
+            let data = self.read_sector(track, sector)?;
+            let end = if data[0] == 0 {
+                (data[1] as usize + 1).max(2)
+            } else {
+                SECTOR_SIZE
+            };
+            result.extend_from_slice(&data[2..end]);
+        }
+        Ok(result)
+    }
+
+    fn is_write_protected(&self) -> bool {
+        false
+    }
+
+    fn write_file(
+        &mut self,
+        name: &[u8],
+        file_type: FileType,
+        data: &[u8],
+        replace: Option<usize>,
+    ) -> Result<(), String> {
+        let existing = match replace {
+            Some(index) => {
+                let (dir_track, dir_sector, slot) = self.find_entry(index)?;
+                let entry = &self.read_sector(dir_track, dir_sector)?[slot * ENTRY_SIZE..];
+                let chain = self.read_chain(entry[3], entry[4])?;
+                Some((dir_track, dir_sector, slot, chain))
+            }
+            None => {
+                if self.get_entries().iter().any(|entry| entry.name == name) {
+                    return Err("file exists".to_string());
+                }
+                None
+            }
+        };
+        let blocks = data.len().div_ceil(254).max(1);
+        if blocks > self.get_blocks_free() as usize {
+            return Err("disk full".to_string());
+        }
+        let (dir_track, dir_sector, slot) = match existing {
+            Some((dir_track, dir_sector, slot, _)) => (dir_track, dir_sector, slot),
+            None => self.find_dir_slot()?,
+        };
+        let mut sectors = Vec::with_capacity(blocks);
+        let mut prev = None;
+        for _ in 0..blocks {
+            let next = self
+                .find_free(prev)
+                .ok_or_else(|| "disk full".to_string())?;
+            self.allocate(next.0, next.1);
+            sectors.push(next);
+            prev = Some(next);
+        }
+        for (i, chunk) in data.chunks(254).enumerate() {
+            let mut block = [0u8; SECTOR_SIZE];
+            match sectors.get(i + 1) {
+                Some((track, sector)) => {
+                    block[0] = *track;
+                    block[1] = *sector;
+                }
+                None => block[1] = (chunk.len() + 1) as u8,
+            }
+            block[2..2 + chunk.len()].copy_from_slice(chunk);
+            self.write_sector(sectors[i].0, sectors[i].1, &block);
+        }
+        if data.is_empty() {
+            let mut block = [0u8; SECTOR_SIZE];
+            block[1] = 1;
+            self.write_sector(sectors[0].0, sectors[0].1, &block);
+        }
+        let mut dir = self.read_sector(dir_track, dir_sector)?.to_vec();
+        let entry = &mut dir[slot * ENTRY_SIZE..(slot + 1) * ENTRY_SIZE];
+        entry[2] = 0x80 | file_type.code();
+        entry[3] = sectors[0].0;
+        entry[4] = sectors[0].1;
+        for (i, c) in entry[5..21].iter_mut().enumerate() {
+            *c = name.get(i).cloned().unwrap_or(PADDING);
+        }
+        for c in entry[21..30].iter_mut() {
+            *c = 0;
+        }
+        entry[30] = blocks as u8;
+        entry[31] = (blocks >> 8) as u8;
+        self.write_sector(dir_track, dir_sector, &dir);
+        // The replaced file is released only after its entry points to the new contents
+        if let Some((_, _, _, chain)) = existing {
+            for (track, sector) in chain {
+                self.free(track, sector);
+            }
+        }
+        Ok(())
+    }
+}
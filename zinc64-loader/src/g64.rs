@@ -0,0 +1,203 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+use byteorder::{ByteOrder, LittleEndian};
+use zinc64_core::device::drive::gcr;
+use zinc64_core::device::drive::GcrDisk;
+use zinc64_core::factory::Disk;
+use zinc64_system::autostart;
+use zinc64_system::{Autostart, AutostartMethod, Image, C64};
+
+use super::Loader;
+use crate::io::{self, Reader};
+
+// SPEC: http://ist.uwaterloo.ca/~schepers/formats/G64.TXT
+
+static HEADER_SIG: &[u8; 8] = b"GCR-1541";
+
+const HEADER_SIZE: usize = 12;
+const VERSION: u8 = 0;
+const MAX_TRACK_SIZE: usize = 7928;
+
+struct G64Image {
+    disk: Option<GcrDisk>,
+}
+
+impl Image for G64Image {
+    fn mount(&mut self, c64: &mut C64) {
+        info!(target: "loader", "Mounting G64 image");
+        if !c64.attach_disk(Box::new(self.disk.take().unwrap())) {
+            warn!(target: "loader", "G64 images require true drive emulation");
+        }
+    }
+
+    fn unmount(&mut self, c64: &mut C64) {
+        c64.detach_disk();
+    }
+}
+
+pub struct G64Loader;
+
+impl G64Loader {
+    pub fn new() -> impl Loader {
+        Self {}
+    }
+}
+
+impl Loader for G64Loader {
+    fn autostart(&self, reader: &mut dyn Reader) -> io::Result<AutostartMethod> {
+        let image = self.load(reader)?;
+        let autostart = Autostart::new(autostart::Mode::LoadRun, image);
+        Ok(AutostartMethod::WithAutostart(Some(autostart)))
+    }
+
+    fn load(&self, reader: &mut dyn Reader) -> io::Result<Box<dyn Image>> {
+        info!(target: "loader", "Loading G64");
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let disk = G64Reader::read(&data)?;
+        Ok(Box::new(G64Image { disk: Some(disk) }))
+    }
+}
+
+/// Parses G64 image into GCR tracks that are spun by the drive bit by bit.
+pub struct G64Reader;
+
+impl G64Reader {
+    pub fn read(data: &[u8]) -> io::Result<GcrDisk> {
+        if data.len() < HEADER_SIZE || &data[0..8] != HEADER_SIG {
+            return Err("invalid disk signature".to_owned());
+        }
+        let version = data[8];
+        let half_tracks = data[9] as usize;
+        let max_track_size = LittleEndian::read_u16(&data[10..12]);
+        info!(
+            target: "loader",
+            "Found disk, version {}, half tracks {}, max track size {}",
+            version,
+            half_tracks,
+            max_track_size
+        );
+        if half_tracks > gcr::MAX_HALF_TRACKS {
+            return Err(format!("invalid number of half tracks {}", half_tracks));
+        }
+        let speed_table = HEADER_SIZE + half_tracks * 4;
+        if data.len() < speed_table + half_tracks * 4 {
+            return Err("invalid disk header".to_owned());
+        }
+        let mut disk = GcrDisk::new(gcr::MAX_HALF_TRACKS);
+        for half_track in 0..half_tracks {
+            let offset = read_u32_at(data, HEADER_SIZE + half_track * 4) as usize;
+            if offset == 0 {
+                continue;
+            }
+            if offset + 2 > data.len() {
+                return Err(format!("invalid offset for half track {}", half_track));
+            }
+            let size = LittleEndian::read_u16(&data[offset..offset + 2]) as usize;
+            if offset + 2 + size > data.len() {
+                return Err(format!("invalid size for half track {}", half_track));
+            }
+            let track = data[offset + 2..offset + 2 + size].to_vec();
+            // Values above 3 point to per byte speed zones which are not supported,
+            // the zone DOS would use for the track is assumed instead.
+            let speed_zone = match read_u32_at(data, speed_table + half_track * 4) {
+                zone @ 0..=3 => zone as u8,
+                _ => gcr::speed_zone((half_track / 2 + 1) as u8),
+            };
+            disk.set_track(half_track, track, speed_zone);
+        }
+        Ok(disk)
+    }
+}
+
+/// Serializes disk tracks to G64 image.
+pub struct G64Writer;
+
+impl G64Writer {
+    pub fn write(disk: &dyn Disk) -> Vec<u8> {
+        let half_tracks = disk.get_half_tracks().min(gcr::MAX_HALF_TRACKS);
+        let max_track_size = (0..half_tracks)
+            .filter_map(|half_track| disk.get_track(half_track))
+            .map(|track| track.len())
+            .fold(MAX_TRACK_SIZE, usize::max);
+        let mut data = Vec::new();
+        data.extend_from_slice(HEADER_SIG);
+        data.push(VERSION);
+        data.push(half_tracks as u8);
+        write_u16(&mut data, max_track_size as u16);
+        let mut offset = HEADER_SIZE + half_tracks * 8;
+        for half_track in 0..half_tracks {
+            if disk.get_track(half_track).is_some() {
+                write_u32(&mut data, offset as u32);
+                offset += 2 + max_track_size;
+            } else {
+                write_u32(&mut data, 0);
+            }
+        }
+        for half_track in 0..half_tracks {
+            let speed_zone = if disk.get_track(half_track).is_some() {
+                disk.get_speed_zone(half_track)
+            } else {
+                0
+            };
+            write_u32(&mut data, u32::from(speed_zone));
+        }
+        for half_track in 0..half_tracks {
+            if let Some(track) = disk.get_track(half_track) {
+                write_u16(&mut data, track.len() as u16);
+                data.extend_from_slice(track);
+                let padding = max_track_size - track.len();
+                data.resize(data.len() + padding, gcr::GCR_GAP);
+            }
+        }
+        data
+    }
+}
+
+fn read_u32_at(data: &[u8], offset: usize) -> u32 {
+    LittleEndian::read_u32(&data[offset..offset + 4])
+}
+
+fn write_u16(data: &mut Vec<u8>, value: u16) {
+    let mut buf = [0u8; 2];
+    LittleEndian::write_u16(&mut buf, value);
+    data.extend_from_slice(&buf);
+}
+
+fn write_u32(data: &mut Vec<u8>, value: u32) {
+    let mut buf = [0u8; 4];
+    LittleEndian::write_u32(&mut buf, value);
+    data.extend_from_slice(&buf);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_read_back() {
+        let mut disk = GcrDisk::new(gcr::MAX_HALF_TRACKS);
+        disk.set_track(0, vec![0x55; 7692], 3);
+        disk.set_track(2, vec![0xaa; 7500], 3);
+        disk.set_track(68, vec![0x5a; 6250], 0);
+        disk.write(2, 100, 0xff);
+        let data = G64Writer::write(&disk);
+        let result = G64Reader::read(&data).unwrap();
+        assert!(!result.is_modified());
+        for half_track in 0..gcr::MAX_HALF_TRACKS {
+            assert_eq!(disk.get_track(half_track), result.get_track(half_track));
+            if disk.get_track(half_track).is_some() {
+                assert_eq!(
+                    disk.get_speed_zone(half_track),
+                    result.get_speed_zone(half_track)
+                );
+            }
+        }
+        assert_eq!(Some(0xff), result.get_track(2).map(|track| track[100]));
+        assert_eq!(data, G64Writer::write(&result));
+    }
+}
@@ -40,3 +40,31 @@ pub trait ReadBytesExt: Reader {
 }
 
 impl<R: Reader + ?Sized> ReadBytesExt for R {}
+
+#[cfg(test)]
+impl Reader for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = buf.len().min(self.len());
+        buf[..len].copy_from_slice(&self[..len]);
+        *self = &self[len..];
+        Ok(len)
+    }
+
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        let len = self.len();
+        buf.extend_from_slice(self);
+        *self = &self[len..];
+        Ok(len)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        if buf.len() > self.len() {
+            return Err("unexpected end of data".to_owned());
+        }
+        self.read(buf).map(|_| ())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        *self = &self[amt.min(self.len())..];
+    }
+}
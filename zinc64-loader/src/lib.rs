@@ -15,24 +15,43 @@ extern crate log;
 
 mod bin;
 mod crt;
+mod d64;
+mod g64;
 mod io;
 mod p00;
 mod prg;
+mod sid;
+mod t64;
 mod tap;
 
 #[cfg(not(feature = "std"))]
 use alloc::prelude::*;
+use zinc64_system::virtual_drive::FileType;
 use zinc64_system::{AutostartMethod, Image};
 
+static DISK_EXTENSIONS: [&str; 5] = ["d64", "d71", "d80", "d81", "d82"];
+
 pub use crate::bin::BinLoader;
+pub use crate::crt::CrtWriter;
+pub use crate::g64::{G64Reader, G64Writer};
 pub use crate::io::{Reader, Result};
+pub use crate::p00::{P00Loader, Pc64File};
+pub use crate::sid::{SidLoader, SidTune};
+pub use crate::t64::T64Loader;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Format {
     Bin,
     Crt,
-    P00,
+    D64,
+    G64,
+    /// PC64 file with the type given by the extension, P00 to P99, S00, U00, R00 or D00.
+    P00(FileType),
     Prg,
+    /// PSID or RSID music file.
+    Sid,
     Tap,
+    T64,
 }
 
 impl Format {
@@ -40,10 +59,75 @@ impl Format {
         match ext {
             Some("bin") => Some(Format::Bin),
             Some("crt") => Some(Format::Crt),
-            Some("p00") => Some(Format::P00),
-            Some("P00") => Some(Format::P00),
+            Some("d64") => Some(Format::D64),
+            Some("D64") => Some(Format::D64),
+            Some("g64") => Some(Format::G64),
+            Some("G64") => Some(Format::G64),
             Some("prg") => Some(Format::Prg),
+            Some("sid") => Some(Format::Sid),
+            Some("SID") => Some(Format::Sid),
             Some("tap") => Some(Format::Tap),
+            Some("t64") => Some(Format::T64),
+            Some("T64") => Some(Format::T64),
+            Some(ext) => Self::pc64_type(ext).map(Format::P00),
+            None => None,
+        }
+    }
+
+    /// Detect format from the leading bytes of the image. Formats without a signature are
+    /// not detected, see `Loaders::from_content` for heuristics applied to those.
+    pub fn from_signature(header: &[u8]) -> Option<Format> {
+        if header.starts_with(b"C64 CARTRIDGE") {
+            Some(Format::Crt)
+        } else if header.starts_with(b"C64-TAPE-RAW") {
+            Some(Format::Tap)
+        } else if header.starts_with(b"C64File") {
+            Some(Format::P00(FileType::Prg))
+        } else if header.starts_with(b"PSID") || header.starts_with(b"RSID") {
+            Some(Format::Sid)
+        } else if header.starts_with(b"GCR-1541") {
+            Some(Format::G64)
+        } else if header.starts_with(b"C64 tape image") || header.starts_with(b"C64S tape") {
+            Some(Format::T64)
+        } else {
+            None
+        }
+    }
+
+    /// Guess format of images without a signature from the image size and PRG load address.
+    fn from_heuristics(header: &[u8], size: usize) -> Option<Format> {
+        match size {
+            174_848 | 175_531 | 196_608 | 197_376 => Some(Format::D64),
+            3..=0x10001 if header.len() >= 2 => {
+                let address = u16::from(header[0]) as usize | (header[1] as usize) << 8;
+                if address >= 0x0200 && address + size - 2 <= 0x10000 {
+                    Some(Format::Prg)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn pc64_type(ext: &str) -> Option<FileType> {
+        let bytes = ext.as_bytes();
+        if bytes.len() != 3 || !bytes[1].is_ascii_digit() || !bytes[2].is_ascii_digit() {
+            return None;
+        }
+        // Disk images share the D prefix with PC64 files of DEL type
+        if DISK_EXTENSIONS
+            .iter()
+            .any(|disk_ext| ext.eq_ignore_ascii_case(disk_ext))
+        {
+            return None;
+        }
+        match bytes[0].to_ascii_uppercase() {
+            b'P' => Some(FileType::Prg),
+            b'S' => Some(FileType::Seq),
+            b'U' => Some(FileType::Usr),
+            b'R' => Some(FileType::Rel),
+            b'D' => Some(FileType::Del),
             _ => None,
         }
     }
@@ -61,9 +145,31 @@ impl Loaders {
         match kind {
             Format::Bin => Box::new(bin::BinLoader::new(1024)),
             Format::Crt => Box::new(crt::CrtLoader::new()),
-            Format::P00 => Box::new(p00::P00Loader::new()),
+            Format::D64 => Box::new(d64::D64Loader::new()),
+            Format::G64 => Box::new(g64::G64Loader::new()),
+            Format::P00(file_type) => Box::new(p00::P00Loader::new(file_type)),
             Format::Prg => Box::new(prg::PrgLoader::new()),
+            Format::Sid => Box::new(sid::SidLoader::new(None)),
             Format::Tap => Box::new(tap::TapLoader::new()),
+            Format::T64 => Box::new(t64::T64Loader::new(0)),
+        }
+    }
+
+    /// Get loader based on the image signature. Extension is used for images without one,
+    /// followed by size and load address heuristics.
+    pub fn from_content(header: &[u8], size: usize, ext: Option<&str>) -> Result<Box<dyn Loader>> {
+        let kind = match Format::from_signature(header) {
+            // File type of PC64 files is only known from the extension
+            Some(Format::P00(_)) => match Format::from_ext(ext) {
+                Some(Format::P00(file_type)) => Some(Format::P00(file_type)),
+                _ => return Err(format!("Invalid PC64 file extension {}", ext.unwrap_or(""))),
+            },
+            Some(kind) => Some(kind),
+            None => Format::from_ext(ext).or_else(|| Format::from_heuristics(header, size)),
+        };
+        match kind {
+            Some(kind) => Ok(Loaders::from(kind)),
+            None => Err(format!("Unknown image format {}", ext.unwrap_or(""))),
         }
     }
 
@@ -75,3 +181,116 @@ impl Loaders {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pc64_extensions() {
+        assert!(matches!(
+            Format::from_ext(Some("p00")),
+            Some(Format::P00(FileType::Prg))
+        ));
+        assert!(matches!(
+            Format::from_ext(Some("S01")),
+            Some(Format::P00(FileType::Seq))
+        ));
+        assert!(matches!(
+            Format::from_ext(Some("u12")),
+            Some(Format::P00(FileType::Usr))
+        ));
+        assert!(matches!(
+            Format::from_ext(Some("R99")),
+            Some(Format::P00(FileType::Rel))
+        ));
+        assert!(matches!(
+            Format::from_ext(Some("d00")),
+            Some(Format::P00(FileType::Del))
+        ));
+        for ext in &["d71", "d81", "D80", "d82", "p0", "p000", "px0", "x00"] {
+            assert!(Format::from_ext(Some(ext)).is_none(), "extension {}", ext);
+        }
+    }
+    #[test]
+    fn pc64_header_requires_pc64_extension() {
+        let mut header = b"C64File\0DEMO".to_vec();
+        header.resize(0x1c, 0);
+        assert!(Loaders::from_content(&header, 0x1e, Some("s00")).is_ok());
+        assert!(Loaders::from_content(&header, 0x1e, Some("prg")).is_err());
+        assert!(Loaders::from_content(&header, 0x1e, None).is_err());
+    }
+
+    #[test]
+    fn detect_signatures() {
+        let cases: [(&[u8], Option<Format>); 9] = [
+            (b"C64-TAPE-RAW\x01", Some(Format::Tap)),
+            (b"C64 CARTRIDGE   ", Some(Format::Crt)),
+            (b"GCR-1541\0", Some(Format::G64)),
+            (b"C64File\0", Some(Format::P00(FileType::Prg))),
+            (b"PSID\0\x02", Some(Format::Sid)),
+            (b"RSID\0\x02", Some(Format::Sid)),
+            (b"C64 tape image file", Some(Format::T64)),
+            (b"C64S tape file", Some(Format::T64)),
+            (b"\x01\x08\x0b\x08", None),
+        ];
+        for (header, format) in cases.iter() {
+            assert_eq!(
+                *format,
+                Format::from_signature(header),
+                "header {:?}",
+                header
+            );
+        }
+    }
+
+    #[test]
+    fn detect_by_heuristics() {
+        let cases: [(&[u8], usize, Option<Format>); 10] = [
+            (&[0x00, 0x00], 174_848, Some(Format::D64)),
+            (&[0x00, 0x00], 175_531, Some(Format::D64)),
+            (&[0x00, 0x00], 196_608, Some(Format::D64)),
+            (&[0x00, 0x00], 197_376, Some(Format::D64)),
+            (&[0x01, 0x08], 0x100, Some(Format::Prg)),
+            (&[0x00, 0xc0], 0x1002, Some(Format::Prg)),
+            (&[0x00, 0xc0], 0x4003, None),
+            (&[0x00, 0x01], 0x100, None),
+            (&[0x01], 0x100, None),
+            (&[0x01, 0x08], 2, None),
+        ];
+        for (header, size, format) in cases.iter() {
+            assert_eq!(
+                *format,
+                Format::from_heuristics(header, *size),
+                "header {:?}, size {}",
+                header,
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn detect_content() {
+        let cases: [(&[u8], usize, Option<&str>, bool); 9] = [
+            (b"C64-TAPE-RAW\x01", 0x1000, Some("bin"), true),
+            (b"C64 CARTRIDGE   ", 0x4050, None, true),
+            (b"GCR-1541\0", 0x5000, Some("dat"), true),
+            (b"PSID\0\x02", 0x1000, None, true),
+            (b"C64 tape image file", 0x1000, None, true),
+            (&[0x00, 0x00], 174_848, None, true),
+            (&[0x00, 0x00], 197_376, Some("img"), true),
+            (&[0x01, 0x08], 0x100, None, true),
+            (&[0x00, 0x01], 0x100, None, false),
+        ];
+        for (header, size, ext, ok) in cases.iter() {
+            assert_eq!(
+                *ok,
+                Loaders::from_content(header, *size, *ext).is_ok(),
+                "header {:?}, size {}",
+                header,
+                size
+            );
+        }
+        assert!(Loaders::from_content(&[], 0, None).is_err());
+    }
+}
@@ -4,47 +4,137 @@
 
 #[cfg(not(feature = "std"))]
 use alloc::prelude::*;
-use byteorder::LittleEndian;
 use core::str;
 use zinc64_system::autostart;
-use zinc64_system::{Autostart, AutostartMethod, Image, C64};
+use zinc64_system::virtual_drive::{DirEntry, FileType};
+use zinc64_system::{Autostart, AutostartMethod, FileSystem, Image, C64};
 
 use super::Loader;
 use crate::io::{self, ReadBytesExt, Reader};
 
+// SPEC: http://ist.uwaterloo.ca/~schepers/formats/PC64.TXT
+
 static HEADER_SIG: &'static str = "C64File";
 
 struct Header {
     signature: [u8; 7],
     #[allow(dead_code)]
     reserved_1: u8,
-    #[allow(dead_code)]
     filename: [u8; 16],
     #[allow(dead_code)]
     reserved_2: u8,
-    #[allow(dead_code)]
-    reserved_3: u8,
+    record_size: u8,
+}
+
+/// PC64 file along with the original C64 file name and type.
+#[derive(Clone)]
+pub struct Pc64File {
+    pub name: Vec<u8>,
+    pub file_type: FileType,
+    /// Record size of relative files.
+    pub record_size: u8,
+    pub data: Vec<u8>,
+}
+
+impl FileSystem for Pc64File {
+    fn get_name(&self) -> Vec<u8> {
+        self.name.clone()
+    }
+
+    fn get_id(&self) -> Vec<u8> {
+        b"00 2A".to_vec()
+    }
+
+    fn get_blocks_free(&self) -> u16 {
+        0
+    }
+
+    fn get_entries(&self) -> Vec<DirEntry> {
+        vec![DirEntry {
+            name: self.name.clone(),
+            file_type: self.file_type,
+            blocks: (self.data.len() / 254 + 1) as u16,
+        }]
+    }
+
+    fn read_file(&self, index: usize) -> Result<Vec<u8>, String> {
+        if index == 0 {
+            Ok(self.data.clone())
+        } else {
+            Err(format!("invalid file index {}", index))
+        }
+    }
+
+    fn is_write_protected(&self) -> bool {
+        true
+    }
+
+    fn write_file(
+        &mut self,
+        _name: &[u8],
+        _file_type: FileType,
+        _data: &[u8],
+        _replace: Option<usize>,
+    ) -> Result<(), String> {
+        Err("write protected".to_string())
+    }
 }
 
 struct P00Image {
-    data: Vec<u8>,
-    offset: u16,
+    file: Pc64File,
 }
 
 impl Image for P00Image {
     fn mount(&mut self, c64: &mut C64) {
         info!(target: "loader", "Mounting P00 image");
-        c64.load(&self.data, self.offset);
+        if self.file.file_type == FileType::Prg && self.file.data.len() >= 2 {
+            let offset = u16::from(self.file.data[0]) | (u16::from(self.file.data[1]) << 8);
+            c64.load(&self.file.data[2..], offset);
+        } else {
+            if c64.get_drive().is_some() {
+                warn!(target: "loader", "PC64 files require virtual drive");
+            }
+            c64.attach_file_system(Box::new(self.file.clone()));
+        }
     }
 
-    fn unmount(&mut self, _c64: &mut C64) {}
+    fn unmount(&mut self, c64: &mut C64) {
+        if self.file.file_type != FileType::Prg {
+            c64.detach_file_system();
+        }
+    }
 }
 
-pub struct P00Loader;
+pub struct P00Loader {
+    file_type: FileType,
+}
 
 impl P00Loader {
-    pub fn new() -> impl Loader {
-        Self {}
+    /// Create loader for PC64 files, the file type is given by the first letter of the extension.
+    pub fn new(file_type: FileType) -> Self {
+        Self { file_type }
+    }
+
+    /// Read PC64 file exposing its original C64 name and type.
+    pub fn read_file(&self, reader: &mut dyn Reader) -> io::Result<Pc64File> {
+        let header = self
+            .read_header(reader)
+            .map_err(|_| "invalid P00 header".to_owned())?;
+        self.validate_header(&header)?;
+        let name = header
+            .filename
+            .iter()
+            .cloned()
+            .take_while(|c| *c != 0x00 && *c != 0xa0)
+            .collect();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Ok(Pc64File {
+            name,
+            file_type: self.file_type,
+            record_size: header.record_size,
+            data,
+        })
     }
 
     fn read_header(&self, rdr: &mut dyn Reader) -> io::Result<Header> {
@@ -61,7 +151,7 @@ impl P00Loader {
                 filename
             },
             reserved_2: rdr.read_u8()?,
-            reserved_3: rdr.read_u8()?,
+            record_size: rdr.read_u8()?,
         };
         Ok(header)
     }
@@ -80,20 +170,27 @@ impl P00Loader {
 impl Loader for P00Loader {
     fn autostart(&self, reader: &mut dyn Reader) -> io::Result<AutostartMethod> {
         let image = self.load(reader)?;
-        let autostart = Autostart::new(autostart::Mode::Run, image);
-        Ok(AutostartMethod::WithAutostart(Some(autostart)))
+        if self.file_type == FileType::Prg {
+            let autostart = Autostart::new(autostart::Mode::Run, image);
+            Ok(AutostartMethod::WithAutostart(Some(autostart)))
+        } else {
+            Ok(AutostartMethod::WithImage(image))
+        }
     }
 
     fn load(&self, reader: &mut dyn Reader) -> io::Result<Box<dyn Image>> {
         info!(target: "loader", "Loading P00");
-        let header = self
-            .read_header(reader)
-            .map_err(|_| "invalid P00 header".to_owned())?;
-        self.validate_header(&header)?;
-        let offset = reader.read_u16::<LittleEndian>()?;
-        let mut data = Vec::new();
-        reader.read_to_end(&mut data)?;
-        info!(target: "loader", "Program offset 0x{:x}, size {}", offset, data.len());
-        Ok(Box::new(P00Image { data, offset }))
+        let file = self.read_file(reader)?;
+        if file.file_type == FileType::Prg && file.data.len() < 2 {
+            return Err("invalid P00 program".to_owned());
+        }
+        info!(
+            target: "loader",
+            "File {}, type {:?}, size {}",
+            file.name.iter().map(|c| *c as char).collect::<String>(),
+            file.file_type,
+            file.data.len()
+        );
+        Ok(Box::new(P00Image { file }))
     }
 }
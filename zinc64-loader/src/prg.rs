@@ -11,11 +11,17 @@ use zinc64_system::{Autostart, AutostartMethod, Image, C64};
 use super::Loader;
 use crate::io::{self, ReadBytesExt, Reader};
 
-struct PrgImage {
+pub struct PrgImage {
     data: Vec<u8>,
     offset: u16,
 }
 
+impl PrgImage {
+    pub fn new(data: Vec<u8>, offset: u16) -> Self {
+        Self { data, offset }
+    }
+}
+
 impl Image for PrgImage {
     fn mount(&mut self, c64: &mut C64) {
         info!(target: "loader", "Mounting PRG image");
@@ -0,0 +1,752 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+#[cfg(not(feature = "std"))]
+use alloc::vec;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use zinc64_core::factory::{SidModel, SystemModel};
+use zinc64_system::autostart;
+use zinc64_system::{Autostart, AutostartMethod, Image, C64};
+
+use super::Loader;
+use crate::io::{self, Reader};
+
+// SPEC: https://www.hvsc.c64.org/download/C64Music/DOCUMENTS/SID_file_format.txt
+
+// Design:
+//   Tunes are started once the kernal finished booting. PSID tunes get a small driver that
+//   calls init with the song number and then calls play from a raster or CIA 1 timer A
+//   interrupt depending on the song speed. The interrupt goes through the kernal vector at
+//   $0314, so the driver keeps $01 at $37 except while calling into the tune. RSID tunes
+//   expect a real C64 environment and only have init called, BASIC tunes are started with
+//   RUN. Switching songs reinstalls the tune data and the driver to get a clean state.
+
+static PSID_SIG: &[u8; 4] = b"PSID";
+static RSID_SIG: &[u8; 4] = b"RSID";
+
+const HEADER_SIZE_V1: usize = 0x76;
+const HEADER_SIZE_V2: usize = 0x7c;
+const BASIC_START: u16 = 0x0801;
+const SONG_NUMBER_ADDR: u16 = 0x030c;
+
+// Driver locations tried when the tune does not specify free memory
+const DRIVER_LOCATIONS: [(u16, u16); 3] = [(0x0334, 0x03fc), (0xc000, 0xd000), (0x0400, 0x0800)];
+
+const FLAG_MUS_DATA: u16 = 1 << 0;
+const FLAG_BASIC: u16 = 1 << 1;
+
+#[derive(Clone)]
+pub struct SidTune {
+    rsid: bool,
+    version: u16,
+    load_address: u16,
+    init_address: u16,
+    play_address: u16,
+    songs: u16,
+    start_song: u16,
+    speed: u32,
+    name: String,
+    author: String,
+    released: String,
+    flags: u16,
+    start_page: u8,
+    page_length: u8,
+    second_sid: u8,
+    third_sid: u8,
+    data: Vec<u8>,
+    driver_address: u16,
+}
+
+impl SidTune {
+    pub fn parse(data: &[u8]) -> io::Result<SidTune> {
+        if data.len() < HEADER_SIZE_V1 {
+            return Err("invalid SID header".to_owned());
+        }
+        let rsid = match &data[0..4] {
+            sig if sig == PSID_SIG => false,
+            sig if sig == RSID_SIG => true,
+            _ => return Err("invalid SID signature".to_owned()),
+        };
+        let version = BigEndian::read_u16(&data[0x04..0x06]);
+        if !(1..=4).contains(&version) || (rsid && version < 2) {
+            return Err(format!("unsupported SID version {}", version));
+        }
+        let data_offset = BigEndian::read_u16(&data[0x06..0x08]) as usize;
+        let header_size = if version == 1 {
+            HEADER_SIZE_V1
+        } else {
+            HEADER_SIZE_V2
+        };
+        if data_offset < header_size || data_offset > data.len() {
+            return Err(format!("invalid SID data offset 0x{:x}", data_offset));
+        }
+        let v2 = |offset: usize| {
+            if version >= 2 {
+                data[offset]
+            } else {
+                0
+            }
+        };
+        let flags = u16::from(v2(0x76)) << 8 | u16::from(v2(0x77));
+        if flags & FLAG_MUS_DATA != 0 {
+            return Err("unsupported SID with MUS data".to_owned());
+        }
+        let mut load_address = BigEndian::read_u16(&data[0x08..0x0a]);
+        let mut tune_data = &data[data_offset..];
+        if load_address == 0 {
+            if tune_data.len() < 2 {
+                return Err("invalid SID load address".to_owned());
+            }
+            load_address = LittleEndian::read_u16(&tune_data[0..2]);
+            tune_data = &tune_data[2..];
+        }
+        if tune_data.is_empty() || load_address as usize + tune_data.len() > 0x10000 {
+            return Err(format!(
+                "invalid SID data at 0x{:04x}, size {}",
+                load_address,
+                tune_data.len()
+            ));
+        }
+        let init_address = match BigEndian::read_u16(&data[0x0a..0x0c]) {
+            0 => load_address,
+            address => address,
+        };
+        let songs = BigEndian::read_u16(&data[0x0e..0x10]);
+        if songs == 0 || songs > 256 {
+            return Err(format!("invalid SID song count {}", songs));
+        }
+        let start_song = match BigEndian::read_u16(&data[0x10..0x12]) {
+            song if (1..=songs).contains(&song) => song,
+            _ => 1,
+        };
+        let mut tune = SidTune {
+            rsid,
+            version,
+            load_address,
+            init_address,
+            play_address: BigEndian::read_u16(&data[0x0c..0x0e]),
+            songs,
+            start_song,
+            speed: BigEndian::read_u32(&data[0x12..0x16]),
+            name: Self::read_string(&data[0x16..0x36]),
+            author: Self::read_string(&data[0x36..0x56]),
+            released: Self::read_string(&data[0x56..0x76]),
+            flags,
+            start_page: v2(0x78),
+            page_length: v2(0x79),
+            second_sid: if version >= 3 { data[0x7a] } else { 0 },
+            third_sid: if version >= 4 { data[0x7b] } else { 0 },
+            data: tune_data.to_vec(),
+            driver_address: 0,
+        };
+        if tune.is_basic() {
+            if load_address != BASIC_START {
+                return Err(format!(
+                    "invalid BASIC SID load address 0x{:04x}",
+                    load_address
+                ));
+            }
+        } else {
+            tune.driver_address = tune.find_driver_address()?;
+        }
+        info!(
+            target: "loader",
+            "Found {} v{} \"{}\" by {}, songs {}, load 0x{:04x}, init 0x{:04x}, play 0x{:04x}",
+            if rsid { "RSID" } else { "PSID" },
+            version,
+            tune.name,
+            tune.author,
+            songs,
+            load_address,
+            init_address,
+            tune.play_address
+        );
+        Ok(tune)
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_author(&self) -> &str {
+        &self.author
+    }
+
+    pub fn get_released(&self) -> &str {
+        &self.released
+    }
+
+    pub fn get_songs(&self) -> u16 {
+        self.songs
+    }
+
+    pub fn get_start_song(&self) -> u16 {
+        self.start_song
+    }
+
+    /// Get SIDs in addition to the one at $D400 along with their preferred model.
+    pub fn get_extra_sids(&self) -> Vec<(u16, Option<SidModel>)> {
+        let mut sids = Vec::new();
+        for (i, &address) in [self.second_sid, self.third_sid].iter().enumerate() {
+            let valid = match address {
+                0x42..=0x7f | 0xe0..=0xfe => address & 0x01 == 0,
+                _ => false,
+            };
+            if valid {
+                let model = Self::sid_model((self.flags >> (6 + i * 2)) as u8);
+                sids.push((0xd000 | u16::from(address) << 4, model));
+            }
+        }
+        sids
+    }
+
+    /// Get SID model the tune was made for, if it specifies exactly one.
+    pub fn get_sid_model(&self) -> Option<SidModel> {
+        Self::sid_model((self.flags >> 4) as u8)
+    }
+
+    /// Get PAL or NTSC system model the tune was made for, if it specifies exactly one.
+    pub fn get_system_model(&self) -> Option<SystemModel> {
+        match (self.flags >> 2) & 0x03 {
+            1 => Some(SystemModel::c64_pal()),
+            2 => Some(SystemModel::c64_ntsc()),
+            _ => None,
+        }
+    }
+
+    pub fn is_rsid(&self) -> bool {
+        self.rsid
+    }
+
+    fn is_basic(&self) -> bool {
+        self.rsid && self.flags & FLAG_BASIC != 0
+    }
+
+    /// Create autostart starting the song once the system booted.
+    pub fn autostart(&self, song: u16) -> AutostartMethod {
+        let mode = if self.is_basic() {
+            autostart::Mode::Run
+        } else {
+            autostart::Mode::Mount
+        };
+        let image = Box::new(SidImage {
+            tune: self.clone(),
+            song,
+        });
+        AutostartMethod::WithAutostart(Some(Autostart::new(mode, image)))
+    }
+
+    /// Switch to another song of a tune that is already playing.
+    pub fn select_song(&self, c64: &mut C64, song: u16) {
+        if self.is_basic() {
+            // BASIC tunes are started from scratch with RUN
+            self.autostart(song).execute(c64);
+        } else {
+            self.install(c64, song);
+        }
+    }
+
+    /// Load tune data and start the driver for the song, numbered from 1.
+    pub fn install(&self, c64: &mut C64, song: u16) {
+        let song = song.max(1).min(self.songs);
+        info!(target: "loader", "Playing song {}/{}", song, self.songs);
+        c64.load(&self.data, self.load_address);
+        if self.is_basic() {
+            let end = self.load_address + self.data.len() as u16;
+            let (lo, hi) = (end as u8, (end >> 8) as u8);
+            // Variables, arrays and free memory start after the program
+            c64.load(&[lo, hi, lo, hi, lo, hi], 0x002d);
+            c64.load(&[(song - 1) as u8], SONG_NUMBER_ADDR);
+        } else {
+            let driver = self.build_driver(self.driver_address, song);
+            c64.load(&driver, self.driver_address);
+            c64.get_cpu_mut().set_pc(self.driver_address);
+        }
+    }
+
+    #[rustfmt::skip]
+    fn build_driver(&self, base: u16, song: u16) -> Vec<u8> {
+        let mut code = Vec::new();
+        code.extend_from_slice(&[
+            0x78,                   // sei
+            0xd8,                   // cld
+            0xa2, 0xff,             // ldx #$ff
+            0x9a,                   // txs
+        ]);
+        if !self.rsid {
+            // Stop interrupts set up by the kernal or the previous song
+            code.extend_from_slice(&[
+                0xa9, 0x7f,         // lda #$7f
+                0x8d, 0x0d, 0xdc,   // sta $dc0d
+                0x8d, 0x0d, 0xdd,   // sta $dd0d
+                0xad, 0x0d, 0xdc,   // lda $dc0d
+                0xad, 0x0d, 0xdd,   // lda $dd0d
+                0xa9, 0x00,         // lda #$00
+                0x8d, 0x1a, 0xd0,   // sta $d01a
+                0xa9, 0xff,         // lda #$ff
+                0x8d, 0x19, 0xd0,   // sta $d019
+            ]);
+        }
+        let mut sids = vec![0xd400];
+        sids.extend(self.get_extra_sids().iter().map(|(address, _)| *address));
+        for address in sids {
+            code.extend_from_slice(&[
+                0xa9, 0x00,         // lda #$00
+                0xa2, 0x18,         // ldx #$18
+                0x9d, address as u8, (address >> 8) as u8, // sta sid,x
+                0xca,               // dex
+                0x10, 0xfa,         // bpl sta
+            ]);
+        }
+        let init = self.init_address;
+        code.extend_from_slice(&[
+            0xa9, Self::bank_for(init, self.rsid), // lda #bank
+            0x85, 0x01,             // sta $01
+            0xa9, (song - 1) as u8, // lda #song
+            0x20, init as u8, (init >> 8) as u8, // jsr init
+            0xa9, 0x37,             // lda #$37
+            0x85, 0x01,             // sta $01
+        ]);
+        let install_handler = !self.rsid && self.play_address != 0;
+        let handler_patch = code.len();
+        if install_handler {
+            code.extend_from_slice(&[
+                0xa9, 0x00,         // lda #<handler
+                0x8d, 0x14, 0x03,   // sta $0314
+                0xa9, 0x00,         // lda #>handler
+                0x8d, 0x15, 0x03,   // sta $0315
+            ]);
+            if self.is_cia_speed(song) {
+                code.extend_from_slice(&[
+                    0xa9, 0x81,       // lda #$81
+                    0x8d, 0x0d, 0xdc, // sta $dc0d
+                ]);
+            } else {
+                code.extend_from_slice(&[
+                    0xad, 0x11, 0xd0, // lda $d011
+                    0x29, 0x7f,       // and #$7f
+                    0x8d, 0x11, 0xd0, // sta $d011
+                    0xa9, 0x00,       // lda #$00
+                    0x8d, 0x12, 0xd0, // sta $d012
+                    0xa9, 0x01,       // lda #$01
+                    0x8d, 0x1a, 0xd0, // sta $d01a
+                ]);
+            }
+        }
+        code.push(0x58);            // cli
+        let idle = base.wrapping_add(code.len() as u16);
+        code.extend_from_slice(&[
+            0x4c, idle as u8, (idle >> 8) as u8, // jmp idle
+        ]);
+        if install_handler {
+            let handler = base.wrapping_add(code.len() as u16);
+            code[handler_patch + 1] = handler as u8;
+            code[handler_patch + 6] = (handler >> 8) as u8;
+            let play = self.play_address;
+            code.extend_from_slice(&[
+                0xa9, 0xff,         // lda #$ff
+                0x8d, 0x19, 0xd0,   // sta $d019
+                0xad, 0x0d, 0xdc,   // lda $dc0d
+                0xa9, Self::bank_for(play, false), // lda #bank
+                0x85, 0x01,         // sta $01
+                0x20, play as u8, (play >> 8) as u8, // jsr play
+                0xa9, 0x37,         // lda #$37
+                0x85, 0x01,         // sta $01
+                0x4c, 0x81, 0xea,   // jmp $ea81
+            ]);
+        }
+        code
+    }
+
+    fn find_driver_address(&self) -> io::Result<u16> {
+        let size = self.build_driver(0, 1).len() as u32;
+        let tune_start = u32::from(self.load_address);
+        let tune_end = tune_start + self.data.len() as u32;
+        let free = |start: u32, end: u32| {
+            start + size <= end && (start + size <= tune_start || start >= tune_end)
+        };
+        if self.version >= 2 && self.start_page != 0 && self.start_page != 0xff {
+            let start = u32::from(self.start_page) << 8;
+            let end = start + (u32::from(self.page_length) << 8);
+            if free(start, end) {
+                return Ok(start as u16);
+            }
+        }
+        DRIVER_LOCATIONS
+            .iter()
+            .find(|(start, end)| free(u32::from(*start), u32::from(*end)))
+            .map(|(start, _)| *start)
+            .ok_or_else(|| "no free memory for SID driver".to_owned())
+    }
+
+    fn is_cia_speed(&self, song: u16) -> bool {
+        let bit = (song - 1).min(31);
+        self.rsid || self.speed & (1 << bit) != 0
+    }
+
+    fn bank_for(address: u16, rsid: bool) -> u8 {
+        match address {
+            _ if rsid => 0x37,
+            0xa000..=0xbfff => 0x36,
+            0xd000..=0xdfff => 0x34,
+            0xe000..=0xffff => 0x35,
+            _ => 0x37,
+        }
+    }
+
+    fn read_string(data: &[u8]) -> String {
+        data.iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as char)
+            .collect()
+    }
+
+    fn sid_model(value: u8) -> Option<SidModel> {
+        match value & 0x03 {
+            1 => Some(SidModel::Mos6581),
+            2 => Some(SidModel::Mos8580),
+            _ => None,
+        }
+    }
+}
+
+struct SidImage {
+    tune: SidTune,
+    song: u16,
+}
+
+impl Image for SidImage {
+    fn mount(&mut self, c64: &mut C64) {
+        info!(target: "loader", "Mounting SID image");
+        self.tune.install(c64, self.song);
+    }
+
+    fn unmount(&mut self, _c64: &mut C64) {}
+}
+
+pub struct SidLoader {
+    song: Option<u16>,
+}
+
+impl SidLoader {
+    /// Create loader for the specified song, starting with 1, or the default song of the tune.
+    pub fn new(song: Option<u16>) -> Self {
+        Self { song }
+    }
+}
+
+impl Loader for SidLoader {
+    fn autostart(&self, reader: &mut dyn Reader) -> io::Result<AutostartMethod> {
+        info!(target: "loader", "Loading SID");
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let tune = SidTune::parse(&data)?;
+        Ok(tune.autostart(self.song.unwrap_or(tune.start_song)))
+    }
+
+    fn load(&self, reader: &mut dyn Reader) -> io::Result<Box<dyn Image>> {
+        info!(target: "loader", "Loading SID");
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let tune = SidTune::parse(&data)?;
+        let song = self.song.unwrap_or(tune.start_song);
+        Ok(Box::new(SidImage { tune, song }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Header {
+        rsid: bool,
+        version: u16,
+        load_address: u16,
+        init_address: u16,
+        play_address: u16,
+        songs: u16,
+        start_song: u16,
+        speed: u32,
+        flags: u16,
+        start_page: u8,
+        page_length: u8,
+        second_sid: u8,
+        third_sid: u8,
+    }
+
+    impl Default for Header {
+        fn default() -> Self {
+            Header {
+                rsid: false,
+                version: 2,
+                load_address: 0x1000,
+                init_address: 0x1000,
+                play_address: 0x1003,
+                songs: 1,
+                start_song: 1,
+                speed: 0,
+                flags: 0,
+                start_page: 0,
+                page_length: 0,
+                second_sid: 0,
+                third_sid: 0,
+            }
+        }
+    }
+
+    fn build_tune(header: &Header, data: &[u8]) -> Vec<u8> {
+        let header_size = if header.version == 1 {
+            HEADER_SIZE_V1
+        } else {
+            HEADER_SIZE_V2
+        };
+        let mut tune = vec![0; header_size];
+        tune[0..4].copy_from_slice(if header.rsid { RSID_SIG } else { PSID_SIG });
+        BigEndian::write_u16(&mut tune[0x04..0x06], header.version);
+        BigEndian::write_u16(&mut tune[0x06..0x08], header_size as u16);
+        BigEndian::write_u16(&mut tune[0x08..0x0a], header.load_address);
+        BigEndian::write_u16(&mut tune[0x0a..0x0c], header.init_address);
+        BigEndian::write_u16(&mut tune[0x0c..0x0e], header.play_address);
+        BigEndian::write_u16(&mut tune[0x0e..0x10], header.songs);
+        BigEndian::write_u16(&mut tune[0x10..0x12], header.start_song);
+        BigEndian::write_u32(&mut tune[0x12..0x16], header.speed);
+        tune[0x16..0x1a].copy_from_slice(b"Name");
+        tune[0x36..0x3c].copy_from_slice(b"Author");
+        tune[0x56..0x5a].copy_from_slice(b"1987");
+        if header.version >= 2 {
+            BigEndian::write_u16(&mut tune[0x76..0x78], header.flags);
+            tune[0x78] = header.start_page;
+            tune[0x79] = header.page_length;
+            tune[0x7a] = header.second_sid;
+            tune[0x7b] = header.third_sid;
+        }
+        tune.extend_from_slice(data);
+        tune
+    }
+
+    fn find(code: &[u8], pattern: &[u8]) -> Option<usize> {
+        code.windows(pattern.len())
+            .position(|window| window == pattern)
+    }
+
+    #[test]
+    fn parse_v1_with_load_address_in_data() {
+        let header = Header {
+            version: 1,
+            load_address: 0,
+            init_address: 0,
+            songs: 3,
+            start_song: 2,
+            ..Header::default()
+        };
+        let tune = SidTune::parse(&build_tune(&header, &[0x00, 0x20, 0x60, 0x60])).unwrap();
+        assert!(!tune.is_rsid());
+        assert_eq!(0x2000, tune.load_address);
+        assert_eq!(0x2000, tune.init_address);
+        assert_eq!(vec![0x60, 0x60], tune.data);
+        assert_eq!(3, tune.get_songs());
+        assert_eq!(2, tune.get_start_song());
+        assert_eq!("Name", tune.get_name());
+        assert_eq!("Author", tune.get_author());
+        assert_eq!("1987", tune.get_released());
+        assert_eq!(None, tune.get_sid_model());
+        assert!(tune.get_system_model().is_none());
+        assert!(tune.get_extra_sids().is_empty());
+    }
+
+    #[test]
+    fn parse_v2_models_and_start_page() {
+        let header = Header {
+            flags: 2 << 4 | 2 << 2,
+            start_page: 0x20,
+            page_length: 0x01,
+            second_sid: 0x42,
+            start_song: 5,
+            ..Header::default()
+        };
+        let tune = SidTune::parse(&build_tune(&header, &[0x60; 4])).unwrap();
+        assert_eq!(Some(SidModel::Mos8580), tune.get_sid_model());
+        assert_eq!(
+            SystemModel::c64_ntsc().cpu_freq,
+            tune.get_system_model().unwrap().cpu_freq
+        );
+        // Extra SIDs are only defined from v3 on and the start song falls back to 1
+        assert!(tune.get_extra_sids().is_empty());
+        assert_eq!(1, tune.get_start_song());
+        assert_eq!(0x2000, tune.driver_address);
+    }
+
+    #[test]
+    fn parse_v3_and_v4_extra_sids() {
+        let header = Header {
+            version: 3,
+            flags: 1 << 4 | 2 << 6 | 1 << 8,
+            second_sid: 0x42,
+            third_sid: 0xe0,
+            ..Header::default()
+        };
+        let tune = SidTune::parse(&build_tune(&header, &[0x60; 4])).unwrap();
+        assert_eq!(Some(SidModel::Mos6581), tune.get_sid_model());
+        assert_eq!(
+            vec![(0xd420, Some(SidModel::Mos8580))],
+            tune.get_extra_sids()
+        );
+        let header = Header {
+            version: 4,
+            ..header
+        };
+        let tune = SidTune::parse(&build_tune(&header, &[0x60; 4])).unwrap();
+        assert_eq!(
+            vec![
+                (0xd420, Some(SidModel::Mos8580)),
+                (0xde00, Some(SidModel::Mos6581))
+            ],
+            tune.get_extra_sids()
+        );
+    }
+
+    #[test]
+    fn ignore_invalid_extra_sid_addresses() {
+        for address in &[0x40, 0x43, 0x80, 0xd4, 0xff] {
+            let header = Header {
+                version: 4,
+                second_sid: *address,
+                third_sid: *address,
+                ..Header::default()
+            };
+            let tune = SidTune::parse(&build_tune(&header, &[0x60; 4])).unwrap();
+            assert!(tune.get_extra_sids().is_empty(), "address 0x{:x}", address);
+        }
+    }
+
+    #[test]
+    fn speed_bits_select_cia_per_song() {
+        let header = Header {
+            songs: 40,
+            speed: 0x8000_0002,
+            ..Header::default()
+        };
+        let tune = SidTune::parse(&build_tune(&header, &[0x60; 4])).unwrap();
+        assert!(!tune.is_cia_speed(1));
+        assert!(tune.is_cia_speed(2));
+        assert!(!tune.is_cia_speed(3));
+        assert!(!tune.is_cia_speed(31));
+        // Songs from 32 on share the last bit
+        assert!(tune.is_cia_speed(32));
+        assert!(tune.is_cia_speed(40));
+        let header = Header {
+            rsid: true,
+            play_address: 0,
+            ..Header::default()
+        };
+        let tune = SidTune::parse(&build_tune(&header, &[0x60; 4])).unwrap();
+        assert!(tune.is_cia_speed(1));
+    }
+
+    #[test]
+    fn reject_invalid_headers() {
+        let mut data = build_tune(&Header::default(), &[0x60; 4]);
+        data[0] = b'X';
+        assert!(SidTune::parse(&data).is_err());
+        let header = Header {
+            rsid: true,
+            version: 1,
+            ..Header::default()
+        };
+        assert!(SidTune::parse(&build_tune(&header, &[0x60; 4])).is_err());
+        let header = Header {
+            flags: FLAG_MUS_DATA,
+            ..Header::default()
+        };
+        assert!(SidTune::parse(&build_tune(&header, &[0x60; 4])).is_err());
+        let header = Header {
+            songs: 0,
+            ..Header::default()
+        };
+        assert!(SidTune::parse(&build_tune(&header, &[0x60; 4])).is_err());
+        let header = Header {
+            load_address: 0xfffe,
+            ..Header::default()
+        };
+        assert!(SidTune::parse(&build_tune(&header, &[0x60; 4])).is_err());
+    }
+
+    #[test]
+    fn driver_calls_init_and_play_from_raster_interrupt() {
+        let header = Header {
+            songs: 3,
+            ..Header::default()
+        };
+        let tune = SidTune::parse(&build_tune(&header, &[0x60; 4])).unwrap();
+        assert_eq!(0x0334, tune.driver_address);
+        let base = tune.driver_address;
+        let code = tune.build_driver(base, 2);
+        assert!(code.len() <= 0x03fc - 0x0334);
+        // Init is called with the song number in A
+        assert!(find(&code, &[0xa9, 0x01, 0x20, 0x00, 0x10]).is_some());
+        // Raster interrupt on line 0
+        assert!(find(&code, &[0xa9, 0x01, 0x8d, 0x1a, 0xd0]).is_some());
+        // Idle loop jumps to itself
+        let idle = find(&code, &[0x58, 0x4c]).unwrap() + 1;
+        let target = base + idle as u16;
+        assert_eq!(
+            [0x4c, target as u8, (target >> 8) as u8],
+            code[idle..idle + 3]
+        );
+        // Handler installed at $0314 acknowledges the interrupt and calls play
+        let handler = idle + 3;
+        let address = base + handler as u16;
+        let install = find(&code, &[0x8d, 0x14, 0x03]).unwrap();
+        assert_eq!(address as u8, code[install - 1]);
+        assert_eq!((address >> 8) as u8, code[install + 4]);
+        assert_eq!([0xa9, 0xff, 0x8d, 0x19, 0xd0], code[handler..handler + 5]);
+        assert!(find(&code[handler..], &[0x20, 0x03, 0x10]).is_some());
+        assert_eq!([0x4c, 0x81, 0xea], code[code.len() - 3..]);
+    }
+
+    #[test]
+    fn driver_uses_cia_timer_and_clears_extra_sids() {
+        let header = Header {
+            version: 3,
+            speed: 1,
+            second_sid: 0x42,
+            ..Header::default()
+        };
+        let tune = SidTune::parse(&build_tune(&header, &[0x60; 4])).unwrap();
+        let code = tune.build_driver(tune.driver_address, 1);
+        assert!(find(&code, &[0xa9, 0x81, 0x8d, 0x0d, 0xdc]).is_some());
+        assert!(find(&code, &[0x9d, 0x00, 0xd4]).is_some());
+        assert!(find(&code, &[0x9d, 0x20, 0xd4]).is_some());
+    }
+
+    #[test]
+    fn rsid_driver_only_calls_init() {
+        let header = Header {
+            rsid: true,
+            load_address: 0xc000,
+            init_address: 0xc000,
+            play_address: 0,
+            ..Header::default()
+        };
+        let tune = SidTune::parse(&build_tune(&header, &[0x60; 4])).unwrap();
+        let code = tune.build_driver(tune.driver_address, 1);
+        assert!(find(&code, &[0x8d, 0x14, 0x03]).is_none());
+        assert!(find(&code, &[0x8d, 0x0d, 0xdc]).is_none());
+        assert!(find(&code, &[0x20, 0x00, 0xc0]).is_some());
+    }
+
+    #[test]
+    fn driver_avoids_tune_memory() {
+        let header = Header {
+            load_address: 0x0300,
+            init_address: 0x0300,
+            play_address: 0x0303,
+            ..Header::default()
+        };
+        let tune = SidTune::parse(&build_tune(&header, &[0x60; 0x100])).unwrap();
+        assert_eq!(0xc000, tune.driver_address);
+    }
+}
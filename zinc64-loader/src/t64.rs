@@ -0,0 +1,224 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+use byteorder::{ByteOrder, LittleEndian};
+use zinc64_system::autostart;
+use zinc64_system::{Autostart, AutostartMethod, Image};
+
+use super::Loader;
+use crate::io::{self, Reader};
+use crate::prg::PrgImage;
+
+// SPEC: http://ist.uwaterloo.ca/~schepers/formats/T64.TXT
+
+static HEADER_SIG: &[u8; 3] = b"C64";
+
+const HEADER_SIZE: usize = 0x40;
+const ENTRY_SIZE: usize = 0x20;
+
+struct Entry {
+    entry_type: u8,
+    start: u16,
+    end: u16,
+    offset: usize,
+    filename: [u8; 16],
+}
+
+pub struct T64Loader {
+    entry: usize,
+}
+
+impl T64Loader {
+    /// Create loader for the specified directory entry, starting with 0 for the first file.
+    pub fn new(entry: usize) -> Self {
+        Self { entry }
+    }
+
+    fn read_entries(&self, data: &[u8]) -> io::Result<Vec<Entry>> {
+        if data.len() < HEADER_SIZE || &data[0..3] != HEADER_SIG {
+            return Err("invalid T64 signature".to_owned());
+        }
+        let version = LittleEndian::read_u16(&data[0x20..0x22]);
+        let max_entries = LittleEndian::read_u16(&data[0x22..0x24]) as usize;
+        let used_entries = LittleEndian::read_u16(&data[0x24..0x26]);
+        info!(
+            target: "loader",
+            "Found tape, version 0x{:x}, entries {}/{}",
+            version,
+            used_entries,
+            max_entries
+        );
+        // Some images report no directory slots, assume the single entry they contain
+        let max_entries = max_entries.max(1);
+        let mut entries = Vec::new();
+        for i in 0..max_entries {
+            let pos = HEADER_SIZE + i * ENTRY_SIZE;
+            if pos + ENTRY_SIZE > data.len() {
+                break;
+            }
+            let entry = &data[pos..pos + ENTRY_SIZE];
+            if entry[0] == 0 {
+                continue;
+            }
+            let mut filename = [0u8; 16];
+            filename.copy_from_slice(&entry[0x10..0x20]);
+            entries.push(Entry {
+                entry_type: entry[0],
+                start: LittleEndian::read_u16(&entry[2..4]),
+                end: LittleEndian::read_u16(&entry[4..6]),
+                offset: LittleEndian::read_u32(&entry[8..12]) as usize,
+                filename,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Get file size taking into account end addresses commonly broken by tape converters.
+    /// The space up to the next file or the end of the image limits the size.
+    fn get_size(entry: &Entry, entries: &[Entry], len: usize) -> usize {
+        let next = entries
+            .iter()
+            .map(|other| other.offset)
+            .filter(|offset| *offset > entry.offset)
+            .min()
+            .unwrap_or(len)
+            .min(len);
+        let available = next.saturating_sub(entry.offset);
+        let declared = entry.end.wrapping_sub(entry.start) as usize;
+        if declared == 0 || declared > available {
+            if declared != available {
+                warn!(
+                    target: "loader",
+                    "Invalid end address 0x{:x}, using size {}",
+                    entry.end,
+                    available
+                );
+            }
+            available
+        } else {
+            declared
+        }
+    }
+}
+
+impl Loader for T64Loader {
+    fn autostart(&self, reader: &mut dyn Reader) -> io::Result<AutostartMethod> {
+        let image = self.load(reader)?;
+        let autostart = Autostart::new(autostart::Mode::Run, image);
+        Ok(AutostartMethod::WithAutostart(Some(autostart)))
+    }
+
+    fn load(&self, reader: &mut dyn Reader) -> io::Result<Box<dyn Image>> {
+        info!(target: "loader", "Loading T64");
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let entries = self.read_entries(&data)?;
+        let entry = entries
+            .get(self.entry)
+            .ok_or_else(|| format!("invalid T64 entry {}", self.entry))?;
+        if entry.offset > data.len() {
+            return Err(format!("invalid T64 entry offset 0x{:x}", entry.offset));
+        }
+        if entry.entry_type != 1 {
+            warn!(target: "loader", "Unsupported entry type {}", entry.entry_type);
+        }
+        let size = Self::get_size(entry, &entries, data.len());
+        let filename = entry
+            .filename
+            .iter()
+            .map(|c| *c as char)
+            .collect::<String>();
+        info!(
+            target: "loader",
+            "Program {}, address 0x{:x}, offset 0x{:x}, size {}",
+            filename.trim_end(),
+            entry.start,
+            entry.offset,
+            size
+        );
+        let program = data[entry.offset..entry.offset + size].to_vec();
+        Ok(Box::new(PrgImage::new(program, entry.start)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Entry start, end and offset of each file
+    fn build_tape(max_entries: u16, files: &[(u16, u16, u32)], data_len: usize) -> Vec<u8> {
+        let mut tape = vec![0u8; HEADER_SIZE];
+        tape[0..19].copy_from_slice(b"C64 tape image file");
+        LittleEndian::write_u16(&mut tape[0x20..0x22], 0x0101);
+        LittleEndian::write_u16(&mut tape[0x22..0x24], max_entries);
+        LittleEndian::write_u16(&mut tape[0x24..0x26], files.len() as u16);
+        for (i, (start, end, offset)) in files.iter().enumerate() {
+            let mut entry = [0x20u8; ENTRY_SIZE];
+            entry[0] = 1;
+            entry[1] = 0x82;
+            LittleEndian::write_u16(&mut entry[2..4], *start);
+            LittleEndian::write_u16(&mut entry[4..6], *end);
+            LittleEndian::write_u32(&mut entry[8..12], *offset);
+            entry[0x10] = b'A' + i as u8;
+            tape.extend_from_slice(&entry);
+        }
+        tape.resize(HEADER_SIZE + max_entries.max(1) as usize * ENTRY_SIZE, 0);
+        tape.resize(tape.len() + data_len, 0xea);
+        tape
+    }
+
+    fn load(entry: usize, tape: &[u8]) -> io::Result<Box<dyn Image>> {
+        let mut reader = tape;
+        T64Loader::new(entry).load(&mut reader)
+    }
+
+    #[test]
+    fn read_entries() {
+        let tape = build_tape(3, &[(0x0801, 0x0811, 0xa0), (0xc000, 0xc010, 0xb0)], 0x20);
+        let entries = T64Loader::new(0).read_entries(&tape).unwrap();
+        assert_eq!(2, entries.len());
+        assert_eq!(0x0801, entries[0].start);
+        assert_eq!(0x0811, entries[0].end);
+        assert_eq!(0xa0, entries[0].offset);
+        assert_eq!(b'A', entries[0].filename[0]);
+        assert_eq!(0xc000, entries[1].start);
+        assert_eq!(0xb0, entries[1].offset);
+        assert_eq!(b'B', entries[1].filename[0]);
+        assert!(T64Loader::new(0).read_entries(&tape[..0x20]).is_err());
+    }
+
+    #[test]
+    fn get_size_clamps_broken_end_address() {
+        let tape = build_tape(2, &[(0x0801, 0xc3c6, 0x80), (0x1000, 0x0000, 0x90)], 0x20);
+        let entries = T64Loader::new(0).read_entries(&tape).unwrap();
+        // Limited by the next file
+        assert_eq!(0x10, T64Loader::get_size(&entries[0], &entries, tape.len()));
+        // Limited by the end of the image
+        assert_eq!(0x10, T64Loader::get_size(&entries[1], &entries, tape.len()));
+    }
+
+    #[test]
+    fn zero_max_entries_reads_single_entry() {
+        let tape = build_tape(0, &[(0x0801, 0x0811, 0x60)], 0x10);
+        let entries = T64Loader::new(0).read_entries(&tape).unwrap();
+        assert_eq!(1, entries.len());
+        assert!(load(0, &tape).is_ok());
+    }
+
+    #[test]
+    fn reject_invalid_entries() {
+        let tape = build_tape(1, &[(0x0801, 0x0811, 0x60)], 0x10);
+        match load(1, &tape) {
+            Err(err) => assert_eq!("invalid T64 entry 1", err),
+            Ok(_) => panic!("missing entry accepted"),
+        }
+        let tape = build_tape(1, &[(0x0801, 0x0811, 0x100)], 0x10);
+        match load(0, &tape) {
+            Err(err) => assert_eq!("invalid T64 entry offset 0x100", err),
+            Ok(_) => panic!("entry past end of image accepted"),
+        }
+    }
+}
@@ -36,7 +36,9 @@ struct TapImage {
 impl Image for TapImage {
     fn mount(&mut self, c64: &mut C64) {
         info!(target: "loader", "Mounting TAP image");
-        c64.attach_tape(self.tape.take().unwrap());
+        if !c64.attach_tape(self.tape.take().unwrap()) {
+            warn!(target: "loader", "TAP images require a model with a datassette");
+        }
     }
 
     fn unmount(&mut self, c64: &mut C64) {
@@ -114,6 +116,10 @@ struct TapTape {
 }
 
 impl Tape for TapTape {
+    fn get_pos(&self) -> usize {
+        self.pos
+    }
+
     fn read_pulse(&mut self) -> Option<u32> {
         if self.pos < self.data.len() {
             let value = self.data[self.pos] as u32;
@@ -140,7 +146,7 @@ impl Tape for TapTape {
     }
 
     fn seek(&mut self, pos: usize) -> bool {
-        if pos < self.data.len() {
+        if pos <= self.data.len() {
             self.pos = pos;
             true
         } else {
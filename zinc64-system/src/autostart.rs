@@ -13,7 +13,11 @@ pub trait Image {
 }
 
 pub enum Mode {
+    /// Mount image that starts on its own, without typing a command.
+    Mount,
     Run,
+    LoadDirectory,
+    LoadRun,
 }
 
 pub struct Autostart {
@@ -28,14 +32,19 @@ impl Autostart {
 
     pub fn execute(&mut self, c64: &mut C64) {
         self.image.mount(c64);
-        let keyboard = c64.get_keyboard();
-        let command = self.get_command().to_string() + "\n";
-        keyboard.enqueue(&command);
+        let command = self.get_command();
+        if !command.is_empty() {
+            let keyboard = c64.get_keyboard();
+            keyboard.enqueue(&(command.to_string() + "\n"));
+        }
     }
 
     fn get_command(&self) -> &str {
         match self.mode {
+            Mode::Mount => "",
             Mode::Run => "RUN",
+            Mode::LoadDirectory => "LOAD\"$\",8\nLIST",
+            Mode::LoadRun => "LOAD\"*\",8,1\nRUN",
         }
     }
 }
@@ -18,16 +18,33 @@ use zinc64_core::factory::*;
 use zinc64_core::util::*;
 
 use super::breakpoint::BreakpointManager;
-use super::{Autostart, Config};
+use super::movie::{InputPlayer, InputRecorder, Movie};
+use super::rewind::RewindBuffer;
+use super::virtual_drive::{FileSystem, VirtualDrive};
+use super::{Autostart, Config, SidConfig};
+use zinc64_core::device::cartridge::HwType;
 use zinc64_core::device::joystick;
-use zinc64_core::device::{Cartridge, Datassette, Joystick, Keyboard};
-use zinc64_core::factory::Tape;
+use zinc64_core::device::{
+    Cartridge, Datassette, Drive, IecBus, Joystick, Keyboard, LightPen, Reu,
+};
+use zinc64_core::factory::{Disk, Tape};
 use zinc64_core::mem::{ExpansionPort, Pla};
+use zinc64_core::sound::{MixerChannel, SidCapture, SidLog, SidWrite, SoundMixer, VoiceControl};
 
 // Design:
 //   C64 represents the machine itself and all of its components. Connections between different
 //   components are managed as component dependencies.
 
+// Snapshot format:
+//   Header   "Z64S" magic followed by u16 format version
+//   Sections clock, cpu, cia 1/2, vic, sid, extra sids, ram, color ram, expansion port, pla,
+//            datassette, keyboard, joysticks and drive, in that order
+//   Shared I/O lines are saved by the chip that drives them. Disk contents are not saved,
+//   REU RAM is saved with the expansion port.
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"Z64S";
+const SNAPSHOT_VERSION: u16 = 7;
+
 #[allow(dead_code)]
 #[derive(Copy, Clone)]
 enum BaseAddr {
@@ -51,24 +68,39 @@ pub struct C64 {
     cia_1: Shared<dyn Chip>,
     cia_2: Shared<dyn Chip>,
     sid: Shared<dyn Chip>,
+    extra_sids: Vec<Shared<dyn Chip>>,
     vic: Shared<dyn Chip>,
     // Memory
     color_ram: Shared<Ram>,
     expansion_port: Shared<ExpansionPort>,
+    mmu: Shared<Pla>,
     ram: Shared<Ram>,
+    reu: Option<Shared<Reu>>,
     // Peripherals
     datassette: Shared<Datassette>,
+    drive: Option<Shared<Drive>>,
     joystick_1: Option<Joystick>,
     joystick_2: Option<Joystick>,
     keyboard: Keyboard,
+    light_pen: Shared<LightPen>,
+    virtual_drive: VirtualDrive,
+    // Input
+    joystick_states: [SharedCell<u8>; 2],
+    keyboard_matrix: Shared<[u8; 16]>,
+    input_player: Option<InputPlayer>,
+    input_recorder: Option<InputRecorder>,
     // Buffers
     frame_buffer: Shared<dyn VideoOutput>,
     sound_buffer: Arc<dyn SoundOutput>,
+    sound_mixer: Option<Shared<SoundMixer>>,
+    sid_log: Shared<SidLog>,
+    voice_controls: Vec<Shared<VoiceControl>>,
     // Runtime State
     autostart: Option<Autostart>,
     breakpoints: BreakpointManager,
     clock: Rc<Clock>,
     frame_count: u32,
+    rewind: RewindBuffer,
     tick_fn: TickFn,
     vsync_flag: SharedCell<bool>,
 }
@@ -95,6 +127,7 @@ impl C64 {
         let cia_1_flag_pin = new_shared(Pin::new_low());
         let cia_1_port_a = new_shared(IoPort::new(0x00, 0xff));
         let cia_1_port_b = new_shared(IoPort::new(0x00, 0xff));
+        let light_pen = new_shared(LightPen::new(joystick_1_state.clone()));
         let cia_2_flag_pin = new_shared(Pin::new_low());
         let cia_2_port_a = new_shared(IoPort::new(0x00, 0xff));
         let cia_2_port_b = new_shared(IoPort::new(0x00, 0xff));
@@ -125,7 +158,73 @@ impl C64 {
             cia_2_flag_pin.clone(),
             nmi_line.clone(),
         );
-        let sid = factory.new_sid(config.model.sid_model, clock.clone(), sound_buffer.clone());
+        let mut sid_configs: Vec<&SidConfig> = Vec::new();
+        for sid_config in config.extra_sids.iter() {
+            if let Err(err) = sid_config.validate() {
+                error!(target: "c64", "Failed to initialize SID, error: {}", err);
+            } else if sid_configs
+                .iter()
+                .any(|other| other.base_address == sid_config.base_address)
+            {
+                error!(target: "c64", "Failed to initialize SID, error: duplicate address 0x{:04x}", sid_config.base_address);
+            } else {
+                sid_configs.push(sid_config);
+            }
+        }
+        // Each SID gets its own channel when there is more than one
+        let sound_mixer = if !sid_configs.is_empty() {
+            Some(new_shared(SoundMixer::new(
+                sid_configs.len() + 1,
+                sound_buffer.clone(),
+            )))
+        } else {
+            None
+        };
+        #[allow(clippy::arc_with_non_send_sync)]
+        let sid_output = |channel: usize| -> Arc<dyn SoundOutput> {
+            match sound_mixer {
+                Some(ref mixer) => Arc::new(MixerChannel::new(mixer.clone(), channel)),
+                None => sound_buffer.clone(),
+            }
+        };
+        // Register writes of all SIDs are captured to one log
+        let sid_log = new_shared(SidLog::new());
+        let sid_capture = |sid: Shared<dyn Chip>, base_address: u16| -> Shared<dyn Chip> {
+            new_shared(SidCapture::new(
+                sid,
+                base_address,
+                clock.clone(),
+                sid_log.clone(),
+            ))
+        };
+        let voice_controls = (0..=sid_configs.len())
+            .map(|_| new_shared(VoiceControl::new()))
+            .collect::<Vec<_>>();
+        let sid = sid_capture(
+            factory.new_sid_with_voice_control(
+                config.model.sid_model,
+                clock.clone(),
+                sid_output(0),
+                voice_controls[0].clone(),
+            ),
+            0xd400,
+        );
+        let extra_sids = sid_configs
+            .iter()
+            .enumerate()
+            .map(|(i, sid_config)| {
+                let sid = factory.new_sid_with_voice_control(
+                    sid_config.model,
+                    clock.clone(),
+                    sid_output(i + 1),
+                    voice_controls[i + 1].clone(),
+                );
+                (
+                    sid_config.base_address,
+                    sid_capture(sid, sid_config.base_address),
+                )
+            })
+            .collect::<Vec<_>>();
         let vic = factory.new_vic(
             config.model.vic_model,
             color_ram.clone(),
@@ -136,10 +235,46 @@ impl C64 {
             vsync_flag.clone(),
             ba_line.clone(),
             irq_line.clone(),
+            light_pen.clone(),
         );
 
         // Memory Controller and Processor
-        let expansion_port = new_shared(ExpansionPort::new(exp_io_line.clone()));
+        let expansion_port = new_shared(ExpansionPort::new(exp_io_line.clone(), nmi_line.clone()));
+        // Expansions whose image fails to load are left detached, so the image they
+        // were loaded from is not overwritten with blank RAM when saved back.
+        if let Some(ref georam_config) = config.georam {
+            let georam = Cartridge::new_georam(georam_config.size).and_then(|mut georam| {
+                if let Some(ref image) = georam_config.image {
+                    georam.load_ram(image)?;
+                }
+                Ok(georam)
+            });
+            match georam {
+                Ok(georam) => expansion_port.borrow_mut().attach(georam),
+                Err(err) => {
+                    error!(target: "c64", "Failed to initialize GeoRAM, error: {}", err);
+                }
+            }
+        }
+        let reu = config.reu.as_ref().and_then(|reu_config| {
+            let reu = Reu::new(reu_config.size, irq_line.clone()).and_then(|mut reu| {
+                if let Some(ref image) = reu_config.image {
+                    reu.load_ram(image)?;
+                }
+                Ok(reu)
+            });
+            match reu {
+                Ok(reu) => {
+                    let reu = new_shared(reu);
+                    expansion_port.borrow_mut().attach_reu(reu.clone());
+                    Some(reu)
+                }
+                Err(err) => {
+                    error!(target: "c64", "Failed to initialize REU, error: {}", err);
+                    None
+                }
+            }
+        });
         let mmu = new_shared(Pla::new());
         let mem = factory.new_memory(
             mmu.clone(),
@@ -152,6 +287,7 @@ impl C64 {
             rom_charset.clone(),
             rom_kernal.clone(),
             sid.clone(),
+            extra_sids.clone(),
             vic.clone(),
         );
         let cpu = factory.new_cpu(
@@ -183,6 +319,16 @@ impl C64 {
             None
         };
         let keyboard = Keyboard::new(keyboard_matrix.clone());
+        let iec_bus = new_shared(IecBus::new());
+        let drive = config.roms.drive.as_ref().and_then(|rom| {
+            match Drive::new(rom, 8, config.model.cpu_freq, iec_bus.clone()) {
+                Ok(drive) => Some(new_shared(drive)),
+                Err(err) => {
+                    error!(target: "c64", "Failed to initialize drive, error: {}", err);
+                    None
+                }
+            }
+        });
 
         // Observers
         let exp_io_line_clone_1 = exp_io_line.clone();
@@ -204,47 +350,98 @@ impl C64 {
                 let mode = cpu_port_io & 0x07 | expansion_port_io & 0x18;
                 mmu_clone_2.borrow_mut().switch_banks(mode);
             }));
+        let light_pen_clone = light_pen.clone();
+        cia_1_port_b
+            .borrow_mut()
+            .set_observer(Box::new(move |value| {
+                light_pen_clone
+                    .borrow_mut()
+                    .set_port_output(value & 0x10 != 0);
+            }));
+        let iec_bus_clone_1 = iec_bus.clone();
         let vic_base_address_clone = vic_base_address.clone();
         cia_2_port_a
             .borrow_mut()
             .set_observer(Box::new(move |value| {
                 let base_address = ((!value & 0x03) as u16) << 14;
                 vic_base_address_clone.set(base_address);
+                iec_bus_clone_1.borrow_mut().set_cia_output(value);
             }));
         let tick_fn: TickFn = {
+            let ba_line_clone = ba_line.clone();
             let cia_1_clone = cia_1.clone();
             let cia_2_clone = cia_2.clone();
             let clock_clone = clock.clone();
+            let cia_2_port_a_clone = cia_2_port_a.clone();
             let datassette_clone = datassette.clone();
+            let drive_clone = drive.clone();
+            let expansion_port_clone = expansion_port.clone();
+            let iec_bus_clone_2 = iec_bus.clone();
+            let mem_clone = mem.clone();
+            let reu_clone = reu.clone();
             let vic_clone = vic.clone();
             Rc::new(move || {
                 vic_clone.borrow_mut().clock();
+                // REU transfers wait for VIC bad line and sprite fetches and hold BA low
+                // until the transfer is done.
+                if let Some(ref reu) = reu_clone {
+                    let mut reu = reu.borrow_mut();
+                    if reu.is_dma_active() {
+                        if !ba_line_clone.borrow().is_low() {
+                            reu.clock_dma(&mut *mem_clone.borrow_mut());
+                        }
+                        if reu.is_dma_active() {
+                            ba_line_clone.borrow_mut().set_active(false);
+                        }
+                    }
+                }
                 cia_1_clone.borrow_mut().clock();
                 cia_2_clone.borrow_mut().clock();
                 datassette_clone.borrow_mut().clock();
+                expansion_port_clone.borrow_mut().clock();
+                if let Some(ref drive) = drive_clone {
+                    drive.borrow_mut().clock();
+                    let iec_input = iec_bus_clone_2.borrow().get_cia_input();
+                    cia_2_port_a_clone.borrow_mut().set_input(iec_input);
+                }
                 clock_clone.tick();
             })
         };
+        let rewind = RewindBuffer::new(config.rewind.capacity, config.rewind.interval);
         C64 {
             config,
             cpu,
             cia_1: cia_1.clone(),
             cia_2: cia_2.clone(),
             sid: sid.clone(),
+            extra_sids: extra_sids.into_iter().map(|(_, sid)| sid).collect(),
             vic: vic.clone(),
             color_ram: color_ram.clone(),
             expansion_port: expansion_port.clone(),
+            mmu,
             ram: ram.clone(),
+            reu,
             datassette,
+            drive,
             joystick_1: joystick1,
             joystick_2: joystick2,
             keyboard,
+            light_pen,
+            virtual_drive: VirtualDrive::new(),
+            joystick_states: [joystick_1_state, joystick_2_state],
+            keyboard_matrix,
+            input_player: None,
+            input_recorder: None,
             frame_buffer: frame_buffer.clone(),
             sound_buffer: sound_buffer.clone(),
+            sound_mixer,
+            sid_log,
+            voice_controls,
             autostart: None,
             breakpoints: BreakpointManager::default(),
             clock,
             frame_count: 0,
+            rewind,
             tick_fn,
             vsync_flag,
         }
@@ -290,6 +487,14 @@ impl C64 {
         self.datassette.clone()
     }
 
+    pub fn get_drive(&self) -> Option<Shared<Drive>> {
+        self.drive.clone()
+    }
+
+    pub fn get_expansion_port(&self) -> Shared<ExpansionPort> {
+        self.expansion_port.clone()
+    }
+
     pub fn get_frame_count(&self) -> u32 {
         self.frame_count
     }
@@ -314,14 +519,44 @@ impl C64 {
         &mut self.keyboard
     }
 
+    pub fn get_light_pen(&self) -> Shared<LightPen> {
+        self.light_pen.clone()
+    }
+
+    pub fn get_reu(&self) -> Option<Shared<Reu>> {
+        self.reu.clone()
+    }
+
+    pub fn get_rewind(&self) -> &RewindBuffer {
+        &self.rewind
+    }
+
+    pub fn get_rewind_mut(&mut self) -> &mut RewindBuffer {
+        &mut self.rewind
+    }
+
     pub fn get_sid(&self) -> Shared<dyn Chip> {
         self.sid.clone()
     }
 
+    /// Get SID chips configured in addition to the primary SID.
+    pub fn get_extra_sids(&self) -> &[Shared<dyn Chip>] {
+        &self.extra_sids
+    }
+
+    /// Get voice controls of all SIDs, starting with the primary SID.
+    pub fn get_voice_controls(&self) -> &[Shared<VoiceControl>] {
+        &self.voice_controls
+    }
+
     pub fn get_vic(&self) -> Shared<dyn Chip> {
         self.vic.clone()
     }
 
+    pub fn get_virtual_drive(&self) -> &VirtualDrive {
+        &self.virtual_drive
+    }
+
     pub fn get_vsync(&self) -> bool {
         self.vsync_flag.get()
     }
@@ -368,11 +603,17 @@ impl C64 {
         self.cia_1.borrow_mut().reset();
         self.cia_2.borrow_mut().reset();
         self.sid.borrow_mut().reset();
+        for sid in self.extra_sids.iter() {
+            sid.borrow_mut().reset();
+        }
         self.vic.borrow_mut().reset();
         // I/O
         self.expansion_port.borrow_mut().reset();
         // Peripherals
         self.datassette.borrow_mut().reset();
+        if let Some(ref drive) = self.drive {
+            drive.borrow_mut().reset();
+        }
         if let Some(ref mut joystick) = self.joystick_1 {
             joystick.reset();
         }
@@ -380,60 +621,383 @@ impl C64 {
             joystick.reset();
         }
         self.keyboard.reset();
+        self.virtual_drive.reset();
         self.frame_buffer.borrow_mut().reset();
-        self.sound_buffer.reset();
+        match self.sound_mixer {
+            Some(ref mixer) => mixer.borrow_mut().reset(),
+            None => self.sound_buffer.reset(),
+        }
         // Runtime State
         self.frame_count = 0;
+        self.rewind.clear();
         self.vsync_flag.set(false);
     }
 
+    pub fn save_state(&self) -> Vec<u8> {
+        self.write_state(StateWriter::with_capacity(0x12000))
+    }
+
+    fn write_state(&self, mut state: StateWriter) -> Vec<u8> {
+        state.write_tag(SNAPSHOT_MAGIC);
+        state.write_u16(SNAPSHOT_VERSION);
+        state.write_u64(self.clock.get());
+        state.write_u32(self.frame_count);
+        state.write_bool(self.vsync_flag.get());
+        // Chipset
+        self.cpu.save_state(&mut state);
+        self.cia_1.borrow().save_state(&mut state);
+        self.cia_2.borrow().save_state(&mut state);
+        self.vic.borrow().save_state(&mut state);
+        self.sid.borrow().save_state(&mut state);
+        state.write_u8(self.extra_sids.len() as u8);
+        for sid in self.extra_sids.iter() {
+            sid.borrow().save_state(&mut state);
+        }
+        // Memory
+        self.ram.borrow().save_state(&mut state);
+        self.color_ram.borrow().save_state(&mut state);
+        self.expansion_port.borrow().save_state(&mut state);
+        self.mmu.borrow().save_state(&mut state);
+        // Peripherals
+        self.datassette.borrow().save_state(&mut state);
+        self.keyboard.save_state(&mut state);
+        for joystick in [&self.joystick_1, &self.joystick_2].iter() {
+            if let Some(ref joystick) = joystick {
+                state.write_bool(true);
+                joystick.save_state(&mut state);
+            } else {
+                state.write_bool(false);
+            }
+        }
+        if let Some(ref drive) = self.drive {
+            state.write_bool(true);
+            drive.borrow().save_state(&mut state);
+        } else {
+            state.write_bool(false);
+        }
+        state.into_inner()
+    }
+
+    /// Restore machine state from a snapshot. The machine is left unchanged if the
+    /// snapshot is invalid or does not match the machine configuration.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let backup = self.save_state();
+        match self.read_state(data) {
+            Ok(()) => {
+                self.autostart = None;
+                Ok(())
+            }
+            Err(err) => {
+                if let Err(restore_err) = self.read_state(&backup) {
+                    error!(target: "c64", "Failed to restore state, error: {}", restore_err);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn read_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut state = StateReader::new(data);
+        state
+            .read_tag(SNAPSHOT_MAGIC)
+            .map_err(|_| "Invalid snapshot".to_string())?;
+        let version = state.read_u16()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(format!("Unsupported snapshot version {}", version));
+        }
+        self.clock.set(state.read_u64()?);
+        self.frame_count = state.read_u32()?;
+        self.vsync_flag.set(state.read_bool()?);
+        // Chipset
+        self.cpu.load_state(&mut state)?;
+        self.cia_1.borrow_mut().load_state(&mut state)?;
+        self.cia_2.borrow_mut().load_state(&mut state)?;
+        self.vic.borrow_mut().load_state(&mut state)?;
+        self.sid.borrow_mut().load_state(&mut state)?;
+        if state.read_u8()? as usize != self.extra_sids.len() {
+            return Err("Invalid snapshot, SID configuration does not match".to_string());
+        }
+        for sid in self.extra_sids.iter() {
+            sid.borrow_mut().load_state(&mut state)?;
+        }
+        // Memory
+        self.ram.borrow_mut().load_state(&mut state)?;
+        self.color_ram.borrow_mut().load_state(&mut state)?;
+        self.expansion_port.borrow_mut().load_state(&mut state)?;
+        self.mmu.borrow_mut().load_state(&mut state)?;
+        // Peripherals
+        self.datassette.borrow_mut().load_state(&mut state)?;
+        self.keyboard.load_state(&mut state)?;
+        for joystick in [&mut self.joystick_1, &mut self.joystick_2].iter_mut() {
+            let present = state.read_bool()?;
+            match joystick {
+                Some(ref mut joystick) if present => joystick.load_state(&mut state)?,
+                None if !present => (),
+                _ => {
+                    return Err(
+                        "Invalid snapshot, joystick configuration does not match".to_string()
+                    )
+                }
+            }
+        }
+        let drive_present = state.read_bool()?;
+        match self.drive {
+            Some(ref drive) if drive_present => drive.borrow_mut().load_state(&mut state)?,
+            None if !drive_present => (),
+            _ => return Err("Invalid snapshot, drive configuration does not match".to_string()),
+        }
+        if !state.is_eos() {
+            return Err("Invalid snapshot, unexpected data at end".to_string());
+        }
+        Ok(())
+    }
+
     pub fn run_frame(&mut self) -> bool {
-        let tick_fn = self.tick_fn.clone();
+        self.poll_input();
         let bp_present = self.breakpoints.is_bp_present();
+        self.run_frame_internal(bp_present)
+    }
+
+    fn run_frame_internal(&mut self, check_breakpoints: bool) -> bool {
+        let tick_fn = self.tick_fn.clone();
         while !self.vsync_flag.get() {
             self.step_internal(&tick_fn);
-            if bp_present && self.check_breakpoints() {
+            if check_breakpoints && self.check_breakpoints() {
                 break;
             }
         }
         if self.vsync_flag.get() {
-            self.sid.borrow_mut().process_vsync();
-            self.cia_1.borrow_mut().process_vsync();
-            self.cia_2.borrow_mut().process_vsync();
-            self.frame_count = self.frame_count.wrapping_add(1);
+            self.process_vsync();
         }
         self.vsync_flag.get()
     }
 
     pub fn step(&mut self) {
+        self.poll_input();
         let tick_fn = self.tick_fn.clone();
         self.step_internal(&tick_fn);
         if self.vsync_flag.get() {
-            self.sid.borrow_mut().process_vsync();
-            self.cia_1.borrow_mut().process_vsync();
-            self.cia_2.borrow_mut().process_vsync();
-            self.frame_count = self.frame_count.wrapping_add(1);
+            self.process_vsync();
+        }
+    }
+
+    fn process_vsync(&mut self) {
+        self.sid.borrow_mut().process_vsync();
+        for sid in self.extra_sids.iter() {
+            sid.borrow_mut().process_vsync();
+        }
+        if let Some(ref mixer) = self.sound_mixer {
+            mixer.borrow_mut().flush();
+        }
+        self.cia_1.borrow_mut().process_vsync();
+        self.cia_2.borrow_mut().process_vsync();
+        self.frame_count = self.frame_count.wrapping_add(1);
+        if self.rewind.is_due(self.frame_count) {
+            let snapshot =
+                self.write_state(StateWriter::with_capacity(0x12000).skip_expansion_ram());
+            self.rewind.push(self.frame_count, snapshot);
         }
     }
 
     #[inline]
     pub fn step_internal(&mut self, tick_fn: &TickFn) {
+        if let Some(ref mut player) = self.input_player {
+            if player.is_due(self.clock.get()) {
+                player.advance(self.clock.get());
+                apply_input(player, &self.keyboard_matrix, &self.joystick_states);
+            }
+        }
         self.cpu.step(&tick_fn);
         if self.autostart.is_some() && self.cpu.get_pc() == (BaseAddr::BootComplete.addr()) {
             if let Some(mut autostart) = self.autostart.take() {
                 autostart.execute(self);
             }
         }
+        if self.drive.is_none() && self.virtual_drive.has_disk() {
+            let pc = self.cpu.get_pc();
+            if let Bank::Kernal = self.mmu.borrow().map(pc) {
+                self.virtual_drive.process_trap(&mut *self.cpu);
+            }
+        }
+    }
+
+    // -- Rewind Ops
+
+    /// Restore the most recent snapshot taken before the current frame.
+    /// Returns false if there are no snapshots left to rewind to.
+    pub fn rewind(&mut self) -> Result<bool, String> {
+        if let Some(snapshot) = self.rewind.pop_before(self.frame_count) {
+            self.load_state(&snapshot.data)?;
+            self.seek_input();
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Step back to the end of the previous frame by restoring the nearest snapshot
+    /// and replaying frames from there.
+    pub fn step_back(&mut self) -> Result<(), String> {
+        let target = self.frame_count.wrapping_sub(1);
+        let data = match self.rewind.find(target) {
+            Some(snapshot) => snapshot.data.clone(),
+            None => return Err("No rewind data available".to_string()),
+        };
+        self.load_state(&data)?;
+        self.seek_input();
+        self.rewind.truncate(self.frame_count);
+        while self.frame_count != target {
+            self.vsync_flag.set(false);
+            self.run_frame_internal(false);
+        }
+        Ok(())
+    }
+
+    // -- Input Ops
+
+    pub fn is_playing_back(&self) -> bool {
+        self.input_player.is_some()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.input_recorder.is_some()
+    }
+
+    /// Start recording input changes. The movie begins with a snapshot of the current state.
+    pub fn start_recording(&mut self) {
+        let recorder = InputRecorder::new(
+            self.save_state(),
+            self.clock.get(),
+            *self.keyboard_matrix.borrow(),
+            [self.joystick_states[0].get(), self.joystick_states[1].get()],
+        );
+        self.input_recorder = Some(recorder);
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Movie> {
+        self.poll_input();
+        let cycle = self.clock.get();
+        self.input_recorder
+            .take()
+            .map(|recorder| recorder.finish(cycle))
+    }
+
+    /// Restore the movie snapshot and replay its input. Host input is ignored until
+    /// the playback is done. Pending autostart is kept so that images attached
+    /// before recording started are run the same way.
+    pub fn start_playback(&mut self, movie: Movie) -> Result<(), String> {
+        let autostart = self.autostart.take();
+        self.load_state(&movie.snapshot)?;
+        self.autostart = autostart;
+        let player = InputPlayer::new(
+            movie,
+            self.clock.get(),
+            *self.keyboard_matrix.borrow(),
+            [self.joystick_states[0].get(), self.joystick_states[1].get()],
+        );
+        self.input_player = Some(player);
+        Ok(())
+    }
+
+    pub fn stop_playback(&mut self) {
+        self.input_player = None;
+    }
+
+    /// Start capturing writes to SID registers, discarding writes captured before.
+    pub fn start_sid_capture(&mut self) {
+        self.sid_log.borrow_mut().start();
+    }
+
+    pub fn stop_sid_capture(&mut self) -> Vec<SidWrite> {
+        self.sid_log.borrow_mut().stop()
+    }
+
+    /// Bring input recording and playback in line with the clock after a rewind snapshot
+    /// was restored. Recording starts over if the snapshot predates it, playback stops.
+    fn seek_input(&mut self) {
+        let cycle = self.clock.get();
+        let keyboard = *self.keyboard_matrix.borrow();
+        let joystick = [self.joystick_states[0].get(), self.joystick_states[1].get()];
+        let restart_recording = match self.input_recorder {
+            Some(ref mut recorder) => !recorder.rewind(cycle, &keyboard, joystick),
+            None => false,
+        };
+        if restart_recording {
+            info!(target: "c64", "Rewound before recording started, recording again");
+            self.start_recording();
+        }
+        let stop_playback = match self.input_player {
+            Some(ref mut player) => !player.seek(cycle, &keyboard, joystick),
+            None => false,
+        };
+        if stop_playback {
+            info!(target: "c64", "Rewound before playback started, playback stopped");
+            self.input_player = None;
+        }
+    }
+
+    fn poll_input(&mut self) {
+        let cycle = self.clock.get();
+        if let Some(ref mut player) = self.input_player {
+            if player.is_done(cycle) {
+                info!(target: "c64", "Playback finished at cycle {}", cycle);
+                self.input_player = None;
+            } else {
+                apply_input(player, &self.keyboard_matrix, &self.joystick_states);
+            }
+        }
+        if let Some(ref mut recorder) = self.input_recorder {
+            recorder.poll(
+                cycle,
+                &self.keyboard_matrix.borrow(),
+                [self.joystick_states[0].get(), self.joystick_states[1].get()],
+            );
+        }
     }
 
     // -- Peripherals Ops
 
     pub fn attach_cartridge(&mut self, cartridge: Cartridge) {
-        self.expansion_port.borrow_mut().attach(cartridge);
+        let mut expansion_port = self.expansion_port.borrow_mut();
+        let replaces_georam = expansion_port
+            .get_cartridge()
+            .is_some_and(|current| current.get_hw_type() == HwType::GeoRam);
+        if replaces_georam && cartridge.get_hw_type() != HwType::GeoRam {
+            warn!(target: "c64", "Cartridge replaces GeoRAM, its RAM is not saved back");
+        }
+        expansion_port.attach(cartridge);
     }
 
-    pub fn attach_tape(&mut self, tape: Box<dyn Tape>) {
-        self.datassette.borrow_mut().attach(tape);
+    /// Insert disk into the drive. Returns false if true drive emulation is not enabled.
+    pub fn attach_disk(&mut self, disk: Box<dyn Disk>) -> bool {
+        if let Some(ref drive) = self.drive {
+            drive.borrow_mut().attach_disk(disk);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Insert disk into the virtual drive serving kernal I/O for device 8. The virtual drive
+    /// is only used when true drive emulation is not enabled.
+    pub fn attach_file_system(&mut self, fs: Box<dyn FileSystem>) {
+        self.virtual_drive.attach(fs);
+    }
+
+    /// Insert tape into the datassette. Returns false if the model has no datassette port.
+    pub fn attach_tape(&mut self, tape: Box<dyn Tape>) -> bool {
+        if self.config.model.has_datassette {
+            self.datassette.borrow_mut().attach(tape);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Press the freeze button of the attached cartridge. Returns false if the cartridge
+    /// does not support freezing.
+    pub fn freeze_cartridge(&mut self) -> bool {
+        self.expansion_port.borrow_mut().freeze()
     }
 
     pub fn detach_cartridge(&mut self) {
@@ -441,11 +1005,35 @@ impl C64 {
         self.reset(false);
     }
 
+    pub fn detach_disk(&mut self) -> Option<Box<dyn Disk>> {
+        match self.drive {
+            Some(ref drive) => drive.borrow_mut().detach_disk(),
+            None => None,
+        }
+    }
+
+    pub fn detach_file_system(&mut self) -> Option<Box<dyn FileSystem>> {
+        self.virtual_drive.detach()
+    }
+
     pub fn detach_tape(&mut self) {
         self.datassette.borrow_mut().detach();
     }
 }
 
+fn apply_input(
+    player: &InputPlayer,
+    keyboard_matrix: &Shared<[u8; 16]>,
+    joystick_states: &[SharedCell<u8>; 2],
+) {
+    keyboard_matrix
+        .borrow_mut()
+        .copy_from_slice(player.get_keyboard());
+    let joystick = player.get_joystick();
+    joystick_states[0].set(joystick[0]);
+    joystick_states[1].set(joystick[1]);
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::C64Factory;
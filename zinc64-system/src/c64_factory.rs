@@ -17,11 +17,12 @@ use zinc64_core::util::*;
 
 use super::Config;
 use zinc64_core::cpu::Cpu6510;
+use zinc64_core::device::LightPen;
 use zinc64_core::io::cia;
 use zinc64_core::io::Cia;
 use zinc64_core::mem::{Memory, Mmio};
 use zinc64_core::sound::sid::SamplingMethod;
-use zinc64_core::sound::Sid;
+use zinc64_core::sound::{Sid, VoiceControl};
 use zinc64_core::video::{Vic, VicMemory};
 
 pub struct C64Factory {
@@ -32,6 +33,22 @@ impl C64Factory {
     pub fn new(config: Rc<Config>) -> C64Factory {
         C64Factory { config }
     }
+
+    fn build_sid(
+        &self,
+        chip_model: SidModel,
+        system_clock: Rc<Clock>,
+        sound_buffer: Arc<dyn SoundOutput>,
+    ) -> Sid {
+        let mut sid = Sid::new(chip_model, system_clock, sound_buffer);
+        sid.set_sampling_parameters(
+            SamplingMethod::Fast,
+            self.config.model.cpu_freq,
+            self.config.sound.sample_rate,
+        );
+        sid.enable_filter(self.config.sound.sid_filters);
+        sid
+    }
 }
 
 impl ChipFactory for C64Factory {
@@ -95,13 +112,18 @@ impl ChipFactory for C64Factory {
         system_clock: Rc<Clock>,
         sound_buffer: Arc<dyn SoundOutput>,
     ) -> Shared<dyn Chip> {
-        let mut sid = Sid::new(chip_model, system_clock, sound_buffer);
-        sid.set_sampling_parameters(
-            SamplingMethod::Fast,
-            self.config.model.cpu_freq,
-            self.config.sound.sample_rate,
-        );
-        sid.enable_filter(self.config.sound.sid_filters);
+        new_shared(self.build_sid(chip_model, system_clock, sound_buffer))
+    }
+
+    fn new_sid_with_voice_control(
+        &self,
+        chip_model: SidModel,
+        system_clock: Rc<Clock>,
+        sound_buffer: Arc<dyn SoundOutput>,
+        voice_control: Shared<VoiceControl>,
+    ) -> Shared<dyn Chip> {
+        let mut sid = self.build_sid(chip_model, system_clock, sound_buffer);
+        sid.set_voice_control(voice_control);
         new_shared(sid)
     }
 
@@ -116,6 +138,7 @@ impl ChipFactory for C64Factory {
         vsync_flag: SharedCell<bool>,
         ba_line: Shared<Pin>,
         irq_line: Shared<IrqLine>,
+        light_pen: Shared<LightPen>,
     ) -> Shared<dyn Chip> {
         let vic_mem = VicMemory::new(vic_base_address, rom_charset, ram);
         new_shared(Vic::new(
@@ -126,6 +149,7 @@ impl ChipFactory for C64Factory {
             vsync_flag,
             ba_line,
             irq_line,
+            light_pen,
         ))
     }
 
@@ -143,9 +167,18 @@ impl ChipFactory for C64Factory {
         rom_charset: Shared<Rom>,
         rom_kernal: Shared<Rom>,
         sid: Shared<dyn Chip>,
+        extra_sids: Vec<(u16, Shared<dyn Chip>)>,
         vic: Shared<dyn Chip>,
     ) -> Shared<dyn Addressable> {
-        let io = Mmio::new(cia_1, cia_2, color_ram, expansion_port.clone(), sid, vic);
+        let io = Mmio::new(
+            cia_1,
+            cia_2,
+            color_ram,
+            expansion_port.clone(),
+            sid,
+            extra_sids,
+            vic,
+        );
         new_shared(Memory::new(
             mmu,
             expansion_port.clone(),
@@ -7,11 +7,18 @@ use alloc::prelude::*;
 #[cfg(not(feature = "std"))]
 use alloc::vec;
 use zinc64_core::device::joystick;
-use zinc64_core::factory::SystemModel;
+use zinc64_core::factory::{SidModel, SystemModel};
 
 pub struct Config {
     pub model: SystemModel,
+    /// SID chips in addition to the one at $D400.
+    pub extra_sids: Vec<SidConfig>,
+    /// GeoRAM attached to the expansion port in place of a cartridge, if any.
+    pub georam: Option<GeoRamConfig>,
     pub joystick: JoystickConfig,
+    /// RAM Expansion Unit attached to the expansion port, if any.
+    pub reu: Option<ReuConfig>,
+    pub rewind: RewindConfig,
     pub sound: SoundConfig,
     pub roms: RomData,
 }
@@ -20,7 +27,11 @@ impl Config {
     pub fn new(model: SystemModel) -> Config {
         Config {
             model,
+            extra_sids: Vec::new(),
+            georam: None,
             joystick: JoystickConfig::default(),
+            reu: None,
+            rewind: RewindConfig::default(),
             sound: SoundConfig::default(),
             roms: RomData::default(),
         }
@@ -34,13 +45,30 @@ impl Config {
     ) -> Config {
         Config {
             model,
+            extra_sids: Vec::new(),
+            georam: None,
             joystick: JoystickConfig::default(),
+            reu: None,
+            rewind: RewindConfig::default(),
             sound: SoundConfig::default(),
             roms: RomData::new(basic, charset, kernal),
         }
     }
 }
 
+pub struct GeoRamConfig {
+    /// RAM size in bytes, 512K up to 4M.
+    pub size: usize,
+    /// RAM image loaded on startup.
+    pub image: Option<Vec<u8>>,
+}
+
+impl GeoRamConfig {
+    pub fn new(size: usize) -> GeoRamConfig {
+        GeoRamConfig { size, image: None }
+    }
+}
+
 pub struct JoystickConfig {
     pub axis_motion_threshold: i16,
     pub joystick_1: joystick::Mode,
@@ -57,10 +85,39 @@ impl JoystickConfig {
     }
 }
 
+pub struct ReuConfig {
+    /// RAM size in bytes, 128K (1700), 256K (1764), 512K (1750) or larger up to 16M.
+    pub size: usize,
+    /// RAM image loaded on startup.
+    pub image: Option<Vec<u8>>,
+}
+
+impl ReuConfig {
+    pub fn new(size: usize) -> ReuConfig {
+        ReuConfig { size, image: None }
+    }
+}
+
+pub struct RewindConfig {
+    pub capacity: usize,
+    pub interval: u32,
+}
+
+impl Default for RewindConfig {
+    fn default() -> RewindConfig {
+        RewindConfig {
+            capacity: 100,
+            interval: 5,
+        }
+    }
+}
+
 pub struct RomData {
     pub basic: Vec<u8>,
     pub charset: Vec<u8>,
     pub kernal: Vec<u8>,
+    /// 1541 DOS ROM, true drive emulation is enabled when present.
+    pub drive: Option<Vec<u8>>,
 }
 
 impl RomData {
@@ -69,6 +126,7 @@ impl RomData {
             basic: vec![0x00; 0x2000],
             charset: vec![0x00; 0x1000],
             kernal: vec![0x00; 0x2000],
+            drive: None,
         }
     }
 
@@ -77,6 +135,30 @@ impl RomData {
             basic: basic.to_vec(),
             charset: charset.to_vec(),
             kernal: kernal.to_vec(),
+            drive: None,
+        }
+    }
+}
+
+pub struct SidConfig {
+    /// Base address of the chip registers, $D420 up to $D7E0 or $DE00 up to $DFE0 in steps
+    /// of $20.
+    pub base_address: u16,
+    pub model: SidModel,
+}
+
+impl SidConfig {
+    pub fn new(base_address: u16, model: SidModel) -> SidConfig {
+        SidConfig {
+            base_address,
+            model,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self.base_address {
+            0xd420..=0xd7e0 | 0xde00..=0xdfe0 if self.base_address & 0x1f == 0 => Ok(()),
+            address => Err(format!("invalid SID address 0x{:04x}", address)),
         }
     }
 }
@@ -19,10 +19,16 @@ pub mod c64;
 mod c64_factory;
 mod condition;
 pub mod config;
+pub mod movie;
+pub mod rewind;
+pub mod virtual_drive;
 
 pub use self::autostart::{Autostart, AutostartMethod, Image};
 pub use self::breakpoint::Breakpoint;
 pub use self::c64::C64;
 pub use self::c64_factory::C64Factory;
 pub use self::condition::Condition;
-pub use self::config::Config;
+pub use self::config::{Config, GeoRamConfig, ReuConfig, SidConfig};
+pub use self::movie::Movie;
+pub use self::rewind::RewindBuffer;
+pub use self::virtual_drive::{FileSystem, VirtualDrive};
@@ -0,0 +1,329 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+use zinc64_core::util::{StateReader, StateWriter};
+
+// Design:
+//   A movie is a machine snapshot taken when recording started followed by the list of input
+//   changes stamped with the clock cycle at which they became visible to the machine. Input is
+//   captured at the keyboard matrix and joystick port level so playback does not depend on
+//   host key mappings or event queues.
+
+// Movie format:
+//   Header   "Z64M" magic followed by u16 format version
+//   Snapshot u32 length followed by snapshot data
+//   End      u64 cycle at which recording stopped
+//   Events   u32 count followed by events (u64 cycle, u8 device, u8 index, u8 value)
+
+const MOVIE_MAGIC: &[u8; 4] = b"Z64M";
+const MOVIE_VERSION: u16 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputDevice {
+    Keyboard = 0,
+    Joystick = 1,
+}
+
+impl InputDevice {
+    pub fn from(value: u8) -> Result<InputDevice, String> {
+        match value {
+            0 => Ok(InputDevice::Keyboard),
+            1 => Ok(InputDevice::Joystick),
+            _ => Err(format!("Invalid input device {}", value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InputEvent {
+    pub cycle: u64,
+    pub device: InputDevice,
+    pub index: u8,
+    pub value: u8,
+}
+
+pub struct Movie {
+    pub snapshot: Vec<u8>,
+    pub end_cycle: u64,
+    pub events: Vec<InputEvent>,
+}
+
+impl Movie {
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, String> {
+        let mut state = StateReader::new(data);
+        state
+            .read_tag(MOVIE_MAGIC)
+            .map_err(|_| "Invalid movie".to_string())?;
+        let version = state.read_u16()?;
+        if version != MOVIE_VERSION {
+            return Err(format!("Unsupported movie version {}", version));
+        }
+        let snapshot = state.read_byte_vec()?;
+        let end_cycle = state.read_u64()?;
+        let count = state.read_u32()?;
+        let mut events = Vec::new();
+        let mut last_cycle = 0;
+        for _ in 0..count {
+            let event = InputEvent {
+                cycle: state.read_u64()?,
+                device: InputDevice::from(state.read_u8()?)?,
+                index: state.read_u8()?,
+                value: state.read_u8()?,
+            };
+            let max_index = match event.device {
+                InputDevice::Keyboard => 16,
+                InputDevice::Joystick => 2,
+            };
+            if event.index >= max_index || event.cycle < last_cycle {
+                return Err(format!("Invalid movie event at cycle {}", event.cycle));
+            }
+            last_cycle = event.cycle;
+            events.push(event);
+        }
+        Ok(Movie {
+            snapshot,
+            end_cycle,
+            events,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut state = StateWriter::with_capacity(self.snapshot.len() + self.events.len() * 11);
+        state.write_tag(MOVIE_MAGIC);
+        state.write_u16(MOVIE_VERSION);
+        state.write_bytes(&self.snapshot);
+        state.write_u64(self.end_cycle);
+        state.write_u32(self.events.len() as u32);
+        for event in &self.events {
+            state.write_u64(event.cycle);
+            state.write_u8(event.device as u8);
+            state.write_u8(event.index);
+            state.write_u8(event.value);
+        }
+        state.into_inner()
+    }
+}
+
+pub struct InputRecorder {
+    movie: Movie,
+    start_cycle: u64,
+    keyboard: [u8; 16],
+    joystick: [u8; 2],
+}
+
+impl InputRecorder {
+    pub fn new(snapshot: Vec<u8>, cycle: u64, keyboard: [u8; 16], joystick: [u8; 2]) -> Self {
+        Self {
+            movie: Movie {
+                snapshot,
+                end_cycle: 0,
+                events: Vec::new(),
+            },
+            start_cycle: cycle,
+            keyboard,
+            joystick,
+        }
+    }
+
+    pub fn finish(mut self, cycle: u64) -> Movie {
+        self.movie.end_cycle = cycle;
+        self.movie
+    }
+
+    pub fn poll(&mut self, cycle: u64, keyboard: &[u8; 16], joystick: [u8; 2]) {
+        for (i, value) in keyboard.iter().enumerate() {
+            if self.keyboard[i] != *value {
+                self.keyboard[i] = *value;
+                self.push(cycle, InputDevice::Keyboard, i, *value);
+            }
+        }
+        for (i, value) in joystick.iter().enumerate() {
+            if self.joystick[i] != *value {
+                self.joystick[i] = *value;
+                self.push(cycle, InputDevice::Joystick, i, *value);
+            }
+        }
+    }
+
+    /// Drop events recorded from the specified cycle on once the machine was restored to it,
+    /// continuing with the restored input state. Returns false if the cycle is before the
+    /// start of the recording.
+    pub fn rewind(&mut self, cycle: u64, keyboard: &[u8; 16], joystick: [u8; 2]) -> bool {
+        if cycle < self.start_cycle {
+            return false;
+        }
+        let pos = self
+            .movie
+            .events
+            .iter()
+            .position(|event| event.cycle >= cycle)
+            .unwrap_or(self.movie.events.len());
+        self.movie.events.truncate(pos);
+        self.keyboard = *keyboard;
+        self.joystick = joystick;
+        true
+    }
+
+    fn push(&mut self, cycle: u64, device: InputDevice, index: usize, value: u8) {
+        self.movie.events.push(InputEvent {
+            cycle,
+            device,
+            index: index as u8,
+            value,
+        });
+    }
+}
+
+pub struct InputPlayer {
+    events: Vec<InputEvent>,
+    start_cycle: u64,
+    end_cycle: u64,
+    pos: usize,
+    keyboard: [u8; 16],
+    joystick: [u8; 2],
+}
+
+impl InputPlayer {
+    pub fn new(movie: Movie, cycle: u64, keyboard: [u8; 16], joystick: [u8; 2]) -> Self {
+        Self {
+            events: movie.events,
+            start_cycle: cycle,
+            end_cycle: movie.end_cycle,
+            pos: 0,
+            keyboard,
+            joystick,
+        }
+    }
+
+    pub fn get_keyboard(&self) -> &[u8; 16] {
+        &self.keyboard
+    }
+
+    pub fn get_joystick(&self) -> [u8; 2] {
+        self.joystick
+    }
+
+    #[inline]
+    pub fn is_due(&self, cycle: u64) -> bool {
+        self.pos < self.events.len() && self.events[self.pos].cycle <= cycle
+    }
+
+    pub fn is_done(&self, cycle: u64) -> bool {
+        self.pos >= self.events.len() && cycle >= self.end_cycle
+    }
+
+    /// Continue playback from the specified cycle once the machine was restored to it, with
+    /// the restored input state. Returns false if the cycle is before the start of the movie.
+    pub fn seek(&mut self, cycle: u64, keyboard: &[u8; 16], joystick: [u8; 2]) -> bool {
+        if cycle < self.start_cycle {
+            return false;
+        }
+        self.pos = self
+            .events
+            .iter()
+            .position(|event| event.cycle >= cycle)
+            .unwrap_or(self.events.len());
+        self.keyboard = *keyboard;
+        self.joystick = joystick;
+        true
+    }
+
+    /// Apply all events up to and including the specified cycle.
+    pub fn advance(&mut self, cycle: u64) {
+        while self.is_due(cycle) {
+            let event = self.events[self.pos];
+            match event.device {
+                InputDevice::Keyboard => self.keyboard[event.index as usize] = event.value,
+                InputDevice::Joystick => self.joystick[event.index as usize] = event.value,
+            }
+            self.pos += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_changes_only() {
+        let mut recorder = InputRecorder::new(vec![1, 2], 0, [0xff; 16], [0; 2]);
+        recorder.poll(10, &[0xff; 16], [0; 2]);
+        let mut keyboard = [0xff; 16];
+        keyboard[1] = 0xdf;
+        recorder.poll(20, &keyboard, [0x10, 0]);
+        let movie = recorder.finish(30);
+        assert_eq!(30, movie.end_cycle);
+        assert_eq!(2, movie.events.len());
+        assert_eq!(
+            InputEvent {
+                cycle: 20,
+                device: InputDevice::Keyboard,
+                index: 1,
+                value: 0xdf,
+            },
+            movie.events[0]
+        );
+        assert_eq!(InputDevice::Joystick, movie.events[1].device);
+    }
+
+    #[test]
+    fn write_and_read_movie() {
+        let mut recorder = InputRecorder::new(vec![1, 2, 3], 0, [0xff; 16], [0; 2]);
+        recorder.poll(100, &[0xfe; 16], [0; 2]);
+        let movie = recorder.finish(200);
+        let data = movie.to_bytes();
+        let movie2 = Movie::from_bytes(&data).unwrap();
+        assert_eq!(movie.snapshot, movie2.snapshot);
+        assert_eq!(movie.end_cycle, movie2.end_cycle);
+        assert_eq!(movie.events, movie2.events);
+    }
+
+    #[test]
+    fn play_events_in_order() {
+        let mut recorder = InputRecorder::new(Vec::new(), 0, [0xff; 16], [0; 2]);
+        recorder.poll(100, &[0xff; 16], [1, 0]);
+        recorder.poll(200, &[0xff; 16], [0, 0]);
+        let mut player = InputPlayer::new(recorder.finish(300), 0, [0xff; 16], [0; 2]);
+        assert!(!player.is_due(99));
+        player.advance(150);
+        assert_eq!([1, 0], player.get_joystick());
+        player.advance(250);
+        assert_eq!([0, 0], player.get_joystick());
+        assert!(!player.is_done(250));
+        assert!(player.is_done(300));
+    }
+    #[test]
+    fn rewind_drops_later_events() {
+        let mut recorder = InputRecorder::new(Vec::new(), 50, [0xff; 16], [0; 2]);
+        recorder.poll(100, &[0xff; 16], [1, 0]);
+        recorder.poll(200, &[0xff; 16], [2, 0]);
+        assert!(!recorder.rewind(40, &[0xff; 16], [0; 2]));
+        assert!(recorder.rewind(200, &[0xff; 16], [1, 0]));
+        recorder.poll(150, &[0xff; 16], [1, 0]);
+        recorder.poll(180, &[0xff; 16], [3, 0]);
+        let movie = recorder.finish(300);
+        assert_eq!(
+            vec![100, 180],
+            movie.events.iter().map(|e| e.cycle).collect::<Vec<_>>()
+        );
+        assert!(Movie::from_bytes(&movie.to_bytes()).is_ok());
+    }
+
+    #[test]
+    fn seek_replays_from_cycle() {
+        let mut recorder = InputRecorder::new(Vec::new(), 50, [0xff; 16], [0; 2]);
+        recorder.poll(100, &[0xff; 16], [1, 0]);
+        recorder.poll(200, &[0xff; 16], [2, 0]);
+        let mut player = InputPlayer::new(recorder.finish(300), 50, [0xff; 16], [0; 2]);
+        player.advance(250);
+        assert!(!player.seek(40, &[0xff; 16], [0; 2]));
+        assert!(player.seek(150, &[0xff; 16], [1, 0]));
+        assert!(player.is_due(200));
+        player.advance(200);
+        assert_eq!([2, 0], player.get_joystick());
+    }
+}
@@ -0,0 +1,135 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::collections::VecDeque;
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+#[cfg(feature = "std")]
+use std::collections::VecDeque;
+
+// Design:
+//   RewindBuffer keeps machine snapshots taken every N frames in a bounded ring buffer.
+//   Oldest snapshots are dropped once the buffer is full. Snapshots past the current frame
+//   are discarded when the machine is rewound as they belong to an abandoned timeline.
+//   Snapshots leave out REU and GeoRAM contents to keep memory use bounded, so expansion
+//   RAM keeps its current contents when the machine is rewound.
+
+pub struct Snapshot {
+    pub frame: u32,
+    pub data: Vec<u8>,
+}
+
+pub struct RewindBuffer {
+    capacity: usize,
+    interval: u32,
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize, interval: u32) -> Self {
+        Self {
+            capacity,
+            interval,
+            snapshots: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn get_interval(&self) -> u32 {
+        self.interval
+    }
+
+    pub fn is_due(&self, frame: u32) -> bool {
+        self.capacity > 0 && frame.checked_rem(self.interval) == Some(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+
+    pub fn push(&mut self, frame: u32, data: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.truncate(frame.wrapping_sub(1));
+        while self.snapshots.len() >= self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Snapshot { frame, data });
+    }
+
+    /// Remove and return the most recent snapshot taken before the specified frame.
+    pub fn pop_before(&mut self, frame: u32) -> Option<Snapshot> {
+        self.truncate(frame.wrapping_sub(1));
+        self.snapshots.pop_back()
+    }
+
+    /// Find the most recent snapshot taken at or before the specified frame.
+    pub fn find(&self, frame: u32) -> Option<&Snapshot> {
+        self.snapshots.iter().rev().find(|s| s.frame <= frame)
+    }
+
+    /// Drop all snapshots taken after the specified frame.
+    pub fn truncate(&mut self, frame: u32) {
+        while let Some(true) = self.snapshots.back().map(|s| s.frame > frame) {
+            self.snapshots.pop_back();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_drops_oldest() {
+        let mut buffer = RewindBuffer::new(2, 1);
+        buffer.push(1, vec![1]);
+        buffer.push(2, vec![2]);
+        buffer.push(3, vec![3]);
+        assert_eq!(2, buffer.len());
+        assert!(buffer.find(1).is_none());
+        assert_eq!(2, buffer.find(2).unwrap().frame);
+    }
+
+    #[test]
+    fn push_discards_future() {
+        let mut buffer = RewindBuffer::new(4, 1);
+        buffer.push(1, vec![1]);
+        buffer.push(2, vec![2]);
+        buffer.push(3, vec![3]);
+        buffer.push(2, vec![4]);
+        assert_eq!(2, buffer.len());
+        assert_eq!(vec![4], buffer.find(3).unwrap().data);
+    }
+
+    #[test]
+    fn pop_before_frame() {
+        let mut buffer = RewindBuffer::new(4, 5);
+        buffer.push(5, vec![1]);
+        buffer.push(10, vec![2]);
+        assert_eq!(5, buffer.pop_before(10).unwrap().frame);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn is_due_on_interval() {
+        let buffer = RewindBuffer::new(4, 5);
+        assert!(buffer.is_due(10));
+        assert!(!buffer.is_due(11));
+        assert!(!RewindBuffer::new(0, 5).is_due(10));
+    }
+}
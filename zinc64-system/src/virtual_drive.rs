@@ -0,0 +1,597 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+#[cfg(not(feature = "std"))]
+use alloc::prelude::*;
+use zinc64_core::factory::{Cpu, Register};
+
+// Design:
+//   VirtualDrive emulates device 8 at the file level by trapping kernal I/O routines.
+//   Traps are placed on the routines the kernal vectors at $031a-$0333 point to by default,
+//   so programs that redirect those vectors bypass the virtual drive. When a trap applies,
+//   the routine is completed here and control returns to the caller as if RTS was executed.
+//   Only the routine entry points of the stock kernal are recognized.
+
+pub const DEVICE: u8 = 8;
+
+const MAX_FILES: u8 = 10;
+
+#[derive(Clone, Copy)]
+enum Trap {
+    Open = 0xf34a,
+    Close = 0xf291,
+    Chkin = 0xf20e,
+    Clrchn = 0xf333,
+    Chrin = 0xf157,
+    Getin = 0xf13e,
+    Load = 0xf4a5,
+    Save = 0xf5ed,
+}
+
+impl Trap {
+    fn from(pc: u16) -> Option<Trap> {
+        match pc {
+            0xf34a => Some(Trap::Open),
+            0xf291 => Some(Trap::Close),
+            0xf20e => Some(Trap::Chkin),
+            0xf333 => Some(Trap::Clrchn),
+            0xf157 => Some(Trap::Chrin),
+            0xf13e => Some(Trap::Getin),
+            0xf4a5 => Some(Trap::Load),
+            0xf5ed => Some(Trap::Save),
+            _ => None,
+        }
+    }
+}
+
+// Spec: C64 kernal zero page and file table locations
+mod zp {
+    pub const STATUS: u16 = 0x90;
+    pub const VERIFY: u16 = 0x93;
+    pub const LDTND: u16 = 0x98;
+    pub const DFLTN: u16 = 0x99;
+    pub const EAL: u16 = 0xae;
+    pub const FNLEN: u16 = 0xb7;
+    pub const LA: u16 = 0xb8;
+    pub const SA: u16 = 0xb9;
+    pub const FA: u16 = 0xba;
+    pub const FNADR: u16 = 0xbb;
+    pub const STAL: u16 = 0xc1;
+    pub const MEMUSS: u16 = 0xc3;
+    pub const LAT: u16 = 0x0259;
+    pub const FAT: u16 = 0x0263;
+    pub const SAT: u16 = 0x026d;
+}
+
+// Spec: kernal error codes returned in A with carry set
+mod error {
+    pub const TOO_MANY_FILES: u8 = 1;
+    pub const FILE_OPEN: u8 = 2;
+    pub const FILE_NOT_FOUND: u8 = 4;
+    pub const DEVICE_NOT_PRESENT: u8 = 5;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileType {
+    Del,
+    Seq,
+    Prg,
+    Usr,
+    Rel,
+}
+
+impl FileType {
+    pub fn from(code: u8) -> FileType {
+        match code & 0x07 {
+            1 => FileType::Seq,
+            2 => FileType::Prg,
+            3 => FileType::Usr,
+            4 => FileType::Rel,
+            _ => FileType::Del,
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            FileType::Del => 0,
+            FileType::Seq => 1,
+            FileType::Prg => 2,
+            FileType::Usr => 3,
+            FileType::Rel => 4,
+        }
+    }
+
+    pub fn name(self) -> &'static [u8] {
+        match self {
+            FileType::Del => b"DEL",
+            FileType::Seq => b"SEQ",
+            FileType::Prg => b"PRG",
+            FileType::Usr => b"USR",
+            FileType::Rel => b"REL",
+        }
+    }
+}
+
+/// Directory entry with the file name in PETSCII without padding.
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    pub name: Vec<u8>,
+    pub file_type: FileType,
+    pub blocks: u16,
+}
+
+/// FileSystem provides file level access to a disk image.
+pub trait FileSystem {
+    /// Get disk name in PETSCII without padding.
+    fn get_name(&self) -> Vec<u8>;
+    /// Get disk id and DOS type as shown in the directory header.
+    fn get_id(&self) -> Vec<u8>;
+    fn get_blocks_free(&self) -> u16;
+    fn get_entries(&self) -> Vec<DirEntry>;
+    /// Read contents of the file at the specified directory index.
+    fn read_file(&self, index: usize) -> Result<Vec<u8>, String>;
+    fn is_write_protected(&self) -> bool;
+    /// Create a file with the specified contents. If `replace` holds a directory index,
+    /// that file is replaced only after the new contents have been written.
+    fn write_file(
+        &mut self,
+        name: &[u8],
+        file_type: FileType,
+        data: &[u8],
+        replace: Option<usize>,
+    ) -> Result<(), String>;
+}
+
+struct Channel {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+pub struct VirtualDrive {
+    fs: Option<Box<dyn FileSystem>>,
+    channels: Vec<Option<Channel>>,
+    input_channel: usize,
+    status: Vec<u8>,
+}
+
+impl VirtualDrive {
+    pub fn new() -> Self {
+        let mut channels = Vec::with_capacity(16);
+        for _ in 0..16 {
+            channels.push(None);
+        }
+        Self {
+            fs: None,
+            channels,
+            input_channel: 0,
+            status: Self::format_status(0, "OK"),
+        }
+    }
+
+    pub fn attach(&mut self, fs: Box<dyn FileSystem>) {
+        self.reset();
+        self.fs = Some(fs);
+    }
+
+    pub fn detach(&mut self) -> Option<Box<dyn FileSystem>> {
+        self.reset();
+        self.fs.take()
+    }
+
+    pub fn has_disk(&self) -> bool {
+        self.fs.is_some()
+    }
+
+    /// Get the message pending on the error channel.
+    pub fn get_status(&self) -> &[u8] {
+        &self.status
+    }
+
+    pub fn reset(&mut self) {
+        for channel in self.channels.iter_mut() {
+            *channel = None;
+        }
+        self.input_channel = 0;
+        self.status = Self::format_status(0, "OK");
+    }
+
+    /// Complete kernal routine at the current pc if it targets the virtual drive.
+    /// Returns true if the trap was handled.
+    pub fn process_trap(&mut self, cpu: &mut dyn Cpu) -> bool {
+        let trap = match Trap::from(cpu.get_pc()) {
+            Some(trap) => trap,
+            None => return false,
+        };
+        let handled = match trap {
+            Trap::Open => self.open(cpu),
+            Trap::Close => self.close(cpu),
+            Trap::Chkin => self.chkin(cpu),
+            Trap::Clrchn => {
+                // Let kernal continue with the default input device
+                if cpu.read(zp::DFLTN) == DEVICE {
+                    cpu.write(zp::DFLTN, 0);
+                }
+                false
+            }
+            Trap::Chrin | Trap::Getin => self.chrin(cpu),
+            Trap::Load => self.load(cpu),
+            Trap::Save => self.save(cpu),
+        };
+        if handled {
+            debug!(target: "vdrive", "Trap at 0x{:04x}", trap as u16);
+        }
+        handled
+    }
+
+    // -- Kernal Routines
+
+    fn open(&mut self, cpu: &mut dyn Cpu) -> bool {
+        if cpu.read(zp::FA) != DEVICE {
+            return false;
+        }
+        let la = cpu.read(zp::LA);
+        let sa = cpu.read(zp::SA) & 0x0f;
+        let count = cpu.read(zp::LDTND);
+        if Self::find_file(cpu, la).is_some() {
+            return_error(cpu, error::FILE_OPEN);
+            return true;
+        }
+        if count >= MAX_FILES {
+            return_error(cpu, error::TOO_MANY_FILES);
+            return true;
+        }
+        cpu.write(zp::LAT + count as u16, la);
+        cpu.write(zp::FAT + count as u16, DEVICE);
+        cpu.write(zp::SAT + count as u16, sa | 0x60);
+        cpu.write(zp::LDTND, count + 1);
+        let name = read_filename(cpu);
+        if sa == 15 {
+            // Commands are accepted but not executed
+            self.status = Self::format_status(0, "OK");
+        } else {
+            let data = self.open_file(&name).unwrap_or_default();
+            self.channels[sa as usize] = Some(Channel { data, pos: 0 });
+        }
+        cpu.write(zp::STATUS, 0);
+        return_ok(cpu);
+        true
+    }
+
+    fn close(&mut self, cpu: &mut dyn Cpu) -> bool {
+        let la = cpu.get_register(Register::A);
+        let index = match Self::find_file(cpu, la) {
+            Some(index) => index,
+            None => return false,
+        };
+        if cpu.read(zp::FAT + index as u16) != DEVICE {
+            return false;
+        }
+        let sa = cpu.read(zp::SAT + index as u16) & 0x0f;
+        if sa != 15 {
+            self.channels[sa as usize] = None;
+        }
+        let last = cpu.read(zp::LDTND) - 1;
+        cpu.write(zp::LDTND, last);
+        if index != last {
+            for table in &[zp::LAT, zp::FAT, zp::SAT] {
+                let value = cpu.read(table + last as u16);
+                cpu.write(table + index as u16, value);
+            }
+        }
+        return_ok(cpu);
+        true
+    }
+
+    fn chkin(&mut self, cpu: &mut dyn Cpu) -> bool {
+        let la = cpu.get_register(Register::X);
+        let index = match Self::find_file(cpu, la) {
+            Some(index) => index,
+            None => return false,
+        };
+        if cpu.read(zp::FAT + index as u16) != DEVICE {
+            return false;
+        }
+        self.input_channel = (cpu.read(zp::SAT + index as u16) & 0x0f) as usize;
+        cpu.write(zp::DFLTN, DEVICE);
+        return_ok(cpu);
+        true
+    }
+
+    fn chrin(&mut self, cpu: &mut dyn Cpu) -> bool {
+        if cpu.read(zp::DFLTN) != DEVICE {
+            return false;
+        }
+        let (value, eof) = if self.input_channel == 15 {
+            let value = self.status.remove(0);
+            let eof = self.status.is_empty();
+            if eof {
+                self.status = Self::format_status(0, "OK");
+            }
+            (value, eof)
+        } else {
+            match self.channels[self.input_channel] {
+                Some(ref mut channel) if channel.pos < channel.data.len() => {
+                    let value = channel.data[channel.pos];
+                    channel.pos += 1;
+                    (value, channel.pos == channel.data.len())
+                }
+                _ => {
+                    let status = cpu.read(zp::STATUS);
+                    cpu.write(zp::STATUS, status | 0x42);
+                    (0x0d, false)
+                }
+            }
+        };
+        if eof {
+            let status = cpu.read(zp::STATUS);
+            cpu.write(zp::STATUS, status | 0x40);
+        }
+        cpu.set_register(Register::A, value);
+        return_ok(cpu);
+        true
+    }
+
+    fn load(&mut self, cpu: &mut dyn Cpu) -> bool {
+        if cpu.read(zp::FA) != DEVICE {
+            return false;
+        }
+        let verify = cpu.get_register(Register::A) != 0;
+        cpu.write(zp::VERIFY, cpu.get_register(Register::A));
+        cpu.write(zp::STATUS, 0);
+        let name = read_filename(cpu);
+        let data = match self.open_file(&name) {
+            Some(ref data) if data.len() >= 2 => data.clone(),
+            _ => {
+                return_error(cpu, error::FILE_NOT_FOUND);
+                return true;
+            }
+        };
+        let mut address = if cpu.read(zp::SA) == 0 {
+            read_word(cpu, zp::MEMUSS)
+        } else {
+            u16::from(data[0]) | (u16::from(data[1]) << 8)
+        };
+        info!(target: "vdrive", "Loading {} bytes at 0x{:04x}", data.len() - 2, address);
+        let mut status = 0x40;
+        for byte in &data[2..] {
+            if verify {
+                if cpu.read(address) != *byte {
+                    status |= 0x10;
+                }
+            } else {
+                cpu.write(address, *byte);
+            }
+            address = address.wrapping_add(1);
+        }
+        cpu.write(zp::STATUS, status);
+        cpu.write(zp::EAL, address as u8);
+        cpu.write(zp::EAL + 1, (address >> 8) as u8);
+        cpu.set_register(Register::X, address as u8);
+        cpu.set_register(Register::Y, (address >> 8) as u8);
+        return_ok(cpu);
+        true
+    }
+
+    fn save(&mut self, cpu: &mut dyn Cpu) -> bool {
+        if cpu.read(zp::FA) != DEVICE {
+            return false;
+        }
+        let start = read_word(cpu, zp::STAL);
+        let end = read_word(cpu, zp::EAL);
+        let mut name = read_filename(cpu);
+        let replace = name.starts_with(b"@");
+        if replace {
+            name.remove(0);
+        }
+        let (name, _) = parse_filename(&name);
+        let mut data = vec![start as u8, (start >> 8) as u8];
+        let mut address = start;
+        while address != end {
+            data.push(cpu.read(address));
+            address = address.wrapping_add(1);
+        }
+        info!(target: "vdrive", "Saving {} bytes from 0x{:04x}", data.len() - 2, start);
+        match self.fs {
+            Some(ref mut fs) => {
+                let existing = fs.get_entries().iter().position(|entry| entry.name == name);
+                self.status = if fs.is_write_protected() {
+                    Self::format_status(26, "WRITE PROTECT ON")
+                } else if existing.is_some() && !replace {
+                    Self::format_status(63, "FILE EXISTS")
+                } else {
+                    match fs.write_file(&name, FileType::Prg, &data, existing) {
+                        Ok(_) => Self::format_status(0, "OK"),
+                        Err(err) => {
+                            warn!(target: "vdrive", "Failed to save file, error: {}", err);
+                            match err.as_str() {
+                                "write protected" => Self::format_status(26, "WRITE PROTECT ON"),
+                                "file exists" => Self::format_status(63, "FILE EXISTS"),
+                                "disk full" | "directory full" => {
+                                    Self::format_status(72, "DISK FULL")
+                                }
+                                _ => Self::format_status(25, "WRITE ERROR"),
+                            }
+                        }
+                    }
+                };
+                cpu.write(zp::STATUS, 0);
+                return_ok(cpu);
+            }
+            None => return_error(cpu, error::DEVICE_NOT_PRESENT),
+        }
+        true
+    }
+
+    // -- Internal Ops
+
+    fn find_file(cpu: &dyn Cpu, la: u8) -> Option<u8> {
+        let count = cpu.read(zp::LDTND).min(MAX_FILES);
+        (0..count).find(|i| cpu.read(zp::LAT + *i as u16) == la)
+    }
+
+    fn format_status(code: u8, message: &str) -> Vec<u8> {
+        format!("{:02},{},00,00\r", code, message).into_bytes()
+    }
+
+    fn open_file(&mut self, name: &[u8]) -> Option<Vec<u8>> {
+        let fs = self.fs.as_ref()?;
+        let result = if name.starts_with(b"$") {
+            Some(format_directory(fs.as_ref()))
+        } else {
+            let (pattern, _) = parse_filename(name);
+            fs.get_entries()
+                .iter()
+                .position(|entry| {
+                    entry.file_type != FileType::Del && matches_pattern(&pattern, &entry.name)
+                })
+                .and_then(|index| match fs.read_file(index) {
+                    Ok(data) => Some(data),
+                    Err(err) => {
+                        warn!(target: "vdrive", "Failed to read file, error: {}", err);
+                        None
+                    }
+                })
+        };
+        self.status = if result.is_some() {
+            Self::format_status(0, "OK")
+        } else {
+            Self::format_status(62, "FILE NOT FOUND")
+        };
+        result
+    }
+}
+
+impl Default for VirtualDrive {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn read_filename(cpu: &dyn Cpu) -> Vec<u8> {
+    let address = read_word(cpu, zp::FNADR);
+    let len = cpu.read(zp::FNLEN);
+    (0..len)
+        .map(|i| cpu.read(address.wrapping_add(u16::from(i))))
+        .collect()
+}
+
+fn read_word(cpu: &dyn Cpu, address: u16) -> u16 {
+    u16::from(cpu.read(address)) | (u16::from(cpu.read(address + 1)) << 8)
+}
+
+fn return_error(cpu: &mut dyn Cpu, code: u8) {
+    cpu.set_register(Register::A, code);
+    let p = cpu.get_register(Register::P);
+    cpu.set_register(Register::P, p | 0x01);
+    return_from_subroutine(cpu);
+}
+
+fn return_ok(cpu: &mut dyn Cpu) {
+    let p = cpu.get_register(Register::P);
+    cpu.set_register(Register::P, p & !0x01);
+    return_from_subroutine(cpu);
+}
+
+fn return_from_subroutine(cpu: &mut dyn Cpu) {
+    let sp = cpu.get_register(Register::SP);
+    let lo = cpu.read(0x0100 + u16::from(sp.wrapping_add(1)));
+    let hi = cpu.read(0x0100 + u16::from(sp.wrapping_add(2)));
+    cpu.set_register(Register::SP, sp.wrapping_add(2));
+    cpu.set_pc((u16::from(lo) | (u16::from(hi) << 8)).wrapping_add(1));
+}
+
+/// Strip drive prefix and split file name from its type and mode suffixes.
+fn parse_filename(name: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let name = match name.iter().position(|c| *c == b':') {
+        Some(pos) => &name[pos + 1..],
+        None => name,
+    };
+    match name.iter().position(|c| *c == b',') {
+        Some(pos) => (name[..pos].to_vec(), name[pos + 1..].to_vec()),
+        None => (name.to_vec(), Vec::new()),
+    }
+}
+
+/// Match file name against pattern using DOS wildcards: '?' matches any character
+/// and '*' matches the rest of the name.
+fn matches_pattern(pattern: &[u8], name: &[u8]) -> bool {
+    for (i, c) in pattern.iter().enumerate() {
+        match *c {
+            b'*' => return true,
+            b'?' if i < name.len() => (),
+            _ if i < name.len() && name[i] == *c => (),
+            _ => return false,
+        }
+    }
+    pattern.len() == name.len()
+}
+
+/// Format directory listing as a BASIC program loaded at $0401.
+fn format_directory(fs: &dyn FileSystem) -> Vec<u8> {
+    let mut data = vec![0x01, 0x04];
+    let mut header = vec![0x12, b'"'];
+    header.extend_from_slice(&pad(&fs.get_name(), 16));
+    header.extend_from_slice(b"\" ");
+    header.extend_from_slice(&fs.get_id());
+    append_line(&mut data, 0, &header);
+    for entry in fs.get_entries() {
+        let mut line = Vec::new();
+        let indent = match entry.blocks {
+            0..=9 => 3,
+            10..=99 => 2,
+            _ => 1,
+        };
+        line.resize(indent, b' ');
+        line.push(b'"');
+        line.extend_from_slice(&entry.name);
+        line.push(b'"');
+        line.resize(indent + 18, b' ');
+        line.push(b' ');
+        line.extend_from_slice(entry.file_type.name());
+        append_line(&mut data, entry.blocks, &line);
+    }
+    let mut footer = b"BLOCKS FREE.".to_vec();
+    footer.resize(25, b' ');
+    append_line(&mut data, fs.get_blocks_free(), &footer);
+    data.extend_from_slice(&[0x00, 0x00]);
+    data
+}
+
+fn append_line(data: &mut Vec<u8>, number: u16, text: &[u8]) {
+    let next = 0x0401 + (data.len() - 2) + 4 + text.len() + 1;
+    data.push(next as u8);
+    data.push((next >> 8) as u8);
+    data.push(number as u8);
+    data.push((number >> 8) as u8);
+    data.extend_from_slice(text);
+    data.push(0x00);
+}
+
+fn pad(value: &[u8], len: usize) -> Vec<u8> {
+    let mut result = value.iter().take(len).cloned().collect::<Vec<u8>>();
+    result.resize(len, b' ');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_wildcards() {
+        assert!(matches_pattern(b"*", b"GAME"));
+        assert!(matches_pattern(b"GA*", b"GAME"));
+        assert!(matches_pattern(b"G?ME", b"GAME"));
+        assert!(matches_pattern(b"GAME", b"GAME"));
+        assert!(!matches_pattern(b"GAM", b"GAME"));
+        assert!(!matches_pattern(b"GAMES", b"GAME"));
+    }
+
+    #[test]
+    fn parse_drive_prefix_and_type() {
+        let (name, suffix) = parse_filename(b"0:GAME,S,R");
+        assert_eq!(b"GAME".to_vec(), name);
+        assert_eq!(b"S,R".to_vec(), suffix);
+    }
+}
@@ -2,18 +2,21 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+use std::fs;
 use std::net::SocketAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::{mpsc, Arc};
 use std::thread;
 
-use glutin::event::Event;
+use glutin::event::{Event, WindowEvent};
 use zinc64_core::device::joystick;
 use zinc64_core::util::Shared;
 use zinc64_debug::{Command, Debugger};
+use zinc64_loader::SidTune;
 use zinc64_system::C64;
 
-use crate::audio::SoundBuffer;
+use crate::audio::{SoundBuffer, SoundRecorder};
+use crate::cli;
 use crate::console::Console;
 use crate::debug::Debug;
 use crate::framework::{Context, State};
@@ -35,9 +38,24 @@ pub struct Options {
     pub jam_action: JamAction,
     pub speed: u8,
     pub warp_mode: bool,
+    // Movie
+    pub record: Option<PathBuf>,
+    // Devices
+    pub crt_image: Option<PathBuf>,
+    pub crt_write_back: bool,
+    pub disk_image: Option<PathBuf>,
+    pub disk_write_back: bool,
+    pub georam_image: Option<PathBuf>,
+    pub reu_image: Option<PathBuf>,
+    // Sound
+    pub sid_log: Option<PathBuf>,
+    // Player
+    pub sid_tune: Option<SidTune>,
+    pub sid_song: u16,
     // Controllers
     pub joydev_1: joystick::Mode,
     pub joydev_2: joystick::Mode,
+    pub light_pen: Option<joystick::Button>,
     // Debug
     pub debug: bool,
     pub dbg_address: SocketAddr,
@@ -60,6 +78,7 @@ pub struct AppState {
     pub debug: Debug,
     pub options: Options,
     pub sound_buffer: Arc<SoundBuffer>,
+    pub sound_recorder: Arc<SoundRecorder>,
     pub video_buffer: Shared<VideoBuffer>,
 }
 
@@ -73,6 +92,7 @@ impl App {
         ctx: &mut Context,
         c64: C64,
         sound_buffer: Arc<SoundBuffer>,
+        sound_recorder: Arc<SoundRecorder>,
         video_buffer: Shared<VideoBuffer>,
         options: Options,
     ) -> Result<App, String> {
@@ -111,6 +131,7 @@ impl App {
             debug: Debug::new(debug_rx),
             options,
             sound_buffer,
+            sound_recorder,
             video_buffer,
         };
         let main_screen = MainScreen::build(ctx, &mut state)?;
@@ -119,6 +140,49 @@ impl App {
         Ok(App { state, screens })
     }
 
+    fn save_on_exit(&mut self) {
+        self.save_recording();
+        if let Some(path) = self.state.options.sid_log.take() {
+            if let Err(err) = cli::save_sid_log(&mut self.state.c64, &path) {
+                error!("Failed to save SID log, error: {}", err);
+            }
+        }
+        if let Err(err) = self.state.sound_recorder.stop() {
+            error!("Failed to save audio recording, error: {}", err);
+        }
+        if let Some(path) = self.state.options.crt_image.take() {
+            if let Err(err) = cli::save_crt_image(&self.state.c64, &path) {
+                error!("Failed to save cartridge image, error: {}", err);
+            }
+        }
+        if let Some(path) = self.state.options.disk_image.take() {
+            if let Err(err) = cli::save_disk_image(&self.state.c64, &path) {
+                error!("Failed to save disk image, error: {}", err);
+            }
+        }
+        if let Some(path) = self.state.options.georam_image.take() {
+            if let Err(err) = cli::save_georam_image(&self.state.c64, &path) {
+                error!("Failed to save GeoRAM image, error: {}", err);
+            }
+        }
+        if let Some(path) = self.state.options.reu_image.take() {
+            if let Err(err) = cli::save_reu_image(&self.state.c64, &path) {
+                error!("Failed to save REU image, error: {}", err);
+            }
+        }
+    }
+
+    fn save_recording(&mut self) {
+        if let Some(path) = self.state.options.record.take() {
+            if let Some(movie) = self.state.c64.stop_recording() {
+                info!("Saving movie to {}", path.display());
+                if let Err(err) = fs::write(&path, movie.to_bytes()) {
+                    error!("Failed to save movie, error: {}", err);
+                }
+            }
+        }
+    }
+
     fn process_transition(&mut self, transition: Transition<AppState>) {
         match transition {
             Transition::None => {}
@@ -134,12 +198,20 @@ impl App {
 
 impl State for App {
     fn handle_event(&mut self, ctx: &mut Context, event: Event<()>) -> Result<(), String> {
+        if let Event::WindowEvent {
+            event: WindowEvent::CloseRequested,
+            ..
+        } = event
+        {
+            self.save_on_exit();
+        }
         match self.screens.last_mut() {
             Some(screen) => {
                 let transition = screen.handle_event(ctx, &mut self.state, event)?;
                 self.process_transition(transition);
             }
             None => {
+                self.save_on_exit();
                 ctx.running = false;
             }
         }
@@ -153,6 +225,7 @@ impl State for App {
                 self.process_transition(transition);
             }
             None => {
+                self.save_on_exit();
                 ctx.running = false;
             }
         }
@@ -166,6 +239,7 @@ impl State for App {
                 self.process_transition(transition);
             }
             None => {
+                self.save_on_exit();
                 ctx.running = false;
             }
         }
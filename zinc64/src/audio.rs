@@ -4,6 +4,7 @@
 #![allow(unused)]
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::cast_lossless))]
 
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::thread;
 
@@ -11,7 +12,7 @@ use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::SampleFormat;
 use zinc64_core::factory::SoundOutput;
 
-use crate::util::CircularBuffer;
+use crate::util::{CircularBuffer, WavWriter};
 
 // TODO app: audio warp handling
 
@@ -178,9 +179,15 @@ fn write_data<T>(
     let state = state.lock().unwrap();
     let mut input = input.buffer.lock().unwrap();
     for frame in output.chunks_mut(channels) {
-        let value = input.pop().unwrap_or(0);
-        for sample in frame.iter_mut() {
+        let (left, right) = input.pop().unwrap_or((0, 0));
+        for (i, sample) in frame.iter_mut().enumerate() {
             if !state.mute {
+                let value = match (i, channels) {
+                    (_, 1) => ((left as i32 + right as i32) / 2) as i16,
+                    (0, _) => left,
+                    (1, _) => right,
+                    _ => ((left as i32 + right as i32) / 2) as i16,
+                };
                 let value = ((value as i32 * state.scaler) >> (SCALER_SHIFT as i32)) as i16;
                 let formatted_value = T::from_sample(value);
                 *sample = formatted_value;
@@ -191,11 +198,14 @@ fn write_data<T>(
     }
 }
 
+/// Buffer of left and right sample pairs. Output of multiple SIDs is panned from left to
+/// right in the order of the chips, mono output is played on both sides.
 pub struct SoundBuffer {
-    buffer: Mutex<CircularBuffer<i16>>,
+    buffer: Mutex<CircularBuffer<(i16, i16)>>,
 }
 
 impl SoundBuffer {
+    /// Create buffer with capacity for the specified number of stereo frames.
     pub fn new(length: usize) -> Self {
         SoundBuffer {
             buffer: Mutex::new(CircularBuffer::new(length)),
@@ -203,6 +213,25 @@ impl SoundBuffer {
     }
 }
 
+fn pan(frame: &[i16]) -> (i16, i16) {
+    if frame.len() < 2 {
+        let value = frame.first().cloned().unwrap_or(0);
+        return (value, value);
+    }
+    // Chip i of n is positioned at i / (n - 1) between left and right, gains are scaled so
+    // that all chips at full level do not exceed the sample range.
+    let last = (frame.len() - 1) as i32;
+    let mut left = 0i32;
+    let mut right = 0i32;
+    for (i, sample) in frame.iter().enumerate() {
+        let position = i as i32;
+        left += *sample as i32 * (last - position);
+        right += *sample as i32 * position;
+    }
+    let scale = last * frame.len() as i32 / 2;
+    ((left / scale) as i16, (right / scale) as i16)
+}
+
 impl SoundOutput for SoundBuffer {
     fn reset(&self) {
         let mut output = self.buffer.lock().unwrap();
@@ -212,7 +241,97 @@ impl SoundOutput for SoundBuffer {
     fn write(&self, samples: &[i16]) {
         let mut output = self.buffer.lock().unwrap();
         for sample in samples {
-            output.push(*sample);
+            output.push((*sample, *sample));
+        }
+    }
+
+    fn write_frames(&self, frames: &[i16], channels: usize) {
+        let mut output = self.buffer.lock().unwrap();
+        for frame in frames.chunks(channels) {
+            output.push(pan(frame));
+        }
+    }
+}
+
+/// Sound output passing samples through to another output, which also writes them to a WAV
+/// file while recording. Multiple SIDs are recorded in stereo panned the same way as for
+/// playback.
+pub struct SoundRecorder {
+    output: Arc<dyn SoundOutput>,
+    sample_rate: u32,
+    channels: u16,
+    writer: Mutex<Option<WavWriter>>,
+}
+
+impl SoundRecorder {
+    pub fn new(output: Arc<dyn SoundOutput>, sample_rate: u32, channels: u16) -> Self {
+        SoundRecorder {
+            output,
+            sample_rate,
+            channels,
+            writer: Mutex::new(None),
         }
     }
+
+    pub fn is_recording(&self) -> bool {
+        self.writer.lock().unwrap().is_some()
+    }
+
+    /// Start recording to the file, finishing the recording in progress.
+    pub fn start(&self, path: &Path) -> Result<(), String> {
+        self.stop()?;
+        info!("Recording audio to {}", path.display());
+        let wav = WavWriter::create(path, self.sample_rate, self.channels)?;
+        *self.writer.lock().unwrap() = Some(wav);
+        Ok(())
+    }
+
+    /// Finish the recording in progress. Returns false if there was none.
+    pub fn stop(&self) -> Result<bool, String> {
+        match self.writer.lock().unwrap().take() {
+            Some(wav) => {
+                info!("Stopped audio recording");
+                wav.finish()?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn record<I>(&self, mut samples: I)
+    where
+        I: Iterator<Item = (i16, i16)>,
+    {
+        let mut writer = self.writer.lock().unwrap();
+        if let Some(ref mut wav) = *writer {
+            let stereo = wav.get_channels() == 2;
+            let result = samples.try_for_each(|(left, right)| {
+                if stereo {
+                    wav.write(left).and_then(|_| wav.write(right))
+                } else {
+                    wav.write(((left as i32 + right as i32) / 2) as i16)
+                }
+            });
+            if let Err(err) = result {
+                error!("Failed to record audio, error: {}", err);
+                *writer = None;
+            }
+        }
+    }
+}
+
+impl SoundOutput for SoundRecorder {
+    fn reset(&self) {
+        self.output.reset();
+    }
+
+    fn write(&self, samples: &[i16]) {
+        self.output.write(samples);
+        self.record(samples.iter().map(|sample| (*sample, *sample)));
+    }
+
+    fn write_frames(&self, frames: &[i16], channels: usize) {
+        self.output.write_frames(frames, channels);
+        self.record(frames.chunks(channels).map(pan));
+    }
 }
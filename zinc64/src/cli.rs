@@ -3,17 +3,21 @@
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
 use std::error::Error;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, Read};
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 
 use structopt::StructOpt;
-use zinc64_core::device::joystick;
-use zinc64_core::factory::SystemModel;
-use zinc64_system::{Config, C64};
+use zinc64_core::device::{cartridge, joystick, reu};
+use zinc64_core::factory::{SidModel, SystemModel};
+use zinc64_core::sound::{SidLog, SidLogPlayer};
+use zinc64_loader::{CrtWriter, Format, G64Writer, Loader, SidTune, T64Loader};
+use zinc64_system::{Config, GeoRamConfig, Movie, ReuConfig, SidConfig, C64};
 
 use crate::app::{self, JamAction};
+use crate::headless;
+use crate::util::{self, WavWriter};
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "zinc64")]
@@ -22,11 +26,11 @@ pub struct Opt {
     #[structopt(parse(from_os_str))]
     pub image: Option<PathBuf>,
 
-    /// set NTSC or PAL variants
-    #[structopt(long, default_value = "pal")]
-    pub model: String,
-    /// start in console mode
+    /// set model to pal, ntsc, ntsc-old, pal-n, c64c, sx64 or c64gs, defaults to a SID tune or PAL
     #[structopt(long)]
+    pub model: Option<String>,
+    /// start in console mode without audio or video devices
+    #[structopt(long, alias = "headless")]
     pub console: bool,
     /// set speed of the emulator
     #[structopt(long)]
@@ -43,6 +47,20 @@ pub struct Opt {
     )]
     pub jam_action: JamAction,
 
+    // -- Headless
+    /// stop console mode after the specified number of cycles
+    #[structopt(long = "limit-cycles")]
+    pub limit_cycles: Option<u64>,
+    /// stop console mode after the specified number of frames
+    #[structopt(long = "limit-frames")]
+    pub limit_frames: Option<u32>,
+    /// stop console mode when cpu jam is detected
+    #[structopt(long = "exit-on-jam")]
+    pub exit_on_jam: bool,
+    /// save screen as PPM image when console mode stops
+    #[structopt(long = "dump-screen", parse(from_os_str))]
+    pub dump_screen: Option<PathBuf>,
+
     // -- Ui
     /// window width
     #[structopt(long, default_value = "800")]
@@ -69,6 +87,27 @@ pub struct Opt {
         parse(try_from_str = parse_joy_mode)
     )]
     pub joydev_2: joystick::Mode,
+    /// attach a light pen or gun to port 1 driven by the mouse, with the button on up or left
+    #[structopt(long = "lightpen", parse(try_from_str = parse_light_pen_button))]
+    pub light_pen: Option<joystick::Button>,
+    /// save programmed EasyFlash contents back to the CRT image on exit
+    #[structopt(long = "crt-write-back", requires = "image")]
+    pub crt_write_back: bool,
+    /// save disk contents written by the drive back to the G64 image
+    #[structopt(long = "disk-write-back", requires = "image")]
+    pub disk_write_back: bool,
+    /// attach GeoRAM with the specified size in KB (512 up to 4096)
+    #[structopt(long, parse(try_from_str = parse_georam_size))]
+    pub georam: Option<usize>,
+    /// load GeoRAM image from this file and save it back on exit
+    #[structopt(long = "georam-image", parse(from_os_str), requires = "georam")]
+    pub georam_image: Option<PathBuf>,
+    /// attach REU with the specified size in KB (128, 256, 512 up to 16384)
+    #[structopt(long, parse(try_from_str = parse_reu_size))]
+    pub reu: Option<usize>,
+    /// load REU RAM image from this file and save it back on exit
+    #[structopt(long = "reu-image", parse(from_os_str), requires = "reu")]
+    pub reu_image: Option<PathBuf>,
 
     // -- Roms
     /// filename of the basic ROM
@@ -80,6 +119,9 @@ pub struct Opt {
     /// filename of the kernal ROM
     #[structopt(long, parse(from_os_str), group = "rom")]
     pub kernal: Option<PathBuf>,
+    /// filename of the 1541 DOS ROM, enables true drive emulation
+    #[structopt(long = "drive-rom", parse(from_os_str))]
+    pub drive_rom: Option<PathBuf>,
 
     // -- Sound
     /// disable sound playback
@@ -88,6 +130,24 @@ pub struct Opt {
     /// disable SID filters
     #[structopt(long = "nosidfilters")]
     pub no_sid_filters: bool,
+    /// add SID at the specified address with optional model, e.g. d420 or de00:8580
+    #[structopt(long = "sid", parse(try_from_str = parse_sid))]
+    pub extra_sids: Vec<(u16, Option<SidModel>)>,
+    /// select song of a SID tune, starting with 1
+    #[structopt(long, requires = "image")]
+    pub subtune: Option<u16>,
+    /// select file of a T64 image, starting with 1
+    #[structopt(long = "t64-entry", parse(try_from_str = parse_t64_entry), requires = "image")]
+    pub t64_entry: Option<usize>,
+    /// record audio to the specified WAV file
+    #[structopt(long = "record-audio", parse(from_os_str))]
+    pub record_audio: Option<PathBuf>,
+    /// capture SID register writes to the specified log file
+    #[structopt(long = "sid-log", parse(from_os_str))]
+    pub sid_log: Option<PathBuf>,
+    /// render SID register writes from the log file to the audio recording and exit
+    #[structopt(long = "play-sid-log", parse(from_os_str))]
+    pub play_sid_log: Option<PathBuf>,
     /// set sound sample rate in Hz
     #[structopt(long = "sound-rate", default_value = "44100")]
     pub sound_rate: u32,
@@ -95,6 +155,14 @@ pub struct Opt {
     #[structopt(long = "sound-samples", default_value = "2048")]
     pub sound_samples: u32,
 
+    // -- Movie
+    /// record input to the specified movie file
+    #[structopt(long, parse(from_os_str), conflicts_with = "playback")]
+    pub record: Option<PathBuf>,
+    /// play back input from the specified movie file
+    #[structopt(long, parse(from_os_str))]
+    pub playback: Option<PathBuf>,
+
     // -- Debug
     /// set breakpoint at this address
     #[structopt(long)]
@@ -126,22 +194,61 @@ pub struct Opt {
     pub log_target_level: Vec<(String, String)>,
 }
 
-pub fn build_app_options(opt: &Opt) -> Result<app::Options, String> {
+pub fn build_app_options(opt: &Opt, tune: Option<SidTune>) -> Result<app::Options, String> {
+    let song = match tune {
+        Some(ref tune) => get_subtune(opt, tune)?,
+        None => 0,
+    };
     Ok(app::Options {
         jam_action: opt.jam_action,
         speed: opt.speed.unwrap_or(100),
         warp_mode: opt.warp_mode,
         joydev_1: opt.joydev_1,
         joydev_2: opt.joydev_2,
+        light_pen: opt.light_pen,
+        record: opt.record.clone(),
+        crt_image: get_crt_image(opt),
+        crt_write_back: opt.crt_write_back,
+        disk_image: get_disk_image(opt),
+        disk_write_back: opt.disk_write_back,
+        georam_image: opt.georam_image.clone(),
+        reu_image: opt.reu_image.clone(),
+        sid_log: opt.sid_log.clone(),
+        sid_tune: tune,
+        sid_song: song,
         debug: opt.debug,
         dbg_address: opt.dbg_address,
         rap_address: SocketAddr::from(([127, 0, 0, 1], 9999)), // opt.rap_address,
     })
 }
 
-pub fn build_emu_config(opt: &Opt) -> Result<Config, String> {
-    let model = SystemModel::from(opt.model.as_str());
+pub fn build_headless_options(opt: &Opt) -> Result<headless::Options, String> {
+    Ok(headless::Options {
+        dump_screen: opt.dump_screen.clone(),
+        exit_on_jam: opt.exit_on_jam,
+        jam_action: opt.jam_action,
+        limit_cycles: opt.limit_cycles,
+        limit_frames: opt.limit_frames,
+    })
+}
+
+pub fn build_emu_config(opt: &Opt, tune: Option<&SidTune>) -> Result<Config, String> {
+    let model = match opt.model {
+        Some(ref model) => SystemModel::from(model.as_str()),
+        None => tune
+            .and_then(|tune| tune.get_system_model())
+            .unwrap_or_else(SystemModel::c64_pal),
+    };
+    if model.cartridge_only {
+        match opt.image {
+            Some(ref path) => util::check_model_image(&model, path)?,
+            None => return Err("The selected model only runs CRT cartridge images".to_string()),
+        }
+    }
     let mut config = Config::new(model);
+    if let Some(sid_model) = tune.and_then(|tune| tune.get_sid_model()) {
+        config.model.sid_model = sid_model;
+    }
     config.joystick.joystick_1 = opt.joydev_1;
     config.joystick.joystick_2 = opt.joydev_2;
     let basic_path = Path::new(
@@ -156,15 +263,71 @@ pub fn build_emu_config(opt: &Opt) -> Result<Config, String> {
             .map(|path| Path::new(path))
             .unwrap_or(Path::new("res/rom/characters.rom")),
     );
-    let kernal_path = Path::new(
-        opt.kernal
-            .as_ref()
-            .map(|path| Path::new(path))
-            .unwrap_or(Path::new("res/rom/kernal.rom")),
-    );
+    let kernal_path = opt
+        .kernal
+        .clone()
+        .unwrap_or_else(|| Path::new("res/rom").join(config.model.kernal_rom));
     config.roms.basic = load_file(basic_path).map_err(|_| format!("Invalid rom: basic"))?;
     config.roms.charset = load_file(charset_path).map_err(|_| format!("Invalid rom: charset"))?;
-    config.roms.kernal = load_file(kernal_path).map_err(|_| format!("Invalid rom: kernal"))?;
+    config.roms.kernal = load_file(&kernal_path)
+        .map_err(|_| format!("Invalid rom: kernal {}", kernal_path.display()))?;
+    if let Some(ref path) = opt.drive_rom {
+        config.roms.drive = Some(load_file(path).map_err(|_| "Invalid rom: drive".to_string())?);
+    }
+    if opt.georam.is_some() && opt.reu.is_some() {
+        return Err("GeoRAM and REU can not be attached together".to_string());
+    }
+    if let Some(size) = opt.georam {
+        let mut georam_config = GeoRamConfig::new(size);
+        if let Some(ref path) = opt.georam_image {
+            if path.exists() {
+                let image =
+                    load_file(path).map_err(|err| format!("Invalid GeoRAM image: {}", err))?;
+                if image.len() > size {
+                    return Err(format!(
+                        "Invalid GeoRAM image: size {} exceeds GeoRAM size {}",
+                        image.len(),
+                        size
+                    ));
+                }
+                georam_config.image = Some(image);
+            }
+        }
+        config.georam = Some(georam_config);
+    }
+    if let Some(size) = opt.reu {
+        let mut reu_config = ReuConfig::new(size);
+        if let Some(ref path) = opt.reu_image {
+            if path.exists() {
+                let image = load_file(path).map_err(|err| format!("Invalid REU image: {}", err))?;
+                if image.len() > size {
+                    return Err(format!(
+                        "Invalid REU image: size {} exceeds REU size {}",
+                        image.len(),
+                        size
+                    ));
+                }
+                reu_config.image = Some(image);
+            }
+        }
+        config.reu = Some(reu_config);
+    }
+    for (base_address, model) in opt.extra_sids.iter() {
+        let model = model.unwrap_or(config.model.sid_model);
+        config.extra_sids.push(SidConfig::new(*base_address, model));
+    }
+    if let Some(tune) = tune {
+        for (base_address, model) in tune.get_extra_sids() {
+            if config
+                .extra_sids
+                .iter()
+                .all(|sid| sid.base_address != base_address)
+            {
+                let model = model.unwrap_or(config.model.sid_model);
+                config.extra_sids.push(SidConfig::new(base_address, model));
+            }
+        }
+    }
     config.sound.enable = !opt.no_sound;
     config.sound.buffer_size = opt.sound_samples as usize;
     config.sound.sample_rate = opt.sound_rate;
@@ -177,6 +340,155 @@ pub fn set_c64_options(c64: &mut C64, opt: &Opt) -> Result<(), String> {
     Ok(())
 }
 
+pub fn set_c64_movie_options(c64: &mut C64, opt: &Opt) -> Result<(), String> {
+    if let Some(ref path) = opt.playback {
+        let data = load_file(path).map_err(|err| format!("Invalid movie: {}", err))?;
+        let movie = Movie::from_bytes(&data)?;
+        c64.start_playback(movie)?;
+    }
+    if opt.record.is_some() {
+        c64.start_recording();
+    }
+    Ok(())
+}
+
+/// Load SID tune if the image is a PSID or RSID file.
+pub fn load_sid_tune(opt: &Opt) -> Result<Option<SidTune>, String> {
+    match opt.image {
+        Some(ref path) => util::load_sid_tune(path),
+        None => Ok(None),
+    }
+}
+
+/// Get loader for the image, using the T64 entry if one is selected.
+pub fn find_image_loader(opt: &Opt, path: &Path) -> Result<Box<dyn Loader>, String> {
+    match opt.t64_entry {
+        Some(entry) => match util::find_format(path)? {
+            Some(Format::T64) => Ok(Box::new(T64Loader::new(entry - 1))),
+            _ => Err("T64 entry requires a T64 image".to_string()),
+        },
+        None => util::find_loader(path),
+    }
+}
+
+/// Get song of the tune to start with, either selected or the default one.
+pub fn get_subtune(opt: &Opt, tune: &SidTune) -> Result<u16, String> {
+    match opt.subtune {
+        Some(song) if song < 1 || song > tune.get_songs() => Err(format!(
+            "Invalid subtune {}, tune has {} songs",
+            song,
+            tune.get_songs()
+        )),
+        Some(song) => Ok(song),
+        None => Ok(tune.get_start_song()),
+    }
+}
+
+/// Get image the cartridge is saved back to if write back is enabled and the image is a
+/// cartridge.
+pub fn get_crt_image(opt: &Opt) -> Option<PathBuf> {
+    if opt.crt_write_back {
+        opt.image
+            .clone()
+            .filter(|path| matches!(util::find_format(path), Ok(Some(Format::Crt))))
+    } else {
+        None
+    }
+}
+
+/// Get image the disk is saved back to if write back is enabled and the image is a G64
+/// disk.
+pub fn get_disk_image(opt: &Opt) -> Option<PathBuf> {
+    if opt.disk_write_back {
+        opt.image
+            .clone()
+            .filter(|path| matches!(util::find_format(path), Ok(Some(Format::G64))))
+    } else {
+        None
+    }
+}
+
+/// Save attached cartridge to CRT image if its flash memory was modified.
+pub fn save_crt_image(c64: &C64, path: &Path) -> Result<(), String> {
+    let expansion_port = c64.get_expansion_port();
+    let expansion_port = expansion_port.borrow();
+    if let Some(cartridge) = expansion_port.get_cartridge() {
+        if cartridge.is_flash_modified() {
+            info!("Saving cartridge image to {}", path.display());
+            fs::write(path, CrtWriter::write(cartridge)).map_err(|err| format!("{}", err))?;
+        }
+    }
+    Ok(())
+}
+
+/// Save disk in the drive to G64 image if it was written to.
+pub fn save_disk_image(c64: &C64, path: &Path) -> Result<(), String> {
+    if let Some(drive) = c64.get_drive() {
+        let drive = drive.borrow();
+        if let Some(disk) = drive.get_disk() {
+            if disk.is_modified() {
+                info!("Saving disk image to {}", path.display());
+                fs::write(path, G64Writer::write(disk)).map_err(|err| format!("{}", err))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn save_georam_image(c64: &C64, path: &Path) -> Result<(), String> {
+    let expansion_port = c64.get_expansion_port();
+    let expansion_port = expansion_port.borrow();
+    if let Some(cartridge) = expansion_port.get_cartridge() {
+        if cartridge.get_hw_type() == cartridge::HwType::GeoRam {
+            if let Some(ram) = cartridge.get_ram() {
+                info!("Saving GeoRAM image to {}", path.display());
+                fs::write(path, ram).map_err(|err| format!("{}", err))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn save_reu_image(c64: &C64, path: &Path) -> Result<(), String> {
+    if let Some(reu) = c64.get_reu() {
+        info!("Saving REU image to {}", path.display());
+        fs::write(path, reu.borrow().get_ram()).map_err(|err| format!("{}", err))?;
+    }
+    Ok(())
+}
+
+pub fn save_sid_log(c64: &mut C64, path: &Path) -> Result<(), String> {
+    let writes = c64.stop_sid_capture();
+    info!("Saving {} SID writes to {}", writes.len(), path.display());
+    fs::write(path, SidLog::to_text(&writes)).map_err(|err| format!("{}", err))
+}
+
+/// Render writes to the SID at $D400 from the log to a WAV file, followed by one second of
+/// output after the last write.
+pub fn play_sid_log(config: &Config, path: &Path, wav_path: &Path) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}", err))?;
+    let writes = SidLog::from_text(&text)?;
+    let mut player = SidLogPlayer::new(
+        config.model.sid_model,
+        0xd400,
+        config.model.cpu_freq,
+        config.sound.sample_rate,
+    );
+    let mut samples = Vec::new();
+    player.play(&writes, config.model.cpu_freq, &mut samples);
+    info!(
+        "Rendering {} SID writes from {} to {}",
+        writes.len(),
+        path.display(),
+        wav_path.display()
+    );
+    let mut wav = WavWriter::create(wav_path, config.sound.sample_rate, 1)?;
+    for sample in samples {
+        wav.write(sample)?;
+    }
+    wav.finish()
+}
+
 fn set_c64_debug_options(c64: &mut C64, opt: &Opt) -> Result<(), String> {
     for bp in &opt.bp {
         c64.get_bpm_mut().set(*bp, false);
@@ -210,6 +522,16 @@ fn parse_joy_mode(mode: &str) -> Result<joystick::Mode, Box<dyn Error>> {
     }
 }
 
+fn parse_light_pen_button(button: &str) -> Result<joystick::Button, Box<dyn Error>> {
+    match button {
+        "up" => Ok(joystick::Button::Up),
+        "left" => Ok(joystick::Button::Left),
+        _ => Err(Box::<dyn Error>::from(
+            "invalid light pen button".to_string(),
+        )),
+    }
+}
+
 fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn Error>>
 where
     T: std::str::FromStr,
@@ -223,6 +545,48 @@ where
     Ok((s[..pos].parse()?, s[pos + 1..].parse()?))
 }
 
+fn parse_georam_size(s: &str) -> Result<usize, Box<dyn Error>> {
+    let size = s.parse::<usize>()? * 1024;
+    if size.is_power_of_two()
+        && (cartridge::GEORAM_MIN_SIZE..=cartridge::GEORAM_MAX_SIZE).contains(&size)
+    {
+        Ok(size)
+    } else {
+        Err(Box::<dyn Error>::from("invalid GeoRAM size".to_string()))
+    }
+}
+
+fn parse_reu_size(s: &str) -> Result<usize, Box<dyn Error>> {
+    let size = s.parse::<usize>()? * 1024;
+    if size.is_power_of_two() && (reu::MIN_SIZE..=reu::MAX_SIZE).contains(&size) {
+        Ok(size)
+    } else {
+        Err(Box::<dyn Error>::from("invalid REU size".to_string()))
+    }
+}
+
+fn parse_t64_entry(s: &str) -> Result<usize, Box<dyn Error>> {
+    match s.parse::<usize>()? {
+        0 => Err(Box::<dyn Error>::from("invalid T64 entry".to_string())),
+        entry => Ok(entry),
+    }
+}
+
+fn parse_sid(s: &str) -> Result<(u16, Option<SidModel>), Box<dyn Error>> {
+    let mut parts = s.splitn(2, ':');
+    let address = parts.next().unwrap_or("");
+    let address = address.trim_start_matches('$').trim_start_matches("0x");
+    let base_address = u16::from_str_radix(address, 16)?;
+    let model = match parts.next() {
+        Some("6581") => Some(SidModel::Mos6581),
+        Some("8580") => Some(SidModel::Mos8580),
+        Some(_) => return Err(Box::<dyn Error>::from("invalid SID model".to_string())),
+        None => None,
+    };
+    SidConfig::new(base_address, model.unwrap_or(SidModel::Mos6581)).validate()?;
+    Ok((base_address, model))
+}
+
 fn parse_socket_addr(s: &str) -> Result<SocketAddr, Box<dyn Error>> {
     s.parse::<SocketAddr>()
         .map_err(|_| Box::<dyn Error>::from("invalid address".to_string()))
@@ -0,0 +1,43 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+use std::io::Write;
+use std::path::Path;
+use std::result::Result;
+use std::sync::Arc;
+
+use super::Handler;
+use crate::audio::SoundRecorder;
+
+pub struct AudioCommand {
+    recorder: Arc<SoundRecorder>,
+    path: Option<String>,
+}
+
+impl AudioCommand {
+    /// Create command starting a recording to the file, or stopping it without one.
+    pub fn new(recorder: Arc<SoundRecorder>, path: Option<String>) -> Self {
+        Self { recorder, path }
+    }
+
+    pub fn help() -> &'static str {
+        "audio record <wav_path> | audio stop"
+    }
+}
+
+impl Handler for AudioCommand {
+    fn run(&mut self, out: &mut dyn Write) -> Result<(), String> {
+        let text = if let Some(path) = self.path.as_ref() {
+            self.recorder.start(Path::new(path))?;
+            format!("Recording audio to {}.\n", path)
+        } else if self.recorder.stop()? {
+            "Stopped audio recording.\n".to_string()
+        } else {
+            "Audio is not being recorded.\n".to_string()
+        };
+        out.write(text.as_bytes())
+            .map_err(|err| format!("{}", err))?;
+        Ok(())
+    }
+}
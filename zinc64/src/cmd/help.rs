@@ -5,6 +5,7 @@
 use std::io::Write;
 use std::result::Result;
 
+use super::audio::AudioCommand;
 use super::load::LoadCommand;
 use super::ls::LsCommand;
 use super::reset::ResetCommand;
@@ -30,6 +31,7 @@ impl HelpCommand {
 
     fn help() -> String {
         let mut buffer = String::new();
+        buffer.push_str("audio\n");
         buffer.push_str("load\n");
         buffer.push_str("ls\n");
         buffer.push_str("reset\n");
@@ -43,6 +45,7 @@ impl Handler for HelpCommand {
     fn run(&mut self, out: &mut dyn Write) -> Result<(), String> {
         if let Some(command) = self.command.as_ref() {
             let text = match command.trim().to_lowercase().as_str() {
+                "audio" => HelpCommand::format(AudioCommand::help(), ""),
                 "load" => HelpCommand::format(LoadCommand::help(), ""),
                 "ls" => HelpCommand::format(LsCommand::help(), ""),
                 "reset" => HelpCommand::format(ResetCommand::help(), ""),
@@ -7,11 +7,10 @@ use std::io::{BufReader, Write};
 use std::path::Path;
 use std::result::Result;
 
-use zinc64_loader::Loaders;
 use zinc64_system::C64;
 
 use super::Handler;
-use crate::util::FileReader;
+use crate::util::{check_model_image, find_loader, FileReader};
 
 pub struct LoadCommand<'a> {
     c64: &'a mut C64,
@@ -31,8 +30,8 @@ impl<'a> LoadCommand<'a> {
 impl<'a> Handler for LoadCommand<'a> {
     fn run(&mut self, out: &mut dyn Write) -> Result<(), String> {
         let path = Path::new(&self.path);
-        let ext = path.extension().map(|s| s.to_str().unwrap());
-        let loader = Loaders::from_ext(ext)?;
+        check_model_image(&self.c64.get_config().model, path)?;
+        let loader = find_loader(path)?;
         let file = File::open(path).map_err(|err| format!("{}", err))?;
         let mut reader = FileReader(BufReader::new(file));
         let mut autostart = loader.autostart(&mut reader)?;
@@ -2,12 +2,15 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
-use std::fs;
-use std::io::Write;
+use std::fs::{self, File};
+use std::io::{BufReader, Write};
 use std::path::Path;
 use std::result::Result;
 
+use zinc64_loader::{Format, P00Loader};
+
 use super::Handler;
+use crate::util::FileReader;
 
 pub struct LsCommand {
     path: Option<String>,
@@ -21,6 +24,23 @@ impl LsCommand {
     pub fn help() -> &'static str {
         "ls [<dir>]"
     }
+
+    /// Describe PC64 files with their original C64 name and type.
+    fn describe(path: &Path) -> Option<String> {
+        let ext = path.extension().and_then(|s| s.to_str());
+        if let Some(Format::P00(file_type)) = Format::from_ext(ext) {
+            let file = File::open(path).ok()?;
+            let mut reader = FileReader(BufReader::new(file));
+            let pc64 = P00Loader::new(file_type).read_file(&mut reader).ok()?;
+            Some(format!(
+                "  \"{}\" {}",
+                String::from_utf8_lossy(&pc64.name),
+                String::from_utf8_lossy(file_type.name())
+            ))
+        } else {
+            None
+        }
+    }
 }
 
 impl Handler for LsCommand {
@@ -30,7 +50,11 @@ impl Handler for LsCommand {
         if dir.is_dir() {
             let entries = fs::read_dir(dir).map_err(|err| format!("{}", err))?;
             for entry in entries {
-                let path = format!("{}", entry.unwrap().path().display());
+                let entry_path = entry.unwrap().path();
+                let mut path = format!("{}", entry_path.display());
+                if let Some(description) = LsCommand::describe(&entry_path) {
+                    path.push_str(&description);
+                }
                 if !path.is_empty() {
                     out.write(path.as_ref()).map_err(|err| format!("{}", err))?;
                     out.write("\n".as_bytes())
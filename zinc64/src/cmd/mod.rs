@@ -2,6 +2,7 @@
 // Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
 // Licensed under the GPLv3. See LICENSE file in the project root for full license text.
 
+mod audio;
 mod help;
 mod load;
 mod ls;
@@ -10,16 +11,21 @@ mod reset;
 
 use std::io::Write;
 use std::result::Result;
+use std::sync::Arc;
 
 use zinc64_system::C64;
 
+use self::audio::AudioCommand;
 use self::help::HelpCommand;
 use self::load::LoadCommand;
 use self::ls::LsCommand;
 use self::parser::Parser;
 use self::reset::ResetCommand;
+use crate::audio::SoundRecorder;
 
 pub enum Cmd {
+    /// Start audio recording to the file or stop it.
+    Audio(Option<String>),
     Help(Option<String>),
     Load(String),
     Ls(Option<String>),
@@ -32,18 +38,21 @@ trait Handler {
 
 pub struct Executor {
     parser: Parser,
+    sound_recorder: Arc<SoundRecorder>,
 }
 
 impl Executor {
-    pub fn new() -> Self {
+    pub fn new(sound_recorder: Arc<SoundRecorder>) -> Self {
         Executor {
             parser: Parser::new(),
+            sound_recorder,
         }
     }
 
     pub fn execute(&self, input: &str, c64: &mut C64, out: &mut dyn Write) -> Result<(), String> {
         let command = self.parser.parse(input)?;
         let mut handler: Box<dyn Handler> = match command {
+            Cmd::Audio(path) => Box::new(AudioCommand::new(self.sound_recorder.clone(), path)),
             Cmd::Load(path) => Box::new(LoadCommand::new(c64, path)),
             Cmd::Ls(path) => Box::new(LsCommand::new(path)),
             Cmd::Reset(hard) => Box::new(ResetCommand::new(c64, hard)),
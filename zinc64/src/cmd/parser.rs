@@ -29,6 +29,7 @@ impl Parser {
         let mut tokens = input.split_whitespace();
         if let Some(command) = tokens.next() {
             match command.to_lowercase().as_str() {
+                "audio" => self.parse_audio(&mut tokens),
                 "load" => self.parse_load(&mut tokens),
                 "ls" => self.parse_ls(&mut tokens),
                 "reset" => self.parse_reset(&mut tokens),
@@ -48,6 +49,20 @@ impl Parser {
     }
     */
 
+    fn parse_audio(&self, tokens: &mut dyn Iterator<Item = &str>) -> Result<Cmd, String> {
+        let path = match tokens.next() {
+            Some("record") => match tokens.next() {
+                Some(value) => Some(String::from(value)),
+                None => return Err("missing argument".to_string()),
+            },
+            Some("stop") => None,
+            Some(token) => return Err(format!("Invalid argument {}", token)),
+            None => return Err("missing argument".to_string()),
+        };
+        self.ensure_eos(tokens)?;
+        Ok(Cmd::Audio(path))
+    }
+
     fn parse_help(&self, tokens: &mut dyn Iterator<Item = &str>) -> Result<Cmd, String> {
         let command = tokens.next().map(|s| s.to_string());
         self.ensure_eos(tokens)?;
@@ -84,6 +84,7 @@ impl Debug {
             Command::Continue => self.continue_(c64),
             Command::SysQuit => self.quit(c64),
             Command::Step => self.step(c64),
+            Command::StepBack => self.step_back(c64),
             Command::BpClear => self.bp_clear(c64),
             Command::BpCondition(index, ref expr, radix) => {
                 self.bp_condition(c64, index, expr, radix)
@@ -142,6 +143,11 @@ impl Debug {
         CmdResult::ok(Output::Number(bp_hit))
     }
 
+    fn step_back(&self, c64: &mut C64) -> Result<CmdResult, String> {
+        c64.step_back()?;
+        CmdResult::unit()
+    }
+
     fn bp_clear(&self, c64: &mut C64) -> Result<CmdResult, String> {
         let bpm = c64.get_bpm_mut();
         bpm.clear();
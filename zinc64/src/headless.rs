@@ -0,0 +1,167 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use zinc64_core::util::Shared;
+use zinc64_system::C64;
+
+use crate::app::JamAction;
+use crate::video::VideoBuffer;
+
+// Design:
+//   Headless runner executes the machine without opening any audio or video devices. Frame
+//   and cycle limits are counted from the start of the run so they are not affected by
+//   resets triggered by autostart.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Limit,
+    CpuJam,
+    Breakpoint,
+}
+
+impl Outcome {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Limit => 0,
+            Outcome::CpuJam => 2,
+            Outcome::Breakpoint => 3,
+        }
+    }
+}
+
+pub struct Options {
+    pub dump_screen: Option<PathBuf>,
+    pub exit_on_jam: bool,
+    pub jam_action: JamAction,
+    pub limit_cycles: Option<u64>,
+    pub limit_frames: Option<u32>,
+}
+
+pub struct Headless {
+    options: Options,
+    video_buffer: Shared<VideoBuffer>,
+    // Runtime State
+    cycles: u64,
+    frames: u32,
+}
+
+impl Headless {
+    pub fn new(options: Options, video_buffer: Shared<VideoBuffer>) -> Self {
+        Self {
+            options,
+            video_buffer,
+            cycles: 0,
+            frames: 0,
+        }
+    }
+
+    pub fn run(&mut self, c64: &mut C64) -> Result<Outcome, String> {
+        let outcome = loop {
+            if self.is_limit_reached() {
+                break Outcome::Limit;
+            }
+            let last_cycles = c64.get_cycles();
+            let completed = if self.options.limit_cycles.is_some() {
+                self.run_step(c64)
+            } else {
+                self.run_frame(c64)
+            };
+            let cycles = c64.get_cycles();
+            // Clock is restarted on reset
+            self.cycles += if cycles >= last_cycles {
+                cycles - last_cycles
+            } else {
+                cycles
+            };
+            if !completed {
+                info!(target: "main", "Breakpoint hit at 0x{:x}", c64.get_cpu().get_pc());
+                break Outcome::Breakpoint;
+            }
+            if c64.is_cpu_jam() && self.process_cpu_jam(c64) {
+                break Outcome::CpuJam;
+            }
+        };
+        info!(
+            target: "main",
+            "Stopped after {} frames, {} cycles, outcome {:?}",
+            self.frames,
+            self.cycles,
+            outcome
+        );
+        if let Some(ref path) = self.options.dump_screen {
+            self.dump_screen(c64, path)
+                .map_err(|err| format!("Failed to dump screen, error: {}", err))?;
+        }
+        Ok(outcome)
+    }
+
+    fn is_limit_reached(&self) -> bool {
+        match (self.options.limit_cycles, self.options.limit_frames) {
+            (Some(limit), _) if self.cycles >= limit => true,
+            (_, Some(limit)) if self.frames >= limit => true,
+            _ => false,
+        }
+    }
+
+    fn process_cpu_jam(&mut self, c64: &mut C64) -> bool {
+        let jam_action = if self.options.exit_on_jam {
+            JamAction::Quit
+        } else {
+            self.options.jam_action
+        };
+        match jam_action {
+            JamAction::Continue => false,
+            JamAction::Quit => {
+                warn!(target: "main", "CPU JAM detected at 0x{:x}", c64.get_cpu().get_pc());
+                true
+            }
+            JamAction::Reset => {
+                warn!(target: "main", "CPU JAM detected at 0x{:x}", c64.get_cpu().get_pc());
+                c64.reset(false);
+                false
+            }
+        }
+    }
+
+    fn run_frame(&mut self, c64: &mut C64) -> bool {
+        let vsync = c64.run_frame();
+        if vsync {
+            c64.reset_vsync();
+            self.frames += 1;
+        }
+        vsync
+    }
+
+    fn run_step(&mut self, c64: &mut C64) -> bool {
+        c64.step();
+        if c64.get_vsync() {
+            c64.reset_vsync();
+            self.frames += 1;
+        }
+        !(c64.get_bpm().is_bp_present() && c64.check_breakpoints())
+    }
+
+    /// Write the visible part of the frame buffer as a binary PPM image.
+    fn dump_screen(&self, c64: &C64, path: &Path) -> std::io::Result<()> {
+        let model = &c64.get_config().model;
+        let (width, _) = model.frame_buffer_size;
+        let (offset_x, offset_y) = model.viewport_offset;
+        let (viewport_width, viewport_height) = model.viewport_size;
+        let video_buffer = self.video_buffer.borrow();
+        let pixels = video_buffer.get_pixel_data();
+        let mut writer = BufWriter::new(File::create(path)?);
+        write!(writer, "P6\n{} {}\n255\n", viewport_width, viewport_height)?;
+        for y in offset_y..offset_y + viewport_height {
+            for x in offset_x..offset_x + viewport_width {
+                let index = ((y * width + x) * 4) as usize;
+                writer.write_all(&pixels[index..index + 3])?;
+            }
+        }
+        writer.flush()
+    }
+}
@@ -6,25 +6,40 @@
 
 use std::collections::HashSet;
 
-use glutin::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use glutin::event::{ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent};
 use zinc64_core::device::joystick::Button;
 use zinc64_system::C64;
 
 use crate::util::keymap::KeyMap;
 
 pub struct InputSystem {
+    // Configuration
+    light_pen: bool,
+    // Runtime State
+    window_size: (f64, f64),
     _pressed_joy_keys: HashSet<VirtualKeyCode>,
     _pressed_joy_buttons: Vec<Button>,
 }
 
 impl InputSystem {
-    pub fn build() -> Result<InputSystem, String> {
+    pub fn build(light_pen: bool, window_size: (u32, u32)) -> Result<InputSystem, String> {
         Ok(InputSystem {
+            light_pen,
+            window_size: (f64::from(window_size.0), f64::from(window_size.1)),
             _pressed_joy_keys: HashSet::new(),
             _pressed_joy_buttons: Vec::new(),
         })
     }
 
+    /// Map window coordinates to the frame buffer, as the window shows the viewport.
+    fn map_light_pen(&self, c64: &C64, x: f64, y: f64) -> (u16, u16) {
+        let offset = c64.get_config().model.viewport_offset;
+        let size = c64.get_config().model.viewport_size;
+        let frame_x = f64::from(offset.0) + x * f64::from(size.0) / self.window_size.0;
+        let frame_y = f64::from(offset.1) + y * f64::from(size.1) / self.window_size.1;
+        (frame_x as u16, frame_y as u16)
+    }
+
     pub fn handle_event(&mut self, c64: &mut C64, event: &Event<()>) {
         match event {
             Event::WindowEvent { event, .. } => match event {
@@ -49,6 +64,27 @@ impl InputSystem {
                         }
                     }
                 },
+                WindowEvent::Resized(size) => {
+                    self.window_size = (f64::from(size.width), f64::from(size.height));
+                }
+                WindowEvent::CursorMoved { position, .. } if self.light_pen => {
+                    let position = self.map_light_pen(c64, position.x, position.y);
+                    c64.get_light_pen()
+                        .borrow_mut()
+                        .set_position(Some(position));
+                }
+                WindowEvent::CursorLeft { .. } if self.light_pen => {
+                    c64.get_light_pen().borrow_mut().set_position(None);
+                }
+                WindowEvent::MouseInput {
+                    state,
+                    button: MouseButton::Left,
+                    ..
+                } if self.light_pen => {
+                    c64.get_light_pen()
+                        .borrow_mut()
+                        .set_button(*state == ElementState::Pressed);
+                }
                 _ => (),
             },
             _ => (),
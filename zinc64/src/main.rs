@@ -13,15 +13,17 @@ mod console;
 mod debug;
 mod framework;
 mod gfx;
+mod headless;
 mod input;
 mod palette;
 mod platform;
+mod scope;
 mod time;
 mod ui;
 mod util;
 mod video;
 
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::BufReader;
 use std::path::Path;
 use std::process;
@@ -30,12 +32,12 @@ use std::sync::Arc;
 
 use structopt::StructOpt;
 use zinc64_core::util::new_shared;
-use zinc64_loader::Loaders;
 use zinc64_system::{C64Factory, C64};
 
 use crate::app::App;
-use crate::audio::SoundBuffer;
+use crate::audio::{SoundBuffer, SoundRecorder};
 use crate::cli::Opt;
+use crate::headless::Headless;
 use crate::palette::Palette;
 use crate::util::{FileReader, Logger};
 use crate::video::VideoBuffer;
@@ -45,7 +47,7 @@ static NAME: &str = "zinc64";
 fn main() {
     let opt = Opt::from_args();
     match run(&opt) {
-        Ok(_) => process::exit(0),
+        Ok(code) => process::exit(code),
         Err(err) => {
             println!("Error: {}", err);
             process::exit(1)
@@ -53,9 +55,9 @@ fn main() {
     };
 }
 
-fn load_image(c64: &mut C64, path: &Path) -> Result<(), String> {
-    let ext = path.extension().map(|s| s.to_str().unwrap());
-    let loader = Loaders::from_ext(ext)?;
+fn load_image(c64: &mut C64, opt: &Opt, path: &Path) -> Result<(), String> {
+    util::check_model_image(&c64.get_config().model, path)?;
+    let loader = cli::find_image_loader(opt, path)?;
     let file = File::open(path).map_err(|err| format!("{}", err))?;
     let mut reader = FileReader(BufReader::new(file));
     let mut autostart = loader.autostart(&mut reader)?;
@@ -63,33 +65,83 @@ fn load_image(c64: &mut C64, path: &Path) -> Result<(), String> {
     Ok(())
 }
 
-fn run(opt: &Opt) -> Result<(), String> {
+fn run(opt: &Opt) -> Result<i32, String> {
     let logger = Logger::build(opt.log_level.as_str(), &opt.log_target_level)?;
     Logger::enable(logger)?;
     info!("Starting {}", NAME);
-    let config = Rc::new(cli::build_emu_config(opt)?);
+    let sid_tune = cli::load_sid_tune(opt)?;
+    let config = Rc::new(cli::build_emu_config(opt, sid_tune.as_ref())?);
+    if let Some(ref path) = opt.play_sid_log {
+        let wav_path = opt
+            .record_audio
+            .as_ref()
+            .ok_or("SID log playback requires --record-audio")?;
+        cli::play_sid_log(&config, path, wav_path)?;
+        return Ok(0);
+    }
     let sound_buffer = Arc::new(SoundBuffer::new(config.sound.buffer_size << 2));
+    let sound_channels = if config.extra_sids.is_empty() { 1 } else { 2 };
+    #[allow(clippy::arc_with_non_send_sync)]
+    let sound_recorder = Arc::new(SoundRecorder::new(
+        sound_buffer.clone(),
+        config.sound.sample_rate,
+        sound_channels,
+    ));
+    if let Some(ref path) = opt.record_audio {
+        sound_recorder.start(path)?;
+    }
     let video_buffer = new_shared(VideoBuffer::new(
         config.model.frame_buffer_size.0,
         config.model.frame_buffer_size.1,
-        Palette::default(),
+        Palette::from(config.model.vic_model),
     ));
     let chip_factory = Box::new(C64Factory::new(config.clone()));
     let mut c64 = C64::build(
         config.clone(),
         &*chip_factory,
         video_buffer.clone(),
-        sound_buffer.clone(),
+        sound_recorder.clone(),
     );
     cli::set_c64_options(&mut c64, opt)?;
     c64.reset(true);
-    if let Some(image_path) = &opt.image {
-        load_image(&mut c64, Path::new(image_path))?;
+    if let Some(ref tune) = sid_tune {
+        let song = cli::get_subtune(opt, tune)?;
+        tune.autostart(song).execute(&mut c64);
+    } else if let Some(image_path) = &opt.image {
+        load_image(&mut c64, opt, Path::new(image_path))?;
+    }
+    cli::set_c64_movie_options(&mut c64, opt)?;
+    if opt.sid_log.is_some() {
+        c64.start_sid_capture();
     }
     if opt.console {
-        run_console(&mut c64);
+        let headless_options = cli::build_headless_options(opt)?;
+        let mut headless = Headless::new(headless_options, video_buffer.clone());
+        let outcome = headless.run(&mut c64)?;
+        sound_recorder.stop()?;
+        if let Some(ref path) = opt.sid_log {
+            cli::save_sid_log(&mut c64, path)?;
+        }
+        if let Some(ref path) = opt.record {
+            if let Some(movie) = c64.stop_recording() {
+                fs::write(path, movie.to_bytes()).map_err(|err| format!("{}", err))?;
+            }
+        }
+        if let Some(ref path) = cli::get_crt_image(opt) {
+            cli::save_crt_image(&c64, path)?;
+        }
+        if let Some(ref path) = cli::get_disk_image(opt) {
+            cli::save_disk_image(&c64, path)?;
+        }
+        if let Some(ref path) = opt.georam_image {
+            cli::save_georam_image(&c64, path)?;
+        }
+        if let Some(ref path) = opt.reu_image {
+            cli::save_reu_image(&c64, path)?;
+        }
+        Ok(outcome.exit_code())
     } else {
-        let app_options = cli::build_app_options(opt)?;
+        let app_options = cli::build_app_options(opt, sid_tune)?;
         let fx_options = framework::Options {
             title: NAME.to_owned(),
             window_size: (opt.width, opt.height),
@@ -100,21 +152,11 @@ fn run(opt: &Opt) -> Result<(), String> {
                 ctx,
                 c64,
                 sound_buffer.clone(),
+                sound_recorder.clone(),
                 video_buffer.clone(),
                 app_options,
             )
         })?;
-    }
-    Ok(())
-}
-
-fn run_console(c64: &mut C64) {
-    loop {
-        c64.run_frame();
-        c64.reset_vsync();
-        if c64.is_cpu_jam() {
-            warn!(target: "main", "CPU JAM detected at 0x{:x}", c64.get_cpu().get_pc());
-            break;
-        }
+        Ok(0)
     }
 }
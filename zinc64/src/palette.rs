@@ -6,11 +6,20 @@
 
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::unreadable_literal))]
 
+use zinc64_core::factory::VicModel;
+
 use crate::gfx::Color;
 
 pub struct Palette;
 
 impl Palette {
+    pub fn from(vic_model: VicModel) -> [u32; 16] {
+        match vic_model {
+            VicModel::Mos8565 => Palette::new_luminances(),
+            _ => Palette::default(),
+        }
+    }
+
     pub fn default() -> [u32; 16] {
         [
             Color::from_rgb(0x00, 0x00, 0x00).rgba(), // Black
@@ -31,4 +40,26 @@ impl Palette {
             Color::from_rgb(0x95, 0x95, 0x95).rgba(), // LightGray
         ]
     }
+
+    /// Colors of the 85xx chips, which use different luminance levels than the 65xx.
+    pub fn new_luminances() -> [u32; 16] {
+        [
+            Color::from_rgb(0x00, 0x00, 0x00).rgba(), // Black
+            Color::from_rgb(0xff, 0xff, 0xff).rgba(), // White
+            Color::from_rgb(0x81, 0x33, 0x38).rgba(), // Red
+            Color::from_rgb(0x75, 0xce, 0xc8).rgba(), // Cyan
+            Color::from_rgb(0x8e, 0x3c, 0x97).rgba(), // Purple
+            Color::from_rgb(0x56, 0xac, 0x4d).rgba(), // Green
+            Color::from_rgb(0x2e, 0x2c, 0x9b).rgba(), // Blue
+            Color::from_rgb(0xed, 0xf1, 0x71).rgba(), // Yellow
+            Color::from_rgb(0x8e, 0x50, 0x29).rgba(), // Orange
+            Color::from_rgb(0x55, 0x38, 0x00).rgba(), // Brown
+            Color::from_rgb(0xc4, 0x6c, 0x71).rgba(), // LightRed
+            Color::from_rgb(0x4a, 0x4a, 0x4a).rgba(), // DarkGray
+            Color::from_rgb(0x7b, 0x7b, 0x7b).rgba(), // MediumGray
+            Color::from_rgb(0xa9, 0xff, 0x9f).rgba(), // LightGreen
+            Color::from_rgb(0x70, 0x6d, 0xeb).rgba(), // LightBlue
+            Color::from_rgb(0xb2, 0xb2, 0xb2).rgba(), // LightGray
+        ]
+    }
 }
@@ -0,0 +1,130 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+use std::rc::Rc;
+use std::result::Result;
+
+use cgmath::num_traits::zero;
+use cgmath::{vec2, Vector2};
+use zinc64_core::sound::{SidChannel, VoiceControl};
+
+use crate::framework::Context;
+use crate::gfx::{gl, sprite, Color, Rect, RectI};
+
+// Design:
+//   Voice waveforms are drawn over the bottom of the screen in three bands, one per voice,
+//   from the taps of the primary SID. Each sample becomes a thin quad from the previous
+//   sample to itself, so steep edges of pulse and saw waves stay connected. The window
+//   starts at a rising zero crossing when there is one, which keeps periodic waves steady.
+
+const SAMPLES: usize = 512;
+const OVERLAY_TOP: f32 = 0.7;
+const LINE_WIDTH: f32 = 0.004;
+
+pub struct ScopeRenderer {
+    // Configuration
+    palette: [Color; 3],
+    background: Color,
+    muted: Color,
+    // Resources
+    batch: sprite::Batch,
+    texture: Rc<gl::Texture>,
+}
+
+impl ScopeRenderer {
+    pub fn build(ctx: &mut Context) -> Result<ScopeRenderer, String> {
+        let window_size = ctx.platform.windowed_context.window().inner_size();
+        let gl = &mut ctx.platform.gl;
+        // Quads are drawn in solid colors from a single white texel
+        let texture = Rc::new(gl.create_texture(vec2(1, 1))?);
+        gl.set_texture_data(&texture, &[0xff; 4]);
+        let mut batch = sprite::Batch::new(gl, 3 * SAMPLES + 3)?;
+        batch.set_projection(gl, Rect::from_points(zero(), vec2(1.0, 1.0)), true);
+        batch.set_viewport(
+            gl,
+            RectI::new(
+                zero(),
+                Vector2::new(window_size.width as i32, window_size.height as i32),
+            ),
+        );
+        Ok(ScopeRenderer {
+            palette: [
+                Color::from_rgb(0x8e, 0xc0, 0x7c),
+                Color::from_rgb(0x83, 0xa5, 0x98),
+                Color::from_rgb(0xfa, 0xbd, 0x2f),
+            ],
+            background: Color::from_rgba(0x28, 0x28, 0x28, 0xc0),
+            muted: Color::from_rgb(0x66, 0x5c, 0x54),
+            batch,
+            texture,
+        })
+    }
+
+    pub fn update_viewport(&mut self, ctx: &mut Context, width: i32, height: i32) {
+        self.batch.set_viewport(
+            &mut ctx.platform.gl,
+            RectI::new(zero(), vec2(width, height)),
+        );
+    }
+
+    pub fn render(&mut self, ctx: &mut Context, voice_control: &VoiceControl) {
+        let gl = &mut ctx.platform.gl;
+        let band_height = (1.0 - OVERLAY_TOP) / 3.0;
+        let texel = Rect::from_points(zero(), vec2(1.0, 1.0));
+        self.batch.begin(gl, Some(self.texture.clone()));
+        for voice in 0..3 {
+            let top = OVERLAY_TOP + band_height * voice as f32;
+            let center = top + band_height / 2.0;
+            let scale = band_height / 2.0 / 32768.0;
+            self.batch.push(
+                gl,
+                Rect::from_points(vec2(0.0, top), vec2(1.0, top + band_height)),
+                texel,
+                self.background,
+            );
+            let channel = SidChannel::from(voice).unwrap();
+            // Routing is not known here, so soloing the filter shows the voice mutes
+            let muted = match voice_control.get_solo() {
+                Some(SidChannel::Filter) | None => voice_control.is_muted(channel),
+                Some(solo) => solo != channel,
+            };
+            let color = if !muted {
+                self.palette[voice]
+            } else {
+                self.muted
+            };
+            let samples = Self::find_window(voice_control.get_tap(voice));
+            let step = 1.0 / SAMPLES as f32;
+            let mut prev_y = center - f32::from(samples.first().cloned().unwrap_or(0)) * scale;
+            for (i, sample) in samples.iter().enumerate() {
+                let x = i as f32 * step;
+                let y = center - f32::from(*sample) * scale;
+                let (y1, y2) = if y < prev_y { (y, prev_y) } else { (prev_y, y) };
+                self.batch.push(
+                    gl,
+                    Rect::from_points(
+                        vec2(x, y1 - LINE_WIDTH / 2.0),
+                        vec2(x + step, y2 + LINE_WIDTH / 2.0),
+                    ),
+                    texel,
+                    color,
+                );
+                prev_y = y;
+            }
+        }
+        self.batch.end(gl);
+    }
+
+    fn find_window(tap: &[i16]) -> &[i16] {
+        if tap.len() <= SAMPLES {
+            return tap;
+        }
+        let last = tap.len() - SAMPLES;
+        let start = (1..=last)
+            .rev()
+            .find(|&i| tap[i - 1] < 0 && tap[i] >= 0)
+            .unwrap_or(last);
+        &tap[start..start + SAMPLES]
+    }
+}
@@ -90,7 +90,7 @@ impl ConsoleScreen {
             batch,
             font,
             font_tex,
-            cmd_handler: Executor::new(),
+            cmd_handler: Executor::new(state.sound_recorder.clone()),
             cursor_timer: CURSOR_BLINK_DUR,
             cursor_visibility: false,
             history_pos: -1,
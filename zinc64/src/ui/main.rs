@@ -10,24 +10,30 @@ use std::result::Result;
 
 use glutin::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use glutin::window::Fullscreen;
-use zinc64_loader::Loaders;
+use zinc64_core::sound::SidChannel;
+use zinc64_loader::Format;
 
 use crate::app::{AppState, JamAction, RuntimeState};
 use crate::audio::AudioRenderer;
+use crate::cli;
 use crate::framework::Context;
 use crate::input::InputSystem;
+use crate::scope::ScopeRenderer;
 use crate::ui::console::ConsoleScreen;
 use crate::ui::{Screen, Transition};
-use crate::util::FileReader;
+use crate::util::{check_model_image, find_format, find_loader, load_sid_tune, FileReader};
 use crate::video::VideoRenderer;
 
 pub struct MainScreen {
     // Components
     audio_device: AudioRenderer,
     input_system: InputSystem,
+    scope_renderer: ScopeRenderer,
     video_renderer: VideoRenderer,
     // Runtime State
     next_keyboard_event: u64,
+    rewinding: bool,
+    scope_visible: bool,
 }
 
 impl MainScreen {
@@ -44,28 +50,93 @@ impl MainScreen {
         audio_device.start();
         // Initialize video
         let video_renderer = VideoRenderer::build(ctx, state)?;
+        let scope_renderer = ScopeRenderer::build(ctx)?;
         // Initialize input
-        let input_system = InputSystem::build()?;
+        let window_size = ctx.platform.windowed_context.window().inner_size();
+        if let Some(button) = state.options.light_pen {
+            state
+                .c64
+                .get_light_pen()
+                .borrow_mut()
+                .set_button_line(button);
+        }
+        let input_system = InputSystem::build(
+            state.options.light_pen.is_some(),
+            (window_size.width, window_size.height),
+        )?;
         Ok(MainScreen {
             audio_device,
             input_system,
+            scope_renderer,
             video_renderer,
             next_keyboard_event: 0,
+            rewinding: false,
+            scope_visible: false,
         })
     }
 
+    fn freeze(&mut self, state: &mut AppState) {
+        if !state.c64.freeze_cartridge() {
+            warn!("Attached cartridge does not support freeze");
+        }
+    }
+
     fn halt(&mut self, state: &mut AppState) -> Result<(), String> {
         self.set_state(state, RuntimeState::Halted);
         state.debug.halt()
     }
 
     fn load_image(&mut self, state: &mut AppState, path: &Path) -> Result<(), String> {
-        let ext = path.extension().map(|s| s.to_str().unwrap());
-        let loader = Loaders::from_ext(ext)?;
+        check_model_image(&state.c64.get_config().model, path)?;
+        if let Some(tune) = load_sid_tune(path)? {
+            // The system is built for the tune given on the command line only
+            let config = state.c64.get_config();
+            let missing_sid = tune.get_extra_sids().iter().any(|(address, _)| {
+                !config
+                    .extra_sids
+                    .iter()
+                    .any(|sid| sid.base_address == *address)
+            });
+            if missing_sid
+                || tune
+                    .get_sid_model()
+                    .is_some_and(|model| model != config.model.sid_model)
+            {
+                warn!("SID model or additional SIDs of the tune are not applied");
+            }
+            let song = tune.get_start_song();
+            tune.autostart(song).execute(&mut state.c64);
+            state.options.sid_tune = Some(tune);
+            state.options.sid_song = song;
+            return Ok(());
+        }
+        state.options.sid_tune = None;
+        let format = find_format(path)?;
+        // The disk in the drive is replaced, so it is saved back before it is ejected
+        if let Some(Format::D64) | Some(Format::G64) = format {
+            if let Some(disk_image) = state.options.disk_image.take() {
+                if let Err(err) = cli::save_disk_image(&state.c64, &disk_image) {
+                    error!("Failed to save disk image, error: {}", err);
+                }
+            }
+            if state.options.disk_write_back && matches!(format, Some(Format::G64)) {
+                state.options.disk_image = Some(path.to_path_buf());
+            }
+        }
+        let loader = find_loader(path)?;
         let file = File::open(path).map_err(|err| format!("{}", err))?;
         let mut reader = FileReader(BufReader::new(file));
         let mut autostart = loader.autostart(&mut reader)?;
         autostart.execute(&mut state.c64);
+        // The cartridge attached before is replaced, so it is only saved back to the image
+        // the new one was loaded from
+        if let Some(Format::Crt) = format {
+            state.options.crt_image = if state.options.crt_write_back {
+                Some(path.to_path_buf())
+            } else {
+                None
+            };
+        }
         Ok(())
     }
 
@@ -101,6 +172,24 @@ impl MainScreen {
         self.next_keyboard_event = 0;
     }
 
+    fn rewind(&mut self, state: &mut AppState) -> Result<(), String> {
+        if state.c64.rewind()? {
+            state.c64.reset_vsync();
+            state.c64.run_frame();
+        }
+        Ok(())
+    }
+
+    fn select_song(&mut self, state: &mut AppState, delta: i32) {
+        if let Some(ref tune) = state.options.sid_tune {
+            let song = i32::from(state.options.sid_song) + delta;
+            if song >= 1 && song <= i32::from(tune.get_songs()) {
+                state.options.sid_song = song as u16;
+                tune.select_song(&mut state.c64, song as u16);
+            }
+        }
+    }
+
     fn set_state(&mut self, state: &mut AppState, new_state: RuntimeState) {
         if state.state != new_state {
             state.state = new_state;
@@ -136,6 +225,13 @@ impl MainScreen {
         self.audio_device.toggle_mute();
     }
 
+    fn toggle_scope(&mut self, state: &mut AppState) {
+        self.scope_visible = !self.scope_visible;
+        state.c64.get_voice_controls()[0]
+            .borrow_mut()
+            .enable_taps(self.scope_visible);
+    }
+
     fn toggle_pause(&mut self, state: &mut AppState) {
         let emu_state = state.state;
         match emu_state {
@@ -145,6 +241,24 @@ impl MainScreen {
         };
     }
 
+    fn toggle_voice_mute(&mut self, state: &mut AppState, channel: SidChannel) {
+        for voice_control in state.c64.get_voice_controls() {
+            let muted = voice_control.borrow().is_muted(channel);
+            voice_control.borrow_mut().set_muted(channel, !muted);
+        }
+    }
+
+    fn toggle_voice_solo(&mut self, state: &mut AppState, channel: SidChannel) {
+        for voice_control in state.c64.get_voice_controls() {
+            let solo = if voice_control.borrow().get_solo() != Some(channel) {
+                Some(channel)
+            } else {
+                None
+            };
+            voice_control.borrow_mut().set_solo(solo);
+        }
+    }
+
     fn toggle_warp(&mut self, ctx: &mut Context, state: &mut AppState) {
         let value = state.options.warp_mode;
         state.options.warp_mode = !value;
@@ -180,6 +294,8 @@ impl Screen<AppState> for MainScreen {
                 WindowEvent::Resized(size) => {
                     self.video_renderer
                         .update_viewport(ctx, size.width as i32, size.height as i32);
+                    self.scope_renderer
+                        .update_viewport(ctx, size.width as i32, size.height as i32);
                     Ok(Transition::None)
                 }
                 WindowEvent::KeyboardInput {
@@ -204,6 +320,10 @@ impl Screen<AppState> for MainScreen {
                         self.toggle_mute();
                         Ok(Transition::None)
                     }
+                    (VirtualKeyCode::O, ElementState::Pressed) if modifiers.alt() => {
+                        self.toggle_scope(app_state);
+                        Ok(Transition::None)
+                    }
                     (VirtualKeyCode::P, ElementState::Pressed) if modifiers.alt() => {
                         self.toggle_pause(app_state);
                         Ok(Transition::None)
@@ -212,10 +332,49 @@ impl Screen<AppState> for MainScreen {
                         self.set_state(app_state, RuntimeState::Stopped);
                         Ok(Transition::None)
                     }
+                    (VirtualKeyCode::R, ElementState::Pressed) if modifiers.alt() => {
+                        self.rewinding = true;
+                        Ok(Transition::None)
+                    }
+                    (VirtualKeyCode::R, ElementState::Released) => {
+                        self.rewinding = false;
+                        Ok(Transition::None)
+                    }
                     (VirtualKeyCode::W, ElementState::Pressed) if modifiers.alt() => {
                         self.toggle_warp(ctx, app_state);
                         Ok(Transition::None)
                     }
+                    (VirtualKeyCode::Z, ElementState::Pressed) if modifiers.alt() => {
+                        self.freeze(app_state);
+                        Ok(Transition::None)
+                    }
+                    (VirtualKeyCode::Key1, ElementState::Pressed)
+                    | (VirtualKeyCode::Key2, ElementState::Pressed)
+                    | (VirtualKeyCode::Key3, ElementState::Pressed)
+                    | (VirtualKeyCode::Key4, ElementState::Pressed)
+                        if modifiers.alt() =>
+                    {
+                        let channel = match virtual_code {
+                            VirtualKeyCode::Key1 => SidChannel::Voice1,
+                            VirtualKeyCode::Key2 => SidChannel::Voice2,
+                            VirtualKeyCode::Key3 => SidChannel::Voice3,
+                            _ => SidChannel::Filter,
+                        };
+                        if modifiers.shift() {
+                            self.toggle_voice_solo(app_state, channel);
+                        } else {
+                            self.toggle_voice_mute(app_state, channel);
+                        }
+                        Ok(Transition::None)
+                    }
+                    (VirtualKeyCode::Left, ElementState::Pressed) if modifiers.alt() => {
+                        self.select_song(app_state, -1);
+                        Ok(Transition::None)
+                    }
+                    (VirtualKeyCode::Right, ElementState::Pressed) if modifiers.alt() => {
+                        self.select_song(app_state, 1);
+                        Ok(Transition::None)
+                    }
                     (VirtualKeyCode::Return, ElementState::Pressed) if modifiers.alt() => {
                         self.toggle_fullscreen(ctx);
                         Ok(Transition::None)
@@ -266,6 +425,10 @@ impl Screen<AppState> for MainScreen {
             }
         }
         match state.state {
+            RuntimeState::Running if self.rewinding => {
+                self.rewind(state)?;
+                Ok(Transition::None)
+            }
             RuntimeState::Running => {
                 let vsync = state.c64.run_frame();
                 if !vsync {
@@ -286,6 +449,10 @@ impl Screen<AppState> for MainScreen {
     ) -> Result<Transition<AppState>, String> {
         if state.c64.get_vsync() {
             self.video_renderer.render(ctx)?;
+            if self.scope_visible {
+                let voice_control = state.c64.get_voice_controls()[0].clone();
+                self.scope_renderer.render(ctx, &voice_control.borrow());
+            }
             state.c64.reset_vsync();
             ctx.platform
                 .windowed_context
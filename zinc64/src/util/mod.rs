@@ -6,7 +6,9 @@ pub mod circular_buffer;
 pub mod keymap;
 mod logger;
 pub mod reader;
+mod wav;
 
 pub use self::circular_buffer::CircularBuffer;
 pub use self::logger::Logger;
-pub use self::reader::FileReader;
+pub use self::reader::{check_model_image, find_format, find_loader, load_sid_tune, FileReader};
+pub use self::wav::WavWriter;
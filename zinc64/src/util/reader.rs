@@ -7,7 +7,8 @@ use std::fs::File;
 use std::io::{BufRead, BufReader, Read};
 use std::path::Path;
 
-use zinc64_loader::{Reader, Result};
+use zinc64_core::factory::SystemModel;
+use zinc64_loader::{Format, Loader, Loaders, Reader, Result, SidTune};
 
 pub struct FileReader(pub BufReader<File>);
 
@@ -40,3 +41,42 @@ pub fn read_file(path: &Path) -> Result<Vec<u8>> {
     reader.read_to_end(&mut data)?;
     Ok(data)
 }
+
+/// Get loader for the image detecting its format from the content.
+pub fn find_loader(path: &Path) -> Result<Box<dyn Loader>> {
+    let file = File::open(path).map_err(|err| format!("{}", err))?;
+    let size = file.metadata().map_err(|err| format!("{}", err))?.len() as usize;
+    let mut header = Vec::with_capacity(64);
+    file.take(64)
+        .read_to_end(&mut header)
+        .map_err(|err| format!("{}", err))?;
+    let ext = path.extension().and_then(|s| s.to_str());
+    Loaders::from_content(&header, size, ext)
+}
+
+/// Detect image format from its signature.
+pub fn find_format(path: &Path) -> Result<Option<Format>> {
+    let file = File::open(path).map_err(|err| format!("{}", err))?;
+    let mut header = Vec::with_capacity(64);
+    file.take(64)
+        .read_to_end(&mut header)
+        .map_err(|err| format!("{}", err))?;
+    Ok(Format::from_signature(&header))
+}
+
+/// Reject images other than CRT cartridges if the system model only boots from those.
+pub fn check_model_image(model: &SystemModel, path: &Path) -> Result<()> {
+    if model.cartridge_only && !matches!(find_format(path)?, Some(Format::Crt)) {
+        return Err("The selected model only runs CRT cartridge images".to_string());
+    }
+    Ok(())
+}
+
+/// Load SID tune if the image is a PSID or RSID file.
+pub fn load_sid_tune(path: &Path) -> Result<Option<SidTune>> {
+    let data = read_file(path)?;
+    match Format::from_signature(&data) {
+        Some(Format::Sid) => SidTune::parse(&data).map(Some),
+        _ => Ok(None),
+    }
+}
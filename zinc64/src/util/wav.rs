@@ -0,0 +1,80 @@
+// This file is part of zinc64.
+// Copyright (c) 2016-2019 Sebastian Jastrzebski. All rights reserved.
+// Licensed under the GPLv3. See LICENSE file in the project root for full license text.
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+// SPEC: http://soundfile.sapp.org/doc/WaveFormat/
+
+const HEADER_SIZE: u32 = 44;
+const BITS_PER_SAMPLE: u16 = 16;
+
+/// Writer of 16-bit PCM WAV files. Chunk sizes are filled in when the file is finished.
+pub struct WavWriter {
+    writer: BufWriter<File>,
+    channels: u16,
+    samples: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, sample_rate: u32, channels: u16) -> Result<WavWriter, String> {
+        let file = File::create(path).map_err(|err| format!("{}", err))?;
+        let mut wav = WavWriter {
+            writer: BufWriter::new(file),
+            channels,
+            samples: 0,
+        };
+        wav.write_header(sample_rate, 0)
+            .map_err(|err| format!("{}", err))?;
+        Ok(wav)
+    }
+
+    pub fn get_channels(&self) -> u16 {
+        self.channels
+    }
+
+    pub fn write(&mut self, sample: i16) -> Result<(), String> {
+        self.writer
+            .write_i16::<LittleEndian>(sample)
+            .map_err(|err| format!("{}", err))?;
+        self.samples += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), String> {
+        self.write_sizes().map_err(|err| format!("{}", err))
+    }
+
+    fn write_header(&mut self, sample_rate: u32, data_size: u32) -> std::io::Result<()> {
+        let block_align = self.channels * BITS_PER_SAMPLE / 8;
+        let w = &mut self.writer;
+        w.write_all(b"RIFF")?;
+        w.write_u32::<LittleEndian>(HEADER_SIZE - 8 + data_size)?;
+        w.write_all(b"WAVE")?;
+        w.write_all(b"fmt ")?;
+        w.write_u32::<LittleEndian>(16)?;
+        w.write_u16::<LittleEndian>(1)?; // PCM
+        w.write_u16::<LittleEndian>(self.channels)?;
+        w.write_u32::<LittleEndian>(sample_rate)?;
+        w.write_u32::<LittleEndian>(sample_rate * u32::from(block_align))?;
+        w.write_u16::<LittleEndian>(block_align)?;
+        w.write_u16::<LittleEndian>(BITS_PER_SAMPLE)?;
+        w.write_all(b"data")?;
+        w.write_u32::<LittleEndian>(data_size)
+    }
+
+    fn write_sizes(&mut self) -> std::io::Result<()> {
+        let data_size = self.samples * u32::from(BITS_PER_SAMPLE / 8);
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_u32::<LittleEndian>(HEADER_SIZE - 8 + data_size)?;
+        self.writer
+            .seek(SeekFrom::Start(u64::from(HEADER_SIZE - 4)))?;
+        self.writer.write_u32::<LittleEndian>(data_size)?;
+        self.writer.flush()
+    }
+}